@@ -6,8 +6,12 @@
  * and the overall update loop.
  */
 
+use boids::scenario::Scenario;
+use boids::{culling, Boid, Simulation, SimulationParams};
+use nannou::prelude::{pt2, Rect};
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use rand::Rng;
+use std::path::Path;
 use std::time::Duration;
 
 // Import the necessary types from the main crate
@@ -114,6 +118,100 @@ fn bench_update_loop(c: &mut Criterion) {
     group.finish();
 }
 
+// Benchmark one full physics step (spatial grid and parallel processing enabled)
+// on large flocks, where the pass is limited by memory bandwidth
+fn bench_physics_step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("physics_step");
+    
+    for num_boids in [50_000, 100_000, 200_000].iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(num_boids), num_boids, |b, &n| {
+            let mut params = SimulationParams::default();
+            params.num_boids = n;
+            params.world_size = BENCHMARK_WORLD_SIZE;
+            params.enable_spatial_grid = true;
+            params.enable_parallel = true;
+            let mut sim = Simulation::with_seed(params, 42);
+            
+            b.iter(|| {
+                sim.step_once();
+                black_box(&sim.flock.positions);
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+// Benchmark one physics step on 50k boids with the exact and the fast math flocking rules
+fn bench_fast_math(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fast_math");
+    
+    for fast_math in [false, true].iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(fast_math), fast_math, |b, &fast_math| {
+            let mut params = SimulationParams::default();
+            params.num_boids = 50_000;
+            params.world_size = BENCHMARK_WORLD_SIZE;
+            params.fast_math = fast_math;
+            let mut sim = Simulation::with_seed(params, 42);
+            
+            b.iter(|| {
+                sim.step_once();
+                black_box(&sim.flock.positions);
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+// Benchmark frustum culling with the spatial grid at full-world zoom, where every cell
+// overlaps the visible area
+fn bench_culling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("culling");
+    
+    for num_boids in [50_000, 200_000].iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(num_boids), num_boids, |b, &n| {
+            let mut params = SimulationParams::default();
+            params.num_boids = n;
+            params.world_size = BENCHMARK_WORLD_SIZE;
+            let mut sim = Simulation::with_seed(params, 42);
+            sim.step_once(); // Fills the spatial grid
+            let boids: Vec<Boid> = sim.flock.iter().collect();
+            
+            let half_world = BENCHMARK_WORLD_SIZE / 2.0;
+            let visible_area = Rect::from_corners(pt2(-half_world, -half_world), pt2(half_world, half_world));
+            let visible_area = culling::expand_rect(visible_area, 10.0);
+            
+            b.iter(|| {
+                black_box(culling::cull_with_spatial_grid(&boids, &sim.spatial_grid, &sim.params, 0.5, visible_area));
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+// Benchmark one physics step of each checked-in scenario, the same worlds that
+// `--scenario` runs in the app and in headless mode
+fn bench_scenarios(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scenario");
+    
+    for name in ["small", "medium", "large"] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios").join(format!("{}.toml", name));
+        let scenario = Scenario::load(&path).unwrap();
+        group.bench_function(name, |b| {
+            let mut sim = scenario.build_simulation();
+            
+            b.iter(|| {
+                sim.step_once();
+                black_box(&sim.flock.positions);
+            });
+        });
+    }
+    
+    group.finish();
+}
+
 // Configure the benchmarks
 criterion_group! {
     name = benches;
@@ -121,7 +219,7 @@ criterion_group! {
         .sample_size(10)
         .measurement_time(Duration::from_secs(5))
         .warm_up_time(Duration::from_secs(1));
-    targets = bench_spatial_grid, bench_force_calculations, bench_update_loop
+    targets = bench_spatial_grid, bench_force_calculations, bench_update_loop, bench_physics_step, bench_culling, bench_fast_math, bench_scenarios
 }
 
 criterion_main!(benches); 
\ No newline at end of file
@@ -0,0 +1,58 @@
+/*
+ * Stream Client Example
+ * 
+ * Subscribes to the simulation's UDP stream and prints the flock's centroid for
+ * every frame received. Start the simulation with streaming enabled first:
+ * 
+ *   cargo run --release -- --stream 9000
+ *   cargo run --example stream_client -- 127.0.0.1:9000
+ * 
+ * See src/stream.rs for the frame layout.
+ */
+
+use boids::physics::wrapped_centroid;
+use boids::stream::{self, SUBSCRIPTION_TIMEOUT};
+use std::io;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+fn main() -> io::Result<()> {
+    let server = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:9000".to_string());
+    
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+    println!("Subscribing to {}", server);
+    
+    // Any datagram subscribes; resend well within the timeout to stay subscribed
+    let resubscribe_interval = SUBSCRIPTION_TIMEOUT / 3;
+    let mut last_subscribe: Option<Instant> = None;
+    let mut buffer = vec![0u8; 65536];
+    
+    loop {
+        if last_subscribe.is_none_or(|time| time.elapsed() >= resubscribe_interval) {
+            socket.send_to(b"subscribe", &server)?;
+            last_subscribe = Some(Instant::now());
+        }
+        
+        let len = match socket.recv(&mut buffer) {
+            Ok(len) => len,
+            // No frame yet (or the simulation isn't running): keep resubscribing
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ConnectionRefused) => continue,
+            Err(err) => return Err(err),
+        };
+        
+        let Some(frame) = stream::decode_frame(&buffer[..len]) else {
+            eprintln!("warning: ignoring malformed frame of {} bytes", len);
+            continue;
+        };
+        
+        let positions = frame.boids.iter().map(|&(position, _)| position);
+        match wrapped_centroid(positions, frame.world_size) {
+            Some(centroid) => println!(
+                "frame {}: {}/{} boids, centroid ({:.1}, {:.1})",
+                frame.frame, frame.boids.len(), frame.total_boids, centroid.x, centroid.y
+            ),
+            None => println!("frame {}: no boids", frame.frame),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/*
+ * Allocation Counter Module
+ * 
+ * A global allocator that counts heap allocations on top of the system allocator, for the
+ * allocations-per-frame figure in the debug panel. The binary installs it; library users
+ * who don't install it see a count of zero, and the debug panel then hides the figure.
+ * 
+ * Counting costs one relaxed atomic increment per allocation.
+ */
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct CountingAllocator;
+
+// Safety: every call is forwarded unchanged to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+    
+    // A reallocation may move the block, so it counts as an allocation too
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// Allocations since the program started, on every thread (zero if the counting allocator
+// isn't installed)
+pub fn allocation_count() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
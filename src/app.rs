@@ -7,78 +7,142 @@
  * 
  * The application is structured to optimize performance through:
  * - Modular code organization for better maintainability
- * - Fixed timestep physics with interpolated rendering
+ * - Fixed timestep physics on a dedicated thread, with rendering interpolated
+ *   between the last two physics snapshots
  * - Efficient spatial partitioning and frustum culling
  * - Adaptive cell sizing for optimal spatial grid performance
+ * 
+ * The flocking state itself lives in a head-less Simulation owned by the physics
+ * thread; the Model keeps a copy of the parameters for the UI, a view of the
+ * latest boid snapshot, and the window, camera and selection state.
  */
 
 use nannou::prelude::*;
-use nannou_egui::Egui;
-use rand::Rng;
-use std::cell::UnsafeCell;
-use std::time::{Duration, Instant};
-
-use crate::boid::Boid;
-use crate::camera::Camera;
-use crate::spatial_grid::SpatialGrid;
+use nannou_egui::{egui, Egui};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::boid::{boid_render_position, boid_render_velocity};
+use crate::breeds;
+use crate::camera::{Camera, FollowTarget};
+use crate::cli;
+use crate::culling::{RenderBudget, VisibleAreaCache};
+use crate::config::{self, Config};
+use crate::export::{self, ExportProgress, Exporter};
+use crate::flock::BoidId;
+use crate::instanced::InstancedRenderer;
+use crate::modulation::Modulator;
 use crate::params::SimulationParams;
-use crate::debug::DebugInfo;
+use crate::physics_thread::{PhysicsCommand, PhysicsThread, SimulationView};
+use crate::debug::{DebugInfo, SelectedBoidDebug};
+use crate::effects::Effects;
 use crate::physics;
 use crate::renderer;
 use crate::input;
+use crate::region::ParamRegion;
+use crate::timeline::Timeline;
 use crate::ui;
+use crate::wall::Wall;
+use crate::watch::{self, ParamWatcher};
 
 // Main model for the application
 pub struct Model {
-    pub boids: Vec<Boid>,
-    pub params: SimulationParams,
+    pub params: SimulationParams, // Edited by the UI and sent to the physics thread every frame
+    pub modulation: Modulator, // LFO mappings applied to the parameters sent to the physics thread
+    pub timeline: Timeline, // Playhead of the keyframe animation (the keyframes are in the parameters)
+    pub physics: PhysicsThread, // Owns the simulation and steps it on its own thread
+    pub view: SimulationView, // Latest boid snapshot from the physics thread
+    pub effects: Effects, // Boids fading in and out (render only)
     pub egui: Egui,
-    pub debug_info: UnsafeCell<DebugInfo>,
+    pub ui_visible: bool, // Whether the egui controls window is shown
+    pub ui_window: ui::WindowState, // Where the controls window is, so it can be moved back on screen
+    pub clamp_flash: Option<(egui::Id, f64)>, // Numeric field that last clamped a typed value, and when (egui time)
+    pub debug_info: Mutex<DebugInfo>, // Also written by the renderer (culling statistics)
     pub camera: Camera,
     pub mouse_position: Vec2,
-    pub spatial_grid: SpatialGrid,
-    pub cached_visible_boids: UnsafeCell<Option<Vec<usize>>>,
-    pub render_needed: UnsafeCell<bool>,
-    pub _last_camera_state: Option<(Vec2, f32)>, // Marked as intentionally unused
-    // Fixed timestep physics variables
-    pub physics_accumulator: Duration,
-    pub physics_step_size: Duration,
-    pub last_update_time: Instant,
-    pub interpolation_alpha: f32,
+    pub cached_visible_boids: Mutex<Option<Vec<usize>>>,
+    pub render_needed: AtomicBool,
+    pub last_camera_state: Option<u64>, // Camera and window state of the last update, to notice moves and resizes
     pub _last_render_time: Instant, // Marked as intentionally unused
-    // Frustum culling optimization
-    pub visible_area_cache: Option<Rect>,
+    // Frustum culling area of the last rendered frame, reused while the camera is unchanged
+    pub visible_area_cache: Mutex<Option<VisibleAreaCache>>,
+    // GPU pipeline for instanced boid rendering, created by the renderer on first use
+    pub instanced_renderer: Mutex<Option<InstancedRenderer>>,
+    // Subsampling of the visible boids, adjusted by the renderer after every frame
+    pub render_budget: Mutex<RenderBudget>,
     // Boid selection and following
-    pub selected_boid_index: Option<usize>,
-    // Adaptive cell sizing
-    pub last_cell_size_update: Instant,
-    pub cell_size_update_interval: Duration,
+    pub selected_boid_id: Option<BoidId>,
+    pub selected_boid_index: Option<usize>, // Index of selected_boid_id in the view (resolved every frame)
+    pub selected_boid_debug: Option<SelectedBoidDebug>, // Last recorded forces for the selected boid
+    pub dragged_boid: Option<BoidId>, // Boid being repositioned with the mouse in edit mode
+    pub hovered_boid_index: Option<usize>, // Boid under the cursor (recomputed every frame)
+    // Walls drawn in wall mode (the physics thread has its own copy)
+    pub walls: Vec<Wall>,
+    pub wall_start: Option<Point2>, // First endpoint of the wall being drawn
+    // Parameter regions (the physics thread has its own copy)
+    pub regions: Vec<ParamRegion>,
+    pub selected_region: Option<usize>, // Region whose overrides the UI edits
+    pub dragged_region: Option<(usize, Point2)>, // Region whose corner is being dragged, and the opposite corner
+    // Right-button boid emission
+    pub is_emitting: bool,
+    // Double-click detection
+    pub last_click_time: Option<Instant>,
+    pub last_click_position: Vec2,
+    // Last error from starting an export (e.g. the file couldn't be created)
+    pub export_error: Option<String>,
+    // Progress of the current or last export (written by the physics thread's exporter)
+    pub export_progress: Option<ExportProgress>,
+    // Settings file read at startup and written on exit (None if there is nowhere to save it)
+    pub config_path: Option<PathBuf>,
+    pub status_message: Option<String>, // Result of the last save, preset load or screenshot
+    pub toast: Option<ui::Toast>, // Result of the last reload of the watched parameter file
+    pub param_watcher: Option<ParamWatcher>, // Watches the --watch-params file
+    pub breeds_path: PathBuf, // Breeds file re-read by the UI's reload button
+    pub window_size: (u32, u32), // Saved to the config file on exit
+    pub ui_layout: ui::UiLayout, // Open sections and compact mode of the controls window (also saved)
 }
 
-// Make Model safe to share across threads
-unsafe impl Sync for Model {}
+impl Model {
+    // Redraw the scene on the next frame and drop the visible boids cached while paused.
+    // Called for anything that changes what is drawn or where: rendering parameters, the
+    // selection, camera movement, window resizes and new snapshots.
+    pub fn invalidate_render(&self) {
+        *self.cached_visible_boids.lock().unwrap() = None;
+        self.render_needed.store(true, Ordering::Relaxed);
+    }
+}
 
 // Initialize the model
 pub fn model(app: &App) -> Model {
-    // Get the primary monitor's dimensions
-    let monitor = app.primary_monitor().expect("Failed to get primary monitor");
-    let monitor_size = monitor.size();
+    let overrides = cli::overrides();
     
-    // Calculate window size based on monitor size (80% of monitor size)
-    let window_width = monitor_size.width as f32 * 0.8;
-    let window_height = monitor_size.height as f32 * 0.8;
+    // Saved settings from the last run (defaults if there is no config file)
+    let config_path = overrides.config_path();
+    let config = config_path.as_deref().map(Config::load_or_default).unwrap_or_default();
+    
+    // Use the saved window size, or 80% of the primary monitor's size
+    let (window_width, window_height) = config.window_size.unwrap_or_else(|| {
+        let monitor = app.primary_monitor().expect("Failed to get primary monitor");
+        let monitor_size = monitor.size();
+        ((monitor_size.width as f32 * 0.8) as u32, (monitor_size.height as f32 * 0.8) as u32)
+    });
     
     // Create the main window with dynamic size
     let window_id = app
         .new_window()
         .title("Boid Flocking Simulation")
-        .size(window_width as u32, window_height as u32)
+        .size(window_width, window_height)
         .view(renderer::view)
         .mouse_moved(input::mouse_moved)
         .mouse_pressed(input::mouse_pressed)
         .mouse_released(input::mouse_released)
         .mouse_wheel(input::mouse_wheel)
+        .key_pressed(input::key_pressed)
         .raw_event(input::raw_window_event)
+        .resized(resized)
         .build()
         .unwrap();
     
@@ -88,269 +152,755 @@ pub fn model(app: &App) -> Model {
     // Create the UI
     let egui = Egui::from_window(&window);
     
-    // Create simulation parameters
-    let params = SimulationParams::default();
-    
-    // Create camera
-    let camera = Camera::new();
-    
-    // Create spatial grid, cell size should be at least as large as the largest perception radius
-    let max_radius = f32::max(
-        params.separation_radius,
-        f32::max(params.alignment_radius, params.cohesion_radius)
-    );
-    
-    // Use cell size factor to determine cell size
-    let cell_size = max_radius * params.cell_size_factor;
-    
-    // Create spatial grid with the world size from params
-    let spatial_grid = SpatialGrid::new(cell_size, params.world_size);
-    
-    // Create boids
-    let mut boids = Vec::with_capacity(params.num_boids);
-    let mut rng = rand::thread_rng();
-    
-    // Create boids with random positions within the world bounds
-    for _ in 0..params.num_boids {
-        let half_world = params.world_size / 2.0;
-        let x = rng.gen_range(-half_world..half_world);
-        let y = rng.gen_range(-half_world..half_world);
-        boids.push(Boid::new(x, y));
-    }
-    
-    // Set max speed for all boids
-    for boid in &mut boids {
-        boid.max_speed = params.max_speed;
-    }
-    
-    // Calculate physics step size based on fixed FPS
-    let physics_step_size = Duration::from_secs_f32(1.0 / params.fixed_physics_fps);
+    // Create the simulation from the saved settings plus any command-line overrides
+    // (main has already validated them, so this only fails if the preset file changed)
+    let sim = overrides.build_simulation_from(config.params.clone()).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    });
+    
+    // Create camera at the saved position and zoom
+    let mut camera = Camera::new();
+    camera.position = config.camera_position;
+    camera.zoom = config.camera_zoom.clamp(camera.min_zoom, camera.max_zoom);
+    
+    // The UI edits its own copy of the parameters; the simulation moves to the physics thread
+    let mut params = sim.params.clone();
+    params.take_snapshot();
+    let view = SimulationView::new(&params);
+    let physics = PhysicsThread::spawn(sim);
+    
+    // The watched file is applied on the first frame and again each time it changes
+    let param_watcher = overrides.watch_params.clone().and_then(|path| {
+        ParamWatcher::spawn(path, watch::POLL_INTERVAL)
+            .map_err(|err| eprintln!("warning: failed to watch parameter file: {}", err))
+            .ok()
+    });
     
     // Create the model
-    let mut model = Model {
-        boids,
+    Model {
         params,
+        modulation: Modulator::default(),
+        timeline: Timeline::default(),
+        physics,
+        view,
+        effects: Effects::default(),
         egui,
-        debug_info: UnsafeCell::new(DebugInfo::default()),
+        ui_visible: true,
+        ui_window: ui::WindowState::default(),
+        clamp_flash: None,
+        debug_info: Mutex::new(DebugInfo::default()),
         camera,
         mouse_position: Vec2::ZERO,
-        spatial_grid,
-        cached_visible_boids: UnsafeCell::new(None),
-        render_needed: UnsafeCell::new(true),
-        _last_camera_state: None,
-        physics_accumulator: Duration::from_secs(0),
-        physics_step_size,
-        last_update_time: Instant::now(),
-        interpolation_alpha: 0.0,
+        cached_visible_boids: Mutex::new(None),
+        render_needed: AtomicBool::new(true),
+        last_camera_state: None,
         _last_render_time: Instant::now(),
-        visible_area_cache: None,
+        visible_area_cache: Mutex::new(None),
+        instanced_renderer: Mutex::new(None),
+        render_budget: Mutex::new(RenderBudget::default()),
+        selected_boid_id: None,
         selected_boid_index: None,
-        last_cell_size_update: Instant::now(),
-        cell_size_update_interval: Duration::from_secs(1), // Update cell size every second
+        selected_boid_debug: None,
+        dragged_boid: None,
+        hovered_boid_index: None,
+        walls: Vec::new(),
+        wall_start: None,
+        regions: Vec::new(),
+        selected_region: None,
+        dragged_region: None,
+        is_emitting: false,
+        last_click_time: None,
+        last_click_position: Vec2::ZERO,
+        export_error: None,
+        export_progress: None,
+        config_path,
+        status_message: None,
+        toast: None,
+        param_watcher,
+        breeds_path: overrides.breeds.clone().unwrap_or_else(|| PathBuf::from(breeds::BREEDS_FILE_NAME)),
+        window_size: (window_width, window_height),
+        ui_layout: config.ui_layout,
+    }
+}
+
+// Save the settings and stop the physics thread when the window closes
+pub fn exit(_app: &App, mut model: Model) {
+    if let Err(err) = save_config(&model) {
+        eprintln!("warning: {}", err);
+    }
+    
+    model.physics.shutdown();
+}
+
+// Write the current parameters, camera and window size to the config file.
+// Returns the path that was written.
+pub fn save_config(model: &Model) -> Result<PathBuf, String> {
+    let path = model.config_path.clone()
+        .ok_or_else(|| "No config location found (use --config <PATH>)".to_string())?;
+    
+    let config = Config {
+        params: model.params.clone(),
+        camera_position: model.camera.position,
+        camera_zoom: model.camera.zoom,
+        window_size: Some(model.window_size),
+        ui_layout: model.ui_layout,
     };
+    config.save(&path)
+        .map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
     
-    // Take initial snapshot of parameters
-    model.params.take_snapshot();
+    Ok(path)
+}
+
+// Write the current parameters to a new timestamped file in the preset directory.
+// Returns the path that was written.
+pub fn save_preset(model: &Model) -> Result<PathBuf, String> {
+    let path = Path::new(config::PRESET_DIR).join(export::timestamped_path("toml"));
+    fs::create_dir_all(config::PRESET_DIR)
+        .and_then(|()| fs::write(&path, model.params.to_preset()))
+        .map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+    
+    Ok(path)
+}
+
+// Apply a preset file on top of the current parameters. An invalid preset changes nothing.
+fn load_preset(model: &mut Model, path: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let mut params = model.params.clone();
+    params.apply_preset(&text)
+        .map_err(|err| format!("Invalid preset {}: {}", path.display(), err))?;
+    model.params = params;
+    
+    Ok(())
+}
+
+// Apply the watched parameter file again if it changed since the last frame
+fn reload_watched_params(model: &mut Model) {
+    let path = match &model.param_watcher {
+        Some(watcher) if watcher.changed() => watcher.path().to_path_buf(),
+        _ => return,
+    };
     
-    model
+    let (text, is_error) = match load_preset(model, &path) {
+        Ok(()) => (format!("Reloaded {}", path.display()), false),
+        Err(err) => (err, true),
+    };
+    model.status_message = Some(text.clone());
+    model.toast = Some(ui::Toast::new(text, is_error));
+}
+
+// Window resized: everything cached for the old window size is stale
+fn resized(_app: &App, model: &mut Model, size: Vec2) {
+    let (old_width, old_height) = model.window_size;
+    if model.params.preserve_view_on_resize {
+        model.camera.keep_view_on_resize(vec2(old_width as f32, old_height as f32), size);
+    }
+    model.window_size = (size.x as u32, size.y as u32);
+    
+    *model.visible_area_cache.get_mut().unwrap() = None;
+    model.invalidate_render();
+    model.ui_window.fit_to(size);
+}
+
+// Re-read the breeds file, replacing the species. An invalid file changes nothing.
+fn reload_breeds(model: &mut Model) {
+    let (text, is_error) = match breeds::load(&model.breeds_path) {
+        Ok(breeds) => {
+            let count = breeds.len();
+            model.params.set_breeds(breeds);
+            (format!("Loaded {} breeds from {}", count, model.breeds_path.display()), false)
+        }
+        Err(err) => (err, true),
+    };
+    model.status_message = Some(text.clone());
+    model.toast = Some(ui::Toast::new(text, is_error));
 }
 
 // Update the model
 pub fn update(app: &App, model: &mut Model, update: Update) {
+    // Remember the window size for the config file
+    let window_rect = app.window_rect();
+    model.window_size = (window_rect.w() as u32, window_rect.h() as u32);
+    
     // Update the UI
     let ui_response = ui::update_ui(app, model, &update);
     
-    // Detect parameter changes
-    let (boids_changed, physics_changed, rendering_changed, world_size_changed) = model.params.detect_changes();
+    // Reset parameters before change detection so the new values are picked up
+    if ui_response.reset_params {
+        model.params.reset_to_defaults();
+        
+        // Clear the cached visible boids and force re-render
+        model.invalidate_render();
+    }
     
-    // Handle parameter changes
-    if boids_changed {
-        // Reset boids if the number has changed
-        physics::reset_boids(model);
+    // Presets are also applied before change detection
+    if let Some(path) = &ui_response.load_preset {
+        model.status_message = Some(match load_preset(model, path) {
+            Ok(()) => format!("Loaded {}", path.display()),
+            Err(err) => err,
+        });
     }
     
-    if physics_changed || world_size_changed {
-        // Update max speed for all boids if it changed
-        for boid in &mut model.boids {
-            boid.max_speed = model.params.max_speed;
-        }
-        
-        // Update physics step size if FPS changed
-        model.physics_step_size = Duration::from_secs_f32(1.0 / model.params.fixed_physics_fps);
+    // So is the watched parameter file, so a new boid count or world size resets the flock
+    reload_watched_params(model);
+    
+    // New breeds load into the sliders, which are copied back into the species below
+    if ui_response.reload_breeds {
+        reload_breeds(model);
     }
     
-    // If world size changed, we need to recreate the spatial grid
+    // Keyframed values move the sliders, so they are changed like any other slider edit
+    model.timeline.update(model.view.stats.simulation_time, &mut model.params);
+    
+    // The sliders edit the active species, so copy them into it before change detection
+    model.params.sync_active_species();
+    
+    // Send the parameters to the physics thread before any command that depends on them.
+    // Modulation only changes the copy that is sent, so the sliders keep their base values
+    // and the physics thread sees the swings as ordinary parameter changes.
+    let (boids_changed, _physics_changed, rendering_changed, world_size_changed) = model.params.detect_changes();
     if world_size_changed {
-        // Recalculate cell size
-        let max_radius = f32::max(
-            model.params.separation_radius,
-            f32::max(model.params.alignment_radius, model.params.cohesion_radius)
-        );
-        let cell_size = max_radius * model.params.cell_size_factor;
-        
-        // Create new spatial grid with updated world size
-        model.spatial_grid = SpatialGrid::new(cell_size, model.params.world_size);
-        
-        // Ensure all boids are within the new world bounds
-        let half_world = model.params.world_size / 2.0;
-        for boid in &mut model.boids {
-            // Wrap boids that are outside the new world bounds
-            if boid.position.x > half_world || boid.position.x < -half_world ||
-               boid.position.y > half_world || boid.position.y < -half_world {
-                boid.wrap_edges(model.params.world_size);
-            }
-        }
+        // Scale the radii to the new world (if enabled) before they are sent
+        model.params.scale_with_world_size();
     }
-    
-    // Take a snapshot of the current parameters for change detection in the next frame
     model.params.take_snapshot();
-    
-    // Skip physics updates if paused
     if !model.params.pause_simulation {
-        // Calculate time since last update
-        let now = Instant::now();
-        let dt = now.duration_since(model.last_update_time);
-        model.last_update_time = now;
+        model.modulation.advance(update.since_last.as_secs_f32());
+    }
+    let mut physics_params = model.params.clone();
+    model.modulation.apply(&mut physics_params);
+    model.physics.send(PhysicsCommand::SetParams {
+        params: Box::new(physics_params),
+        resize: boids_changed,
+    });
+    
+    // Spread boids across the species if species were added or removed or the mixture changed
+    if ui_response.species_changed || ui_response.reset_params || ui_response.reload_breeds {
+        model.physics.send(PhysicsCommand::RedistributeSpecies);
+    }
+    
+    // Deselect, delete or freeze the selected boid if requested from the inspector
+    if ui_response.deselect_boid {
+        select_boid(model, None);
+    }
+    if let Some(index) = ui_response.delete_boid {
+        delete_boid(model, index);
+    }
+    if let Some(index) = ui_response.toggle_frozen {
+        toggle_frozen(model, index);
+    }
+    
+    // Return every boid to the base species
+    if ui_response.cure_infection {
+        model.physics.send(PhysicsCommand::CureInfection);
+    }
+    
+    if ui_response.clear_walls {
+        clear_walls(model);
+    }
+    
+    if ui_response.add_region {
+        add_region(model);
+    }
+    if let Some(index) = ui_response.delete_region {
+        delete_region(model, index);
+    }
+    if ui_response.regions_changed {
+        send_regions(model);
+    }
+    
+    if let Some(tag) = ui_response.tag_boids {
+        tag_boids(model, tag, window_rect);
+    }
+    if ui_response.clear_tags {
+        model.physics.send(PhysicsCommand::ClearTags);
+    }
+    
+    // Leaving wall mode abandons a half-drawn wall
+    if !model.params.wall_mode {
+        model.wall_start = None;
+    }
+    
+    // Re-randomize all boids if requested
+    if ui_response.reset_boids {
+        reset_boids(model);
+    }
+    
+    // Advance a paused simulation by one physics step
+    if ui_response.step {
+        model.physics.send(PhysicsCommand::Step);
+    }
+    
+    // Export boid state if requested
+    if ui_response.export_frame {
+        export_frame(model);
+    }
+    if ui_response.toggle_continuous_export {
+        toggle_continuous_export(model);
+    }
+    
+    // Make the current settings the defaults for the next run
+    if ui_response.save_config {
+        model.status_message = Some(match save_config(model) {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(err) => err,
+        });
+    }
+    if ui_response.save_preset {
+        model.status_message = Some(match save_preset(model) {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(err) => err,
+        });
+    }
+    
+    // The window is captured when the next frame is drawn
+    if ui_response.screenshot {
+        let path = export::timestamped_path("png");
+        app.main_window().capture_frame(&path);
+        model.invalidate_render();
+        model.status_message = Some(format!("Screenshot saved to {}", path.display()));
+    }
+    
+    if world_size_changed {
+        // Everything may have moved
+        model.invalidate_render();
+    }
+    
+    // Frame all boids if requested from the UI
+    if ui_response.fit_view {
+        fit_view_to_boids(model, app.window_rect());
+    }
+    
+    // Pan the camera with held keyboard keys
+    input::update_keyboard_pan(app, model, update.since_last.as_secs_f32());
+    
+    // Advance the smooth camera pan (e.g. from a double-click)
+    model.camera.update_pan_animation(update.since_last.as_secs_f32());
+    
+    let cursor_world = model.camera.screen_to_world(model.mouse_position, app.window_rect());
+    
+    // Emit boids at the cursor while the right button is held
+    model.physics.send(PhysicsCommand::SetEmitter(model.is_emitting.then_some(cursor_world)));
+    
+    // Pick up the latest snapshot from the physics thread
+    let target_boids = model.view.stats.target_boids;
+    model.effects.advance(update.since_last.as_secs_f32());
+    if model.view.sync(&model.physics, &model.params, &mut model.effects) {
+        if model.view.stats.target_boids != target_boids {
+            // Emission, spawning or deletion moved the target; show it on the slider
+            // (unless the user is changing it, in which case the new target is still pending)
+            if !boids_changed {
+                model.params.num_boids = model.view.stats.target_boids;
+                model.params.take_snapshot();
+            }
+        }
         
-        // Add to accumulator
-        model.physics_accumulator += dt;
+        // Cached indices and positions are stale
+        model.invalidate_render();
         
-        // Store previous state for interpolation
-        for boid in &mut model.boids {
-            boid.store_previous_state();
+        let stats = &model.view.stats;
+        if model.selected_boid_index.is_some() {
+            if let Some(forces) = &stats.tracked_boid_forces {
+                model.selected_boid_debug = Some(*forces);
+            }
         }
         
-        // Perform fixed timestep updates
-        while model.physics_accumulator >= model.physics_step_size {
-            // Update boids
-            physics::update_boids(model);
-            
-            // Subtract step size from accumulator
-            model.physics_accumulator -= model.physics_step_size;
+        let debug_info = model.debug_info.get_mut().unwrap();
+        if stats.steps > 0 {
+            debug_info.physics_updates_per_frame = Some(stats.steps);
+            if stats.step_duration.is_some() {
+                debug_info.physics_step_duration = stats.step_duration;
+            }
+            debug_info.physics_step_budget = Some(stats.step_size);
+            debug_info.physics_steps_capped = stats.steps_capped;
+            debug_info.chunk_size = stats.chunk_size;
+            debug_info.physics_threads = Some(stats.threads);
+            if model.params.show_debug {
+                let spatial_sorting = model.params.enable_spatial_sorting && model.params.enable_spatial_grid;
+                debug_info.phase_breakdown.record_physics(&stats.timings, spatial_sorting);
+            }
+        }
+        if !stats.species_counts.is_empty() {
+            debug_info.species_history.push(&stats.species_counts);
         }
         
-        // Calculate interpolation alpha
-        if model.params.enable_interpolation {
-            model.interpolation_alpha = model.physics_accumulator.as_secs_f32() / model.physics_step_size.as_secs_f32();
-        } else {
-            model.interpolation_alpha = 0.0;
+        if let Some(metrics) = stats.metrics {
+            debug_info.metrics_history.push(metrics);
         }
+        if let Some(speeds) = stats.speeds {
+            debug_info.speed_history.push(stats.simulation_time, speeds);
+        }
+        
+        // Every boid in the snapshot is alive; dead ones are removed before publishing
+        debug_info.average_energy = stats.average_energy;
+        debug_info.alive_boids = stats.average_energy.map(|_| model.view.boids.len());
+        debug_info.respawning_boids = stats.respawning_boids;
+        debug_info.neighbor_histogram = stats.neighbor_histogram.clone();
+        debug_info.max_steps_per_frame = Some(model.params.max_steps_per_frame);
+        debug_info.dropped_time = stats.dropped_time;
         
-        // Update camera position to follow selected boid if in follow mode
-        if model.camera.follow_mode && model.selected_boid_index.is_some() {
-            let boid_idx = model.selected_boid_index.unwrap();
-            if boid_idx < model.boids.len() {
+        // Spatial grid statistics from the last physics step
+        if let Some((occupied_cells, total_cells, occupancy_percentage, max_cell_population)) = stats.grid_statistics {
+            debug_info.update_grid_stats(
+                occupied_cells, 
+                total_cells, 
+                occupancy_percentage, 
+                max_cell_population
+            );
+        }
+    }
+    
+    // Simulated against wall time for the status strip (every frame, so pauses are seen)
+    let stats = &model.view.stats;
+    model.debug_info.get_mut().unwrap().simulation_clock.update(Instant::now(), stats.simulation_step, stats.simulation_time, model.params.pause_simulation);
+    
+    // Find the selected, dragged and followed boids in the latest snapshot
+    resolve_boid_ids(model);
+    
+    // Record per-rule forces for the selected boid
+    model.physics.send(PhysicsCommand::TrackBoid(model.selected_boid_index));
+    
+    // Keep a dragged boid pinned under the cursor (physics may have moved it)
+    if let Some(index) = model.dragged_boid.and_then(|id| model.view.index_of(id)) {
+        move_boid_to(model, index, cursor_world);
+    }
+    
+    // Blend between the last two snapshots based on the time since the latest one
+    model.view.update_interpolation(Instant::now(), model.params.enable_interpolation, model.params.pause_simulation);
+    
+    // A rendering change (such as switching interpolation off) moves the drawn boids now,
+    // not at the next snapshot, so the visible set cached while paused is stale
+    if rendering_changed {
+        model.invalidate_render();
+    }
+    
+    // Fades keep running while paused (e.g. a boid deleted from the inspector)
+    if model.effects.is_active() {
+        model.invalidate_render();
+    }
+    
+    // Find the boid under the cursor at the positions about to be drawn
+    input::update_hovered_boid(app, model);
+    
+    if !model.params.pause_simulation {
+        // Work out where the camera should be based on what it's following
+        let (params, alpha) = (&model.params, model.view.interpolation_alpha);
+        let follow_position = match model.camera.follow_target {
+            FollowTarget::None => None,
+            FollowTarget::Boid(id) => {
                 // Get the interpolated position of the boid for smooth camera movement
-                let boid_pos = if model.params.enable_interpolation {
-                    model.boids[boid_idx].get_interpolated_position(model.interpolation_alpha)
-                } else {
-                    model.boids[boid_idx].position
-                };
-                
-                // Update camera position to match the boid's position
-                model.camera.position = Vec2::new(boid_pos.x, boid_pos.y);
-                
-                // Force re-render when following a boid
-                unsafe { *model.render_needed.get() = true; }
-                
-                // Clear the cached visible boids when camera moves
-                unsafe { *model.cached_visible_boids.get() = None; }
-                
-                // Clear the visible area cache
-                model.visible_area_cache = None;
+                model.view.index_of(id).map(|index| boid_render_position(&model.view.boids[index], params, alpha))
             }
-        }
+            FollowTarget::CenterOfMass => {
+                // Track the wrap-aware centroid of the whole flock
+                physics::wrapped_centroid(
+                    model.view.boids.iter().map(|boid| boid_render_position(boid, params, alpha)),
+                    params.world_size
+                )
+            }
+        };
+        
+        // Lead a followed boid along its heading, so more of what is ahead is visible
+        let look_ahead = match model.camera.follow_target {
+            FollowTarget::Boid(id) if model.params.follow_look_ahead => model.view.index_of(id).map(|index| {
+                let velocity = boid_render_velocity(&model.view.boids[index], params, alpha);
+                velocity.normalize_or_zero() * FOLLOW_LOOK_AHEAD * window_rect.w().min(window_rect.h()) / model.camera.zoom
+            }),
+            _ => None,
+        };
         
-        // Update adaptive cell size if enabled
-        if model.params.adaptive_cell_sizing && 
-           now.duration_since(model.last_cell_size_update) >= model.cell_size_update_interval {
-            update_adaptive_cell_size(model);
-            model.last_cell_size_update = now;
+        // Move the camera once the target leaves the deadzone (the zoom is left alone)
+        if let Some(target_pos) = follow_position {
+            model.camera.follow(target_pos + look_ahead.unwrap_or(Vec2::ZERO), window_rect, model.params.follow_deadzone);
         }
     }
     
+    // Any camera movement or window resize since the last frame (input, following, the pan
+    // animation) changes what is visible
+    let camera_state = model.camera.state_hash(window_rect);
+    if model.last_camera_state != Some(camera_state) {
+        model.last_camera_state = Some(camera_state);
+        model.invalidate_render();
+    }
+    
     // Update debug info
     if model.params.show_debug {
-        model.debug_info.get_mut().update_from_app(app);
+        let debug_info = model.debug_info.get_mut().unwrap();
+        debug_info.update_from_app(app);
         
-        // Get the cached visible boids
-        let cached_visible_boids = unsafe { &*model.cached_visible_boids.get() };
+        // Get the cached visible boids and culling area
+        let cached_visible_boids = model.cached_visible_boids.get_mut().unwrap();
+        let visible_area = model.visible_area_cache.get_mut().unwrap().map(|cache| cache.area);
         
-        model.debug_info.get_mut().update_from_model(
-            model.selected_boid_index,
-            model.camera.follow_mode,
-            model.interpolation_alpha,
+        debug_info.update_from_model(
+            model.selected_boid_id,
+            model.camera.follow_target,
+            model.view.interpolation_alpha
+        );
+        debug_info.update_culling_stats(
             cached_visible_boids,
-            model.boids.len(),
-            model.visible_area_cache,
+            model.view.boids.len(),
+            visible_area,
             model.params.world_size
         );
     }
     
     // Mark that a render is needed
-    unsafe {
-        *model.render_needed.get() = true;
-    }
+    model.render_needed.store(true, Ordering::Relaxed);
 }
 
-// Update the spatial grid cell size based on boid density
-fn update_adaptive_cell_size(model: &mut Model) {
-    // Calculate the maximum perception radius
-    let max_radius = f32::max(
-        model.params.separation_radius,
-        f32::max(model.params.alignment_radius, model.params.cohesion_radius)
-    );
-    
-    // Calculate average number of neighbors per boid
-    let mut total_neighbors = 0;
-    let sample_size = model.boids.len().min(100); // Sample at most 100 boids for efficiency
+// Fraction of the smaller window dimension the camera leads a followed boid by
+const FOLLOW_LOOK_AHEAD: f32 = 0.25;
+
+// Reset boids to random positions and velocities
+pub fn reset_boids(model: &mut Model) {
+    // The selection is checked against the new count when the snapshot arrives
+    model.physics.send(PhysicsCommand::Reset);
     
-    if sample_size == 0 {
-        return; // No boids to sample
+    // Clear the cached visible boids and force re-render
+    model.invalidate_render();
+}
+
+// Add a burst of boids scattered around a world position
+pub fn spawn_boids_at(model: &mut Model, center: Point2, count: usize) {
+    model.physics.send(PhysicsCommand::Spawn { center, count });
+    model.invalidate_render();
+}
+
+// Remove a boid (the selection is fixed up when the physics thread reports the deletion)
+pub fn delete_boid(model: &mut Model, index: usize) {
+    if index < model.view.boids.len() {
+        model.physics.send(PhysicsCommand::Delete(index));
+    }
+}
+
+// Select the boid at an index of the view (None deselects). The selection is kept by
+// id, so it stays on the same boid when others are removed.
+pub fn select_boid(model: &mut Model, index: Option<usize>) {
+    let id = index.and_then(|index| model.view.boids.get(index)).map(|boid| boid.id);
+    if id != model.selected_boid_id {
+        model.selected_boid_debug = None;
+        // The selection highlight and perception circles move
+        model.invalidate_render();
     }
     
-    let step = model.boids.len() / sample_size;
-    
-    // Extract positions for the spatial grid's calculations
-    let boid_positions: Vec<Point2> = model.boids.iter().map(|boid| boid.position).collect();
-    
-    for i in (0..model.boids.len()).step_by(step.max(1)) {
-        if i >= model.boids.len() {
-            break;
+    model.selected_boid_id = id;
+    model.selected_boid_index = index.filter(|_| id.is_some());
+}
+
+// Toggle whether a boid is frozen in place
+pub fn toggle_frozen(model: &mut Model, index: usize) {
+    model.physics.send(PhysicsCommand::ToggleFrozen(index));
+    model.invalidate_render();
+}
+
+// Convert a boid to the infected species, starting the infection there
+pub fn infect_boid(model: &mut Model, index: usize) {
+    model.physics.send(PhysicsCommand::Infect(index));
+    model.invalidate_render();
+}
+
+// Place a wall endpoint: the first click starts a wall, the second finishes it
+pub fn place_wall_point(model: &mut Model, position: Point2) {
+    match model.wall_start.take() {
+        Some(start) if start.distance(position) > 1.0 => {
+            model.walls.push(Wall::new(start, position));
+            send_walls(model);
         }
-        
-        let nearby = model.spatial_grid.get_nearby_with_distances(
-            model.boids[i].position,
-            &boid_positions,
-            model.params.world_size
-        );
-        
-        total_neighbors += nearby.len();
+        // A second click on the same spot doesn't make a wall
+        Some(_) => {}
+        None => model.wall_start = Some(position),
+    }
+    model.invalidate_render();
+}
+
+// The wall closest to a world position, if one is within max_distance
+pub fn wall_at(model: &Model, position: Point2, max_distance: f32) -> Option<usize> {
+    model.walls.iter()
+        .map(|wall| wall.distance_squared(position))
+        .enumerate()
+        .filter(|&(_, distance_squared)| distance_squared <= max_distance * max_distance)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+}
+
+pub fn delete_wall(model: &mut Model, index: usize) {
+    if index < model.walls.len() {
+        model.walls.remove(index);
+        send_walls(model);
     }
+}
+
+pub fn clear_walls(model: &mut Model) {
+    model.walls.clear();
+    model.wall_start = None;
+    send_walls(model);
+}
+
+// Give the physics thread the current walls
+fn send_walls(model: &mut Model) {
+    model.physics.send(PhysicsCommand::SetWalls(model.walls.clone()));
+    model.invalidate_render();
+}
+
+// Size of a new region relative to the visible part of the world
+const NEW_REGION_FRACTION: f32 = 0.3;
+
+// Add a region without overrides in the middle of the view, and select it
+pub fn add_region(model: &mut Model) {
+    let (width, height) = model.window_size;
+    let half_size = vec2(width as f32, height as f32) * NEW_REGION_FRACTION / model.camera.zoom / 2.0;
+    let center = model.camera.position;
+    let rect = Rect::from_corners(center - half_size, center + half_size);
+    
+    model.regions.push(ParamRegion::new(rect, model.regions.len()));
+    model.selected_region = Some(model.regions.len() - 1);
+    send_regions(model);
+}
+
+pub fn delete_region(model: &mut Model, index: usize) {
+    if index < model.regions.len() {
+        model.regions.remove(index);
+        model.selected_region = None;
+        model.dragged_region = None;
+        send_regions(model);
+    }
+}
+
+// Tag the boids in the selected region, or every boid on screen without a selection
+fn tag_boids(model: &Model, tag: u8, window_rect: Rect) {
+    let area = match model.selected_region.and_then(|index| model.regions.get(index)) {
+        Some(region) => region.rect,
+        None => model.camera.visible_world_rect(window_rect),
+    };
+    model.physics.send(PhysicsCommand::TagBoids { area, tag });
+}
+
+// The region corner closest to a world position, if one is within max_distance.
+// Returns the region's index and the corner opposite the picked one.
+pub fn region_corner_at(model: &Model, position: Point2, max_distance: f32) -> Option<(usize, Point2)> {
+    model.regions.iter()
+        .enumerate()
+        .flat_map(|(index, region)| {
+            let corners = region.corners();
+            (0..4).map(move |corner| (index, corners[corner], corners[(corner + 2) % 4]))
+        })
+        .map(|(index, corner, opposite)| (index, opposite, corner.distance_squared(position)))
+        .filter(|&(_, _, distance_squared)| distance_squared <= max_distance * max_distance)
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(index, opposite, _)| (index, opposite))
+}
+
+// Move the dragged region corner to a world position
+pub fn drag_region_corner(model: &mut Model, position: Point2) {
+    if let Some((index, opposite)) = model.dragged_region {
+        if let Some(region) = model.regions.get_mut(index) {
+            region.rect = Rect::from_corners(opposite, position);
+            send_regions(model);
+        }
+    }
+}
+
+// Give the physics thread the current regions
+fn send_regions(model: &mut Model) {
+    model.physics.send(PhysicsCommand::SetRegions(model.regions.clone()));
+    model.invalidate_render();
+}
+
+// Move a boid to a world position (e.g. while dragging it)
+pub fn move_boid_to(model: &mut Model, index: usize, position: Point2) {
+    model.physics.send(PhysicsCommand::MoveBoid { index, position });
+    model.invalidate_render();
+}
+
+// Write the current state of every boid to a new timestamped file
+pub fn export_frame(model: &mut Model) {
+    let format = model.params.export_format;
+    let path = export::default_export_path(format);
+    start_export(model, Exporter::start(&path, format, false, 1), &path);
+}
+
+// Start a continuous export, or stop the one that is running
+pub fn toggle_continuous_export(model: &mut Model) {
+    let running = model.export_progress.as_ref()
+        .is_some_and(|progress| progress.is_continuous() && progress.is_active());
     
-    let avg_neighbors = total_neighbors as f32 / sample_size as f32;
+    if running {
+        model.physics.send(PhysicsCommand::StopExport);
+    } else {
+        let format = model.params.export_format;
+        let stride = model.params.export_stride as u64;
+        let path = export::default_export_path(format);
+        start_export(model, Exporter::start(&path, format, true, stride), &path);
+    }
+}
+
+// Hand a newly created exporter to the physics thread, or report why it couldn't be created
+fn start_export(model: &mut Model, exporter: std::io::Result<Exporter>, path: &std::path::Path) {
+    match exporter {
+        Ok(exporter) => {
+            model.export_progress = Some(exporter.progress());
+            model.export_error = None;
+            model.physics.send(PhysicsCommand::Export(exporter));
+        }
+        Err(err) => {
+            model.export_error = Some(format!("Failed to create {}: {}", path.display(), err));
+        }
+    }
+}
+
+// Look up the selected boid's index by its id, and deselect (or stop following or
+// dragging) boids that no longer exist: deleted, starved or cut by a smaller boid count
+fn resolve_boid_ids(model: &mut Model) {
+    let view = &model.view;
+    
+    model.selected_boid_index = model.selected_boid_id.and_then(|id| view.index_of(id));
+    if model.selected_boid_index.is_none() {
+        model.selected_boid_id = None;
+        model.selected_boid_debug = None;
+    }
     
-    // Adjust cell size based on average neighbors
-    // Target: around 10-20 neighbors per cell for optimal performance
-    let target_neighbors = 15.0;
-    let current_cell_size = model.spatial_grid.cell_size;
+    model.dragged_boid = model.dragged_boid.filter(|&id| view.index_of(id).is_some());
     
-    let mut new_cell_size = if avg_neighbors > target_neighbors * 1.5 {
-        // Too many neighbors, decrease cell size
-        current_cell_size * 0.9
-    } else if avg_neighbors < target_neighbors * 0.5 {
-        // Too few neighbors, increase cell size
-        current_cell_size * 1.1
+    if let FollowTarget::Boid(id) = model.camera.follow_target {
+        if view.index_of(id).is_none() {
+            model.camera.follow_target = FollowTarget::None;
+        }
+    }
+}
+
+// Move and zoom the camera so that all boids are visible
+pub fn fit_view_to_boids(model: &mut Model, window_rect: Rect) {
+    let world_size = model.params.world_size;
+    let world_bounds = Rect::from_w_h(world_size, world_size);
+    
+    // Compute the bounding box of all boid positions
+    let bounds = if let Some(first) = model.view.boids.first() {
+        let mut min = Vec2::new(first.position.x, first.position.y);
+        let mut max = min;
+        for boid in &model.view.boids {
+            min = min.min(Vec2::new(boid.position.x, boid.position.y));
+            max = max.max(Vec2::new(boid.position.x, boid.position.y));
+        }
+        
+        // On a wrapped world a flock straddling an edge looks like it spans the whole world,
+        // so fall back to the full world bounds when it covers more than half of an axis
+        let half_world = world_size / 2.0;
+        if max.x - min.x > half_world || max.y - min.y > half_world {
+            world_bounds
+        } else {
+            Rect::from_corners(min, max)
+        }
     } else {
-        // Good range, keep current size
-        current_cell_size
+        world_bounds
     };
     
-    // Ensure cell size is at least the maximum perception radius
-    new_cell_size = f32::max(new_cell_size, max_radius * model.params.cell_size_factor);
+    model.camera.fit_to_bounds(bounds, window_rect);
     
-    // Only recreate grid if cell size changed significantly
-    if (new_cell_size - current_cell_size).abs() > current_cell_size * 0.1 {
-        model.spatial_grid = SpatialGrid::new(new_cell_size, model.params.world_size);
-    }
-} 
\ No newline at end of file
+    // Fitting the view moves the camera, so stop following
+    model.camera.follow_target = FollowTarget::None;
+    
+    // Clear the cached visible boids and force re-render
+    model.invalidate_render();
+}
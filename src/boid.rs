@@ -2,7 +2,8 @@
  * Boid Module
  * 
  * This module defines the Boid struct and its behavior.
- * Each boid follows three main rules:
+ * Each boid follows three main rules (computed for the whole flock by
+ * physics::compute_flocking_force):
  * 1. Separation: Avoid crowding neighbors
  * 2. Alignment: Steer towards the average heading of neighbors
  * 3. Cohesion: Steer towards the average position of neighbors
@@ -19,9 +20,15 @@
 
 use nannou::prelude::*;
 use crate::camera::Camera;
-use crate::BOID_SIZE;
+use crate::effects;
+use crate::palette;
+use crate::flock::BoidId;
+use crate::params::{SeparationFalloff, SimulationParams, DEFAULT_MAX_FORCE};
 use rand::Rng;
 
+// Energy of a fully fed boid (energy mode). A boid whose energy reaches zero dies.
+pub const MAX_ENERGY: f32 = 1.0;
+
 #[derive(Clone)]
 pub struct Boid {
     pub position: Point2,      // Current physics position
@@ -32,13 +39,21 @@ pub struct Boid {
     pub max_speed: f32,
     pub max_force: f32,
     pub color: Rgb<u8>,
-    pub is_visible: bool,      // Visibility flag for culling optimization
+    pub species: u8,           // Index into SimulationParams::species
+    pub frozen: bool,          // Frozen boids skip their own update but still influence neighbors
+    pub perched_until: Option<u64>, // Perching: the physics step at which a landed boid takes off
+    pub energy: f32,           // Energy mode: drains over time, 0 means dead
+    pub tag: Option<u8>,       // Experiment group (see the tags module)
+    pub id: BoidId,            // Assigned by Flock::push, never reused within a simulation
 }
 
 impl Boid {
     pub fn new(x: f32, y: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        
+        Self::new_with_rng(x, y, &mut rand::thread_rng())
+    }
+    
+    // Create a boid whose random initial velocity comes from the given RNG (for seeded runs)
+    pub fn new_with_rng<R: Rng>(x: f32, y: f32, rng: &mut R) -> Self {
         // Random initial velocity
         let vx = rng.gen_range(-1.0..1.0);
         let vy = rng.gen_range(-1.0..1.0);
@@ -60,9 +75,14 @@ impl Boid {
             prev_position: position, // Initialize previous to current
             prev_velocity: velocity, // Initialize previous to current
             max_speed: 4.0,
-            max_force: 0.1,
+            max_force: DEFAULT_MAX_FORCE,
             color: rgb(220, 220, 220),
-            is_visible: false, // Initially not visible
+            species: 0,
+            frozen: false,
+            perched_until: None,
+            energy: MAX_ENERGY,
+            tag: None,
+            id: 0,
         }
     }
     
@@ -71,7 +91,9 @@ impl Boid {
         self.acceleration += force;
     }
     
-    // Store current state as previous state before updating
+    // Store current state as previous state before updating. A wrap in the following
+    // update moves the previous position along (see wrap_edges), so the pair always
+    // describes one continuous step.
     pub fn store_previous_state(&mut self) {
         self.prev_position = self.position;
         self.prev_velocity = self.velocity;
@@ -79,31 +101,13 @@ impl Boid {
     
     // Update the boid's position based on its velocity and acceleration
     pub fn update(&mut self) {
-        // Update velocity
-        self.velocity += self.acceleration;
-        
-        // Limit speed (only normalize if exceeding max_speed)
-        let speed_squared = self.velocity.length_squared();
-        let max_speed_squared = self.max_speed * self.max_speed;
-        
-        if speed_squared > max_speed_squared {
-            let speed = speed_squared.sqrt();
-            self.velocity *= self.max_speed / speed;
-        }
-        
-        // Update position
-        self.position += self.velocity;
-        
-        // Reset acceleration
-        self.acceleration = Vec2::ZERO;
+        integrate(&mut self.position, &mut self.velocity, &mut self.acceleration, self.max_speed);
     }
     
-    // Get interpolated position between previous and current state
-    pub fn get_interpolated_position(&self, alpha: f32) -> Point2 {
-        pt2(
-            self.prev_position.x + (self.position.x - self.prev_position.x) * alpha,
-            self.prev_position.y + (self.position.y - self.prev_position.y) * alpha
-        )
+    // Get interpolated position between previous and current state, taking the short way
+    // around when the boid wrapped between the two, and wrapped back into the world
+    pub fn get_interpolated_position(&self, alpha: f32, world_size: f32) -> Point2 {
+        interpolate_wrapped(self.prev_position, self.position, alpha, world_size)
     }
     
     // Get interpolated velocity between previous and current state
@@ -114,246 +118,709 @@ impl Boid {
         )
     }
     
-    // Wrap the boid around the world edges
-    pub fn wrap_edges(&mut self, world_size: f32) {
-        let half_size = world_size / 2.0;
-        
-        if self.position.x > half_size {
-            self.position.x = -half_size;
-            self.prev_position.x = -half_size; // Update previous position too to avoid interpolation issues
-        } else if self.position.x < -half_size {
-            self.position.x = half_size;
-            self.prev_position.x = half_size; // Update previous position too to avoid interpolation issues
+    // The angle the boid is drawn at: along its velocity, or lying flat while perched. With
+    // interpolation the angle turns from the previous velocity's to the current one's the
+    // short way round, so turning boids rotate smoothly between physics steps (interpolating
+    // the velocity vector instead would swing through zero on sharp turns).
+    pub fn heading(&self, params: &SimulationParams, alpha: f32) -> f32 {
+        if self.perched_until.is_some() {
+            return 0.0;
         }
         
-        if self.position.y > half_size {
-            self.position.y = -half_size;
-            self.prev_position.y = -half_size; // Update previous position too to avoid interpolation issues
-        } else if self.position.y < -half_size {
-            self.position.y = half_size;
-            self.prev_position.y = half_size; // Update previous position too to avoid interpolation issues
+        if !params.enable_interpolation {
+            return velocity_angle(self.velocity, self.prev_velocity);
         }
+        
+        let previous = velocity_angle(self.prev_velocity, self.velocity);
+        let current = velocity_angle(self.velocity, self.prev_velocity);
+        lerp_angle(previous, current, alpha)
     }
     
-    // Original versions of the flocking behaviors (without spatial grid)
-    pub fn separation_original(&self, boids: &[Boid], perception_radius: f32, _use_squared_distance: bool) -> Vec2 {
-        let mut steering = Vec2::ZERO;
-        let mut count = 0;
-        
-        // Pre-calculate squared radius for optimization
-        let radius_squared = perception_radius * perception_radius;
-        
-        for other in boids {
-            // Calculate squared distance directly
-            let dx = self.position.x - other.position.x;
-            let dy = self.position.y - other.position.y;
-            let d_squared = dx * dx + dy * dy;
-            
-            // Skip if it's the same boid or outside perception radius
-            if d_squared <= 0.0 || d_squared >= radius_squared {
-                continue;
-            }
-            
-            // Calculate vector pointing away from neighbor
-            // Only calculate actual distance if needed for weighting
-            let d = d_squared.sqrt();
-            
-            // Weight by distance (closer boids have more influence)
-            // Reuse dx and dy instead of creating a new vector
-            steering.x += (dx / d) / d;
-            steering.y += (dy / d) / d;
-            count += 1;
-        }
-        
-        if count > 0 {
-            steering /= count as f32;
-            
-            let steering_length_squared = steering.length_squared();
-            if steering_length_squared > 0.0 {
-                // Implement Reynolds: Steering = Desired - Velocity
-                // Only normalize if needed
-                let steering_length = steering_length_squared.sqrt();
-                let desired = steering * (self.max_speed / steering_length);
-                
-                steering = desired - self.velocity;
-                
-                // Limit force
-                let force_squared = steering.length_squared();
-                let max_force_squared = self.max_force * self.max_force;
-                
-                if force_squared > max_force_squared {
-                    let force_length = force_squared.sqrt();
-                    steering *= self.max_force / force_length;
-                }
-            }
-        }
-        
-        steering
+    // Wrap the boid around the world edges
+    pub fn wrap_edges(&mut self, world_size: f32) {
+        wrap_edges(&mut self.position, &mut self.prev_position, world_size, true);
     }
     
-    // Original versions of the flocking behaviors (without spatial grid)
-    pub fn alignment_original(&self, boids: &[Boid], perception_radius: f32, _use_squared_distance: bool) -> Vec2 {
-        let mut steering = Vec2::ZERO;
-        let mut count = 0;
-        
-        // Pre-calculate squared radius for optimization
-        let radius_squared = perception_radius * perception_radius;
+    // Move the boid back inside the world by wrapping its position modulo the world size.
+    // Unlike wrap_edges this also handles boids that are far outside (e.g. after a large shrink).
+    pub fn wrap_into_world(&mut self, world_size: f32) {
+        wrap_into_world(&mut self.position, &mut self.prev_position, world_size);
+    }
+    
+    // Steer toward where a moving target is going to be (Reynolds pursuit), so a fast
+    // target is cut off instead of being chased from behind
+    pub fn pursue(&self, target_position: Point2, target_velocity: Vec2) -> Vec2 {
+        let predicted = predict_position(self.position, self.max_speed, target_position, target_velocity);
+        arrive(predicted - self.position, self.velocity, self.max_speed, self.max_force, None)
+    }
+    
+    // Steer away from where a moving target is going to be (the opposite of pursue)
+    pub fn evade(&self, target_position: Point2, target_velocity: Vec2) -> Vec2 {
+        let predicted = predict_position(self.position, self.max_speed, target_position, target_velocity);
+        arrive(self.position - predicted, self.velocity, self.max_speed, self.max_force, None)
+    }
+    
+    // The color the boid is drawn in
+    pub fn display_color(&self) -> Rgb<u8> {
+        // Frozen boids are drawn desaturated
+        let mut color = if self.frozen {
+            let gray = (self.color.red as u16 + self.color.green as u16 + self.color.blue as u16) / 3;
+            let desaturate = |c: u8| ((c as u16 + gray * 3) / 4) as u8;
+            rgb(desaturate(self.color.red), desaturate(self.color.green), desaturate(self.color.blue))
+        } else {
+            self.color
+        };
         
-        for other in boids {
-            // Calculate squared distance directly
-            let dx = self.position.x - other.position.x;
-            let dy = self.position.y - other.position.y;
-            let d_squared = dx * dx + dy * dy;
-            
-            // Skip if it's the same boid or outside perception radius
-            if d_squared <= 0.0 || d_squared >= radius_squared {
-                continue;
-            }
-            
-            // Accumulate velocities
-            steering += other.velocity;
-            count += 1;
+        // Starving boids fade out (energy mode)
+        if self.energy < MAX_ENERGY {
+            let brightness = 0.3 + 0.7 * (self.energy / MAX_ENERGY).max(0.0);
+            let dim = |c: u8| (c as f32 * brightness) as u8;
+            color = rgb(dim(color.red), dim(color.green), dim(color.blue));
         }
         
-        if count > 0 {
-            steering /= count as f32;
-            
-            // Only normalize if the steering vector has magnitude
-            let steering_length_squared = steering.length_squared();
-            if steering_length_squared > 0.0 {
-                // Implement Reynolds: Steering = Desired - Velocity
-                let steering_length = steering_length_squared.sqrt();
-                let desired = steering * (self.max_speed / steering_length);
-                
-                steering = desired - self.velocity;
-                
-                // Limit force
-                let force_squared = steering.length_squared();
-                let max_force_squared = self.max_force * self.max_force;
-                
-                if force_squared > max_force_squared {
-                    let force_length = force_squared.sqrt();
-                    steering *= self.max_force / force_length;
-                }
-            }
-        }
-        
-        steering
+        color
     }
     
-    // Original versions of the flocking behaviors (without spatial grid)
-    pub fn cohesion_original(&self, boids: &[Boid], perception_radius: f32, _use_squared_distance: bool) -> Vec2 {
-        let mut sum_position = Vec2::ZERO;
-        let mut count = 0;
-        
-        // Pre-calculate squared radius for optimization
-        let radius_squared = perception_radius * perception_radius;
-        
-        for other in boids {
-            // Calculate squared distance directly
-            let dx = self.position.x - other.position.x;
-            let dy = self.position.y - other.position.y;
-            let d_squared = dx * dx + dy * dy;
-            
-            // Skip if it's the same boid or outside perception radius
-            if d_squared <= 0.0 || d_squared >= radius_squared {
-                continue;
-            }
-            
-            // Accumulate positions (reuse existing Vec2 from position)
-            sum_position.x += other.position.x;
-            sum_position.y += other.position.y;
-            count += 1;
-        }
+    // Draw the boid as a shape boid_size world units long (scaled by the camera zoom)
+    pub fn draw(&self, draw: &Draw, camera: &Camera, window_rect: Rect, alpha: f32, is_selected: bool, params: &SimulationParams) {
+        self.draw_faded(draw, camera, window_rect, alpha, params, 1.0);
         
-        if count > 0 {
-            sum_position /= count as f32;
-            
-            // Create desired velocity towards target
-            let desired = sum_position - Vec2::new(self.position.x, self.position.y);
-            
-            let desired_length_squared = desired.length_squared();
-            if desired_length_squared > 0.0 {
-                // Scale to maximum speed (only normalize if needed)
-                let desired_length = desired_length_squared.sqrt();
-                let desired_normalized = desired * (self.max_speed / desired_length);
-                
-                // Implement Reynolds: Steering = Desired - Velocity
-                let mut steering = desired_normalized - self.velocity;
-                
-                // Limit force
-                let force_squared = steering.length_squared();
-                let max_force_squared = self.max_force * self.max_force;
-                
-                if force_squared > max_force_squared {
-                    let force_length = force_squared.sqrt();
-                    steering *= self.max_force / force_length;
-                }
-                
-                return steering;
-            }
+        // If this is the selected boid, draw a highlight around it
+        if is_selected {
+            // We're keeping the boid selected for camera following,
+            // but not drawing any visual indicators as per user request
+            // The camera movement itself is sufficient to show which boid is being followed
         }
-        
-        Vec2::ZERO
     }
     
-    // Draw the boid
-    pub fn draw(&self, draw: &Draw, camera: &Camera, window_rect: Rect, alpha: f32, is_selected: bool) {
-        // Get interpolated position and velocity
-        let interpolated_position = self.get_interpolated_position(alpha);
-        let interpolated_velocity = self.get_interpolated_velocity(alpha);
+    // Draw the boid partly faded in or out (see the effects module): translucent and smaller
+    pub fn draw_faded(&self, draw: &Draw, camera: &Camera, window_rect: Rect, alpha: f32, params: &SimulationParams, fade: f32) {
+        let (size, shape) = (params.species_boid_size(self.species) * effects::fade_scale(fade), params.boid_shape);
+        
+        // Get interpolated position
+        let interpolated_position = boid_render_position(self, params, alpha);
         
         // Convert boid position from world space to screen space
         let screen_pos = camera.world_to_screen(Vec2::new(interpolated_position.x, interpolated_position.y), window_rect);
         
-        // Calculate the angle of the velocity
-        let angle = interpolated_velocity.y.atan2(interpolated_velocity.x);
+        // Point along the velocity (perched boids lie flat)
+        let angle = self.heading(params, alpha);
         
         // Scale the boid size based on zoom level
-        let scaled_size = BOID_SIZE * camera.zoom;
+        let scaled_size = size * camera.zoom;
         
-        // Use thread-local storage for caching the triangle points
+        // Use thread-local storage for caching the outline points
         thread_local! {
-            static LAST_SIZE: std::cell::Cell<f32> = std::cell::Cell::new(0.0);
-            static CACHED_POINTS: std::cell::RefCell<[Point2; 3]> = std::cell::RefCell::new([
-                pt2(0.0, 0.0), pt2(0.0, 0.0), pt2(0.0, 0.0)
-            ]);
+            static CACHED_SHAPE: std::cell::RefCell<(f32, BoidShape, Vec<Point2>)> =
+                const { std::cell::RefCell::new((0.0, BoidShape::Triangle, Vec::new())) };
         }
         
-        // Only recalculate points if the size has changed
-        LAST_SIZE.with(|last_size| {
-            if (last_size.get() - scaled_size).abs() > 0.01 {
-                last_size.set(scaled_size);
+        let color = edge_tinted(self.display_color(), interpolated_position, params);
+        let color = rgba(color.red, color.green, color.blue, (fade.clamp(0.0, 1.0) * 255.0).round() as u8);
+        
+        match shape {
+            // Circles have no heading to rotate
+            BoidShape::Circle => {
+                draw.ellipse()
+                    .color(color)
+                    .xy(screen_pos)
+                    .radius(scaled_size / 2.0);
+            }
+            BoidShape::Line => {
+                let (sin, cos) = angle.sin_cos();
+                let half_length = vec2(cos, sin) * scaled_size;
+                draw.line()
+                    .start(screen_pos - half_length)
+                    .end(screen_pos + half_length)
+                    .weight(1.0)
+                    .color(color);
+            }
+            BoidShape::Triangle | BoidShape::Dart => CACHED_SHAPE.with(|cached| {
+                let mut cached = cached.borrow_mut();
                 
-                let new_points = [
-                    pt2(scaled_size, 0.0),
-                    pt2(-scaled_size, scaled_size / 2.0),
-                    pt2(-scaled_size, -scaled_size / 2.0),
-                ];
+                // Only recalculate points if the size or shape has changed
+                if (cached.0 - scaled_size).abs() > 0.01 || cached.1 != shape {
+                    *cached = (scaled_size, shape, shape.outline(scaled_size));
+                }
                 
-                CACHED_POINTS.with(|points| {
-                    *points.borrow_mut() = new_points;
-                });
+                draw.polygon()
+                    .color(color)
+                    .points(cached.2.iter().copied())
+                    .xy(screen_pos)
+                    .rotate(angle);
+            }),
+        }
+    }
+}
+
+// How boids are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoidShape {
+    Triangle,
+    Dart, // A triangle with a notch cut out of the back
+    Circle,
+    Line,
+}
+
+impl BoidShape {
+    pub const ALL: [BoidShape; 4] = [BoidShape::Triangle, BoidShape::Dart, BoidShape::Circle, BoidShape::Line];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            BoidShape::Triangle => "Triangle",
+            BoidShape::Dart => "Dart",
+            BoidShape::Circle => "Circle",
+            BoidShape::Line => "Line",
+        }
+    }
+    
+    // The outline of a polygon shape pointing along +x, `size` from the center to the tip
+    // (empty for the shapes that aren't drawn as polygons)
+    pub fn outline(self, size: f32) -> Vec<Point2> {
+        match self {
+            BoidShape::Triangle => vec![
+                pt2(size, 0.0),
+                pt2(-size, size / 2.0),
+                pt2(-size, -size / 2.0),
+            ],
+            BoidShape::Dart => vec![
+                pt2(size, 0.0),
+                pt2(-size, size * 0.6),
+                pt2(-size * 0.4, 0.0),
+                pt2(-size, -size * 0.6),
+            ],
+            BoidShape::Circle | BoidShape::Line => Vec::new(),
+        }
+    }
+}
+
+// Where a boid is drawn: blended between its last two snapshots, or at its latest position
+// with interpolation off. Everything placed on the boids (rendering, culling, picking, the
+// camera follow and the debug overlays) goes through these two, so none can miss the toggle.
+#[inline]
+pub fn boid_render_position(boid: &Boid, params: &SimulationParams, alpha: f32) -> Point2 {
+    if params.enable_interpolation {
+        boid.get_interpolated_position(alpha, params.world_size)
+    } else {
+        boid.position
+    }
+}
+
+// The velocity a boid is drawn with (see boid_render_position)
+#[inline]
+pub fn boid_render_velocity(boid: &Boid, params: &SimulationParams, alpha: f32) -> Vec2 {
+    if params.enable_interpolation {
+        boid.get_interpolated_velocity(alpha)
+    } else {
+        boid.velocity
+    }
+}
+
+// Speeds below this have no reliable direction to draw a boid along
+const MIN_HEADING_SPEED_SQUARED: f32 = 1e-8;
+
+// Angle of a velocity, or of the fallback velocity when it is (nearly) zero, so a boid
+// that stops keeps the heading it had
+fn velocity_angle(velocity: Vec2, fallback: Vec2) -> f32 {
+    let velocity = if velocity.length_squared() < MIN_HEADING_SPEED_SQUARED { fallback } else { velocity };
+    velocity.y.atan2(velocity.x)
+}
+
+// Interpolate between two angles along the shorter arc, across the wrap at ±π
+pub fn lerp_angle(from: f32, to: f32, alpha: f32) -> f32 {
+    let delta = (to - from + PI).rem_euclid(TAU) - PI;
+    from + delta * alpha
+}
+
+// How far into the edge band a position is: 0 outside it, rising to 1 at the world edge
+pub fn edge_closeness(position: Point2, params: &SimulationParams) -> f32 {
+    let distance = params.world_size / 2.0 - position.x.abs().max(position.y.abs());
+    (1.0 - distance / params.edge_band_width.max(1.0)).clamp(0.0, 1.0)
+}
+
+// A boid's color blended toward the edge warning color by its closeness to the edge
+// (unchanged unless tint_edge_boids is on)
+pub fn edge_tinted(color: Rgb<u8>, position: Point2, params: &SimulationParams) -> Rgb<u8> {
+    if !params.tint_edge_boids {
+        return color;
+    }
+    
+    let closeness = edge_closeness(position, params);
+    if closeness <= 0.0 {
+        return color;
+    }
+    
+    let [red, green, blue] = palette::EDGE_WARNING;
+    let blend = |c: u8, warning: u8| (c as f32 + (warning as f32 - c as f32) * closeness).round() as u8;
+    rgb(blend(color.red, red), blend(color.green, green), blend(color.blue, blue))
+}
+
+// The state updates below work on individual fields, so they are shared by the
+// Boid methods and the physics pass over the flock's arrays.
+
+// Approximate 1 / sqrt(x) for x > 0: the bit-level initial estimate refined by two
+// Newton steps, for a relative error below 1e-5. Branch-free, so it vectorizes.
+#[inline]
+pub fn fast_inverse_sqrt(x: f32) -> f32 {
+    let half_x = 0.5 * x;
+    let estimate = f32::from_bits(0x5f37_5a86 - (x.to_bits() >> 1));
+    let estimate = estimate * (1.5 - half_x * estimate * estimate);
+    estimate * (1.5 - half_x * estimate * estimate)
+}
+
+// value / sqrt(x), using fast_inverse_sqrt in the fast math mode
+#[inline]
+pub fn div_sqrt(value: f32, x: f32, fast_math: bool) -> f32 {
+    if fast_math {
+        value * fast_inverse_sqrt(x)
+    } else {
+        value / x.sqrt()
+    }
+}
+
+// Floor on the distance in the separation weighting, so a neighbor on top of a boid can't
+// outweigh all the others by orders of magnitude and wipe out the direction of the push
+pub const MIN_SEPARATION_DISTANCE: f32 = 0.5;
+
+// The factor a neighbor's offset (pointing from the neighbor to the boid) is scaled by in
+// separation, for a neighbor d_squared away: the push along the offset's direction follows
+// the falloff curve, with the distance floored at MIN_SEPARATION_DISTANCE
+#[inline]
+pub fn separation_factor(falloff: SeparationFalloff, power: f32, d_squared: f32, radius: f32, fast_math: bool) -> f32 {
+    let d_squared = d_squared.max(MIN_SEPARATION_DISTANCE * MIN_SEPARATION_DISTANCE);
+    let inverse_d = || div_sqrt(1.0, d_squared, fast_math);
+    
+    match falloff {
+        // offset / d / d needs no square root
+        SeparationFalloff::Inverse => d_squared.recip(),
+        SeparationFalloff::InverseSquare => inverse_d().powi(3),
+        SeparationFalloff::Linear => {
+            let inverse_d = inverse_d();
+            (1.0 - d_squared * inverse_d / radius).max(0.0) * inverse_d
+        }
+        SeparationFalloff::Constant => inverse_d(),
+        SeparationFalloff::Power => inverse_d().powf(power + 1.0),
+    }
+}
+
+// Reynolds seek (Steering = Desired - Velocity) toward a target `offset` away, limited to
+// max_force. With a slowing radius this is the "arrive" behavior: inside the radius the
+// desired speed falls linearly from max_speed to zero, so the boid brakes and comes to
+// rest at the target instead of overshooting and oscillating around it.
+// Without a slowing radius, a zero offset gives no force.
+#[inline]
+pub fn arrive(offset: Vec2, velocity: Vec2, max_speed: f32, max_force: f32, slowing_radius: Option<f32>) -> Vec2 {
+    let distance_squared = offset.length_squared();
+    
+    let desired = match slowing_radius {
+        Some(radius) if distance_squared < radius * radius => {
+            // Scaled by distance / radius, so no normalization is needed
+            offset * (max_speed / radius)
+        }
+        _ => {
+            if distance_squared <= 0.0 {
+                return Vec2::ZERO;
             }
-        });
-        
-        // Draw the boid using the cached points
-        CACHED_POINTS.with(|points| {
-            let points = points.borrow();
-            
-            // Draw the boid with its color
-            draw.polygon()
-                .color(self.color)
-                .points(points.clone())
-                .xy(pt2(screen_pos.x, screen_pos.y))
-                .rotate(angle);
-            
-            // If this is the selected boid, draw a highlight around it
-            if is_selected {
-                // We're keeping the boid selected for camera following,
-                // but not drawing any visual indicators as per user request
-                // The camera movement itself is sufficient to show which boid is being followed
+            offset * (max_speed / distance_squared.sqrt())
+        }
+    };
+    
+    let mut steering = desired - velocity;
+    
+    // Limit force
+    let force_squared = steering.length_squared();
+    let max_force_squared = max_force * max_force;
+    
+    if force_squared > max_force_squared {
+        let force_length = force_squared.sqrt();
+        steering *= max_force / force_length;
+    }
+    
+    steering
+}
+
+// Where a moving target will be after the time a boid at `position` needs to reach the
+// target's current position at max_speed, so the lookahead grows with the distance
+#[inline]
+pub fn predict_position(position: Point2, max_speed: f32, target_position: Point2, target_velocity: Vec2) -> Point2 {
+    let lookahead = position.distance(target_position) / max_speed.max(f32::EPSILON);
+    target_position + target_velocity * lookahead
+}
+
+// Apply the acceleration to the velocity, limit the speed, move, and reset the acceleration
+#[inline]
+pub fn integrate(position: &mut Point2, velocity: &mut Vec2, acceleration: &mut Vec2, max_speed: f32) {
+    integrate_fraction(position, velocity, acceleration, max_speed, 1.0);
+}
+
+// integrate over a fraction of a step (a physics substep): the acceleration and the
+// movement are both scaled by it. A fraction of 1 gives exactly the same result.
+#[inline]
+pub fn integrate_fraction(position: &mut Point2, velocity: &mut Vec2, acceleration: &mut Vec2, max_speed: f32, fraction: f32) {
+    // Update velocity
+    *velocity += *acceleration * fraction;
+    
+    // Limit speed (only normalize if exceeding max_speed)
+    let speed_squared = velocity.length_squared();
+    let max_speed_squared = max_speed * max_speed;
+    
+    if speed_squared > max_speed_squared {
+        let speed = speed_squared.sqrt();
+        *velocity *= max_speed / speed;
+    }
+    
+    // Update position
+    *position += *velocity * fraction;
+    
+    // Reset acceleration
+    *acceleration = Vec2::ZERO;
+}
+
+// The world the brute-force rules measure distances in
+#[derive(Clone, Copy, Debug)]
+pub struct WorldWrap {
+    pub world_size: f32,
+    pub wrap_x: bool, // Off while the x axis is open (wind tunnel mode)
+}
+
+impl WorldWrap {
+    // The shortest vector from one position to another, across the wrapping edges if shorter
+    #[inline]
+    pub fn offset(self, from: Point2, to: Point2) -> Vec2 {
+        let half_world = self.world_size / 2.0;
+        let mut offset = to - from;
+        if self.wrap_x && offset.x.abs() > half_world {
+            offset.x -= self.world_size.copysign(offset.x);
+        }
+        if offset.y.abs() > half_world {
+            offset.y -= self.world_size.copysign(offset.y);
+        }
+        offset
+    }
+}
+
+// Wrap a position around the world edges (only the top and bottom edges unless `wrap_x`).
+// The previous position is moved by the same jump, past the opposite edge, so that the
+// last step stays continuous for interpolation.
+#[inline]
+pub fn wrap_edges(position: &mut Point2, prev_position: &mut Point2, world_size: f32, wrap_x: bool) {
+    let half_size = world_size / 2.0;
+    let unwrapped = *position;
+    
+    if !wrap_x {
+        // Open x axis: the boid is left outside for the caller to remove
+    } else if position.x > half_size {
+        position.x = -half_size;
+    } else if position.x < -half_size {
+        position.x = half_size;
+    }
+    
+    if position.y > half_size {
+        position.y = -half_size;
+    } else if position.y < -half_size {
+        position.y = half_size;
+    }
+    
+    *prev_position += *position - unwrapped;
+}
+
+// A position wrapped modulo the world size (positions inside the world, including on
+// its boundary, are returned unchanged)
+#[inline]
+pub fn wrap_point(position: Point2, world_size: f32) -> Point2 {
+    let half_size = world_size / 2.0;
+    let wrap = |coordinate: f32| if coordinate.abs() > half_size {
+        (coordinate + half_size).rem_euclid(world_size) - half_size
+    } else {
+        coordinate
+    };
+    
+    pt2(wrap(position.x), wrap(position.y))
+}
+
+// Interpolate between two positions in the wrapping world. A step longer than half the
+// world on an axis went out one edge and in at the other, so it is taken the short way
+// around and the result wrapped back into the world.
+#[inline]
+pub fn interpolate_wrapped(from: Point2, to: Point2, alpha: f32, world_size: f32) -> Point2 {
+    let half_size = world_size / 2.0;
+    let shortest = |delta: f32| if delta > half_size {
+        delta - world_size
+    } else if delta < -half_size {
+        delta + world_size
+    } else {
+        delta
+    };
+    
+    let delta = to - from;
+    wrap_point(from + vec2(shortest(delta.x), shortest(delta.y)) * alpha, world_size)
+}
+
+// Wrap a position modulo the world size, snapping the previous position to it
+pub fn wrap_into_world(position: &mut Point2, prev_position: &mut Point2, world_size: f32) {
+    let half_size = world_size / 2.0;
+    
+    position.x = (position.x + half_size).rem_euclid(world_size) - half_size;
+    position.y = (position.y + half_size).rem_euclid(world_size) - half_size;
+    
+    // Snap the previous position too to avoid interpolating across the world
+    *prev_position = *position;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD_SIZE: f32 = 1000.0;
+
+    fn boid_at(x: f32, y: f32, velocity: Vec2) -> Boid {
+        let mut boid = Boid::new(x, y);
+        boid.velocity = velocity;
+        boid.prev_velocity = velocity;
+        boid
+    }
+
+    fn assert_approx_eq(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "expected {} to be approximately {}", a, b);
+    }
+
+    #[test]
+    fn update_limits_speed_to_max_speed() {
+        let mut boid = boid_at(0.0, 0.0, vec2(3.0, 0.0));
+        boid.apply_force(vec2(10.0, 10.0));
+        boid.update();
+
+        assert_approx_eq(boid.velocity.length(), boid.max_speed);
+        // The direction is kept when the speed is limited
+        assert_approx_eq(boid.velocity.x, boid.velocity.y * 13.0 / 10.0);
+    }
+
+    #[test]
+    fn update_moves_by_velocity_and_resets_acceleration() {
+        let mut boid = boid_at(5.0, -5.0, vec2(1.0, 0.5));
+        boid.apply_force(vec2(0.5, 0.0));
+        boid.update();
+
+        assert_eq!(boid.velocity, vec2(1.5, 0.5));
+        assert_eq!(boid.position, pt2(6.5, -4.5));
+        assert_eq!(boid.acceleration, Vec2::ZERO);
+    }
+
+    #[test]
+    fn wrap_edges_maps_out_of_range_positions_to_the_opposite_edge() {
+        let half = WORLD_SIZE / 2.0;
+        let cases = [
+            (pt2(half + 1.0, 0.0), pt2(-half, 0.0)),
+            (pt2(-half - 1.0, 0.0), pt2(half, 0.0)),
+            (pt2(0.0, half + 1.0), pt2(0.0, -half)),
+            (pt2(0.0, -half - 1.0), pt2(0.0, half)),
+            (pt2(half + 1.0, -half - 1.0), pt2(-half, half)),
+        ];
+
+        for (position, expected) in cases {
+            let mut boid = boid_at(position.x, position.y, Vec2::ZERO);
+            boid.wrap_edges(WORLD_SIZE);
+            assert_eq!(boid.position, expected, "wrapping {:?}", position);
+        }
+    }
+
+    #[test]
+    fn wrap_edges_keeps_positions_exactly_on_the_boundary() {
+        let half = WORLD_SIZE / 2.0;
+        for position in [pt2(half, half), pt2(-half, -half), pt2(half, -half), pt2(0.0, half)] {
+            let mut boid = boid_at(position.x, position.y, Vec2::ZERO);
+            boid.wrap_edges(WORLD_SIZE);
+            assert_eq!(boid.position, position);
+        }
+    }
+
+    #[test]
+    fn wrap_edges_keeps_the_last_step_continuous() {
+        let half = WORLD_SIZE / 2.0;
+        let mut boid = boid_at(half - 1.0, 0.0, vec2(3.0, 0.0));
+        boid.store_previous_state();
+        boid.update();
+        boid.wrap_edges(WORLD_SIZE);
+
+        // The previous position is moved past the opposite edge by the same jump
+        assert_eq!(boid.position, pt2(-half, 0.0));
+        assert_eq!(boid.prev_position, pt2(-half - 3.0, 0.0));
+        assert_eq!(boid.get_interpolated_position(0.5, WORLD_SIZE), pt2(-half - 1.5 + WORLD_SIZE, 0.0));
+    }
+
+    #[test]
+    fn interpolation_takes_the_short_way_across_each_edge() {
+        let half = WORLD_SIZE / 2.0;
+        // (previous position, current position, expected position halfway)
+        let cases = [
+            (pt2(half - 2.0, 10.0), pt2(-half + 2.0, 10.0), pt2(half, 10.0)),   // Out the right edge
+            (pt2(-half + 2.0, 10.0), pt2(half - 2.0, 10.0), pt2(-half, 10.0)),  // Out the left edge
+            (pt2(10.0, half - 2.0), pt2(10.0, -half + 2.0), pt2(10.0, half)),   // Out the top edge
+            (pt2(10.0, -half + 2.0), pt2(10.0, half - 2.0), pt2(10.0, -half)),  // Out the bottom edge
+            (pt2(half - 2.0, -half + 4.0), pt2(-half + 2.0, half - 4.0), pt2(half, -half)), // Through a corner
+        ];
+
+        for (prev_position, position, halfway) in cases {
+            let mut boid = boid_at(position.x, position.y, Vec2::ZERO);
+            boid.prev_position = prev_position;
+
+            assert_eq!(boid.get_interpolated_position(0.0, WORLD_SIZE), prev_position);
+            assert_eq!(boid.get_interpolated_position(0.5, WORLD_SIZE), halfway, "from {:?} to {:?}", prev_position, position);
+            assert_eq!(boid.get_interpolated_position(1.0, WORLD_SIZE), position);
+
+            // Past the edge the position is wrapped back into the world
+            let three_quarters = boid.get_interpolated_position(0.75, WORLD_SIZE);
+            assert_approx_eq(three_quarters.distance(position), 0.25 * prev_position.distance(halfway) * 2.0);
+        }
+    }
+
+    #[test]
+    fn interpolation_within_the_world_is_a_plain_lerp() {
+        let mut boid = boid_at(20.0, -10.0, Vec2::ZERO);
+        boid.prev_position = pt2(10.0, 30.0);
+
+        assert_eq!(boid.get_interpolated_position(0.5, WORLD_SIZE), pt2(15.0, 10.0));
+    }
+
+    #[test]
+    fn headings_turn_the_short_way_across_the_wrap() {
+        // From just below +π to just above -π is a small turn through π
+        let halfway = lerp_angle(PI - 0.1, -PI + 0.1, 0.5);
+        assert_approx_eq(halfway.sin(), 0.0);
+        assert_approx_eq(halfway.cos(), -1.0);
+
+        let mut params = SimulationParams::default();
+        params.enable_interpolation = true;
+        let mut boid = boid_at(0.0, 0.0, vec2(0.0, 1.0));
+        boid.prev_velocity = vec2(1.0, 0.0);
+        assert_approx_eq(boid.heading(&params, 0.5), PI / 4.0);
+
+        // A reversal keeps the speed out of the angle: a quarter turn halfway through
+        boid.prev_velocity = vec2(-1.0, 0.001);
+        boid.velocity = vec2(1.0, 0.0);
+        assert!((boid.heading(&params, 0.5).abs() - PI / 2.0).abs() < 0.01);
+
+        // A boid that stopped keeps its last heading
+        boid.prev_velocity = vec2(0.0, -1.0);
+        boid.velocity = Vec2::ZERO;
+        assert_approx_eq(boid.heading(&params, 0.5), -PI / 2.0);
+        params.enable_interpolation = false;
+        assert_approx_eq(boid.heading(&params, 0.5), -PI / 2.0);
+    }
+
+    #[test]
+    fn boids_are_tinted_by_their_closeness_to_the_edge() {
+        let mut params = SimulationParams::default();
+        params.world_size = 1000.0;
+        params.edge_band_width = 100.0;
+        assert_eq!(edge_closeness(pt2(0.0, 0.0), &params), 0.0);
+        assert_approx_eq(edge_closeness(pt2(450.0, 0.0), &params), 0.5);
+        assert_approx_eq(edge_closeness(pt2(0.0, -500.0), &params), 1.0);
+
+        let color = rgb(0, 0, 0);
+        assert_eq!(edge_tinted(color, pt2(0.0, -500.0), &params), color);
+        params.tint_edge_boids = true;
+        assert_eq!(edge_tinted(color, pt2(0.0, 0.0), &params), color);
+        let [red, green, blue] = palette::EDGE_WARNING;
+        assert_eq!(edge_tinted(color, pt2(0.0, -500.0), &params), rgb(red, green, blue));
+    }
+
+    #[test]
+    fn fast_inverse_sqrt_is_close_to_the_exact_value() {
+        for x in [1e-6_f32, 0.01, 0.5, 1.0, 2.0, 123.456, 2500.0, 1e6] {
+            let exact = 1.0 / x.sqrt();
+            assert!((fast_inverse_sqrt(x) - exact).abs() <= exact * 1e-5, "1 / sqrt({})", x);
+        }
+    }
+
+    #[test]
+    fn separation_falloffs_shape_the_push_by_distance() {
+        // The push along the direction to a neighbor d away
+        let push = |falloff, power, d: f32| separation_factor(falloff, power, d * d, 40.0, false) * d;
+
+        assert_approx_eq(push(SeparationFalloff::InverseSquare, 0.0, 10.0) / push(SeparationFalloff::InverseSquare, 0.0, 20.0), 4.0);
+        assert_approx_eq(push(SeparationFalloff::Inverse, 0.0, 10.0) / push(SeparationFalloff::Inverse, 0.0, 20.0), 2.0);
+        assert_approx_eq(push(SeparationFalloff::Constant, 0.0, 10.0), push(SeparationFalloff::Constant, 0.0, 20.0));
+        assert_approx_eq(push(SeparationFalloff::Linear, 0.0, 10.0), 0.75);
+        assert_approx_eq(push(SeparationFalloff::Linear, 0.0, 40.0), 0.0);
+
+        // The power curve covers the fixed ones
+        for d in [1.0, 7.0, 30.0] {
+            assert_approx_eq(push(SeparationFalloff::Power, 2.0, d), push(SeparationFalloff::InverseSquare, 0.0, d));
+            assert_approx_eq(push(SeparationFalloff::Power, 1.0, d), push(SeparationFalloff::Inverse, 0.0, d));
+            assert_approx_eq(push(SeparationFalloff::Power, 0.0, d), push(SeparationFalloff::Constant, 0.0, d));
+        }
+
+        // Overlapping boids push no harder than boids at the distance floor
+        let floor = MIN_SEPARATION_DISTANCE;
+        assert!(push(SeparationFalloff::InverseSquare, 0.0, 1e-4) <= push(SeparationFalloff::InverseSquare, 0.0, floor));
+        assert!(separation_factor(SeparationFalloff::InverseSquare, 0.0, 0.0, 40.0, false).is_finite());
+    }
+
+    // Run a boid seeking a fixed target for a while. Returns its final velocity and the
+    // furthest it strayed from the target over the last 100 steps.
+    fn seek_target(slowing_radius: Option<f32>) -> (Vec2, f32) {
+        let target = pt2(0.0, 0.0);
+        let (max_speed, max_force) = (5.0, 0.5);
+        let mut position = pt2(100.0, 0.0);
+        let mut velocity = vec2(0.0, 3.0); // Starts off moving past the target
+        let mut late_distance: f32 = 0.0;
+
+        for step in 0..2000 {
+            let mut acceleration = arrive(target - position, velocity, max_speed, max_force, slowing_radius);
+            integrate(&mut position, &mut velocity, &mut acceleration, max_speed);
+            if step >= 1900 {
+                late_distance = late_distance.max(position.distance(target));
             }
+        }
+
+        (velocity, late_distance)
+    }
+
+    #[test]
+    fn arriving_boid_comes_to_rest_at_the_target() {
+        let (velocity, late_distance) = seek_target(Some(50.0));
+
+        assert!(late_distance < 0.01, "still {} from the target", late_distance);
+        assert!(velocity.length() < 0.01, "still moving at {:?}", velocity);
+
+        // Plain seeking keeps overshooting the target
+        let (_, late_distance) = seek_target(None);
+        assert!(late_distance > 1.0, "plain seek unexpectedly settled");
+    }
+
+    // Steps a chaser needs to get within catching distance of a target crossing its path
+    fn steps_to_catch(steer: impl Fn(&Boid, Point2, Vec2) -> Vec2) -> usize {
+        let mut chaser = boid_at(0.0, 0.0, Vec2::ZERO);
+        chaser.max_speed = 4.0;
+        chaser.max_force = 0.5;
+        let mut target = pt2(100.0, 0.0);
+        let target_velocity = vec2(0.0, 3.0);
+
+        for step in 0..1000 {
+            if chaser.position.distance(target) < 5.0 {
+                return step;
+            }
+            let force = steer(&chaser, target, target_velocity);
+            chaser.apply_force(force);
+            chaser.update();
+            target += target_velocity;
+        }
+        panic!("the target got away");
+    }
+
+    #[test]
+    fn pursuer_intercepts_sooner_than_a_seeker() {
+        let pursuer = steps_to_catch(|boid, target, velocity| boid.pursue(target, velocity));
+        let seeker = steps_to_catch(|boid, target, _| {
+            arrive(target - boid.position, boid.velocity, boid.max_speed, boid.max_force, None)
         });
+
+        assert!(pursuer < seeker, "pursuer took {} steps, seeker {}", pursuer, seeker);
+
+        // Evading steers away from the target's future position, not its current one
+        let boid = boid_at(0.0, 0.0, Vec2::ZERO);
+        let force = boid.evade(pt2(10.0, 0.0), vec2(0.0, 10.0));
+        assert!(force.x < 0.0 && force.y < 0.0, "evade force {:?}", force);
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,156 @@
+/*
+ * Breeds Module
+ * 
+ * Breeds are species defined in a data file instead of the UI, so flocks of different
+ * kinds of birds can be set up without recompiling. Each `[name]` section of the file
+ * defines one breed; the keys are the species fields:
+ * 
+ *   [sparrow]
+ *   color = "200, 200, 200"
+ *   size = 1.0
+ *   max_speed = 4
+ *   max_force = 0.1
+ *   cohesion_weight = 1.5
+ *   share = 70
+ * 
+ * Keys a breed leaves out take the default species' values. The shares are the mixture
+ * of the flock in percent (scaled to add up to 100). Values outside the slider ranges
+ * are errors, reported with their line number like preset errors.
+ * 
+ * The loaded breeds replace the species table, so a boid's species id is its breed and
+ * the physics reads the breed's weights and radii from the per-species constants.
+ * Without a breeds file the flock has the single default species.
+ */
+
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::palette;
+use crate::params::{self, SimulationParams, SpeciesParams};
+
+// File looked for in the working directory when --breeds isn't given
+pub const BREEDS_FILE_NAME: &str = "breeds.toml";
+
+// Read and parse a breeds file
+pub fn load(path: &Path) -> Result<Vec<SpeciesParams>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read breeds {}: {}", path.display(), err))?;
+    parse(&text).map_err(|err| format!("invalid breeds {}: {}", path.display(), err))
+}
+
+// Parse the breeds in a breeds file, in the order they are defined
+pub fn parse(text: &str) -> Result<Vec<SpeciesParams>, String> {
+    let base = SimulationParams::default().species.remove(0);
+    let mut breeds: Vec<SpeciesParams> = Vec::new();
+    
+    for (line_number, line) in text.lines().enumerate() {
+        parse_line(&mut breeds, &base, line).map_err(|err| format!("line {}: {}", line_number + 1, err))?;
+    }
+    
+    if breeds.is_empty() {
+        return Err("no breeds defined (expected a [name] header)".to_string());
+    }
+    Ok(breeds)
+}
+
+// Start a breed at a `[name]` header, or set a value of the current breed
+fn parse_line(breeds: &mut Vec<SpeciesParams>, base: &SpeciesParams, line: &str) -> Result<(), String> {
+    let content = line.split('#').next().unwrap_or("").trim();
+    if let Some(name) = content.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("empty breed name".to_string());
+        }
+        if breeds.iter().any(|breed| breed.name == name) {
+            return Err(format!("breed {} is defined twice", name));
+        }
+        // Species ids are stored as u8 on each boid
+        if breeds.len() >= u8::MAX as usize {
+            return Err(format!("too many breeds (at most {})", u8::MAX));
+        }
+        
+        breeds.push(SpeciesParams { name: name.to_string(), ..base.clone() });
+        return Ok(());
+    }
+    
+    let Some((key, value)) = params::parse_preset_line(line)? else {
+        return Ok(());
+    };
+    let Some(breed) = breeds.last_mut() else {
+        return Err(format!("{} is set before the first [name] header", key));
+    };
+    
+    match key {
+        "color" => breed.color = palette::parse_color(value)
+            .ok_or_else(|| format!("invalid color for {}: {} (expected \"r, g, b\")", key, value))?,
+        "size" => breed.size = parse_in_range(key, value, SimulationParams::get_species_size_range())?,
+        "max_speed" => breed.max_speed = parse_in_range(key, value, SimulationParams::get_max_speed_range())?,
+        "max_force" => breed.max_force = parse_in_range(key, value, SimulationParams::get_max_force_range())?,
+        "separation_weight" => breed.separation_weight = parse_in_range(key, value, SimulationParams::get_weight_range())?,
+        "alignment_weight" => breed.alignment_weight = parse_in_range(key, value, SimulationParams::get_weight_range())?,
+        "cohesion_weight" => breed.cohesion_weight = parse_in_range(key, value, SimulationParams::get_weight_range())?,
+        "separation_radius" => breed.separation_radius = parse_in_range(key, value, SimulationParams::get_radius_range())?,
+        "alignment_radius" => breed.alignment_radius = parse_in_range(key, value, SimulationParams::get_radius_range())?,
+        "cohesion_radius" => breed.cohesion_radius = parse_in_range(key, value, SimulationParams::get_radius_range())?,
+        "share" => breed.share = parse_in_range(key, value, 0.0..=100.0)?,
+        _ => return Err(format!("unknown breed setting {}", key)),
+    }
+    Ok(())
+}
+
+// Parse a number that must lie within a range
+fn parse_in_range(key: &str, value: &str, range: RangeInclusive<f32>) -> Result<f32, String> {
+    let number = value.parse::<f32>()
+        .map_err(|_| format!("invalid number for {}: {}", key, value))?;
+    if !range.contains(&number) {
+        return Err(format!("{} must be between {} and {}, got {}", key, range.start(), range.end(), number));
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breeds_are_parsed_with_defaults_for_missing_keys() {
+        let breeds = parse("\
+# Small birds and a few predators
+[sparrow]
+color = \"200, 180, 150\"
+share = 70
+
+[hawk]
+size = 2.5   # Drawn larger
+max_speed = 7
+max_force = 0.3
+cohesion_weight = 0.2
+share = 30
+").unwrap();
+
+        let default = &SimulationParams::default().species[0];
+        assert_eq!(breeds.len(), 2);
+        assert_eq!(breeds[0].name, "sparrow");
+        assert_eq!(breeds[0].color, [200, 180, 150]);
+        assert_eq!(breeds[0].share, 70.0);
+        assert_eq!(breeds[0].max_speed, default.max_speed);
+        assert_eq!(breeds[1].name, "hawk");
+        assert_eq!((breeds[1].size, breeds[1].max_speed, breeds[1].max_force), (2.5, 7.0, 0.3));
+        assert_eq!(breeds[1].cohesion_weight, 0.2);
+        assert_eq!(breeds[1].separation_radius, default.separation_radius);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |text: &str| parse(text).err().unwrap();
+
+        assert!(error("[a]\nmax_speed = 50\n").starts_with("line 2: max_speed must be between"));
+        assert!(error("[a]\n\nsize = big\n").starts_with("line 3: invalid number for size"));
+        assert!(error("share = 10\n[a]\n").starts_with("line 1: share is set before"));
+        assert!(error("[a]\n[a]\n").starts_with("line 2: breed a is defined twice"));
+        assert!(error("[a]\nwingspan = 3\n").starts_with("line 2: unknown breed setting"));
+        assert!(error("[a]\ncolor = \"1, 2\"\n").starts_with("line 2: invalid color"));
+        assert!(error("# nothing here\n").starts_with("no breeds"));
+    }
+}
@@ -6,12 +6,37 @@
  * world space and screen space.
  * 
  * Features:
- * - Zooming and panning controls
+ * - Zooming and panning controls (mouse and keyboard)
  * - Coordinate transformations
- * - Boid following mode
+ * - Following a boid or the flock's center of mass, with a deadzone around the center
+ * - Fitting the view to a bounding box
+ * - Smooth animated panning to a point
+ * - A hash of the camera state, so views of the world can be cached until it changes
+ * - Keeping the visible part of the world when the window is resized
  */
 
 use nannou::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::flock::BoidId;
+
+// What the camera is following, if anything
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FollowTarget {
+    None,               // Free camera movement
+    Boid(BoidId),       // Follow a single boid (by id, so it survives index changes)
+    CenterOfMass,       // Follow the (wrap-aware) centroid of the whole flock
+}
+
+// A smooth camera pan from one world position to another
+#[derive(Clone, Copy, Debug)]
+pub struct PanAnimation {
+    pub start: Vec2,
+    pub target: Vec2,
+    pub elapsed: f32,
+    pub duration: f32,
+}
 
 pub struct Camera {
     pub position: Vec2,
@@ -21,7 +46,14 @@ pub struct Camera {
     pub max_zoom: f32,
     pub is_dragging: bool,
     pub last_cursor_pos: Vec2,
-    pub follow_mode: bool,  // Whether the camera is following a boid
+    pub follow_target: FollowTarget,  // What the camera is following
+    pub pan_animation: Option<PanAnimation>,  // Active smooth pan, if any
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Camera {
@@ -34,8 +66,24 @@ impl Camera {
             max_zoom: 5.0,
             is_dragging: false,
             last_cursor_pos: Vec2::ZERO,
-            follow_mode: false,  // Initially not following any boid
+            follow_target: FollowTarget::None,  // Initially not following anything
+            pan_animation: None,
+        }
+    }
+
+    // Whether the camera is currently following a target
+    pub fn is_following(&self) -> bool {
+        self.follow_target != FollowTarget::None
+    }
+
+    // Hash of everything that decides which part of the world is shown: the position,
+    // the zoom and the window size
+    pub fn state_hash(&self, window_rect: Rect) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for value in [self.position.x, self.position.y, self.zoom, window_rect.w(), window_rect.h()] {
+            value.to_bits().hash(&mut hasher);
         }
+        hasher.finish()
     }
 
     // Convert a point from world space to screen space
@@ -54,10 +102,29 @@ impl Camera {
         centered / self.zoom + self.position
     }
 
+    // The part of the world shown in the window
+    pub fn visible_world_rect(&self, window_rect: Rect) -> Rect {
+        Rect::from_xy_wh(self.position, window_rect.wh() / self.zoom)
+    }
+
+    // Adjust the zoom for a window resized from old_size to new_size, so the view keeps
+    // showing the same amount of world around the same center. Only its shape follows the
+    // window's new aspect ratio, and resizing back restores the old zoom.
+    pub fn keep_view_on_resize(&mut self, old_size: Vec2, new_size: Vec2) {
+        let (old_area, new_area) = (old_size.x * old_size.y, new_size.x * new_size.y);
+        // A minimized window shows nothing to keep
+        if old_area <= 0.0 || new_area <= 0.0 {
+            return;
+        }
+        
+        self.zoom = (self.zoom * (new_area / old_area).sqrt()).clamp(self.min_zoom, self.max_zoom);
+    }
+
     // Handle mouse wheel events for zooming
-    pub fn zoom(&mut self, scroll_delta: Vec2, cursor_position: Vec2, window_rect: Rect) {
-        // Calculate zoom factor based on scroll amount
-        let zoom_factor = 1.0 + scroll_delta.y * 0.1;
+    // scroll_delta is measured in wheel lines; sensitivity is the zoom change per line
+    pub fn zoom(&mut self, scroll_delta: Vec2, cursor_position: Vec2, window_rect: Rect, sensitivity: f32) {
+        // Calculate zoom factor based on scroll amount (exponential so large deltas can't flip the sign)
+        let zoom_factor = (scroll_delta.y * sensitivity).exp();
         
         // Calculate cursor position in world space before zoom
         let cursor_world_before = self.screen_to_world(cursor_position, window_rect);
@@ -72,8 +139,60 @@ impl Camera {
         self.position += cursor_world_before - cursor_world_after;
     }
 
+    // Pan the camera by a screen-space offset (scaled so movement is constant on screen)
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.position += screen_delta / self.zoom;
+    }
+
+    // Center the camera on the given world-space bounds and zoom so they fit the window
+    pub fn fit_to_bounds(&mut self, bounds: Rect, window_rect: Rect) {
+        // Leave a ~10% margin around the bounds
+        let margin_factor = 1.1;
+        
+        // Pick the zoom that fits the limiting axis
+        let zoom_x = window_rect.w() / (bounds.w() * margin_factor);
+        let zoom_y = window_rect.h() / (bounds.h() * margin_factor);
+        
+        self.position = bounds.xy();
+        self.zoom = zoom_x.min(zoom_y).clamp(self.min_zoom, self.max_zoom);
+    }
+
+    // Start a smooth pan to a world position without changing zoom
+    pub fn start_pan_to(&mut self, target: Vec2, duration: f32) {
+        self.pan_animation = Some(PanAnimation {
+            start: self.position,
+            target,
+            elapsed: 0.0,
+            duration,
+        });
+    }
+
+    // Advance the smooth pan animation, returning true if the camera moved
+    pub fn update_pan_animation(&mut self, dt: f32) -> bool {
+        let animation = match self.pan_animation.as_mut() {
+            Some(animation) => animation,
+            None => return false,
+        };
+        
+        animation.elapsed += dt;
+        let t = (animation.elapsed / animation.duration).clamp(0.0, 1.0);
+        
+        // Smoothstep easing for a gentle start and stop
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.position = animation.start + (animation.target - animation.start) * eased;
+        
+        if t >= 1.0 {
+            self.pan_animation = None;
+        }
+        
+        true
+    }
+
     // Start dragging the camera
     pub fn start_drag(&mut self, position: Vec2) {
+        // Dragging cancels any running pan animation
+        self.pan_animation = None;
+        
         // Only set the drag start position, don't move the camera yet
         self.drag_start = Some(position);
         self.last_cursor_pos = position;
@@ -99,4 +218,154 @@ impl Camera {
         self.drag_start = None;
         self.is_dragging = false;
     }
-} 
\ No newline at end of file
+    
+    // Keep a followed target in view, moving only as far as needed to bring it back
+    // inside the deadzone: the part of the window within `deadzone` (a fraction of the
+    // window size) of the center on each axis. A deadzone of 0 pins the target to the
+    // center. Returns whether the camera moved.
+    pub fn follow(&mut self, target: Vec2, window_rect: Rect, deadzone: f32) -> bool {
+        let half_extent = window_rect.wh() * deadzone / self.zoom;
+        let offset = target - self.position;
+        let outside = offset - offset.clamp(-half_extent, half_extent);
+        
+        if outside == Vec2::ZERO {
+            return false;
+        }
+        
+        self.position += outside;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "expected {} to be approximately {}", a, b);
+    }
+
+    #[test]
+    fn fit_to_bounds_wide_window_is_limited_by_height() {
+        let mut camera = Camera::new();
+        let bounds = Rect::from_x_y_w_h(100.0, -50.0, 1000.0, 1000.0);
+        let window_rect = Rect::from_w_h(1600.0, 800.0);
+        
+        camera.fit_to_bounds(bounds, window_rect);
+        
+        assert_approx_eq(camera.position.x, 100.0);
+        assert_approx_eq(camera.position.y, -50.0);
+        assert_approx_eq(camera.zoom, 800.0 / 1100.0);
+    }
+
+    #[test]
+    fn fit_to_bounds_tall_window_is_limited_by_width() {
+        let mut camera = Camera::new();
+        let bounds = Rect::from_x_y_w_h(0.0, 0.0, 1000.0, 1000.0);
+        let window_rect = Rect::from_w_h(800.0, 1600.0);
+        
+        camera.fit_to_bounds(bounds, window_rect);
+        
+        assert_approx_eq(camera.zoom, 800.0 / 1100.0);
+    }
+
+    #[test]
+    fn fit_to_bounds_square_window_with_wide_bounds() {
+        let mut camera = Camera::new();
+        let bounds = Rect::from_x_y_w_h(0.0, 0.0, 2000.0, 500.0);
+        let window_rect = Rect::from_w_h(1000.0, 1000.0);
+        
+        camera.fit_to_bounds(bounds, window_rect);
+        
+        assert_approx_eq(camera.zoom, 1000.0 / 2200.0);
+    }
+
+    #[test]
+    fn fit_to_bounds_clamps_zoom() {
+        let mut camera = Camera::new();
+        let window_rect = Rect::from_w_h(1200.0, 900.0);
+        
+        // Tiny bounds would need a huge zoom
+        camera.fit_to_bounds(Rect::from_w_h(1.0, 1.0), window_rect);
+        assert_approx_eq(camera.zoom, camera.max_zoom);
+        
+        // Huge bounds would need a tiny zoom
+        camera.fit_to_bounds(Rect::from_w_h(1.0e6, 1.0e6), window_rect);
+        assert_approx_eq(camera.zoom, camera.min_zoom);
+    }
+
+    #[test]
+    fn follow_only_moves_once_the_target_leaves_the_deadzone() {
+        let mut camera = Camera::new();
+        camera.zoom = 2.0;
+        let window_rect = Rect::from_w_h(1000.0, 800.0);
+        
+        // The deadzone reaches 100 x 80 world units from the center at this zoom
+        assert!(!camera.follow(vec2(90.0, -70.0), window_rect, 0.2));
+        assert_eq!(camera.position, Vec2::ZERO);
+        
+        // Leaving it drags the camera just far enough to put the target on its edge
+        assert!(camera.follow(vec2(130.0, -70.0), window_rect, 0.2));
+        assert_approx_eq(camera.position.x, 30.0);
+        assert_approx_eq(camera.position.y, 0.0);
+        
+        // Without a deadzone the target stays centered
+        camera.follow(vec2(-5.0, 5.0), window_rect, 0.0);
+        assert_eq!(camera.position, vec2(-5.0, 5.0));
+    }
+
+    #[test]
+    fn state_hash_changes_with_the_view_only() {
+        let mut camera = Camera::new();
+        let window_rect = Rect::from_w_h(1000.0, 800.0);
+        let hash = camera.state_hash(window_rect);
+        
+        // Dragging state doesn't change what is shown
+        camera.is_dragging = true;
+        assert_eq!(camera.state_hash(window_rect), hash);
+        
+        camera.zoom = 2.0;
+        assert_ne!(camera.state_hash(window_rect), hash);
+        camera.zoom = 1.0;
+        assert_eq!(camera.state_hash(window_rect), hash);
+        
+        assert_ne!(camera.state_hash(Rect::from_w_h(1000.0, 600.0)), hash);
+        camera.position.x = 10.0;
+        assert_ne!(camera.state_hash(window_rect), hash);
+    }
+
+    #[test]
+    fn resizing_keeps_the_visible_world_area() {
+        let mut camera = Camera::new();
+        camera.position = vec2(200.0, -100.0);
+        camera.zoom = 2.0;
+        let (old_size, new_size) = (vec2(1000.0, 800.0), vec2(1600.0, 500.0));
+        let old_view = camera.visible_world_rect(Rect::from_wh(old_size));
+
+        // A wider, shorter window shows as much of the world, around the same center
+        camera.keep_view_on_resize(old_size, new_size);
+        let new_view = camera.visible_world_rect(Rect::from_wh(new_size));
+        assert_approx_eq(new_view.w() * new_view.h(), old_view.w() * old_view.h());
+        assert_eq!(new_view.xy(), old_view.xy());
+        assert_approx_eq(new_view.w() / new_view.h(), 1600.0 / 500.0);
+
+        // Resizing back restores the old view
+        camera.keep_view_on_resize(new_size, old_size);
+        assert_approx_eq(camera.zoom, 2.0);
+    }
+
+    #[test]
+    fn scaling_the_window_keeps_the_same_view() {
+        let mut camera = Camera::new();
+        let old_view = camera.visible_world_rect(Rect::from_w_h(800.0, 600.0));
+
+        camera.keep_view_on_resize(vec2(800.0, 600.0), vec2(1200.0, 900.0));
+        let new_view = camera.visible_world_rect(Rect::from_w_h(1200.0, 900.0));
+        assert_approx_eq(new_view.w(), old_view.w());
+        assert_approx_eq(new_view.h(), old_view.h());
+
+        // Minimizing the window changes nothing
+        camera.keep_view_on_resize(vec2(1200.0, 900.0), Vec2::ZERO);
+        assert_approx_eq(camera.zoom, 1.5);
+    }
+}
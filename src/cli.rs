@@ -0,0 +1,220 @@
+/*
+ * Command-Line Module
+ * 
+ * This module parses command-line arguments into overrides that are applied on top
+ * of the default simulation parameters before any boids are created. It allows
+ * scripted and benchmark runs without touching the UI.
+ */
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::breeds;
+use crate::config;
+use crate::params::SimulationParams;
+use crate::scenario::Scenario;
+use crate::simulation::Simulation;
+
+// Usage text printed for --help and for invalid arguments
+pub const USAGE: &str = "\
+Usage: boids [OPTIONS]
+
+Options:
+  --boids <N>             Number of boids
+  --world-size <SIZE>     World size in units
+  --seed <SEED>           Seed for reproducible boid placement
+  --no-parallel           Disable parallel processing
+  --threads <N>           Run the physics on N threads (0 = one per core)
+  --paused                Start with the simulation paused
+  --preset <PATH>         Load parameters from a preset file (key = value per line)
+  --watch-params <PATH>   Apply a preset file again whenever it changes, while the simulation runs
+  --breeds <PATH>         Load the boid breeds from this file instead of breeds.toml
+  --scenario <PATH>       Start from a scenario file (seed, steps and parameters) instead of the saved settings
+  --verify                Run the scenario's steps without a window and print the state hash
+  --config <PATH>         Read and save settings in this file instead of boids.toml
+  --stream <PORT>         Stream boid positions to UDP subscribers on this port
+  --bench-mode            Run physics steps without a window, print timing and exit
+  --steps <N>             Number of steps in bench mode (default 1000)
+  --json                  Print bench mode results as JSON
+  --headless-steps <N>    Same as --bench-mode --steps <N>
+  -h, --help              Print this help";
+
+// Overrides collected from the command line
+#[derive(Clone, Debug, Default)]
+pub struct CliOverrides {
+    pub boids: Option<usize>,
+    pub world_size: Option<f32>,
+    pub seed: Option<u64>,
+    pub no_parallel: bool,
+    pub threads: Option<usize>,
+    pub paused: bool,
+    pub preset: Option<PathBuf>,
+    pub watch_params: Option<PathBuf>,
+    pub breeds: Option<PathBuf>,
+    pub scenario: Option<PathBuf>,
+    pub verify: bool,
+    pub config: Option<PathBuf>,
+    pub stream_port: Option<u16>,
+    pub bench_mode: bool,
+    pub steps: Option<usize>,
+    pub json: bool,
+}
+
+// Physics steps run by --bench-mode when --steps is not given
+pub const DEFAULT_BENCH_STEPS: usize = 1000;
+
+// Result of parsing the command line (the overrides are boxed, as they dwarf Help)
+pub enum CliCommand {
+    Run(Box<CliOverrides>),
+    Help,
+}
+
+// Overrides for the windowed app (nannou's model function can't take extra arguments)
+static OVERRIDES: OnceLock<CliOverrides> = OnceLock::new();
+
+// Store the overrides for app::model to pick up
+pub fn set_overrides(overrides: CliOverrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+// The overrides given on the command line, or none if they were never set
+pub fn overrides() -> CliOverrides {
+    OVERRIDES.get().cloned().unwrap_or_default()
+}
+
+// Parse the arguments (excluding the program name)
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<CliCommand, String> {
+    let mut overrides = CliOverrides::default();
+    let mut args = args.into_iter();
+    
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--boids" => overrides.boids = Some(parse_value(&arg, args.next())?),
+            "--world-size" => overrides.world_size = Some(parse_value(&arg, args.next())?),
+            "--seed" => overrides.seed = Some(parse_value(&arg, args.next())?),
+            "--no-parallel" => overrides.no_parallel = true,
+            "--threads" => overrides.threads = Some(parse_value(&arg, args.next())?),
+            "--paused" => overrides.paused = true,
+            "--preset" => overrides.preset = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--watch-params" => overrides.watch_params = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--breeds" => overrides.breeds = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--scenario" => overrides.scenario = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--verify" => overrides.verify = true,
+            "--config" => overrides.config = Some(PathBuf::from(parse_value::<String>(&arg, args.next())?)),
+            "--stream" => overrides.stream_port = Some(parse_value(&arg, args.next())?),
+            "--bench-mode" => overrides.bench_mode = true,
+            "--steps" => overrides.steps = Some(parse_value(&arg, args.next())?),
+            "--json" => overrides.json = true,
+            "--headless-steps" => {
+                overrides.bench_mode = true;
+                overrides.steps = Some(parse_value(&arg, args.next())?);
+            }
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    
+    Ok(CliCommand::Run(Box::new(overrides)))
+}
+
+// Parse the value following a flag
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+impl CliOverrides {
+    // Number of steps to run without a window (--steps, then the scenario's steps), or None
+    // to open the window
+    pub fn bench_steps(&self) -> Result<Option<usize>, String> {
+        if !self.bench_mode && !self.verify {
+            return Ok(None);
+        }
+        
+        let scenario_steps = self.load_scenario()?.map(|scenario| scenario.steps);
+        Ok(Some(self.steps.or(scenario_steps).unwrap_or(DEFAULT_BENCH_STEPS)))
+    }
+    
+    // The scenario given with --scenario, if any
+    pub fn load_scenario(&self) -> Result<Option<Scenario>, String> {
+        self.scenario.as_deref().map(Scenario::load).transpose()
+    }
+    
+    // The config file the windowed app reads and saves its settings in
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(config::default_path)
+    }
+    
+    // The breeds file: --breeds, or breeds.toml in the working directory if there is one.
+    // Scenarios only use an explicit file, so every run of them starts from the same world.
+    pub fn breeds_path(&self) -> Option<PathBuf> {
+        self.breeds.clone().or_else(|| {
+            let path = PathBuf::from(breeds::BREEDS_FILE_NAME);
+            (self.scenario.is_none() && path.exists()).then_some(path)
+        })
+    }
+    
+    // Build the simulation parameters: defaults, then the preset file, then the flags
+    pub fn build_params(&self) -> Result<SimulationParams, String> {
+        self.build_params_from(SimulationParams::default())
+    }
+    
+    // Build the simulation parameters on top of `params` (e.g. the saved config).
+    // A scenario replaces `params`, so every run of it starts from the same world.
+    pub fn build_params_from(&self, mut params: SimulationParams) -> Result<SimulationParams, String> {
+        if let Some(scenario) = self.load_scenario()? {
+            params = scenario.params;
+        }
+        
+        if let Some(path) = self.breeds_path() {
+            params.set_breeds(breeds::load(&path)?);
+        }
+        
+        if let Some(path) = &self.preset {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("failed to read preset {}: {}", path.display(), err))?;
+            params.apply_preset(&text)
+                .map_err(|err| format!("invalid preset {}: {}", path.display(), err))?;
+        }
+        
+        if let Some(boids) = self.boids {
+            let range = SimulationParams::get_num_boids_range();
+            params.num_boids = boids.clamp(*range.start(), *range.end());
+        }
+        if let Some(world_size) = self.world_size {
+            let range = SimulationParams::get_world_size_range();
+            params.world_size = world_size.clamp(*range.start(), *range.end());
+        }
+        if self.no_parallel {
+            params.enable_parallel = false;
+        }
+        if let Some(threads) = self.threads {
+            params.num_threads = threads.min(*SimulationParams::get_num_threads_range().end());
+        }
+        if self.paused {
+            params.pause_simulation = true;
+        }
+        if let Some(port) = self.stream_port {
+            params.enable_streaming = true;
+            params.stream_port = port;
+        }
+        
+        Ok(params)
+    }
+    
+    // Build the simulation from the overrides, seeded if a seed (or a scenario) was given
+    pub fn build_simulation(&self) -> Result<Simulation, String> {
+        self.build_simulation_from(SimulationParams::default())
+    }
+    
+    // Build the simulation with the overrides applied on top of `params`
+    pub fn build_simulation_from(&self, params: SimulationParams) -> Result<Simulation, String> {
+        let params = self.build_params_from(params)?;
+        let scenario_seed = self.load_scenario()?.map(|scenario| scenario.seed);
+        
+        Ok(match self.seed.or(scenario_seed) {
+            Some(seed) => Simulation::with_seed(params, seed),
+            None => Simulation::new(params),
+        })
+    }
+}
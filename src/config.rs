@@ -0,0 +1,267 @@
+/*
+ * Config Module
+ * 
+ * This module persists the user's preferred settings between runs in a `boids.toml`
+ * file: the simulation parameters plus the camera, window and controls layout. It
+ * uses the same flat TOML subset as presets, with the other settings in `[camera]`,
+ * `[window]` and `[ui]` sections.
+ * 
+ * The file is read once at startup and written back when the window closes (or
+ * immediately from the UI's "Save as Default" button). Keys missing from the file
+ * keep their default values, so older files keep working as parameters are added.
+ */
+
+use nannou::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::camera::Camera;
+use crate::params::{self, SimulationParams};
+use crate::ui::{UiLayout, UiSection};
+
+// File name looked for next to the executable and in the config directory
+pub const CONFIG_FILE_NAME: &str = "boids.toml";
+
+// Directory (relative to the working directory) holding the presets offered in the UI
+pub const PRESET_DIR: &str = "presets";
+
+// Settings stored in the config file
+#[derive(Clone)]
+pub struct Config {
+    pub params: SimulationParams,
+    pub camera_position: Vec2,
+    pub camera_zoom: f32,
+    pub window_size: Option<(u32, u32)>, // None = sized from the monitor
+    pub ui_layout: UiLayout,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let camera = Camera::new();
+        
+        Self {
+            params: SimulationParams::default(),
+            camera_position: camera.position,
+            camera_zoom: camera.zoom,
+            window_size: None,
+            ui_layout: UiLayout::default(),
+        }
+    }
+}
+
+impl Config {
+    // Parse a config file on top of the defaults
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut section = "";
+        let mut window_width = None;
+        let mut window_height = None;
+        
+        for (line_number, line) in text.lines().enumerate() {
+            let content = line.split('#').next().unwrap_or("").trim();
+            if let Some(name) = content.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+            
+            let Some((key, value)) = params::parse_preset_line(line)
+                .map_err(|err| format!("line {}: {}", line_number + 1, err))? else {
+                continue;
+            };
+            
+            let parse_f32 = |value: &str| value.parse::<f32>()
+                .map_err(|_| format!("invalid number for {}: {}", key, value));
+            let parse_u32 = |value: &str| value.parse::<u32>()
+                .map_err(|_| format!("invalid integer for {}: {}", key, value));
+            let parse_bool = |value: &str| value.parse::<bool>()
+                .map_err(|_| format!("invalid boolean for {}: {}", key, value));
+            
+            let result = match (section, key) {
+                ("", _) => config.params.set_preset_value(key, value),
+                ("camera", "x") => parse_f32(value).map(|x| config.camera_position.x = x),
+                ("camera", "y") => parse_f32(value).map(|y| config.camera_position.y = y),
+                ("camera", "zoom") => parse_f32(value).map(|zoom| config.camera_zoom = zoom),
+                ("window", "width") => parse_u32(value).map(|width| window_width = Some(width)),
+                ("window", "height") => parse_u32(value).map(|height| window_height = Some(height)),
+                ("ui", "window_open") => parse_bool(value).map(|open| config.ui_layout.window_open = open),
+                ("ui", "compact") => parse_bool(value).map(|compact| config.ui_layout.compact = compact),
+                ("ui", _) => match UiSection::ALL.into_iter().find(|ui_section| ui_section.key() == key) {
+                    Some(ui_section) => parse_bool(value).map(|open| config.ui_layout.set_open(ui_section, open)),
+                    None => Err(format!("unknown ui setting {}", key)),
+                },
+                ("camera", _) | ("window", _) => Err(format!("unknown {} setting {}", section, key)),
+                _ => Err(format!("unknown section [{}]", section)),
+            };
+            result.map_err(|err| format!("line {}: {}", line_number + 1, err))?;
+        }
+        
+        config.params.finish_preset();
+        
+        // A window size is only used if both dimensions are given
+        if let (Some(width), Some(height)) = (window_width, window_height) {
+            config.window_size = Some((width.max(1), height.max(1)));
+        }
+        
+        Ok(config)
+    }
+    
+    // Write the settings in the format read by parse
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from("# Boid simulation settings, written on exit and by \"Save as Default\"\n");
+        text.push_str(&self.params.to_preset());
+        
+        text.push_str("\n[camera]\n");
+        text.push_str(&format!("x = {}\ny = {}\nzoom = {}\n", self.camera_position.x, self.camera_position.y, self.camera_zoom));
+        
+        if let Some((width, height)) = self.window_size {
+            text.push_str("\n[window]\n");
+            text.push_str(&format!("width = {}\nheight = {}\n", width, height));
+        }
+        
+        // The controls window, and which of its sections are open
+        text.push_str("\n[ui]\n");
+        text.push_str(&format!("window_open = {}\ncompact = {}\n", self.ui_layout.window_open, self.ui_layout.compact));
+        for ui_section in UiSection::ALL {
+            text.push_str(&format!("{} = {}\n", ui_section.key(), self.ui_layout.is_open(ui_section)));
+        }
+        
+        text
+    }
+    
+    // Read the config file. A missing file gives the defaults; an unreadable or
+    // invalid one gives the defaults with a warning.
+    pub fn load_or_default(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|err| {
+                eprintln!("warning: ignoring invalid config {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                eprintln!("warning: failed to read config {}: {}", path.display(), err);
+                Self::default()
+            }
+        }
+    }
+    
+    // Write the config file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        
+        fs::write(path, self.to_toml())
+    }
+}
+
+// Preset files (*.toml) in a directory, sorted by name. A missing directory has none.
+pub fn preset_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "toml"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+// Where the config file lives when --config isn't given: next to the executable if
+// there is one there, otherwise in the XDG config directory ($XDG_CONFIG_HOME/boids,
+// or ~/.config/boids)
+pub fn default_path() -> Option<PathBuf> {
+    let beside_executable = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(CONFIG_FILE_NAME)));
+    if beside_executable.as_ref().is_some_and(|path| path.is_file()) {
+        return beside_executable;
+    }
+    
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    
+    config_dir.map(|dir| dir.join("boids").join(CONFIG_FILE_NAME)).or(beside_executable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boid::BoidShape;
+    use crate::palette::Palette;
+
+    #[test]
+    fn saved_config_parses_back_to_the_same_settings() {
+        let mut config = Config::default();
+        config.params.num_boids = 1234;
+        config.params.separation_weight = 2.25;
+        config.params.enable_parallel = false;
+        config.params.time_scale = 0.5;
+        config.params.boid_shape = BoidShape::Dart;
+        config.camera_position = vec2(-120.5, 40.0);
+        config.camera_zoom = 2.5;
+        config.window_size = Some((1280, 720));
+        config.ui_layout.compact = true;
+        config.ui_layout.window_open = false;
+        config.ui_layout.set_open(UiSection::Forces, false);
+        config.ui_layout.set_open(UiSection::Timing, true);
+
+        let parsed = Config::parse(&config.to_toml()).unwrap();
+
+        assert_eq!(parsed.params.num_boids, 1234);
+        assert_eq!(parsed.params.separation_weight, 2.25);
+        assert!(!parsed.params.enable_parallel);
+        assert_eq!(parsed.params.time_scale, 0.5);
+        assert_eq!(parsed.params.boid_shape, BoidShape::Dart);
+        assert_eq!(parsed.camera_position, vec2(-120.5, 40.0));
+        assert_eq!(parsed.camera_zoom, 2.5);
+        assert_eq!(parsed.window_size, Some((1280, 720)));
+        assert_eq!(parsed.ui_layout, config.ui_layout);
+    }
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let defaults = Config::default();
+
+        let parsed = Config::parse("num_boids = 42\n\n[camera]\nzoom = 3\n").unwrap();
+
+        assert_eq!(parsed.params.num_boids, 42);
+        assert_eq!(parsed.params.world_size, defaults.params.world_size);
+        assert_eq!(parsed.params.max_steps_per_frame, defaults.params.max_steps_per_frame);
+        assert_eq!(parsed.camera_zoom, 3.0);
+        assert_eq!(parsed.camera_position, defaults.camera_position);
+        assert_eq!(parsed.window_size, None);
+    }
+
+    #[test]
+    fn invalid_values_and_unknown_sections_are_errors() {
+        assert!(Config::parse("num_boids = many").is_err());
+        assert!(Config::parse("[camera]\nrotation = 1").is_err());
+        assert!(Config::parse("[audio]\nvolume = 1").is_err());
+        assert!(Config::parse("[ui]\nsidebar = true").is_err());
+    }
+
+    #[test]
+    fn palettes_are_saved_and_recolor_the_default_species() {
+        let mut config = Config::default();
+        config.params.add_species();
+        let custom_species_color = config.params.species[1].color;
+        config.params.set_palette(Palette::Neon);
+        config.params.colors.background = [1, 2, 3];
+        config.params.palette = Palette::Custom;
+
+        // Only the species drawn in the old boid color follow the palette
+        let neon_boid = Palette::Neon.colors().unwrap().boid;
+        assert_eq!(config.params.species[0].color, neon_boid);
+        assert_eq!(config.params.species[1].color, custom_species_color);
+
+        let parsed = Config::parse(&config.to_toml()).unwrap();
+
+        assert_eq!(parsed.params.palette, Palette::Custom);
+        assert_eq!(parsed.params.colors, config.params.colors);
+        assert_eq!(parsed.params.species[0].color, neon_boid);
+        assert!(Config::parse("boid_color = \"#ffffff\"").is_err());
+    }
+}
@@ -7,101 +7,132 @@
  * Optimized for performance by:
  * - Using spatial grid for efficient culling when available
  * - Caching visible boids when the simulation is paused
- * - Using visibility flags to avoid duplicate processing
+ * - Collecting the grid cells' boids in parallel, one row of cells per task
+ * - Skipping the position test for cells entirely inside the visible area
+ * - A render budget that draws only every k-th visible boid while drawing is too slow
+ * 
+ * The visible area is grown by a margin covering the boid size and, with interpolation,
+ * the distance a boid can move between two snapshots: the spatial grid holds the latest
+ * positions while boids are drawn between the previous and the latest one.
  */
 
 use nannou::prelude::*;
+use rayon::prelude::*;
 
 use crate::app::Model;
-use crate::boid::Boid;
+use std::time::Duration;
+
+use crate::boid::{boid_render_position, Boid};
+use crate::camera::Camera;
+use crate::flock::BoidId;
+use crate::params::SimulationParams;
+use crate::spatial_grid::SpatialGrid;
+
+// Distance by which the visible area is grown before culling, in world units, for
+// interpolation spanning the given number of physics steps (0 without interpolation)
+pub fn culling_margin(params: &SimulationParams, steps: usize) -> f32 {
+    let max_speed = params.species.iter().map(|species| species.max_speed).fold(params.max_speed, f32::max);
+    let boid_size = params.species.iter().map(|species| params.boid_size * species.size).fold(params.boid_size, f32::max);
+    (max_speed * steps as f32 + boid_size) * params.culling_margin_multiplier
+}
+
+// A rectangle grown by the margin on every side
+pub fn expand_rect(rect: Rect, margin: f32) -> Rect {
+    Rect::from_corners(
+        pt2(rect.left() - margin, rect.bottom() - margin),
+        pt2(rect.right() + margin, rect.top() + margin)
+    )
+}
+
+// Culling area of the last rendered frame, and the state it was computed for
+#[derive(Clone, Copy, Debug)]
+pub struct VisibleAreaCache {
+    pub camera_state: u64, // See Camera::state_hash
+    pub margin: f32,
+    pub area: Rect,
+}
+
+// The part of the world shown in the window
+pub fn window_area(camera: &Camera, window_rect: Rect) -> Rect {
+    Rect::from_corners(
+        camera.screen_to_world(pt2(window_rect.left(), window_rect.bottom()), window_rect),
+        camera.screen_to_world(pt2(window_rect.right(), window_rect.top()), window_rect)
+    )
+}
+
+// The visible area grown by the margin, reusing the last frame's area while the camera,
+// window and margin are unchanged
+pub fn visible_area(model: &Model, window_rect: Rect, margin: f32) -> Rect {
+    let camera_state = model.camera.state_hash(window_rect);
+    let mut cache = model.visible_area_cache.lock().unwrap();
+    
+    match *cache {
+        Some(cached) if cached.camera_state == camera_state && cached.margin == margin => cached.area,
+        _ => {
+            let area = expand_rect(window_area(&model.camera, window_rect), margin);
+            *cache = Some(VisibleAreaCache { camera_state, margin, area });
+            area
+        }
+    }
+}
 
 // Efficient function to get visible boids using the best available method
 pub fn get_visible_boids(model: &Model, visible_area: Rect) -> Vec<usize> {
     // Use cached visible boids if available and simulation is paused
     if model.params.pause_simulation {
-        unsafe {
-            if let Some(cached_indices) = &*model.cached_visible_boids.get() {
-                return cached_indices.clone();
-            }
+        if let Some(cached_indices) = &*model.cached_visible_boids.lock().unwrap() {
+            return cached_indices.clone();
         }
     }
     
+    let boids = &model.view.boids;
+    let alpha = model.view.interpolation_alpha;
+    
     // Choose the most efficient culling method based on available optimizations
     let indices = if model.params.enable_spatial_grid {
         // Use spatial grid for efficient culling
-        cull_with_spatial_grid(model, visible_area)
+        cull_with_spatial_grid(boids, &model.view.spatial_grid, &model.params, alpha, visible_area)
     } else {
         // Use brute force culling
-        cull_brute_force(model, visible_area)
+        cull_brute_force(boids, &model.params, alpha, visible_area)
     };
     
     // Cache the indices if simulation is paused
     if model.params.pause_simulation {
-        unsafe {
-            *model.cached_visible_boids.get() = Some(indices.clone());
-        }
+        *model.cached_visible_boids.lock().unwrap() = Some(indices.clone());
     }
     
     indices
 }
 
 // Brute force culling method
-pub fn cull_brute_force(model: &Model, visible_area: Rect) -> Vec<usize> {
+pub fn cull_brute_force(boids: &[Boid], params: &SimulationParams, alpha: f32, visible_area: Rect) -> Vec<usize> {
     let mut visible_indices = Vec::new();
     
-    // Reset visibility flags for all boids
-    for boid in &model.boids {
-        unsafe {
-            // Use raw pointer to modify the boid without borrowing issues
-            let boid_ptr = boid as *const Boid as *mut Boid;
-            (*boid_ptr).is_visible = false;
-        }
-    }
-    
     // Check each boid for visibility
-    for (i, boid) in model.boids.iter().enumerate() {
-        let pos = if model.params.enable_interpolation {
-            let interpolated_pos = boid.get_interpolated_position(model.interpolation_alpha);
-            Vec2::new(interpolated_pos.x, interpolated_pos.y)
-        } else {
-            Vec2::new(boid.position.x, boid.position.y)
-        };
-        
-        if visible_area.contains(pos) {
+    for (i, boid) in boids.iter().enumerate() {
+        if visible_area.contains(boid_render_position(boid, params, alpha)) {
             visible_indices.push(i);
-            
-            // Mark as visible
-            unsafe {
-                let boid_ptr = boid as *const Boid as *mut Boid;
-                (*boid_ptr).is_visible = true;
-            }
         }
     }
     
     visible_indices
 }
 
-// Use spatial grid for efficient culling
-pub fn cull_with_spatial_grid(model: &Model, visible_area: Rect) -> Vec<usize> {
-    // Reset visibility flags for all boids
-    for boid in &model.boids {
-        unsafe {
-            // Use raw pointer to modify the boid without borrowing issues
-            let boid_ptr = boid as *const Boid as *mut Boid;
-            (*boid_ptr).is_visible = false;
-        }
-    }
-    
+// Use spatial grid for efficient culling. Each boid is in exactly one cell, so the
+// cells' boids can be collected without deduplication, one row of cells per rayon task.
+pub fn cull_with_spatial_grid(boids: &[Boid], spatial_grid: &SpatialGrid, params: &SimulationParams, alpha: f32, visible_area: Rect) -> Vec<usize> {
     // Convert visible area to grid cells
-    let half_world = model.params.world_size / 2.0;
-    let cell_size = model.spatial_grid.cell_size;
-    let grid_size = model.spatial_grid.grid_size;
+    let half_world = params.world_size / 2.0;
+    let cell_size = spatial_grid.cell_size;
+    let grid_size = spatial_grid.grid_size;
+    let to_cell = |coordinate: f32| (coordinate + half_world) / cell_size;
     
     // Calculate grid cell ranges that overlap with the visible area
-    let min_grid_x = ((visible_area.left() + half_world) / cell_size).floor() as isize;
-    let min_grid_y = ((visible_area.bottom() + half_world) / cell_size).floor() as isize;
-    let max_grid_x = ((visible_area.right() + half_world) / cell_size).ceil() as isize;
-    let max_grid_y = ((visible_area.top() + half_world) / cell_size).ceil() as isize;
+    let min_grid_x = to_cell(visible_area.left()).floor() as isize;
+    let min_grid_y = to_cell(visible_area.bottom()).floor() as isize;
+    let max_grid_x = to_cell(visible_area.right()).ceil() as isize;
+    let max_grid_y = to_cell(visible_area.top()).ceil() as isize;
     
     // Clamp to grid boundaries
     let min_grid_x = min_grid_x.clamp(0, grid_size as isize - 1);
@@ -109,53 +140,175 @@ pub fn cull_with_spatial_grid(model: &Model, visible_area: Rect) -> Vec<usize> {
     let max_grid_x = max_grid_x.clamp(0, grid_size as isize - 1);
     let max_grid_y = max_grid_y.clamp(0, grid_size as isize - 1);
     
-    // Collect boids from all cells that overlap with the visible area
-    let mut visible_indices = Vec::with_capacity(
-        ((max_grid_x - min_grid_x + 1) * (max_grid_y - min_grid_y + 1) * 10) as usize
-    );
+    // Cells entirely inside the visible area, whose boids need no position test (their
+    // drawn positions are at most one snapshot's movement away, which the margin covers)
+    let interior_x = to_cell(visible_area.left()).ceil() as isize..to_cell(visible_area.right()).floor() as isize;
+    let interior_y = to_cell(visible_area.bottom()).ceil() as isize..to_cell(visible_area.top()).floor() as isize;
     
-    for grid_y in min_grid_y..=max_grid_y {
+    let rows: Vec<Vec<usize>> = (min_grid_y..=max_grid_y).into_par_iter().map(|grid_y| {
         let y_index = grid_y as usize * grid_size;
+        let interior_row = interior_y.contains(&grid_y);
+        let mut row_indices = Vec::new();
         
         for grid_x in min_grid_x..=max_grid_x {
-            let cell_index = y_index + grid_x as usize;
+            let cell = match spatial_grid.grid.get(y_index + grid_x as usize) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            // Safety check: ensure boid_index is valid
+            let cell_boids = cell.iter().copied().filter(|&boid_index| boid_index < boids.len());
             
-            // Add all boids in this cell
-            if cell_index < model.spatial_grid.grid.len() {
-                for &boid_index in &model.spatial_grid.grid[cell_index] {
-                    // Safety check: ensure boid_index is valid
-                    if boid_index >= model.boids.len() {
-                        continue;
-                    }
-                    
-                    // Skip if already marked as visible
-                    if model.boids[boid_index].is_visible {
-                        continue;
-                    }
-                    
-                    // For cells at the boundary, we need to check if the boid is actually visible
-                    let is_visible = if model.params.enable_interpolation {
-                        let interpolated_pos = model.boids[boid_index].get_interpolated_position(model.interpolation_alpha);
-                        let pos = Vec2::new(interpolated_pos.x, interpolated_pos.y);
-                        visible_area.contains(pos)
-                    } else {
-                        let pos = Vec2::new(model.boids[boid_index].position.x, model.boids[boid_index].position.y);
-                        visible_area.contains(pos)
-                    };
-                    
-                    if is_visible {
-                        visible_indices.push(boid_index);
-                        
-                        // Mark as visible
-                        unsafe {
-                            let boid_ptr = &model.boids[boid_index] as *const Boid as *mut Boid;
-                            (*boid_ptr).is_visible = true;
-                        }
-                    }
-                }
+            if interior_row && interior_x.contains(&grid_x) {
+                row_indices.extend(cell_boids);
+            } else {
+                // For cells at the boundary, we need to check if the boid is actually visible
+                row_indices.extend(cell_boids.filter(|&boid_index| visible_area.contains(boid_render_position(&boids[boid_index], params, alpha))));
             }
         }
+        
+        row_indices
+    }).collect();
+    
+    rows.concat()
+}
+
+// Largest subsampling factor of the render budget (one boid in this many is drawn)
+const MAX_RENDER_STRIDE: usize = 1024;
+
+// Fraction of the budget the draw time must fall below before the stride is halved,
+// which roughly doubles the draw time (the gap keeps the stride from flip-flopping)
+const RENDER_HEADROOM: f32 = 0.4;
+
+// Subsampling of the visible boids while drawing takes longer than a budget. The stride
+// doubles while a frame's draw time is over budget and halves once there is headroom again.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderBudget {
+    pub stride: usize, // Every stride-th boid is drawn (1 draws all of them); always a power of two
+}
+
+impl Default for RenderBudget {
+    fn default() -> Self {
+        Self { stride: 1 }
+    }
+}
+
+impl RenderBudget {
+    // Adjust the stride after a frame took `draw_time` to draw
+    pub fn update(&mut self, draw_time: Duration, budget: Duration) {
+        if draw_time > budget {
+            self.stride = (self.stride * 2).min(MAX_RENDER_STRIDE);
+        } else if self.stride > 1 && draw_time.as_secs_f32() < budget.as_secs_f32() * RENDER_HEADROOM {
+            self.stride /= 2;
+        }
     }
     
-    visible_indices
-} 
\ No newline at end of file
+    // Whether a boid is drawn at the current stride. The choice depends on a hash of the
+    // boid's id rather than its index, so the same boids stay drawn from frame to frame, and
+    // since the stride is a power of two, the boids drawn at a stride are also drawn at every
+    // smaller one.
+    pub fn includes(&self, id: BoidId) -> bool {
+        self.stride <= 1 || mix_id(id).is_multiple_of(self.stride as u64)
+    }
+}
+
+// Spread consecutive ids evenly (the splitmix64 finalizer)
+fn mix_id(id: BoidId) -> u64 {
+    let mut x = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolated_boid_straddling_the_edge_is_visible() {
+        let world_size = 1000.0;
+        let window = Rect::from_corners(pt2(-100.0, -100.0), pt2(100.0, 100.0));
+
+        // The boid is drawn just inside the right edge, on its way to a stored
+        // position one fast step outside the window
+        let mut boid = Boid::new(99.0, 0.0);
+        boid.position = pt2(140.0, 0.0);
+        let boids = [boid];
+        let alpha = 0.0;
+
+        let mut grid = SpatialGrid::new(10.0, world_size);
+        grid.insert(0, boids[0].position, world_size);
+
+        let mut params = SimulationParams::default(); // max_speed 50
+        params.world_size = world_size;
+        let visible_area = expand_rect(window, culling_margin(&params, 1));
+
+        assert_eq!(cull_with_spatial_grid(&boids, &grid, &params, alpha, visible_area), vec![0]);
+        assert_eq!(cull_brute_force(&boids, &params, alpha, visible_area), vec![0]);
+
+        // Without the margin the grid lookup misses the cell the boid is stored in
+        assert!(cull_with_spatial_grid(&boids, &grid, &params, alpha, window).is_empty());
+
+        // With interpolation off the boid is tested at its stored position, outside the window
+        assert_eq!(cull_brute_force(&boids, &params, alpha, window), vec![0]);
+        params.enable_interpolation = false;
+        assert!(cull_brute_force(&boids, &params, alpha, window).is_empty());
+    }
+
+    #[test]
+    fn grid_culling_matches_brute_force() {
+        let world_size = 1000.0;
+        let mut grid = SpatialGrid::new(50.0, world_size);
+        let boids: Vec<Boid> = (0..400)
+            .map(|i| Boid::new((i % 20) as f32 * 50.0 - 490.0 + (i / 20) as f32, (i / 20) as f32 * 50.0 - 480.0))
+            .collect();
+        for (index, boid) in boids.iter().enumerate() {
+            grid.insert(index, boid.position, world_size);
+        }
+
+        // Neither aligned with the cells nor covering the whole world
+        let mut params = SimulationParams::default();
+        params.world_size = world_size;
+        params.enable_interpolation = false;
+        let visible_area = Rect::from_corners(pt2(-230.0, -175.0), pt2(310.0, 95.0));
+        let mut from_grid = cull_with_spatial_grid(&boids, &grid, &params, 0.0, visible_area);
+        from_grid.sort_unstable();
+
+        assert!(!from_grid.is_empty());
+        assert_eq!(from_grid, cull_brute_force(&boids, &params, 0.0, visible_area));
+    }
+
+    #[test]
+    fn the_render_stride_follows_the_draw_time() {
+        let budget = Duration::from_millis(20);
+        let mut render_budget = RenderBudget::default();
+
+        render_budget.update(Duration::from_millis(35), budget);
+        render_budget.update(Duration::from_millis(25), budget);
+        assert_eq!(render_budget.stride, 4);
+
+        // Within budget but without room to double the draw time: no change
+        render_budget.update(Duration::from_millis(12), budget);
+        assert_eq!(render_budget.stride, 4);
+
+        render_budget.update(Duration::from_millis(5), budget);
+        assert_eq!(render_budget.stride, 2);
+    }
+
+    #[test]
+    fn subsampled_boids_are_stable_and_nested() {
+        let ids = 0..10_000u64;
+        let drawn_at = |stride| {
+            let render_budget = RenderBudget { stride };
+            ids.clone().filter(|&id| render_budget.includes(id)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(drawn_at(1).len(), 10_000);
+        let quarter = drawn_at(4);
+        assert!((2_200..2_800).contains(&quarter.len()), "{} boids drawn", quarter.len());
+
+        // Every boid drawn at a stride is drawn at the smaller ones, so halving the stride
+        // only adds boids
+        let half = drawn_at(2);
+        assert!(quarter.iter().all(|id| half.contains(id)));
+    }
+}
@@ -7,18 +7,490 @@
  */
 
 use nannou::prelude::*;
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::alloc_counter;
+use crate::camera::FollowTarget;
+use crate::flock::BoidId;
+use crate::physics::{FlockMetrics, PhysicsTimings};
+
+// Number of frames kept in the performance history
+pub const FRAME_HISTORY_LEN: usize = 240;
+
+// Fixed-size ring buffer of recent frame times and physics step counts.
+// Uses inline arrays so recording a frame never allocates.
+pub struct FrameHistory {
+    frame_times_ms: [f32; FRAME_HISTORY_LEN],
+    physics_steps: [usize; FRAME_HISTORY_LEN],
+    next: usize,
+    len: usize,
+}
+
+// Summary statistics over the frame history
+pub struct FrameStats {
+    pub min_ms: f32,
+    pub avg_ms: f32,
+    pub max_ms: f32,
+    pub one_percent_low_fps: f32,
+}
+
+impl Default for FrameHistory {
+    fn default() -> Self {
+        Self {
+            frame_times_ms: [0.0; FRAME_HISTORY_LEN],
+            physics_steps: [0; FRAME_HISTORY_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl FrameHistory {
+    // Record a frame, overwriting the oldest entry once the buffer is full
+    pub fn push(&mut self, frame_time_ms: f32, physics_steps: usize) {
+        self.frame_times_ms[self.next] = frame_time_ms;
+        self.physics_steps[self.next] = physics_steps;
+        self.next = (self.next + 1) % FRAME_HISTORY_LEN;
+        self.len = (self.len + 1).min(FRAME_HISTORY_LEN);
+    }
+    
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    // Iterate over (frame time in ms, physics steps) from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = (f32, usize)> + '_ {
+        let start = (self.next + FRAME_HISTORY_LEN - self.len) % FRAME_HISTORY_LEN;
+        (0..self.len).map(move |i| {
+            let index = (start + i) % FRAME_HISTORY_LEN;
+            (self.frame_times_ms[index], self.physics_steps[index])
+        })
+    }
+    
+    // Compute min/avg/max frame time and the 1% low framerate
+    pub fn stats(&self) -> Option<FrameStats> {
+        if self.is_empty() {
+            return None;
+        }
+        
+        // Sort a stack copy of the recorded frame times (no heap allocation)
+        let mut sorted = [0.0f32; FRAME_HISTORY_LEN];
+        for (slot, (frame_time, _)) in sorted.iter_mut().zip(self.iter()) {
+            *slot = frame_time;
+        }
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        
+        let total: f32 = sorted.iter().sum();
+        
+        // 1% low: average framerate over the slowest 1% of frames
+        let worst_count = self.len.div_ceil(100);
+        let worst_avg_ms = sorted[self.len - worst_count..].iter().sum::<f32>() / worst_count as f32;
+        
+        Some(FrameStats {
+            min_ms: sorted[0],
+            avg_ms: total / self.len as f32,
+            max_ms: sorted[self.len - 1],
+            one_percent_low_fps: if worst_avg_ms > 0.0 { 1000.0 / worst_avg_ms } else { 0.0 },
+        })
+    }
+}
+
+// Recent boid counts per species, one sample per physics snapshot, for the species graph
+#[derive(Default)]
+pub struct SpeciesHistory {
+    samples: VecDeque<Vec<usize>>,
+}
+
+impl SpeciesHistory {
+    // Record the counts, reusing the oldest sample's allocation once the history is full
+    pub fn push(&mut self, counts: &[usize]) {
+        let mut sample = if self.samples.len() >= FRAME_HISTORY_LEN {
+            self.samples.pop_front().unwrap_or_default()
+        } else {
+            Vec::with_capacity(counts.len())
+        };
+        sample.clear();
+        sample.extend_from_slice(counts);
+        self.samples.push_back(sample);
+    }
+    
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    
+    // The most recent counts
+    pub fn latest(&self) -> Option<&[usize]> {
+        self.samples.back().map(Vec::as_slice)
+    }
+    
+    // Count of one species from oldest to newest (0 where the species didn't exist yet)
+    pub fn series(&self, species: usize) -> impl Iterator<Item = usize> + '_ {
+        self.samples.iter().map(move |sample| sample.get(species).copied().unwrap_or(0))
+    }
+}
+
+// Recent flock metrics, one sample per physics snapshot, for the metrics graph
+#[derive(Default)]
+pub struct MetricsHistory {
+    samples: VecDeque<FlockMetrics>,
+}
+
+impl MetricsHistory {
+    pub fn push(&mut self, metrics: FlockMetrics) {
+        if self.samples.len() >= FRAME_HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(metrics);
+    }
+    
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    
+    pub fn latest(&self) -> Option<&FlockMetrics> {
+        self.samples.back()
+    }
+    
+    // Samples from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &FlockMetrics> + '_ {
+        self.samples.iter()
+    }
+}
+
+// Seconds of simulated time shown in the speed graph
+pub const SPEED_HISTORY_SECONDS: f64 = 10.0;
+// Samples the speed history can hold: one per physics snapshot, so ten seconds at the
+// highest physics rate
+const SPEED_HISTORY_LEN: usize = 2400;
+
+// Speeds over the whole flock, from the physics velocities (not the interpolated ones)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpeedStats {
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+    pub selected: Option<f32>, // Speed of the selected boid
+}
+
+impl SpeedStats {
+    // One pass over the velocities (None without boids)
+    pub fn from_velocities(velocities: &[Vec2], selected: Option<usize>) -> Option<Self> {
+        if velocities.is_empty() {
+            return None;
+        }
+        
+        let (mut total, mut min, mut max) = (0.0, f32::MAX, 0.0f32);
+        for velocity in velocities {
+            let speed = velocity.length();
+            total += speed;
+            min = min.min(speed);
+            max = max.max(speed);
+        }
+        
+        Some(Self {
+            mean: total / velocities.len() as f32,
+            min,
+            max,
+            selected: selected.and_then(|index| velocities.get(index)).map(|velocity| velocity.length()),
+        })
+    }
+}
+
+// Flock speeds over the last SPEED_HISTORY_SECONDS of simulated time, one sample per
+// physics snapshot. The ring buffer is allocated once, so recording never allocates.
+pub struct SpeedHistory {
+    samples: Box<[(f64, SpeedStats)]>, // Simulated time and the speeds at it
+    next: usize,
+    len: usize,
+}
+
+impl Default for SpeedHistory {
+    fn default() -> Self {
+        Self {
+            samples: vec![(0.0, SpeedStats::default()); SPEED_HISTORY_LEN].into_boxed_slice(),
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl SpeedHistory {
+    // Record the speeds at a simulated time. Time going backwards (a reset) starts over.
+    pub fn push(&mut self, time: f64, speeds: SpeedStats) {
+        if matches!(self.latest(), Some((latest, _)) if time < latest) {
+            self.len = 0;
+        }
+        
+        self.samples[self.next] = (time, speeds);
+        self.next = (self.next + 1) % SPEED_HISTORY_LEN;
+        self.len = (self.len + 1).min(SPEED_HISTORY_LEN);
+    }
+    
+    pub fn latest(&self) -> Option<(f64, SpeedStats)> {
+        (self.len > 0).then(|| self.samples[(self.next + SPEED_HISTORY_LEN - 1) % SPEED_HISTORY_LEN])
+    }
+    
+    // The samples of the last SPEED_HISTORY_SECONDS from oldest to newest, with their time
+    // relative to the latest sample (so from -SPEED_HISTORY_SECONDS up to 0)
+    pub fn recent(&self) -> impl Iterator<Item = (f64, SpeedStats)> + '_ {
+        let latest_time = self.latest().map_or(0.0, |(time, _)| time);
+        let start = (self.next + SPEED_HISTORY_LEN - self.len) % SPEED_HISTORY_LEN;
+        (0..self.len)
+            .map(move |i| self.samples[(start + i) % SPEED_HISTORY_LEN])
+            .map(move |(time, speeds)| (time - latest_time, speeds))
+            .filter(|&(age, _)| age >= -SPEED_HISTORY_SECONDS)
+    }
+}
+
+// Buckets of the neighbor histogram: 0, 1-5, 6-10, ..., 96-100, and over 100
+pub const NEIGHBOR_BUCKETS: usize = 22;
+const NEIGHBOR_BUCKET_WIDTH: usize = 5;
+
+// Distribution of the spatial grid neighbor counts of every boid during one physics step
+#[derive(Clone, Debug, PartialEq)]
+pub struct NeighborHistogram {
+    pub buckets: [usize; NEIGHBOR_BUCKETS],
+    pub mean: f32,
+    pub p95: usize, // Smallest count that over 95% of the boids are at or below (exact, not from the buckets)
+}
+
+impl NeighborHistogram {
+    // Build the histogram of a set of neighbor counts (None if there are none)
+    pub fn from_counts(counts: &[usize]) -> Option<Self> {
+        if counts.is_empty() {
+            return None;
+        }
+        
+        let mut buckets = [0; NEIGHBOR_BUCKETS];
+        for &count in counts {
+            buckets[Self::bucket(count)] += 1;
+        }
+        
+        let mean = counts.iter().sum::<usize>() as f32 / counts.len() as f32;
+        
+        // Walk the cumulative counts past 95% of the boids: the count at 0-based rank
+        // floor(0.95 n) in sorted order
+        let mut sorted = counts.to_vec();
+        let rank = (counts.len() * 95 / 100).min(counts.len() - 1);
+        let p95 = *sorted.select_nth_unstable(rank).1;
+        
+        Some(Self { buckets, mean, p95 })
+    }
+    
+    // The bucket a neighbor count falls into
+    pub fn bucket(count: usize) -> usize {
+        if count == 0 {
+            0
+        } else {
+            ((count - 1) / NEIGHBOR_BUCKET_WIDTH + 1).min(NEIGHBOR_BUCKETS - 1)
+        }
+    }
+    
+    // The counts in a bucket, e.g. "6-10"
+    pub fn bucket_label(bucket: usize) -> String {
+        match bucket {
+            0 => "0".to_string(),
+            _ if bucket >= NEIGHBOR_BUCKETS - 1 => format!("{}+", (NEIGHBOR_BUCKETS - 2) * NEIGHBOR_BUCKET_WIDTH + 1),
+            _ => format!("{}-{}", (bucket - 1) * NEIGHBOR_BUCKET_WIDTH + 1, bucket * NEIGHBOR_BUCKET_WIDTH),
+        }
+    }
+}
+
+// Number of frames averaged in the per-phase timing breakdown
+pub const PHASE_AVERAGE_FRAMES: usize = 30;
+
+// Rolling average of a timing in milliseconds over the last PHASE_AVERAGE_FRAMES samples
+#[derive(Clone, Copy)]
+pub struct RollingAverage {
+    samples_ms: [f32; PHASE_AVERAGE_FRAMES],
+    next: usize,
+    len: usize,
+}
+
+impl Default for RollingAverage {
+    fn default() -> Self {
+        Self {
+            samples_ms: [0.0; PHASE_AVERAGE_FRAMES],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl RollingAverage {
+    pub fn push(&mut self, duration: Duration) {
+        self.samples_ms[self.next] = duration.as_secs_f32() * 1000.0;
+        self.next = (self.next + 1) % PHASE_AVERAGE_FRAMES;
+        self.len = (self.len + 1).min(PHASE_AVERAGE_FRAMES);
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    // Average in milliseconds, or zero before the first sample
+    pub fn average_ms(&self) -> f32 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        self.samples_ms[..self.len].iter().sum::<f32>() / self.len as f32
+    }
+}
+
+// Averaged time spent in each phase of a frame, for finding the bottleneck
+#[derive(Default)]
+pub struct PhaseBreakdown {
+    // Physics phases (summed over all physics steps in a frame)
+    pub store_previous: RollingAverage,
+    pub grid_rebuild: RollingAverage,
+    pub neighbor_gather: RollingAverage,
+    pub forces: RollingAverage,
+    pub spatial_sort: RollingAverage,
+    pub substeps: u32, // Force passes per physics step, as last recorded (0 before the first step)
+    // Neighbor gathering plus forces, split by whether spatial sorting was on, to show its speedup
+    pub neighbors_and_forces_unsorted: RollingAverage,
+    pub neighbors_and_forces_sorted: RollingAverage,
+    // Rendering phases
+    pub culling: RollingAverage,
+    pub draw: RollingAverage,
+    // Whole frame, the reference for percentages
+    pub frame: RollingAverage,
+}
+
+impl PhaseBreakdown {
+    pub fn record_physics(&mut self, timings: &PhysicsTimings, spatial_sorting: bool) {
+        self.store_previous.push(timings.store_previous);
+        self.grid_rebuild.push(timings.grid_rebuild);
+        self.neighbor_gather.push(timings.neighbor_gather);
+        self.forces.push(timings.forces);
+        self.spatial_sort.push(timings.spatial_sort);
+        
+        // Frames without a physics step keep the last count
+        if timings.substeps > 0 {
+            self.substeps = timings.substeps;
+        }
+        
+        let neighbors_and_forces = timings.neighbor_gather + timings.forces;
+        if spatial_sorting {
+            self.neighbors_and_forces_sorted.push(neighbors_and_forces);
+        } else {
+            self.neighbors_and_forces_unsorted.push(neighbors_and_forces);
+        }
+    }
+    
+    // How many times faster neighbor gathering plus forces ran with spatial sorting than
+    // without, once both have been measured
+    pub fn spatial_sort_speedup(&self) -> Option<f32> {
+        if self.neighbors_and_forces_sorted.is_empty() || self.neighbors_and_forces_unsorted.is_empty() {
+            return None;
+        }
+        
+        let sorted_ms = self.neighbors_and_forces_sorted.average_ms();
+        (sorted_ms > 0.0).then(|| self.neighbors_and_forces_unsorted.average_ms() / sorted_ms)
+    }
+    
+    pub fn record_render(&mut self, culling: Duration, draw: Duration) {
+        self.culling.push(culling);
+        self.draw.push(draw);
+    }
+}
+
+// Wall time over which the real-time factor is measured
+const REALTIME_WINDOW: Duration = Duration::from_secs(1);
+
+// Simulated time against wall time, for the status strip. The simulation's counters only
+// restart on a reset, which is noticed from the step count going back.
+#[derive(Default)]
+pub struct SimulationClock {
+    pub step: u64, // Physics steps since the last reset
+    pub simulation_time: f64, // Simulated seconds since the last reset
+    pub wall_time: f64, // Wall seconds spent running (not paused) since the last reset
+    samples: VecDeque<(Instant, f64)>, // Recent (wall, simulated) times, for the real-time factor
+    last_update: Option<Instant>,
+}
+
+impl SimulationClock {
+    // Take the counters of the latest snapshot (every frame, whether or not there is a new one)
+    pub fn update(&mut self, now: Instant, step: u64, simulation_time: f64, paused: bool) {
+        if step < self.step {
+            self.wall_time = 0.0;
+            self.samples.clear();
+            self.last_update = None;
+        }
+        self.step = step;
+        self.simulation_time = simulation_time;
+        
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_update = Some(now);
+        
+        // Time spent paused counts toward neither clock
+        if paused {
+            self.samples.clear();
+            return;
+        }
+        self.wall_time += elapsed.as_secs_f64();
+        
+        self.samples.push_back((now, simulation_time));
+        while self.samples.len() > 2 && now.saturating_duration_since(self.samples[0].0) > REALTIME_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+    
+    // Simulated seconds per wall second over the last second (below 1 when the physics can't
+    // keep up), or None while paused or before there is enough to measure
+    pub fn realtime_factor(&self) -> Option<f64> {
+        let (&(first_time, first_simulated), &(last_time, last_simulated)) = (self.samples.front()?, self.samples.back()?);
+        let wall = last_time.saturating_duration_since(first_time).as_secs_f64();
+        (wall >= REALTIME_WINDOW.as_secs_f64() / 4.0).then(|| (last_simulated - first_simulated) / wall)
+    }
+}
+
+// Per-rule forces recorded by the physics pass for the selected boid only
+#[derive(Clone, Copy, Debug)]
+pub struct SelectedBoidDebug {
+    pub index: usize,
+    // Weighted forces applied in the last physics step
+    pub separation_force: Vec2,
+    pub alignment_force: Vec2,
+    pub cohesion_force: Vec2,
+    pub combined_force: Vec2, // Sum of the three, as added to the acceleration
+    // Number of neighbors within each rule's perception radius
+    pub separation_count: usize,
+    pub alignment_count: usize,
+    pub cohesion_count: usize,
+}
 
 // Debug information for the simulation
 pub struct DebugInfo {
     pub fps: f32,
     pub frame_time: Duration,
     pub physics_updates_per_frame: Option<usize>,
+    pub physics_updates_max: Option<usize>, // Most physics steps in one frame over the frame history
+    pub physics_step_duration: Option<Duration>, // Measured time of one physics step
+    pub physics_step_budget: Option<Duration>, // 1 / fixed_physics_fps
+    pub max_steps_per_frame: Option<usize>,
+    pub physics_steps_capped: bool,
+    pub dropped_time: Duration, // Simulated time discarded by the step cap, in total
     pub interpolation_alpha: Option<f32>,
     pub visible_boids_count: Option<usize>,
     pub chunk_size: Option<usize>,
-    pub selected_boid_index: Option<usize>,
-    pub follow_mode_active: bool,
+    pub physics_threads: Option<usize>, // Threads the physics ran on in the last steps
+    pub selected_boid_id: Option<BoidId>,
+    pub follow_target: FollowTarget,
     pub culling_efficiency: Option<f32>,
     pub frustum_area_ratio: Option<f32>,
     // Spatial grid statistics
@@ -26,6 +498,29 @@ pub struct DebugInfo {
     pub grid_total_cells: Option<usize>,
     pub grid_occupancy_percentage: Option<f32>,
     pub grid_max_cell_population: Option<usize>,
+    // Recent frame history for the performance graph
+    pub frame_history: FrameHistory,
+    // Boids per species over time (recorded while the debug overlay is shown)
+    pub species_history: SpeciesHistory,
+    // Polarization, nearest neighbor distance and milling over time (recorded while metrics are computed)
+    pub metrics_history: MetricsHistory,
+    // Mean, min, max and selected boid speed over time (recorded while the debug overlay is shown)
+    pub speed_history: SpeedHistory,
+    // Energy mode population (only set while energy mode is on)
+    pub alive_boids: Option<usize>,
+    pub average_energy: Option<f32>,
+    pub respawning_boids: usize,
+    // Neighbors per boid in the last physics step (only with the spatial grid)
+    pub neighbor_histogram: Option<NeighborHistogram>,
+    // Per-phase timings (only recorded while the debug overlay is shown)
+    pub phase_breakdown: PhaseBreakdown,
+    // Heap allocations on every thread during the last frame (None without the counting allocator)
+    pub allocations_per_frame: Option<usize>,
+    // Every render_stride-th visible boid is drawn (1 unless the render budget is exceeded)
+    pub render_stride: usize,
+    // Simulated and wall time since the last reset, for the status strip
+    pub simulation_clock: SimulationClock,
+    allocation_count: usize, // Total at the end of the last frame
 }
 
 impl Default for DebugInfo {
@@ -34,11 +529,18 @@ impl Default for DebugInfo {
             fps: 0.0,
             frame_time: Duration::ZERO,
             physics_updates_per_frame: None,
+            physics_updates_max: None,
+            physics_step_duration: None,
+            physics_step_budget: None,
+            max_steps_per_frame: None,
+            physics_steps_capped: false,
+            dropped_time: Duration::ZERO,
             interpolation_alpha: None,
             visible_boids_count: None,
             chunk_size: None,
-            selected_boid_index: None,
-            follow_mode_active: false,
+            physics_threads: None,
+            selected_boid_id: None,
+            follow_target: FollowTarget::None,
             culling_efficiency: None,
             frustum_area_ratio: None,
             // Initialize grid statistics
@@ -46,6 +548,19 @@ impl Default for DebugInfo {
             grid_total_cells: None,
             grid_occupancy_percentage: None,
             grid_max_cell_population: None,
+            frame_history: FrameHistory::default(),
+            species_history: SpeciesHistory::default(),
+            metrics_history: MetricsHistory::default(),
+            speed_history: SpeedHistory::default(),
+            alive_boids: None,
+            average_energy: None,
+            respawning_boids: 0,
+            neighbor_histogram: None,
+            phase_breakdown: PhaseBreakdown::default(),
+            allocations_per_frame: None,
+            render_stride: 1,
+            simulation_clock: SimulationClock::default(),
+            allocation_count: 0,
         }
     }
 }
@@ -56,24 +571,41 @@ impl DebugInfo {
         // Basic performance metrics
         self.fps = app.fps();
         self.frame_time = app.duration.since_prev_update;
+        
+        // Record the frame in the history ring buffer
+        self.frame_history.push(
+            self.frame_time.as_secs_f32() * 1000.0,
+            self.physics_updates_per_frame.unwrap_or(0)
+        );
+        self.phase_breakdown.frame.push(self.frame_time);
+        
+        // Rolling max, to spot frames that had to catch up with many physics steps
+        self.physics_updates_max = self.frame_history.iter().map(|(_, steps)| steps).max();
+        
+        let allocation_count = alloc_counter::allocation_count();
+        self.allocations_per_frame = (allocation_count > 0).then(|| allocation_count - self.allocation_count);
+        self.allocation_count = allocation_count;
     }
     
     // Update debug information from model fields
     pub fn update_from_model(&mut self, 
-                            selected_boid_index: Option<usize>,
-                            follow_mode_active: bool,
-                            interpolation_alpha: f32,
-                            cached_visible_boids: &Option<Vec<usize>>,
-                            boids_len: usize,
-                            visible_area_cache: Option<Rect>,
-                            world_size: f32) {
+                            selected_boid_id: Option<BoidId>,
+                            follow_target: FollowTarget,
+                            interpolation_alpha: f32) {
         // Boid selection and camera state
-        self.selected_boid_index = selected_boid_index;
-        self.follow_mode_active = follow_mode_active;
+        self.selected_boid_id = selected_boid_id;
+        self.follow_target = follow_target;
         
         // Interpolation state
         self.interpolation_alpha = Some(interpolation_alpha);
-        
+    }
+    
+    // Update the culling statistics of the last rendered frame
+    pub fn update_culling_stats(&mut self, 
+                               cached_visible_boids: &Option<Vec<usize>>,
+                               boids_len: usize,
+                               visible_area: Option<Rect>, // Culling area of the last rendered frame
+                               world_size: f32) {
         // Calculate visible boids count
         if let Some(visible_boids) = cached_visible_boids {
             self.visible_boids_count = Some(visible_boids.len());
@@ -86,7 +618,7 @@ impl DebugInfo {
         }
         
         // Calculate frustum area ratio if we have a visible area
-        if let Some(visible_area) = visible_area_cache {
+        if let Some(visible_area) = visible_area {
             let visible_area_size = visible_area.w() * visible_area.h();
             let world_area = world_size * world_size;
             self.frustum_area_ratio = Some(visible_area_size / world_area);
@@ -101,4 +633,101 @@ impl DebugInfo {
         self.grid_occupancy_percentage = Some(occupancy_percentage);
         self.grid_max_cell_population = Some(max_cell_population);
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_stats_cover_the_flock_and_the_selected_boid() {
+        let velocities = [vec2(3.0, 4.0), vec2(0.0, 1.0), vec2(0.0, 0.0)];
+        let speeds = SpeedStats::from_velocities(&velocities, Some(0)).unwrap();
+        assert_eq!((speeds.mean, speeds.min, speeds.max), (2.0, 0.0, 5.0));
+        assert_eq!(speeds.selected, Some(5.0));
+        assert_eq!(SpeedStats::from_velocities(&[], None), None);
+    }
+
+    #[test]
+    fn speed_history_keeps_the_last_seconds_and_restarts_on_reset() {
+        let mut history = SpeedHistory::default();
+        let speeds = |mean: f32| SpeedStats { mean, ..SpeedStats::default() };
+        for step in 0..SPEED_HISTORY_LEN + 10 {
+            history.push(step as f64 / 4.0, speeds(step as f32));
+        }
+
+        // Ten seconds at 4 samples per second (exact in binary), oldest first
+        let recent: Vec<(f64, SpeedStats)> = history.recent().collect();
+        assert_eq!(recent.len(), 41);
+        assert_eq!(recent[0].0, -SPEED_HISTORY_SECONDS);
+        assert_eq!(recent.last().unwrap().0, 0.0);
+        assert_eq!(recent.last().unwrap().1.mean, (SPEED_HISTORY_LEN + 9) as f32);
+
+        history.push(0.0, speeds(1.0));
+        assert_eq!(history.recent().count(), 1);
+    }
+
+    #[test]
+    fn spatial_sort_speedup_needs_both_measurements() {
+        let mut breakdown = PhaseBreakdown::default();
+        let timings = |forces_ms: u64| PhysicsTimings { forces: Duration::from_millis(forces_ms), ..PhysicsTimings::default() };
+
+        breakdown.record_physics(&timings(12), false);
+        assert_eq!(breakdown.spatial_sort_speedup(), None);
+
+        breakdown.record_physics(&timings(6), true);
+        breakdown.record_physics(&timings(6), true);
+        assert!((breakdown.spatial_sort_speedup().unwrap() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn neighbor_histogram_buckets_and_percentile() {
+        // 19 boids with 3 neighbors and one in a dense clump with 250
+        let mut counts = vec![3; 19];
+        counts.push(250);
+
+        let histogram = NeighborHistogram::from_counts(&counts).unwrap();
+
+        assert_eq!(histogram.buckets[1], 19);
+        assert_eq!(histogram.buckets[NEIGHBOR_BUCKETS - 1], 1);
+        assert_eq!(histogram.buckets.iter().sum::<usize>(), 20);
+        assert!((histogram.mean - 15.35).abs() < 1e-4);
+        assert_eq!(histogram.p95, 250);
+
+        assert_eq!(NeighborHistogram::bucket(0), 0);
+        assert_eq!(NeighborHistogram::bucket(5), 1);
+        assert_eq!(NeighborHistogram::bucket(6), 2);
+        assert_eq!(NeighborHistogram::bucket(100), 20);
+        assert_eq!(NeighborHistogram::bucket_label(2), "6-10");
+        assert_eq!(NeighborHistogram::bucket_label(NEIGHBOR_BUCKETS - 1), "101+");
+        assert_eq!(NeighborHistogram::from_counts(&[]), None);
+    }
+
+    #[test]
+    fn simulation_clock_pauses_and_restarts_on_reset() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut clock = SimulationClock::default();
+
+        // Half a simulated second per wall second
+        for i in 0..=10 {
+            clock.update(at(i * 100), i * 3, i as f64 * 0.05, false);
+        }
+        assert!((clock.wall_time - 1.0).abs() < 1e-9);
+        assert!((clock.realtime_factor().unwrap() - 0.5).abs() < 1e-9);
+
+        // Paused, neither clock moves and there is no factor
+        clock.update(at(3000), 30, 0.5, true);
+        assert!((clock.wall_time - 1.0).abs() < 1e-9);
+        assert_eq!(clock.realtime_factor(), None);
+
+        // Running again, the factor has to be measured anew
+        clock.update(at(3100), 33, 0.55, false);
+        assert!((clock.wall_time - 1.1).abs() < 1e-9);
+        assert_eq!(clock.realtime_factor(), None);
+
+        // A reset starts both clocks over
+        clock.update(at(3200), 0, 0.0, false);
+        assert_eq!((clock.step, clock.simulation_time, clock.wall_time), (0, 0.0, 0.0));
+    }
+}
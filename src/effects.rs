@@ -0,0 +1,180 @@
+/*
+ * Effects Module
+ * 
+ * Render-only spawn and despawn effects. Boids that appear (a reset, spawning or emitting
+ * at the cursor, respawns after energy death) fade and grow in, and boids that disappear
+ * (deletion, energy death, a reset, leaving the wind tunnel) are drawn for a moment longer
+ * as fading copies. The physics never sees any of this.
+ * 
+ * The effects are found by comparing each snapshot with the previous one. Ids are never
+ * reused, so every id above the previous snapshot's largest is a new boid, and the
+ * previous snapshot's other boids that are missing were removed.
+ * 
+ * At most MAX_EFFECTS boids fade in and MAX_EFFECTS fade out at a time, so resetting a
+ * huge flock doesn't create a fade for every boid: the rest appear or vanish at once.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use crate::boid::Boid;
+use crate::flock::BoidId;
+
+// Seconds a boid takes to fade in or out
+pub const FADE_SECONDS: f32 = 0.3;
+// Most boids fading in, and most fading out, at a time
+pub const MAX_EFFECTS: usize = 4000;
+
+// Size of a boid drawn at a fade level, relative to its full size
+pub fn fade_scale(fade: f32) -> f32 {
+    0.5 + 0.5 * fade
+}
+
+// A removed boid, still drawn where it was last seen while it fades out
+pub struct DyingBoid {
+    pub boid: Boid, // Its last state, standing still
+    age: f32, // Seconds since it was removed
+}
+
+impl DyingBoid {
+    fn new(boid: &Boid) -> Self {
+        let mut boid = boid.clone();
+        boid.prev_position = boid.position;
+        boid.prev_velocity = boid.velocity;
+        Self { boid, age: 0.0 }
+    }
+    
+    // Opacity, from 1 when removed down to 0
+    pub fn fade(&self) -> f32 {
+        (1.0 - self.age / FADE_SECONDS).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Default)]
+pub struct Effects {
+    pub dying: Vec<DyingBoid>,
+    spawned: HashMap<BoidId, f32>, // Seconds since each boid that is fading in appeared
+    max_id: Option<BoidId>, // Largest id in the previous snapshot
+}
+
+impl Effects {
+    // Start the effects for the boids that differ between the previous snapshot (the boids
+    // drawn so far) and the ids of the next one
+    pub fn observe(&mut self, previous: &[Boid], next: impl Iterator<Item = BoidId> + Clone) {
+        let previous_max = self.max_id;
+        let existed = |id: BoidId| matches!(previous_max, Some(max) if id <= max);
+        
+        let mut surviving = 0;
+        for id in next.clone() {
+            if existed(id) {
+                surviving += 1;
+            } else if self.spawned.len() < MAX_EFFECTS {
+                self.spawned.insert(id, 0.0);
+            }
+            self.max_id = self.max_id.max(Some(id));
+        }
+        
+        // Any boid of the previous snapshot that isn't in the next one was removed. When
+        // none survive (a reset) there is no need to look them up.
+        let room = MAX_EFFECTS.saturating_sub(self.dying.len());
+        if surviving < previous.len() && room > 0 {
+            let alive: HashSet<BoidId> = if surviving > 0 {
+                next.filter(|&id| existed(id)).collect()
+            } else {
+                HashSet::new()
+            };
+            self.dying.extend(previous.iter()
+                .filter(|boid| !alive.contains(&boid.id))
+                .take(room)
+                .map(DyingBoid::new));
+        }
+    }
+    
+    // Age the effects by a frame and drop the finished ones
+    pub fn advance(&mut self, dt: f32) {
+        for dying in &mut self.dying {
+            dying.age += dt;
+        }
+        self.dying.retain(|dying| dying.age < FADE_SECONDS);
+        
+        for age in self.spawned.values_mut() {
+            *age += dt;
+        }
+        self.spawned.retain(|_, age| *age < FADE_SECONDS);
+    }
+    
+    // Opacity of a boid: below 1 while it is fading in
+    pub fn spawn_fade(&self, id: BoidId) -> f32 {
+        if self.spawned.is_empty() {
+            return 1.0;
+        }
+        
+        self.spawned.get(&id).map_or(1.0, |age| (age / FADE_SECONDS).clamp(0.0, 1.0))
+    }
+    
+    pub fn is_active(&self) -> bool {
+        !self.dying.is_empty() || !self.spawned.is_empty()
+    }
+    
+    // Stop every effect (the effects were turned off). The next snapshot isn't compared
+    // with anything, so turning them back on doesn't fade in the whole flock.
+    pub fn clear(&mut self, next: impl Iterator<Item = BoidId>) {
+        self.dying.clear();
+        self.spawned.clear();
+        self.max_id = next.max().max(self.max_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boid_with_id(id: BoidId) -> Boid {
+        let mut boid = Boid::new(id as f32, 0.0);
+        boid.id = id;
+        boid
+    }
+
+    fn ids(boids: &[Boid]) -> impl Iterator<Item = BoidId> + Clone + '_ {
+        boids.iter().map(|boid| boid.id)
+    }
+
+    #[test]
+    fn new_ids_fade_in_and_missing_boids_fade_out() {
+        let mut effects = Effects::default();
+        let first: Vec<Boid> = (0..4).map(boid_with_id).collect();
+        effects.observe(&[], ids(&first));
+        effects.advance(FADE_SECONDS);
+        assert!(!effects.is_active());
+
+        // Boid 1 is removed and boid 4 is added
+        let second: Vec<Boid> = [0, 2, 3, 4].iter().map(|&id| boid_with_id(id)).collect();
+        effects.observe(&first, ids(&second));
+        assert_eq!(effects.dying.len(), 1);
+        assert_eq!(effects.dying[0].boid.id, 1);
+        assert_eq!(effects.dying[0].fade(), 1.0);
+        assert_eq!(effects.spawn_fade(4), 0.0);
+        assert_eq!(effects.spawn_fade(0), 1.0);
+
+        effects.advance(FADE_SECONDS / 2.0);
+        assert!((effects.dying[0].fade() - 0.5).abs() < 1e-5);
+        assert!((effects.spawn_fade(4) - 0.5).abs() < 1e-5);
+
+        effects.advance(FADE_SECONDS / 2.0);
+        assert!(!effects.is_active());
+    }
+
+    #[test]
+    fn a_reset_of_a_huge_flock_is_capped() {
+        let mut effects = Effects::default();
+        let old: Vec<Boid> = (0..10_000).map(boid_with_id).collect();
+        effects.clear(ids(&old));
+
+        let new: Vec<Boid> = (10_000..20_000).map(boid_with_id).collect();
+        effects.observe(&old, ids(&new));
+        assert_eq!(effects.dying.len(), MAX_EFFECTS);
+        assert_eq!(effects.spawned.len(), MAX_EFFECTS);
+
+        // Boids that weren't tracked are drawn normally
+        assert_eq!(effects.spawn_fade(19_999), 1.0);
+    }
+}
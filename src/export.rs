@@ -0,0 +1,299 @@
+/*
+ * Export Module
+ * 
+ * This module exports boid state (positions, velocities, neighbor counts and
+ * nearest neighbor distances, plus the flock's polarization and angular momentum
+ * on every row, and each tagged boid's tag with its group's metrics) to CSV or
+ * JSON Lines files for external analysis.
+ * 
+ * Snapshots are collected on the simulation thread and pushed into a channel;
+ * a background writer thread does all file I/O so a slow disk never stalls
+ * the physics. Exports can be a single frame or continuous (one batch of rows
+ * every `stride` physics steps).
+ * 
+ * The progress of an export can be watched from another thread (e.g. the UI
+ * while the exporter itself lives on the physics thread) through ExportProgress.
+ */
+
+use nannou::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::flock::Flock;
+use crate::params::SimulationParams;
+use crate::physics;
+use crate::spatial_grid::SpatialGrid;
+use crate::tags::{self, TagMetrics, TAG_COUNT, TAG_NAMES};
+
+// Output file format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines, // One JSON object per line, so continuous exports can simply append
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::JsonLines => "JSON Lines",
+        }
+    }
+}
+
+// State of one boid at one physics step
+#[derive(Clone, Copy, Debug)]
+pub struct BoidRecord {
+    pub step: u64,
+    pub time: f64, // Simulated seconds at the step
+    pub index: usize,
+    pub position: Point2,
+    pub velocity: Vec2,
+    pub neighbor_count: usize, // Boids within the largest perception radius
+    pub nearest_neighbor_distance: Option<f32>, // Wrapped; None if no boid is within the grid's reach
+    // Flock-wide metrics of the step (the same on every record of a batch)
+    pub polarization: f32,
+    pub angular_momentum: f32,
+    pub tag: Option<u8>, // See the tags module
+    pub tag_metrics: Option<TagMetrics>, // Of the boid's tag group (the same on every record of the group)
+}
+
+// Collect a record for every boid. The spatial grid is rebuilt so neighbor counts
+// and distances are correct even when the grid is disabled for physics.
+pub fn collect_records(step: u64, time: f64, flock: &Flock, grid: &mut SpatialGrid, params: &SimulationParams) -> Vec<BoidRecord> {
+    let positions = &flock.positions;
+    
+    grid.rebuild(positions, params.world_size);
+    let radius = params.max_perception_radius();
+    let radius_sq = radius * radius;
+    grid.set_query_radius(radius);
+    
+    let polarization = physics::order_parameter(flock.velocities.iter().copied()).unwrap_or(0.0);
+    let angular_momentum = physics::angular_momentum(positions, &flock.velocities, params.world_size);
+    let tag_metrics = if tags::any_tagged(flock) {
+        tags::tag_metrics(flock, grid, radius, params.world_size)
+    } else {
+        [None; TAG_COUNT]
+    };
+    
+    positions.iter().zip(&flock.velocities).enumerate().map(|(index, (&position, &velocity))| {
+        let neighbors = grid.get_nearby_with_distances(position, positions, params.world_size);
+        let neighbor_count = neighbors.iter()
+            .filter(|neighbor| neighbor.distance_squared < radius_sq)
+            .count();
+        let nearest_neighbor_distance = neighbors.iter()
+            .map(|neighbor| neighbor.distance_squared)
+            .min_by(f32::total_cmp)
+            .map(f32::sqrt);
+        let tag = flock.properties[index].tag;
+        
+        BoidRecord {
+            step,
+            time,
+            index,
+            position,
+            velocity,
+            neighbor_count,
+            nearest_neighbor_distance,
+            polarization,
+            angular_momentum,
+            tag,
+            tag_metrics: tag.and_then(|tag| tag_metrics[tag as usize]),
+        }
+    }).collect()
+}
+
+// A file being written by a background thread
+pub struct Exporter {
+    stride: u64,
+    sender: Option<Sender<Vec<BoidRecord>>>,
+    progress: ExportProgress,
+}
+
+// Shared view of an export's progress, cheap to clone and safe to read from any thread
+#[derive(Clone)]
+pub struct ExportProgress {
+    path: PathBuf,
+    continuous: bool,
+    active: Arc<AtomicBool>, // Still accepting batches
+    finished: Arc<AtomicBool>, // Writer thread has exited
+    rows_written: Arc<AtomicUsize>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Exporter {
+    // Create the file and start the writer thread.
+    // Continuous exporters accept a batch every `stride` physics steps until stopped.
+    pub fn start(path: &Path, format: ExportFormat, continuous: bool, stride: u64) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let (sender, receiver) = mpsc::channel::<Vec<BoidRecord>>();
+        let progress = ExportProgress {
+            path: path.to_path_buf(),
+            continuous,
+            active: Arc::new(AtomicBool::new(true)),
+            finished: Arc::new(AtomicBool::new(false)),
+            rows_written: Arc::new(AtomicUsize::new(0)),
+            error: Arc::new(Mutex::new(None)),
+        };
+        
+        let writer_progress = progress.clone();
+        thread::spawn(move || {
+            let result = (|| -> io::Result<()> {
+                let mut out = BufWriter::new(file);
+                if format == ExportFormat::Csv {
+                    writeln!(out, "step,time,index,x,y,vx,vy,neighbors,nearest_neighbor,polarization,angular_momentum,tag,tag_centroid_x,tag_centroid_y,tag_spread,tag_mixing")?;
+                }
+                
+                // Runs until the sender is dropped
+                for batch in receiver {
+                    for record in &batch {
+                        write_record(&mut out, record, format)?;
+                    }
+                    out.flush()?;
+                    writer_progress.rows_written.fetch_add(batch.len(), Ordering::Relaxed);
+                }
+                
+                Ok(())
+            })();
+            
+            if let Err(err) = result {
+                *writer_progress.error.lock().unwrap() = Some(err.to_string());
+            }
+            writer_progress.finished.store(true, Ordering::Relaxed);
+        });
+        
+        Ok(Self {
+            stride: stride.max(1),
+            sender: Some(sender),
+            progress,
+        })
+    }
+    
+    // Queue a batch of records for writing (never blocks on the disk)
+    pub fn send(&self, records: Vec<BoidRecord>) {
+        if let Some(sender) = &self.sender {
+            // The writer only disconnects after an I/O error, which is reported via error()
+            let _ = sender.send(records);
+        }
+    }
+    
+    // Whether a continuous export wants a batch for this physics step
+    pub fn wants_step(&self, step: u64) -> bool {
+        self.progress.continuous && self.sender.is_some() && step.is_multiple_of(self.stride)
+    }
+    
+    // Stop accepting batches; the writer finishes the queued rows in the background
+    pub fn stop(&mut self) {
+        self.sender = None;
+        self.progress.active.store(false, Ordering::Relaxed);
+    }
+    
+    // A handle for watching this export from another thread
+    pub fn progress(&self) -> ExportProgress {
+        self.progress.clone()
+    }
+    
+    pub fn is_continuous(&self) -> bool {
+        self.progress.is_continuous()
+    }
+}
+
+impl ExportProgress {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    pub fn is_continuous(&self) -> bool {
+        self.continuous
+    }
+    
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+    
+    // Whether all queued rows have been written (after stop)
+    pub fn is_done(&self) -> bool {
+        !self.is_active() && self.finished.load(Ordering::Relaxed)
+    }
+    
+    pub fn rows_written(&self) -> usize {
+        self.rows_written.load(Ordering::Relaxed)
+    }
+    
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+// Write a single record in the requested format
+fn write_record<W: Write>(out: &mut W, record: &BoidRecord, format: ExportFormat) -> io::Result<()> {
+    // Missing values (no nearest neighbor, an untagged boid) are empty fields in CSV and null in JSON
+    let missing = match format {
+        ExportFormat::Csv => "",
+        ExportFormat::JsonLines => "null",
+    };
+    let optional = |value: Option<f32>| value.map(|value| value.to_string()).unwrap_or_else(|| missing.to_string());
+    
+    let metrics = record.tag_metrics.unwrap_or_default();
+    let centroid = metrics.centroid;
+    let tag_fields = [
+        optional(centroid.map(|centroid| centroid.x)),
+        optional(centroid.map(|centroid| centroid.y)),
+        optional(metrics.spread),
+        optional(metrics.mixing),
+    ];
+    let tag_name = record.tag.map(|tag| TAG_NAMES[tag as usize]);
+    
+    match format {
+        ExportFormat::Csv => writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            record.step, record.time, record.index,
+            record.position.x, record.position.y,
+            record.velocity.x, record.velocity.y,
+            record.neighbor_count,
+            optional(record.nearest_neighbor_distance),
+            record.polarization, record.angular_momentum,
+            tag_name.unwrap_or_default(),
+            tag_fields.join(",")
+        ),
+        ExportFormat::JsonLines => writeln!(
+            out,
+            "{{\"step\":{},\"time\":{},\"index\":{},\"x\":{},\"y\":{},\"vx\":{},\"vy\":{},\"neighbors\":{},\"nearest_neighbor\":{},\"polarization\":{},\"angular_momentum\":{},\"tag\":{},\"tag_centroid_x\":{},\"tag_centroid_y\":{},\"tag_spread\":{},\"tag_mixing\":{}}}",
+            record.step, record.time, record.index,
+            record.position.x, record.position.y,
+            record.velocity.x, record.velocity.y,
+            record.neighbor_count,
+            optional(record.nearest_neighbor_distance),
+            record.polarization, record.angular_momentum,
+            tag_name.map(|name| format!("\"{}\"", name)).unwrap_or_else(|| missing.to_string()),
+            tag_fields[0], tag_fields[1], tag_fields[2], tag_fields[3]
+        ),
+    }
+}
+
+// A timestamped file name in the working directory, e.g. boids_1700000000.csv
+pub fn default_export_path(format: ExportFormat) -> PathBuf {
+    timestamped_path(format.extension())
+}
+
+// A timestamped file name with the given extension, e.g. boids_1700000000.png
+pub fn timestamped_path(extension: &str) -> PathBuf {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(format!("boids_{}.{}", timestamp, extension))
+}
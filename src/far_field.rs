@@ -0,0 +1,168 @@
+/*
+ * Far Field Module
+ * 
+ * Far-field cohesion for large, spread-out flocks. The flocking rules only look at the
+ * 3x3 cells around a boid, so groups further apart than the perception radii never
+ * notice each other. The far field gives every boid a weak extra pull toward the mass
+ * within a much larger radius.
+ * 
+ * The spatial grid already sums the positions inserted into each cell. Once per step
+ * those cells are merged into coarse blocks of about a quarter of the radius (O(cells)),
+ * and each boid only visits the blocks within the radius, so the cost per boid doesn't
+ * grow with the flock or the world. Every block counts as all its boids sitting at the
+ * block centroid, which is plenty for a pull this weak.
+ */
+
+use nannou::prelude::*;
+
+use crate::spatial_grid::SpatialGrid;
+
+// Blocks across the far cohesion radius (more is finer but visits more blocks per boid)
+const BLOCKS_PER_RADIUS: f32 = 4.0;
+
+// Boid counts and centroids of square blocks of grid cells
+pub struct FarField {
+    blocks_per_side: usize,
+    block_size: f32,
+    world_size: f32,
+    wrap_x: bool, // Like SpatialGrid::wrap_x: off while the x axis is open in wind tunnel mode
+    blocks: Vec<(usize, Point2)>, // Count and centroid, row-major from the bottom left
+}
+
+impl FarField {
+    // Merge the cells of a filled grid into blocks sized for the given radius
+    pub fn new(grid: &SpatialGrid, radius: f32, world_size: f32) -> Self {
+        let grid_size = grid.grid_size;
+        let cells_per_block = ((radius / BLOCKS_PER_RADIUS / grid.cell_size).floor() as usize).clamp(1, grid_size);
+        let blocks_per_side = grid_size.div_ceil(cells_per_block);
+        
+        let mut sums = vec![(0, Vec2::ZERO); blocks_per_side * blocks_per_side];
+        for row in 0..grid_size {
+            for column in 0..grid_size {
+                let (count, sum) = grid.cell_mass(row * grid_size + column);
+                if count > 0 {
+                    let block = &mut sums[(row / cells_per_block) * blocks_per_side + column / cells_per_block];
+                    block.0 += count;
+                    block.1 += sum;
+                }
+            }
+        }
+        
+        Self {
+            blocks_per_side,
+            block_size: cells_per_block as f32 * grid.cell_size,
+            world_size,
+            wrap_x: grid.wrap_x,
+            blocks: sums.into_iter()
+                .map(|(count, sum)| (count, if count > 0 { sum / count as f32 } else { Point2::ZERO }))
+                .collect(),
+        }
+    }
+    
+    // Average offset from a position to the boids within `radius` of it (measured to the
+    // block centroids, the short way around the world), or None if there are none
+    pub fn mean_offset(&self, position: Point2, radius: f32) -> Option<Vec2> {
+        let half_world = self.world_size / 2.0;
+        let side = self.blocks_per_side as isize;
+        let to_block = |coordinate: f32| (((coordinate + half_world) / self.block_size).floor() as isize).clamp(0, side - 1);
+        
+        // A block's centroid can be anywhere inside it, so reach one block further
+        let reach = (radius / self.block_size).ceil() as isize + 1;
+        let (columns, rows) = if 2 * reach + 1 >= side {
+            (0..side, 0..side)
+        } else {
+            let (column, row) = (to_block(position.x), to_block(position.y));
+            (column - reach..column + reach + 1, row - reach..row + reach + 1)
+        };
+        
+        let radius_squared = radius * radius;
+        let mut total = 0;
+        let mut sum = Vec2::ZERO;
+        for row in rows {
+            for column in columns.clone() {
+                // Blocks past an open edge aren't there
+                let wrapped_column = column.rem_euclid(side);
+                if !self.wrap_x && wrapped_column != column {
+                    continue;
+                }
+                
+                let (count, centroid) = self.blocks[(row.rem_euclid(side) * side + wrapped_column) as usize];
+                if count == 0 {
+                    continue;
+                }
+                
+                let mut offset = centroid - position;
+                if self.wrap_x && offset.x.abs() > half_world {
+                    offset.x -= self.world_size.copysign(offset.x);
+                }
+                if offset.y.abs() > half_world {
+                    offset.y -= self.world_size.copysign(offset.y);
+                }
+                
+                if offset.length_squared() < radius_squared {
+                    sum += offset * count as f32;
+                    total += count;
+                }
+            }
+        }
+        
+        (total > 0).then(|| sum / total as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD_SIZE: f32 = 2000.0;
+
+    fn filled_grid(positions: &[Point2]) -> SpatialGrid {
+        let mut grid = SpatialGrid::new(50.0, WORLD_SIZE);
+        for (index, &position) in positions.iter().enumerate() {
+            grid.insert(index, position, WORLD_SIZE);
+        }
+        grid
+    }
+
+    #[test]
+    fn cells_keep_their_counts_and_centroids() {
+        let grid = filled_grid(&[pt2(10.0, 10.0), pt2(20.0, 30.0), pt2(-500.0, 0.0)]);
+
+        let (count, sum) = grid.cell_mass(grid.pos_to_cell_index(pt2(10.0, 10.0), WORLD_SIZE));
+        assert_eq!(count, 2);
+        assert_eq!(sum / count as f32, pt2(15.0, 20.0));
+    }
+
+    #[test]
+    fn boids_are_pulled_toward_the_mass_within_the_radius() {
+        // Two clumps of different sizes, and a lone boid far away
+        let mut positions = vec![pt2(-300.0, 0.0); 10];
+        positions.extend([pt2(300.0, 0.0); 30]);
+        positions.push(pt2(0.0, 900.0));
+        let grid = filled_grid(&positions);
+        let field = FarField::new(&grid, 800.0, WORLD_SIZE);
+
+        // The small clump sees the large one, weighted by its count
+        let offset = field.mean_offset(pt2(-300.0, 0.0), 800.0).unwrap();
+        assert!((offset - vec2(450.0, 0.0)).length() < 1.0, "offset {:?}", offset);
+
+        // The lone boid is out of range of both clumps
+        assert_eq!(field.mean_offset(pt2(0.0, 900.0), 800.0), Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn the_pull_wraps_around_the_world_edges() {
+        let grid = filled_grid(&[pt2(950.0, 0.0); 5]);
+        let field = FarField::new(&grid, 400.0, WORLD_SIZE);
+
+        // The mass just across the left edge is 100 units away to the left
+        let offset = field.mean_offset(pt2(-950.0, 0.0), 400.0).unwrap();
+        assert!((offset - vec2(-100.0, 0.0)).length() < 1.0, "offset {:?}", offset);
+
+        // Not when the x axis is open
+        let mut grid = filled_grid(&[pt2(950.0, 0.0); 5]);
+        grid.wrap_x = false;
+        let field = FarField::new(&grid, 400.0, WORLD_SIZE);
+        assert_eq!(field.mean_offset(pt2(-950.0, 0.0), 400.0), None);
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Flock Module
+ * 
+ * This module stores the simulation's boids as a structure of arrays (SoA).
+ * The physics pass is memory-bound and its inner loops only need positions and
+ * velocities, so each field lives in its own contiguous array:
+ * 
+ * - Hot data (positions, velocities, accelerations) is read and written every step
+ * - Previous positions and velocities are only touched once per step, for interpolation
+ * - Cold data (color, max speed and force, species, frozen, perched, tag) is kept in a separate array
+ * - Energy is only touched in energy mode, once per step
+ * - Ids are only read when publishing snapshots
+ * 
+ * Every boid gets an id when it is added. Ids are never reused, so unlike indices
+ * (which change when boids are removed) they keep identifying the same boid.
+ * 
+ * A Boid is still used as a standalone view of a single boid (for the renderer,
+ * the inspector and when adding boids); get() and push() convert between the two.
+ */
+
+use nannou::prelude::*;
+
+use crate::boid::{self, Boid, MAX_ENERGY};
+
+// Identifies a boid for as long as it lives (see Flock::push)
+pub type BoidId = u64;
+
+// Per-boid data that the inner physics loops rarely need
+#[derive(Clone, Copy, Debug)]
+pub struct BoidProperties {
+    pub max_speed: f32,
+    pub max_force: f32,
+    pub color: Rgb<u8>,
+    pub species: u8,  // Index into SimulationParams::species
+    pub frozen: bool, // Frozen boids skip their own update but still influence neighbors
+    pub perched_until: Option<u64>, // Perching: the step at which a landed boid takes off again
+    pub tag: Option<u8>, // Experiment group (see the tags module)
+}
+
+impl BoidProperties {
+    // Whether the boid sits still this step (frozen or perched)
+    #[inline]
+    pub fn is_stationary(&self) -> bool {
+        self.frozen || self.perched_until.is_some()
+    }
+    
+    // A standalone boid with these properties, at rest in its current state
+    pub fn boid_at(&self, position: Point2, velocity: Vec2) -> Boid {
+        Boid {
+            position,
+            velocity,
+            acceleration: Vec2::ZERO,
+            prev_position: position,
+            prev_velocity: velocity,
+            max_speed: self.max_speed,
+            max_force: self.max_force,
+            color: self.color,
+            species: self.species,
+            frozen: self.frozen,
+            perched_until: self.perched_until,
+            energy: MAX_ENERGY,
+            tag: self.tag,
+            id: 0,
+        }
+    }
+}
+
+// All boids of a simulation, one array per field. Every array has the same length.
+#[derive(Clone, Default)]
+pub struct Flock {
+    pub positions: Vec<Point2>,
+    pub velocities: Vec<Vec2>,
+    pub accelerations: Vec<Vec2>,
+    pub prev_positions: Vec<Point2>, // Previous physics positions (for interpolation)
+    pub prev_velocities: Vec<Vec2>,  // Previous physics velocities (for interpolation)
+    pub properties: Vec<BoidProperties>,
+    pub energies: Vec<f32>, // Energy mode: 0 marks a dead boid until it is removed
+    pub ids: Vec<BoidId>,
+    next_id: BoidId, // Kept across clear(), so a reset flock gets new ids
+}
+
+impl Flock {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            positions: Vec::with_capacity(capacity),
+            velocities: Vec::with_capacity(capacity),
+            accelerations: Vec::with_capacity(capacity),
+            prev_positions: Vec::with_capacity(capacity),
+            prev_velocities: Vec::with_capacity(capacity),
+            properties: Vec::with_capacity(capacity),
+            energies: Vec::with_capacity(capacity),
+            ids: Vec::with_capacity(capacity),
+            next_id: 0,
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+    
+    // Copy of a single boid's state
+    pub fn get(&self, index: usize) -> Option<Boid> {
+        let properties = self.properties.get(index)?;
+        let mut boid = properties.boid_at(self.positions[index], self.velocities[index]);
+        boid.acceleration = self.accelerations[index];
+        boid.prev_position = self.prev_positions[index];
+        boid.prev_velocity = self.prev_velocities[index];
+        boid.energy = self.energies[index];
+        boid.id = self.ids[index];
+        Some(boid)
+    }
+    
+    // Copies of all boids, in index order
+    pub fn iter(&self) -> impl Iterator<Item = Boid> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+    
+    // Add a boid at the end, giving it a new id (the boid's own id is ignored)
+    pub fn push(&mut self, boid: Boid) {
+        self.positions.push(boid.position);
+        self.velocities.push(boid.velocity);
+        self.accelerations.push(boid.acceleration);
+        self.prev_positions.push(boid.prev_position);
+        self.prev_velocities.push(boid.prev_velocity);
+        self.properties.push(BoidProperties {
+            max_speed: boid.max_speed,
+            max_force: boid.max_force,
+            color: boid.color,
+            species: boid.species,
+            frozen: boid.frozen,
+            perched_until: boid.perched_until,
+            tag: boid.tag,
+        });
+        self.energies.push(boid.energy);
+        self.ids.push(self.next_id);
+        self.next_id += 1;
+    }
+    
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+    
+    // Remove boids from the end
+    pub fn truncate(&mut self, len: usize) {
+        self.positions.truncate(len);
+        self.velocities.truncate(len);
+        self.accelerations.truncate(len);
+        self.prev_positions.truncate(len);
+        self.prev_velocities.truncate(len);
+        self.properties.truncate(len);
+        self.energies.truncate(len);
+        self.ids.truncate(len);
+    }
+    
+    // Remove a boid, moving the last boid into its slot
+    pub fn swap_remove(&mut self, index: usize) {
+        self.positions.swap_remove(index);
+        self.velocities.swap_remove(index);
+        self.accelerations.swap_remove(index);
+        self.prev_positions.swap_remove(index);
+        self.prev_velocities.swap_remove(index);
+        self.properties.swap_remove(index);
+        self.energies.swap_remove(index);
+        self.ids.swap_remove(index);
+    }
+    
+    // Reorder the boids so that the boid at order[i] moves to index i (order must be a
+    // permutation of 0..len). Ids move with their boids.
+    pub fn permute(&mut self, order: &[usize]) {
+        fn apply<T: Copy>(values: &mut Vec<T>, order: &[usize]) {
+            let permuted = order.iter().map(|&index| values[index]).collect();
+            *values = permuted;
+        }
+        
+        apply(&mut self.positions, order);
+        apply(&mut self.velocities, order);
+        apply(&mut self.accelerations, order);
+        apply(&mut self.prev_positions, order);
+        apply(&mut self.prev_velocities, order);
+        apply(&mut self.properties, order);
+        apply(&mut self.energies, order);
+        apply(&mut self.ids, order);
+    }
+    
+    // Store the current state as the previous state before a physics step
+    pub fn store_previous_state(&mut self) {
+        self.prev_positions.copy_from_slice(&self.positions);
+        self.prev_velocities.copy_from_slice(&self.velocities);
+    }
+    
+    // Move a boid back inside the world (see Boid::wrap_into_world)
+    pub fn wrap_into_world(&mut self, index: usize, world_size: f32) {
+        boid::wrap_into_world(&mut self.positions[index], &mut self.prev_positions[index], world_size);
+    }
+}
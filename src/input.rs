@@ -2,31 +2,84 @@
  * Input Module
  * 
  * This module handles user input events for the boid simulation.
- * It processes mouse movements, clicks, and wheel events for camera control.
+ * It processes mouse movements, clicks, wheel and keyboard events for camera control.
  * 
  * Features:
- * - Camera panning with mouse drag
+ * - Camera panning with left or middle mouse drag
  * - Camera zooming with mouse wheel
+ * - Camera panning (WASD/arrows) and zooming (+/-, PageUp/PageDown) with the keyboard
  * - Handling UI interaction
  * - Boid selection and camera following
+ * - Tracking the boid under the cursor for the hover tooltip
+ * - Double-click on empty space to smoothly center the camera
+ * - Keyboard shortcuts for common toggles
+ * - Emitting boids at the cursor while the right button is held
+ * - Dragging boids to new positions in edit mode (or with Ctrl held)
+ * - Drawing walls in wall mode, and deleting them by clicking near one in edit mode
+ * - Resizing parameter regions by dragging their corners in edit mode
+ * - Exporting boid state (E for one frame, Shift+E to start/stop continuous export)
  */
 
 use nannou::prelude::*;
 use nannou::winit::event::{MouseButton, MouseScrollDelta, TouchPhase};
+use std::time::{Duration, Instant};
 
 use crate::app::Model;
-use crate::BOID_SIZE;
+use crate::boid::{boid_render_position, Boid};
+use crate::camera::FollowTarget;
+use crate::params::SimulationParams;
+use crate::spatial_grid::SpatialGrid;
+
+// Keyboard panning speed in screen pixels per second
+const KEYBOARD_PAN_SPEED: f32 = 600.0;
+// Multiplier applied to the panning speed while shift is held
+const KEYBOARD_PAN_FAST_MULTIPLIER: f32 = 3.0;
+// Zoom step applied per key press (same units as one mouse wheel line)
+const KEYBOARD_ZOOM_STEP: f32 = 1.0;
+// Maximum time between clicks to count as a double-click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(300);
+// Maximum cursor movement in screen pixels between clicks of a double-click
+const DOUBLE_CLICK_DISTANCE: f32 = 5.0;
+// Duration of the smooth pan triggered by double-clicking empty space (seconds)
+const DOUBLE_CLICK_PAN_DURATION: f32 = 0.4;
+// Distance within which the cursor picks a boid, relative to the boid size (a bit larger than the boid)
+const BOID_PICK_SCALE: f32 = 2.0;
+// Smallest pick distance in screen pixels, so tiny or zoomed-out boids stay easy to click
+const MIN_BOID_PICK_PIXELS: f32 = 12.0;
+// Distance in screen pixels within which a click in edit mode picks a wall
+const WALL_PICK_DISTANCE: f32 = 8.0;
+// Distance in screen pixels within which a click in edit mode grabs a region corner
+const REGION_CORNER_PICK_DISTANCE: f32 = 10.0;
+// Trackpad pixel scroll distance treated as one wheel line, so a comfortable
+// two-finger scroll zooms about as much as one wheel notch
+const PIXELS_PER_SCROLL_LINE: f32 = 150.0;
 
 // Mouse moved event handler
-pub fn mouse_moved(_app: &App, model: &mut Model, pos: Point2) {
+pub fn mouse_moved(app: &App, model: &mut Model, pos: Point2) {
     let new_pos = Vec2::new(pos.x, pos.y);
     
+    // Move the dragged boid with the cursor (works while paused too)
+    if let Some(index) = model.dragged_boid.and_then(|id| model.view.index_of(id)) {
+        let world_pos = model.camera.screen_to_world(new_pos, app.window_rect());
+        crate::app::move_boid_to(model, index, world_pos);
+    }
+    
+    // Move the dragged region corner with the cursor
+    if model.dragged_region.is_some() {
+        let world_pos = model.camera.screen_to_world(new_pos, app.window_rect());
+        crate::app::drag_region_corner(model, world_pos);
+    }
+    
+    // The preview of a half-drawn wall follows the cursor
+    if model.wall_start.is_some() {
+        model.invalidate_render();
+    }
+    
     // Update camera drag if we're dragging
     if model.camera.is_dragging {
         model.camera.drag(new_pos);
         // Clear the cached visible boids and force re-render when panning
-        unsafe { *model.cached_visible_boids.get() = None; }
-        unsafe { *model.render_needed.get() = true; }
+        model.invalidate_render();
     }
     
     // Always update the stored mouse position
@@ -35,56 +88,107 @@ pub fn mouse_moved(_app: &App, model: &mut Model, pos: Point2) {
 
 // Mouse pressed event handler
 pub fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    // Holding the right button emits boids at the cursor (spawned in the physics loop)
+    if button == MouseButton::Right && !model.egui.ctx().is_pointer_over_area() {
+        if model.wall_start.is_some() {
+            // Cancel the wall being drawn instead
+            model.wall_start = None;
+            model.invalidate_render();
+        } else {
+            model.is_emitting = true;
+        }
+    }
+    
+    // Middle button always pans, so it never conflicts with boid selection
+    if button == MouseButton::Middle && !model.egui.ctx().is_pointer_over_area() {
+        model.camera.start_drag(model.mouse_position);
+        
+        // Panning away stops following
+        model.camera.follow_target = FollowTarget::None;
+    }
+    
     if button == MouseButton::Left {
         // Check if the click is on the UI before handling it
         if !model.egui.ctx().is_pointer_over_area() {
             // Get the window rectangle for coordinate transformations
             let window_rect = app.window_rect();
             
+            // Detect a double-click (close in both time and screen position to the last click)
+            let now = Instant::now();
+            let is_double_click = model.last_click_time.is_some_and(|last| {
+                now.duration_since(last) <= DOUBLE_CLICK_TIME &&
+                    model.mouse_position.distance(model.last_click_position) <= DOUBLE_CLICK_DISTANCE
+            });
+            
+            // A double-click consumes the click so a third click starts a new sequence
+            if is_double_click {
+                model.last_click_time = None;
+            } else {
+                model.last_click_time = Some(now);
+                model.last_click_position = model.mouse_position;
+            }
+            
             // Convert mouse position from screen space to world space
             let world_pos = model.camera.screen_to_world(model.mouse_position, window_rect);
             
+            // In wall mode every click places a wall endpoint
+            if model.params.wall_mode {
+                crate::app::place_wall_point(model, world_pos);
+                return;
+            }
+            
             // Check if we clicked on a boid
-            let mut clicked_boid = None;
-            let selection_radius = BOID_SIZE * 2.0; // Make the selection area a bit larger than the boid
+            let clicked_boid = boid_at(model, world_pos);
             
-            // Get visible boids to check for selection
-            let visible_boids = if let Some(cached) = unsafe { &*model.cached_visible_boids.get() } {
-                cached.clone()
-            } else {
-                // If no cached visible boids, check all boids
-                (0..model.boids.len()).collect()
-            };
+            let edit_mode = model.params.edit_mode || app.keys.mods.ctrl();
             
-            // Check each visible boid
-            for &boid_idx in &visible_boids {
-                let boid = &model.boids[boid_idx];
+            if let Some(boid_idx) = clicked_boid.filter(|_| edit_mode) {
+                // Grab the boid instead of panning; it follows the cursor until release
+                crate::app::select_boid(model, Some(boid_idx));
+                model.dragged_boid = model.selected_boid_id;
                 
-                // Get interpolated position for accurate selection
-                let boid_pos = boid.get_interpolated_position(model.interpolation_alpha);
-                let distance_squared = (boid_pos.x - world_pos.x).powi(2) + (boid_pos.y - world_pos.y).powi(2);
+                // Following the boid while moving it would drag the camera along too
+                model.camera.follow_target = FollowTarget::None;
                 
-                // Check if the click is within the selection radius
-                if distance_squared <= selection_radius.powi(2) {
-                    clicked_boid = Some(boid_idx);
-                    break;
-                }
-            }
-            
-            if let Some(boid_idx) = clicked_boid {
-                // We clicked on a boid
-                model.selected_boid_index = Some(boid_idx);
-                model.camera.follow_mode = true;
+                model.invalidate_render();
+            } else if let Some(drag) = edit_mode.then(|| crate::app::region_corner_at(model, world_pos, REGION_CORNER_PICK_DISTANCE / model.camera.zoom)).flatten() {
+                // Grab a region corner; the opposite corner stays where it is
+                model.selected_region = Some(drag.0);
+                model.dragged_region = Some(drag);
+            } else if let Some(wall_idx) = edit_mode.then(|| crate::app::wall_at(model, world_pos, WALL_PICK_DISTANCE / model.camera.zoom)).flatten() {
+                // Clicking near a wall in edit mode deletes it
+                crate::app::delete_wall(model, wall_idx);
+            } else if let Some(boid_idx) = clicked_boid.filter(|_| model.params.infection_mode) {
+                // Seed the infection at the clicked boid
+                crate::app::select_boid(model, Some(boid_idx));
+                crate::app::infect_boid(model, boid_idx);
+            } else if let Some(boid_idx) = clicked_boid {
+                // We clicked (or double-clicked) on a boid
+                crate::app::select_boid(model, Some(boid_idx));
+                model.camera.follow_target = model.selected_boid_id.map_or(FollowTarget::None, FollowTarget::Boid);
                 
                 // Force re-render to show the selection
-                unsafe { *model.render_needed.get() = true; }
+                model.invalidate_render();
+            } else if model.params.spawn_at_cursor {
+                // Spawn a burst of boids at the clicked position instead of panning
+                let burst_size = model.params.spawn_burst_size;
+                crate::app::spawn_boids_at(model, world_pos, burst_size);
+                
+                // Clear the cached visible boids and force re-render
+                model.invalidate_render();
+            } else if is_double_click {
+                // Double-click on empty space smoothly centers the camera there
+                model.camera.start_pan_to(world_pos, DOUBLE_CLICK_PAN_DURATION);
+                
+                // Following would immediately override the pan
+                model.camera.follow_target = FollowTarget::None;
             } else {
                 // We didn't click on a boid, start camera drag
                 model.camera.start_drag(model.mouse_position);
                 
-                // If we were following a boid, stop following
-                if model.camera.follow_mode {
-                    model.camera.follow_mode = false;
+                // If we were following something, stop following
+                if model.camera.is_following() {
+                    model.camera.follow_target = FollowTarget::None;
                     // Keep the selected boid highlighted but don't follow it
                 }
             }
@@ -92,33 +196,102 @@ pub fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
     }
 }
 
+// The boid under the cursor at a world position, if any. The pick radius tracks the boid
+// size, but never drops below a few pixels on screen, so it grows as the camera zooms out.
+pub fn boid_at(model: &Model, world_pos: Point2) -> Option<usize> {
+    let radius = (model.params.boid_size * BOID_PICK_SCALE).max(MIN_BOID_PICK_PIXELS / model.camera.zoom);
+    
+    // The view's grid is only kept up to date while the spatial grid is enabled
+    let grid = model.params.enable_spatial_grid.then_some(&model.view.spatial_grid);
+    
+    nearest_boid(&model.view.boids, grid, &model.params, model.view.interpolation_alpha, world_pos, radius)
+}
+
+// The boid closest to a world position within `radius`, comparing the positions that
+// are drawn. With a grid of the boids' latest positions only the cells
+// within the radius are searched; otherwise every boid is checked.
+pub fn nearest_boid(
+    boids: &[Boid],
+    grid: Option<&SpatialGrid>,
+    params: &SimulationParams,
+    alpha: f32,
+    world_pos: Point2,
+    radius: f32,
+) -> Option<usize> {
+    let mut nearest = None;
+    let mut nearest_distance_squared = radius * radius;
+    
+    let check = |boid_idx: usize| {
+        if let Some(boid) = boids.get(boid_idx) {
+            let distance_squared = boid_render_position(boid, params, alpha).distance_squared(world_pos);
+            if distance_squared <= nearest_distance_squared {
+                nearest = Some(boid_idx);
+                nearest_distance_squared = distance_squared;
+            }
+        }
+    };
+    
+    match grid {
+        Some(grid) => grid.for_each_nearby(world_pos, radius, params.world_size, check),
+        None => (0..boids.len()).for_each(check),
+    }
+    
+    nearest
+}
+
+// Track the boid under the cursor for the hover outline and tooltip (once per frame)
+pub fn update_hovered_boid(app: &App, model: &mut Model) {
+    // Nothing is hovered while the pointer is over the UI
+    let hovered = if model.egui.ctx().is_pointer_over_area() {
+        None
+    } else {
+        let world_pos = model.camera.screen_to_world(model.mouse_position, app.window_rect());
+        boid_at(model, world_pos)
+    };
+    
+    if hovered != model.hovered_boid_index {
+        model.hovered_boid_index = hovered;
+        model.invalidate_render();
+    }
+}
+
 // Mouse released event handler
 pub fn mouse_released(_app: &App, model: &mut Model, button: MouseButton) {
-    if button == MouseButton::Left {
+    if button == MouseButton::Left || button == MouseButton::Middle {
         model.camera.end_drag();
     }
+    
+    // Releasing a dragged boid hands it back to the physics
+    if button == MouseButton::Left {
+        model.dragged_boid = None;
+        model.dragged_region = None;
+    }
+    
+    if button == MouseButton::Right {
+        model.is_emitting = false;
+    }
 }
 
 // Mouse wheel event handler for zooming
 pub fn mouse_wheel(_app: &App, model: &mut Model, delta: MouseScrollDelta, _phase: TouchPhase) {
-    match delta {
+    // Convert the scroll to wheel lines
+    let lines = match delta {
         MouseScrollDelta::LineDelta(x, y) => {
             // Handle trackpad pinch gestures and mouse wheel
-            let window_rect = _app.window_rect();
-            model.camera.zoom(vec2(x, y), model.mouse_position, window_rect);
+            vec2(x, y)
         },
         MouseScrollDelta::PixelDelta(pos) => {
-            // Handle pixel delta (less common)
-            let window_rect = _app.window_rect();
-            model.camera.zoom(vec2(pos.x as f32, pos.y as f32) * 0.01, model.mouse_position, window_rect);
+            // Handle pixel delta (trackpads), normalized to wheel lines
+            vec2(pos.x as f32, pos.y as f32) / PIXELS_PER_SCROLL_LINE
         },
-    }
+    };
+    
+    let direction = if model.params.invert_zoom { -1.0 } else { 1.0 };
+    let window_rect = _app.window_rect();
+    model.camera.zoom(lines * direction, model.mouse_position, window_rect, model.params.zoom_sensitivity);
     
     // Clear the cached visible boids and force re-render when zooming
-    unsafe { *model.cached_visible_boids.get() = None; }
-    unsafe { *model.render_needed.get() = true; }
-    // Also clear the visible area cache
-    model.visible_area_cache = None;
+    model.invalidate_render();
 }
 
 // Handle raw window events for egui and camera dragging
@@ -128,6 +301,183 @@ pub fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::ev
     
     // Force re-render when UI is interacted with
     if let nannou::winit::event::WindowEvent::MouseInput { .. } = event {
-        unsafe { *model.render_needed.get() = true; }
+        model.invalidate_render();
+    }
+}
+
+// Key pressed event handler for shortcuts, discrete camera zoom steps and view fitting
+pub fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    // Don't steal key presses while egui is using the keyboard (e.g. text fields)
+    if model.egui.ctx().wants_keyboard_input() {
+        return;
     }
-} 
\ No newline at end of file
+    
+    match key {
+        // F frames all boids in the window
+        Key::F => {
+            crate::app::fit_view_to_boids(model, app.window_rect());
+            return;
+        }
+        // Space toggles pause
+        Key::Space => {
+            model.params.pause_simulation = !model.params.pause_simulation;
+            model.invalidate_render();
+            return;
+        }
+        // F3 toggles debug info (D is taken by keyboard panning)
+        Key::F3 => {
+            model.params.show_debug = !model.params.show_debug;
+            model.invalidate_render();
+            return;
+        }
+        // G toggles the spatial grid
+        Key::G => {
+            model.params.enable_spatial_grid = !model.params.enable_spatial_grid;
+            model.invalidate_render();
+            return;
+        }
+        // C toggles frustum culling
+        Key::C => {
+            model.params.enable_frustum_culling = !model.params.enable_frustum_culling;
+            model.invalidate_render();
+            return;
+        }
+        // H or Tab hides or shows the UI window, and reopens it after it was closed
+        Key::H | Key::Tab => {
+            if model.ui_layout.window_open {
+                model.ui_visible = !model.ui_visible;
+            } else {
+                model.ui_layout.window_open = true;
+                model.ui_visible = true;
+            }
+            model.invalidate_render();
+            return;
+        }
+        // R re-randomizes all boids
+        Key::R => {
+            crate::app::reset_boids(model);
+            model.invalidate_render();
+            return;
+        }
+        // Delete removes the selected boid
+        Key::Delete => {
+            if let Some(index) = model.selected_boid_index {
+                crate::app::delete_boid(model, index);
+            }
+            return;
+        }
+        // P pins (freezes) or unfreezes the selected boid (F is taken by Fit View)
+        Key::P => {
+            if let Some(index) = model.selected_boid_index {
+                crate::app::toggle_frozen(model, index);
+            }
+            return;
+        }
+        // E exports the current frame; Shift+E starts or stops a continuous export
+        Key::E => {
+            if app.keys.mods.shift() {
+                crate::app::toggle_continuous_export(model);
+            } else {
+                crate::app::export_frame(model);
+            }
+            return;
+        }
+        // Escape deselects the boid and exits follow mode, or with neither to undo
+        // closes the UI window
+        Key::Escape => {
+            if model.selected_boid_index.is_some() || !matches!(model.camera.follow_target, FollowTarget::None) {
+                crate::app::select_boid(model, None);
+                model.camera.follow_target = FollowTarget::None;
+            } else {
+                model.ui_layout.window_open = false;
+            }
+            model.invalidate_render();
+            return;
+        }
+        _ => {}
+    }
+    
+    let zoom_direction = match key {
+        Key::Equals | Key::Plus | Key::NumpadAdd | Key::PageUp => 1.0,
+        Key::Minus | Key::NumpadSubtract | Key::PageDown => -1.0,
+        _ => return,
+    };
+    
+    // Zoom around the window center
+    let window_rect = app.window_rect();
+    model.camera.zoom(vec2(0.0, zoom_direction * KEYBOARD_ZOOM_STEP), window_rect.xy(), window_rect, model.params.zoom_sensitivity);
+    
+    // Clear the cached visible boids and force re-render when zooming
+    model.invalidate_render();
+}
+
+// Pan the camera while WASD or arrow keys are held down (called once per frame)
+pub fn update_keyboard_pan(app: &App, model: &mut Model, dt: f32) {
+    // Don't pan while egui is using the keyboard (e.g. text fields)
+    if model.egui.ctx().wants_keyboard_input() {
+        return;
+    }
+    
+    let keys = &app.keys.down;
+    let mut direction = Vec2::ZERO;
+    
+    if keys.contains(&Key::W) || keys.contains(&Key::Up) {
+        direction.y += 1.0;
+    }
+    if keys.contains(&Key::S) || keys.contains(&Key::Down) {
+        direction.y -= 1.0;
+    }
+    if keys.contains(&Key::A) || keys.contains(&Key::Left) {
+        direction.x -= 1.0;
+    }
+    if keys.contains(&Key::D) || keys.contains(&Key::Right) {
+        direction.x += 1.0;
+    }
+    
+    if direction == Vec2::ZERO {
+        return;
+    }
+    
+    // Holding shift pans faster
+    let speed = if app.keys.mods.shift() {
+        KEYBOARD_PAN_SPEED * KEYBOARD_PAN_FAST_MULTIPLIER
+    } else {
+        KEYBOARD_PAN_SPEED
+    };
+    
+    // Camera::pan scales by 1/zoom so movement feels constant on screen
+    model.camera.pan(direction.normalize() * speed * dt);
+    
+    // Panning away stops following
+    if model.camera.is_following() {
+        model.camera.follow_target = FollowTarget::None;
+    }
+    
+    // Clear the cached visible boids and force re-render when panning
+    model.invalidate_render();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD_SIZE: f32 = 1000.0;
+
+    #[test]
+    fn picks_the_closest_of_overlapping_boids() {
+        let boids = vec![Boid::new(0.0, 0.0), Boid::new(4.0, 0.0), Boid::new(8.0, 0.0)];
+        let mut grid = SpatialGrid::new(20.0, WORLD_SIZE);
+        for (i, boid) in boids.iter().enumerate() {
+            grid.insert(i, boid.position, WORLD_SIZE);
+        }
+
+        // All three are within the radius; the first one in the list isn't the closest
+        let mut params = SimulationParams::default();
+        params.world_size = WORLD_SIZE;
+        for grid in [Some(&grid), None] {
+            assert_eq!(nearest_boid(&boids, grid, &params, 1.0, pt2(5.0, 1.0), 12.0), Some(1));
+            assert_eq!(nearest_boid(&boids, grid, &params, 1.0, pt2(7.0, -1.0), 12.0), Some(2));
+            assert_eq!(nearest_boid(&boids, grid, &params, 1.0, pt2(40.0, 0.0), 12.0), None);
+        }
+    }
+}
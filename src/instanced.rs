@@ -0,0 +1,308 @@
+/*
+ * Instanced Rendering Module
+ * 
+ * This module draws the visible boids with a custom wgpu pipeline in a single draw call:
+ * one static mesh of the boid shape at unit size, plus a per-instance buffer of
+ * position, heading, size and color that is refilled every frame from the visible-boid
+ * list. The vertex shader (shaders/boid.wgsl) applies the camera transform.
+ * 
+ * The immediate-mode path tessellates a polygon per boid on the CPU every frame, which
+ * tops out at a few tens of thousands of visible boids; here the CPU only fills one
+ * 32-byte instance per boid. Everything else (world boundary, walls, overlays and debug
+ * drawing) still goes through the nannou draw API, before and after this pass.
+ */
+
+use nannou::prelude::*;
+use nannou::wgpu::{self, util::DeviceExt, BufferInitDescriptor};
+use rayon::prelude::*;
+
+use crate::app::Model;
+use crate::boid::{self, Boid, BoidShape};
+use crate::camera::Camera;
+use crate::effects;
+use crate::params::SimulationParams;
+
+// Segments of the circle mesh
+const CIRCLE_SEGMENTS: usize = 12;
+// Instances the instance buffer has room for at first (it grows in powers of two)
+const INITIAL_INSTANCE_CAPACITY: usize = 1024;
+
+// One corner of the boid mesh
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+// Shader locations of the mesh vertex and the instance fields
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x2];
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![1 => Float32x2, 2 => Float32, 3 => Float32, 4 => Float32x4];
+
+// Per-boid data read by the vertex shader
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoidInstance {
+    pub position: [f32; 2], // World position (interpolated)
+    pub angle: f32,         // Heading in radians
+    pub scale: f32,         // Boid size in world units
+    pub color: [f32; 4],    // Linear RGBA, like the colors the draw API hands to the GPU
+}
+
+impl BoidInstance {
+    pub fn new(boid: &Boid, params: &SimulationParams, alpha: f32) -> Self {
+        let position = boid::boid_render_position(boid, params, alpha);
+        let color = boid::edge_tinted(boid.display_color(), position, params).into_format::<f32>().into_linear();
+        
+        Self {
+            position: [position.x, position.y],
+            angle: boid.heading(params, alpha),
+            scale: params.species_boid_size(boid.species),
+            color: [color.red, color.green, color.blue, 1.0],
+        }
+    }
+    
+    // The instance drawn partly faded in or out (see the effects module): translucent and smaller
+    pub fn faded(mut self, fade: f32) -> Self {
+        self.scale *= effects::fade_scale(fade);
+        self.color[3] = fade;
+        self
+    }
+}
+
+// The camera transform (laid out like the Camera struct in the shader)
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CameraUniforms {
+    view: [f32; 4],        // Camera position, zoom, unused
+    half_window: [f32; 4], // Half the window size in pixels, unused, unused
+}
+
+impl CameraUniforms {
+    fn new(camera: &Camera, window_rect: Rect) -> Self {
+        Self {
+            view: [camera.position.x, camera.position.y, camera.zoom, 0.0],
+            half_window: [window_rect.w() / 2.0, window_rect.h() / 2.0, 0.0, 0.0],
+        }
+    }
+}
+
+// Whether a shape can be drawn instanced (lines are one pixel wide at any zoom, which
+// a mesh scaled in world units can't reproduce)
+pub fn supports_shape(shape: BoidShape) -> bool {
+    shape != BoidShape::Line
+}
+
+// Triangles covering a shape of size 1, pointing along +x (empty for lines)
+pub fn shape_mesh(shape: BoidShape) -> Vec<Point2> {
+    match shape {
+        BoidShape::Triangle => shape.outline(1.0),
+        BoidShape::Dart => {
+            // The notch makes the outline concave, so split it at the notch
+            let outline = shape.outline(1.0);
+            vec![outline[0], outline[1], outline[2], outline[0], outline[2], outline[3]]
+        }
+        BoidShape::Circle => (0..CIRCLE_SEGMENTS)
+            .flat_map(|segment| {
+                let corner = |segment: usize| {
+                    let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                    pt2(angle.cos(), angle.sin()) * 0.5
+                };
+                [Point2::ZERO, corner(segment), corner(segment + 1)]
+            })
+            .collect(),
+        BoidShape::Line => Vec::new(),
+    }
+}
+
+// The GPU pipeline and buffers, created on the first instanced frame
+pub struct InstancedRenderer {
+    pipeline: wgpu::RenderPipeline,
+    sample_count: u32,
+    camera_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    shape: BoidShape,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    instances: Vec<BoidInstance>, // Refilled every frame (kept to reuse the allocation)
+}
+
+impl InstancedRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("shaders/boid.wgsl"));
+        
+        let camera_uniforms = CameraUniforms { view: [0.0; 4], half_window: [1.0; 4] };
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("boid camera"),
+            contents: unsafe { wgpu::bytes::from(&camera_uniforms) },
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = wgpu::BindGroupLayoutBuilder::new()
+            .uniform_buffer(wgpu::ShaderStages::VERTEX, false)
+            .build(device);
+        let bind_group = wgpu::BindGroupBuilder::new()
+            .buffer::<CameraUniforms>(&camera_buffer, 0..1)
+            .build(device, &bind_group_layout);
+        
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("boid pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = wgpu::RenderPipelineBuilder::from_layout(&pipeline_layout, &shader)
+            .vertex_entry_point("vs_main")
+            .fragment_shader(&shader)
+            .fragment_entry_point("fs_main")
+            .color_format(format)
+            .add_vertex_buffer::<Vertex>(&VERTEX_ATTRIBUTES)
+            .add_instance_buffer::<BoidInstance>(&INSTANCE_ATTRIBUTES)
+            .sample_count(sample_count)
+            .build(device);
+        
+        let shape = BoidShape::Triangle;
+        let (vertex_buffer, vertex_count) = create_mesh_buffer(device, shape);
+        
+        Self {
+            pipeline,
+            sample_count,
+            camera_buffer,
+            bind_group,
+            shape,
+            vertex_buffer,
+            vertex_count,
+            instance_buffer: create_instance_buffer(device, INITIAL_INSTANCE_CAPACITY),
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
+            instances: Vec::new(),
+        }
+    }
+    
+    // Whether the pipeline can draw into a frame with this many samples per pixel
+    pub fn matches(&self, sample_count: u32) -> bool {
+        self.sample_count == sample_count
+    }
+    
+    // Draw the boids at the given indices into the frame, on top of what is already there
+    pub fn draw(&mut self, frame: &Frame, model: &Model, indices: &[usize], window_rect: Rect) {
+        let (boids, alpha) = (&model.view.boids, model.view.interpolation_alpha);
+        let shape = model.params.boid_shape;
+        let device = frame.device_queue_pair().device();
+        let queue = frame.device_queue_pair().queue();
+        
+        if shape != self.shape {
+            let (vertex_buffer, vertex_count) = create_mesh_buffer(device, shape);
+            self.vertex_buffer = vertex_buffer;
+            self.vertex_count = vertex_count;
+            self.shape = shape;
+        }
+        
+        // The visible boids (those still fading in are translucent), then the removed boids
+        // that are fading out. Borrowed before the parallel build, as the model itself isn't
+        // Sync and can't be shared with the workers.
+        let (params, effects) = (&model.params, &model.effects);
+        self.instances.clear();
+        self.instances.par_extend(indices.par_iter().map(|&index| {
+            let boid = &boids[index];
+            BoidInstance::new(boid, params, alpha).faded(effects.spawn_fade(boid.id))
+        }));
+        self.instances.extend(effects.dying.iter()
+            .map(|dying| BoidInstance::new(&dying.boid, params, alpha).faded(dying.fade())));
+        if self.instances.is_empty() || self.vertex_count == 0 {
+            return;
+        }
+        
+        if self.instances.len() > self.instance_capacity {
+            self.instance_capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.instance_capacity);
+        }
+        
+        // Both writes land before the frame's commands run
+        let camera_uniforms = CameraUniforms::new(&model.camera, window_rect);
+        queue.write_buffer(&self.camera_buffer, 0, unsafe { wgpu::bytes::from(&camera_uniforms) });
+        queue.write_buffer(&self.instance_buffer, 0, unsafe { wgpu::bytes::from_slice(&self.instances) });
+        
+        // The frame's texture is loaded, not cleared, so this draws over the earlier layers
+        let mut encoder = frame.command_encoder();
+        let mut render_pass = wgpu::RenderPassBuilder::new()
+            .color_attachment(frame.texture_view(), |color| color)
+            .begin(&mut encoder);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..self.instances.len() as u32);
+    }
+}
+
+// A vertex buffer holding a shape's mesh, and its vertex count
+fn create_mesh_buffer(device: &wgpu::Device, shape: BoidShape) -> (wgpu::Buffer, u32) {
+    let mut vertices: Vec<Vertex> = shape_mesh(shape).iter().map(|point| Vertex { position: [point.x, point.y] }).collect();
+    
+    // Buffers can't be empty, so shapes without a mesh get a degenerate triangle
+    let vertex_count = vertices.len() as u32;
+    if vertices.is_empty() {
+        vertices = vec![Vertex { position: [0.0; 2] }; 3];
+    }
+    
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("boid mesh"),
+        contents: unsafe { wgpu::bytes::from_slice(&vertices) },
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    (buffer, vertex_count)
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("boid instances"),
+        size: (capacity * std::mem::size_of::<BoidInstance>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Twice the signed area of a triangle (positive when counter-clockwise)
+    fn doubled_area(a: Point2, b: Point2, c: Point2) -> f32 {
+        (b - a).perp_dot(c - a)
+    }
+
+    #[test]
+    fn meshes_are_triangle_lists_that_point_along_x() {
+        for shape in BoidShape::ALL {
+            let mesh = shape_mesh(shape);
+            assert_eq!(mesh.len() % 3, 0);
+            assert_eq!(mesh.is_empty(), !supports_shape(shape));
+
+            for triangle in mesh.chunks(3) {
+                assert!(doubled_area(triangle[0], triangle[1], triangle[2]).abs() > 1e-4);
+            }
+        }
+
+        // The polygon shapes keep their tip at +x and their size
+        let dart = shape_mesh(BoidShape::Dart);
+        assert!(dart.iter().all(|point| point.x <= 1.0));
+        assert!(dart.contains(&pt2(1.0, 0.0)));
+        assert_eq!(shape_mesh(BoidShape::Triangle), BoidShape::Triangle.outline(1.0));
+    }
+
+    #[test]
+    fn instances_use_the_interpolated_state() {
+        let mut boid = Boid::new(10.0, 0.0);
+        boid.prev_position = pt2(0.0, 0.0);
+        boid.velocity = vec2(0.0, 2.0);
+        boid.prev_velocity = vec2(0.0, 2.0);
+
+        let mut params = SimulationParams::default();
+        params.world_size = 1000.0;
+        params.boid_size = 6.0;
+        let instance = BoidInstance::new(&boid, &params, 0.5);
+        assert_eq!(instance.position, [5.0, 0.0]);
+        assert!((instance.angle - PI / 2.0).abs() < 1e-6);
+        assert_eq!(instance.scale, 6.0);
+        assert_eq!(instance.color[3], 1.0);
+    }
+}
@@ -7,14 +7,17 @@
 
 // Re-export key components for easier access
 pub use boid::Boid;
+pub use flock::Flock;
 pub use camera::Camera;
 pub use spatial_grid::SpatialGrid;
 pub use params::SimulationParams;
 pub use debug::DebugInfo;
 pub use app::Model;
+pub use simulation::Simulation;
 
 // Define modules
 pub mod boid;
+pub mod flock;
 pub mod camera;
 pub mod spatial_grid;
 pub mod params;
@@ -22,9 +25,31 @@ pub mod debug;
 pub mod app;
 pub mod ui;
 pub mod physics;
+pub mod simulation;
 pub mod renderer;
 pub mod culling;
 pub mod input;
+pub mod cli;
+pub mod config;
+pub mod export;
+pub mod stream;
+pub mod wall;
+pub mod region;
+pub mod pheromone;
+pub mod physics_thread;
+pub mod instanced;
+pub mod modulation;
+pub mod far_field;
+pub mod osc;
+pub mod alloc_counter;
+pub mod palette;
+pub mod scenario;
+pub mod timeline;
+pub mod effects;
+pub mod watch;
+pub mod tags;
+pub mod placement;
+pub mod breeds;
 
 // Constants
 pub const BOID_SIZE: f32 = 6.0;
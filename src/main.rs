@@ -16,26 +16,141 @@
  * - Spatial partitioning for efficient neighbor lookups
  * - Parallel processing for performance optimization
  * - Modular code organization for better maintainability
+ * - Command-line overrides for scripted runs (see --help)
+ * - Headless benchmark mode (--bench-mode)
+ * - Fixed-seed scenario files for comparable runs (--scenario, --verify)
  */
 
-// Import the library modules
-mod boid;
-mod camera;
-mod spatial_grid;
-mod params;
-mod debug;
-mod app;
-mod ui;
-mod physics;
-mod renderer;
-mod culling;
-mod input;
+// The modules live in the library (see lib.rs), so they are compiled once
+use boids::{alloc_counter, app, cli, physics, scenario, simulation};
 
-// Re-export constants
-pub const BOID_SIZE: f32 = 6.0;
+// Counts allocations for the debug panel
+#[global_allocator]
+static ALLOCATOR: alloc_counter::CountingAllocator = alloc_counter::CountingAllocator;
 
 fn main() {
+    // Parse command-line overrides before opening the window
+    let overrides = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::CliCommand::Run(overrides)) => *overrides,
+        Ok(cli::CliCommand::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    
+    // Run without a window if requested
+    let bench_steps = overrides.bench_steps().unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    });
+    if let Some(steps) = bench_steps {
+        let sim = overrides.build_simulation().unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        });
+        if overrides.verify {
+            run_verify(sim, steps, overrides.json);
+        } else {
+            run_benchmark(sim, steps, overrides.json);
+        }
+        return;
+    }
+    
+    // Validate the parameters (including the preset file) before opening the window
+    if let Err(err) = overrides.build_params() {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+    
+    cli::set_overrides(overrides);
+    
     nannou::app(app::model)
         .update(app::update)
+        .exit(app::exit)
         .run();
 }
+
+// Run a fixed number of physics steps and print the hash of the resulting state, which
+// only changes if the simulation's behavior does
+fn run_verify(mut sim: simulation::Simulation, steps: usize, json: bool) {
+    sim.step_n(steps);
+    let hash = scenario::state_hash(&sim.flock);
+    
+    if json {
+        println!("{{\"steps\":{},\"boids\":{},\"state_hash\":\"{:016x}\"}}", steps, sim.flock.len(), hash);
+    } else {
+        println!("{} steps with {} boids", steps, sim.flock.len());
+        println!("State hash: {:016x}", hash);
+    }
+}
+
+// Run a fixed number of physics steps without a window and print timing,
+// including the average time of each physics phase
+fn run_benchmark(mut sim: simulation::Simulation, steps: usize, json: bool) {
+    // The phase timers only run while debug info is enabled
+    sim.params.show_debug = true;
+    sim.physics_timings = physics::PhysicsTimings::default();
+    
+    let start = std::time::Instant::now();
+    sim.step_n(steps);
+    let elapsed = start.elapsed().as_secs_f64();
+    
+    // step_n never resets the timings, so they hold the totals over all steps
+    let steps_f = steps.max(1) as f64;
+    let timings = sim.physics_timings;
+    let phases = [
+        ("store_previous", "Store previous state", timings.store_previous),
+        ("grid_rebuild", "Grid clear + insert", timings.grid_rebuild),
+        ("neighbor_gather", "Neighbor gathering", timings.neighbor_gather),
+        ("forces", "Forces + wrap", timings.forces),
+    ];
+    let step_ms = elapsed * 1000.0 / steps_f;
+    let steps_per_second = if elapsed > 0.0 { steps as f64 / elapsed } else { 0.0 };
+    let threads = if sim.params.enable_parallel { sim.thread_count() } else { 1 };
+    
+    if json {
+        let phase_fields: Vec<String> = phases.iter()
+            .map(|(key, _, total)| format!("\"{}\":{:.6}", key, total.as_secs_f64() * 1000.0 / steps_f))
+            .collect();
+        println!(
+            "{{\"steps\":{},\"boids\":{},\"spatial_grid\":{},\"parallel\":{},\"threads\":{},\"substeps\":{},\"neighbor_list_interval\":{},\"total_s\":{:.6},\"steps_per_second\":{:.3},\"step_ms\":{:.6},\"phases_ms\":{{{}}}}}",
+            steps,
+            sim.flock.len(),
+            sim.params.enable_spatial_grid,
+            sim.params.enable_parallel,
+            threads,
+            timings.substeps,
+            sim.params.neighbor_list_interval,
+            elapsed,
+            steps_per_second,
+            step_ms,
+            phase_fields.join(",")
+        );
+    } else {
+        println!(
+            "{} steps with {} boids (spatial grid: {}, parallel: {}, threads: {})",
+            steps,
+            sim.flock.len(),
+            if sim.params.enable_spatial_grid { "on" } else { "off" },
+            if sim.params.enable_parallel { "on" } else { "off" },
+            threads
+        );
+        println!("Total time: {:.3} s", elapsed);
+        println!("Steps/second: {:.1}", steps_per_second);
+        println!(
+            "Average step: {:.3} ms ({} substeps, neighbor lists gathered every {} steps)",
+            step_ms,
+            timings.substeps,
+            sim.params.neighbor_list_interval.max(1)
+        );
+        for (_, label, total) in phases {
+            let phase_ms = total.as_secs_f64() * 1000.0 / steps_f;
+            let percentage = if step_ms > 0.0 { phase_ms / step_ms * 100.0 } else { 0.0 };
+            println!("  {}: {:.3} ms ({:.1}%)", label, phase_ms, percentage);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+/*
+ * Modulation Module
+ * 
+ * Parameter modulation for live visuals. Each mapping takes a signal in [-1, 1] from a
+ * source (for now a built-in LFO), scales it by an amplitude, shifts it by an offset and
+ * adds the result to one flocking parameter of every species.
+ * 
+ * The mappings are evaluated once per frame on the copy of the parameters that is sent
+ * to the physics thread, so the sliders keep their base values while the physics
+ * thread's change detection reacts to the swings like it does to slider changes (e.g.
+ * rebuilding the spatial grid when a radius grows). The boid count can't be modulated,
+ * so modulation never resizes or resets the flock.
+ */
+
+use std::f64::consts::TAU;
+use std::ops::RangeInclusive;
+
+use crate::params::{SimulationParams, SpeciesParams};
+
+// Shape of an LFO's signal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 2] = [Waveform::Sine, Waveform::Triangle];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            Waveform::Sine => "Sine",
+            Waveform::Triangle => "Triangle",
+        }
+    }
+    
+    // The signal at a phase in [0, 1): 0 at the start, 1 at a quarter, -1 at three quarters
+    fn sample(self, phase: f64) -> f32 {
+        match self {
+            Waveform::Sine => (phase * TAU).sin() as f32,
+            Waveform::Triangle => (1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs()) as f32,
+        }
+    }
+}
+
+// Where a mapping's signal comes from (external inputs such as OSC would be further variants)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModSource {
+    Lfo { waveform: Waveform, rate: f32 }, // Rate in cycles per second
+}
+
+impl ModSource {
+    // The signal at a time in seconds, in [-1, 1]
+    pub fn value(&self, time: f64) -> f32 {
+        match *self {
+            ModSource::Lfo { waveform, rate } => waveform.sample((time * rate as f64).fract()),
+        }
+    }
+}
+
+// The parameters a mapping can drive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModTarget {
+    SeparationWeight,
+    AlignmentWeight,
+    CohesionWeight,
+    SeparationRadius,
+    AlignmentRadius,
+    CohesionRadius,
+    MaxSpeed,
+}
+
+impl ModTarget {
+    pub const ALL: [ModTarget; 7] = [
+        ModTarget::SeparationWeight,
+        ModTarget::AlignmentWeight,
+        ModTarget::CohesionWeight,
+        ModTarget::SeparationRadius,
+        ModTarget::AlignmentRadius,
+        ModTarget::CohesionRadius,
+        ModTarget::MaxSpeed,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            ModTarget::SeparationWeight => "Separation Weight",
+            ModTarget::AlignmentWeight => "Alignment Weight",
+            ModTarget::CohesionWeight => "Cohesion Weight",
+            ModTarget::SeparationRadius => "Separation Radius",
+            ModTarget::AlignmentRadius => "Alignment Radius",
+            ModTarget::CohesionRadius => "Cohesion Radius",
+            ModTarget::MaxSpeed => "Max Speed",
+        }
+    }
+    
+    // Name in preset files (the parameter's preset key)
+    pub fn key(self) -> &'static str {
+        match self {
+            ModTarget::SeparationWeight => "separation_weight",
+            ModTarget::AlignmentWeight => "alignment_weight",
+            ModTarget::CohesionWeight => "cohesion_weight",
+            ModTarget::SeparationRadius => "separation_radius",
+            ModTarget::AlignmentRadius => "alignment_radius",
+            ModTarget::CohesionRadius => "cohesion_radius",
+            ModTarget::MaxSpeed => "max_speed",
+        }
+    }
+    
+    // The slider range, which modulated values are clamped to
+    pub fn range(self) -> RangeInclusive<f32> {
+        match self {
+            ModTarget::SeparationWeight | ModTarget::AlignmentWeight | ModTarget::CohesionWeight => SimulationParams::get_weight_range(),
+            ModTarget::SeparationRadius | ModTarget::AlignmentRadius | ModTarget::CohesionRadius => SimulationParams::get_radius_range(),
+            ModTarget::MaxSpeed => SimulationParams::get_max_speed_range(),
+        }
+    }
+    
+    // The slider value (the active species' value)
+    pub fn slider_value(self, params: &mut SimulationParams) -> &mut f32 {
+        match self {
+            ModTarget::SeparationWeight => &mut params.separation_weight,
+            ModTarget::AlignmentWeight => &mut params.alignment_weight,
+            ModTarget::CohesionWeight => &mut params.cohesion_weight,
+            ModTarget::SeparationRadius => &mut params.separation_radius,
+            ModTarget::AlignmentRadius => &mut params.alignment_radius,
+            ModTarget::CohesionRadius => &mut params.cohesion_radius,
+            ModTarget::MaxSpeed => &mut params.max_speed,
+        }
+    }
+    
+    fn species_value(self, species: &mut SpeciesParams) -> &mut f32 {
+        match self {
+            ModTarget::SeparationWeight => &mut species.separation_weight,
+            ModTarget::AlignmentWeight => &mut species.alignment_weight,
+            ModTarget::CohesionWeight => &mut species.cohesion_weight,
+            ModTarget::SeparationRadius => &mut species.separation_radius,
+            ModTarget::AlignmentRadius => &mut species.alignment_radius,
+            ModTarget::CohesionRadius => &mut species.cohesion_radius,
+            ModTarget::MaxSpeed => &mut species.max_speed,
+        }
+    }
+}
+
+// Range of the LFO rate slider, in cycles per second
+pub fn get_lfo_rate_range() -> RangeInclusive<f32> {
+    0.01..=10.0
+}
+
+// One source driving one parameter: base value + offset + amplitude * signal
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mapping {
+    pub source: ModSource,
+    pub target: ModTarget,
+    pub amplitude: f32,
+    pub offset: f32,
+}
+
+impl Mapping {
+    // A slow sine swinging the target by a tenth of its range
+    pub fn new(target: ModTarget) -> Self {
+        Self {
+            source: ModSource::Lfo { waveform: Waveform::Sine, rate: 0.25 },
+            target,
+            amplitude: span(&target.range()) * 0.1,
+            offset: 0.0,
+        }
+    }
+    
+    // What the mapping adds to the base value at a time
+    pub fn delta(&self, time: f64) -> f32 {
+        self.offset + self.amplitude * self.source.value(time)
+    }
+    
+    // The target's value at a time, from its base value
+    pub fn modulated(&self, base: f32, time: f64) -> f32 {
+        let range = self.target.range();
+        (base + self.delta(time)).clamp(*range.start(), *range.end())
+    }
+}
+
+// Width of a slider range
+pub fn span(range: &RangeInclusive<f32>) -> f32 {
+    range.end() - range.start()
+}
+
+// The mappings and the clock their sources run on
+#[derive(Clone, Debug, Default)]
+pub struct Modulator {
+    pub mappings: Vec<Mapping>,
+    pub time: f64, // Seconds the sources have run (f64 so the phase stays exact over a long set)
+}
+
+impl Modulator {
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt as f64;
+    }
+    
+    // Modulate every species' values, then refresh the slider values from the active species.
+    // Several mappings on the same target add up.
+    pub fn apply(&self, params: &mut SimulationParams) {
+        if self.mappings.is_empty() {
+            return;
+        }
+        
+        for mapping in &self.mappings {
+            for species in &mut params.species {
+                let value = mapping.target.species_value(species);
+                *value = mapping.modulated(*value, self.time);
+            }
+        }
+        params.load_active_species();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "expected {} to be approximately {}", a, b);
+    }
+
+    #[test]
+    fn waveforms_peak_at_the_same_phases() {
+        for waveform in Waveform::ALL {
+            assert_approx_eq(waveform.sample(0.0), 0.0);
+            assert_approx_eq(waveform.sample(0.25), 1.0);
+            assert_approx_eq(waveform.sample(0.5), 0.0);
+            assert_approx_eq(waveform.sample(0.75), -1.0);
+        }
+
+        // The triangle is linear between the peaks
+        assert_approx_eq(Waveform::Triangle.sample(0.125), 0.5);
+        assert_approx_eq(Waveform::Triangle.sample(0.875), -0.5);
+
+        // An LFO at 2 Hz is at its peak after an eighth of a second
+        let lfo = ModSource::Lfo { waveform: Waveform::Sine, rate: 2.0 };
+        assert_approx_eq(lfo.value(0.125), 1.0);
+        assert_approx_eq(lfo.value(100.125), 1.0);
+    }
+
+    #[test]
+    fn mappings_modulate_every_species_within_the_range() {
+        let mut params = SimulationParams::default();
+        params.add_species();
+        params.species[0].cohesion_weight = 1.0;
+        params.species[1].cohesion_weight = 2.0;
+        params.active_species = 1;
+
+        let mut modulator = Modulator::default();
+        let mut mapping = Mapping::new(ModTarget::CohesionWeight);
+        mapping.source = ModSource::Lfo { waveform: Waveform::Triangle, rate: 1.0 };
+        mapping.amplitude = 0.5;
+        mapping.offset = 0.25;
+        modulator.mappings.push(mapping);
+        modulator.advance(0.25);
+
+        let mut modulated = params.clone();
+        modulator.apply(&mut modulated);
+        assert_approx_eq(modulated.species[0].cohesion_weight, 1.75);
+        assert_approx_eq(modulated.species[1].cohesion_weight, 2.75);
+        assert_approx_eq(modulated.cohesion_weight, 2.75);
+
+        // Other parameters are left alone
+        assert_eq!(modulated.species[0].separation_weight, params.species[0].separation_weight);
+        assert_eq!(modulated.max_speed, params.max_speed);
+
+        // Swings past the end of the range are clamped
+        modulator.mappings[0].amplitude = 100.0;
+        let mut modulated = params.clone();
+        modulator.apply(&mut modulated);
+        assert_eq!(modulated.species[0].cohesion_weight, *ModTarget::CohesionWeight.range().end());
+    }
+}
@@ -0,0 +1,319 @@
+/*
+ * OSC Module
+ * 
+ * This module sends flock metrics as Open Sound Control messages over UDP, so lighting
+ * rigs, synthesizers and other creative-coding tools can react to the flock. OSC messages
+ * are simple enough to encode here, so no OSC library is needed.
+ * 
+ * Messages (one datagram each, sent at most `rate` times per second):
+ * 
+ *   /boids/polarization  f      order parameter: 0 = disordered, 1 = fully aligned
+ *   /boids/centroid      f f    wrapped centroid of the flock
+ *   /boids/count         i      number of boids
+ *   /boids/selected      i f f  id and position of the selected boid (while one is selected)
+ * 
+ * As in the stream module, the simulation thread only encodes the messages and pushes
+ * them into a small bounded channel; a background thread resolves the target host and
+ * sends them. Network errors (an unknown host, an unreachable network) are logged and
+ * shown in the UI while the sender keeps going, so the simulation never waits on them.
+ */
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::flock::Flock;
+use crate::params::SimulationParams;
+use crate::physics::{self, FlockMetrics};
+
+// Batches of messages waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 4;
+
+// How often a host that failed to resolve is looked up again
+const RESOLVE_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+// An OSC message argument
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+}
+
+// Append a string in OSC form: null-terminated, then padded with nulls to a multiple of 4 bytes
+fn push_osc_string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.resize(bytes.len() + 4 - text.len() % 4, 0);
+}
+
+// Encode a message: the address, the type tags, then the big-endian arguments
+pub fn encode_message(address: &str, args: &[OscArg]) -> Vec<u8> {
+    let type_tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+        }))
+        .collect();
+    
+    let mut bytes = Vec::with_capacity(address.len() + type_tags.len() + 8 + args.len() * 4);
+    push_osc_string(&mut bytes, address);
+    push_osc_string(&mut bytes, &type_tags);
+    for arg in args {
+        match arg {
+            OscArg::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+    
+    bytes
+}
+
+// Encode the messages enabled in the parameters. Polarization is only sent while the
+// metrics are computed (SimulationParams::metrics_enabled includes the OSC output).
+pub fn collect_messages(
+    params: &SimulationParams,
+    flock: &Flock,
+    metrics: Option<&FlockMetrics>,
+    selected: Option<usize>,
+) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    
+    if params.osc_send_polarization {
+        if let Some(metrics) = metrics {
+            messages.push(encode_message("/boids/polarization", &[OscArg::Float(metrics.polarization)]));
+        }
+    }
+    
+    if params.osc_send_centroid {
+        if let Some(centroid) = physics::wrapped_centroid(flock.positions.iter().copied(), params.world_size) {
+            messages.push(encode_message("/boids/centroid", &[OscArg::Float(centroid.x), OscArg::Float(centroid.y)]));
+        }
+    }
+    
+    if params.osc_send_count {
+        messages.push(encode_message("/boids/count", &[OscArg::Int(flock.len() as i32)]));
+    }
+    
+    if params.osc_send_selected {
+        if let Some(index) = selected.filter(|&index| index < flock.len()) {
+            let position = flock.positions[index];
+            messages.push(encode_message(
+                "/boids/selected",
+                &[OscArg::Int(flock.ids[index] as i32), OscArg::Float(position.x), OscArg::Float(position.y)],
+            ));
+        }
+    }
+    
+    messages
+}
+
+pub struct OscSender {
+    host: String,
+    port: u16,
+    interval: Duration, // Shortest time between two batches
+    last_sent: Option<Instant>,
+    sender: SyncSender<Vec<Vec<u8>>>,
+    status: OscStatus,
+}
+
+// Shared view of the sender's state, cheap to clone and safe to read from any thread
+#[derive(Clone)]
+pub struct OscStatus {
+    target: String, // host:port as configured
+    messages_sent: Arc<AtomicUsize>,
+    batches_dropped: Arc<AtomicUsize>, // Skipped because the sender was behind or the host unresolved
+    error: Arc<Mutex<Option<String>>>, // Latest network error, cleared once sending works again
+}
+
+impl OscSender {
+    // Open a UDP socket and start the sender thread. The host is resolved on that thread,
+    // so a slow or failing lookup doesn't hold up the caller.
+    pub fn start(host: &str, port: u16, rate: f32) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        let (sender, receiver) = mpsc::sync_channel::<Vec<Vec<u8>>>(QUEUE_CAPACITY);
+        let status = OscStatus {
+            target: format!("{}:{}", host, port),
+            messages_sent: Arc::new(AtomicUsize::new(0)),
+            batches_dropped: Arc::new(AtomicUsize::new(0)),
+            error: Arc::new(Mutex::new(None)),
+        };
+        
+        let sender_status = status.clone();
+        let target_host = host.to_string();
+        thread::spawn(move || {
+            let mut address: Option<SocketAddr> = None;
+            let mut last_lookup: Option<Instant> = None;
+            
+            // Runs until the OscSender is dropped
+            while let Ok(messages) = receiver.recv() {
+                if address.is_none() && last_lookup.is_none_or(|time| time.elapsed() >= RESOLVE_RETRY_INTERVAL) {
+                    last_lookup = Some(Instant::now());
+                    match (target_host.as_str(), port).to_socket_addrs() {
+                        Ok(mut addresses) => {
+                            address = addresses.find(SocketAddr::is_ipv4);
+                            if address.is_none() {
+                                sender_status.set_error(Some(format!("no IPv4 address for {}", sender_status.target)));
+                            }
+                        }
+                        Err(err) => sender_status.set_error(Some(format!("failed to resolve {}: {}", sender_status.target, err))),
+                    }
+                }
+                
+                let Some(address) = address else {
+                    sender_status.batches_dropped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                };
+                
+                for message in &messages {
+                    match socket.send_to(message, address) {
+                        Ok(_) => {
+                            sender_status.messages_sent.fetch_add(1, Ordering::Relaxed);
+                            sender_status.set_error(None);
+                        }
+                        Err(err) => sender_status.set_error(Some(format!("failed to send to {}: {}", sender_status.target, err))),
+                    }
+                }
+            }
+        });
+        
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            interval: rate_interval(rate),
+            last_sent: None,
+            sender,
+            status,
+        })
+    }
+    
+    // Change the send rate without restarting the sender
+    pub fn configure(&mut self, rate: f32) {
+        self.interval = rate_interval(rate);
+    }
+    
+    // Whether the sender sends to this host and port
+    pub fn targets(&self, host: &str, port: u16) -> bool {
+        self.host == host && self.port == port
+    }
+    
+    // Whether a batch is due at `now`; if it is, the next one is due an interval later
+    pub fn due(&mut self, now: Instant) -> bool {
+        let due = self.last_sent.is_none_or(|last_sent| now.duration_since(last_sent) >= self.interval);
+        if due {
+            self.last_sent = Some(now);
+        }
+        due
+    }
+    
+    // Queue a batch of encoded messages, dropping it if the sender thread is behind (never blocks)
+    pub fn send(&self, messages: Vec<Vec<u8>>) {
+        if messages.is_empty() {
+            return;
+        }
+        
+        match self.sender.try_send(messages) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.status.batches_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // The sender thread only exits when this is dropped
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+    
+    // A handle for watching this sender from another thread
+    pub fn status(&self) -> OscStatus {
+        self.status.clone()
+    }
+}
+
+// Time between batches for a rate in messages per second
+fn rate_interval(rate: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / rate.max(0.1))
+}
+
+impl OscStatus {
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+    
+    pub fn messages_sent(&self) -> usize {
+        self.messages_sent.load(Ordering::Relaxed)
+    }
+    
+    pub fn batches_dropped(&self) -> usize {
+        self.batches_dropped.load(Ordering::Relaxed)
+    }
+    
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+    
+    // Record the latest error, logging it only when it changes so a dead host doesn't flood the log
+    fn set_error(&self, error: Option<String>) {
+        let mut current = self.error.lock().unwrap();
+        if *current != error {
+            if let Some(error) = &error {
+                eprintln!("warning: OSC output: {}", error);
+            }
+            *current = error;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boid::Boid;
+
+    #[test]
+    fn messages_are_padded_to_four_bytes() {
+        let bytes = encode_message("/boids/count", &[OscArg::Int(3)]);
+
+        // "/boids/count" is 12 bytes, so it gets 4 bytes of nulls; ",i" gets 2
+        let mut expected = b"/boids/count\0\0\0\0,i\0\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        assert_eq!(bytes, expected);
+
+        let bytes = encode_message("/boids/centroid", &[OscArg::Float(1.0), OscArg::Float(-2.0)]);
+        assert_eq!(&bytes[..20], b"/boids/centroid\0,ff\0");
+        assert_eq!(&bytes[20..], [1.0f32.to_be_bytes(), (-2.0f32).to_be_bytes()].concat());
+    }
+
+    #[test]
+    fn only_the_enabled_messages_are_collected() {
+        let mut flock = Flock::with_capacity(2);
+        flock.push(Boid::new(10.0, 20.0));
+        flock.push(Boid::new(30.0, 20.0));
+        let metrics = FlockMetrics { polarization: 0.5, mean_nearest_neighbor_distance: None, angular_momentum: 0.0 };
+        let mut params = SimulationParams::default();
+        params.osc_send_polarization = true;
+        params.osc_send_centroid = false;
+        params.osc_send_count = true;
+        params.osc_send_selected = true;
+
+        let messages = collect_messages(&params, &flock, Some(&metrics), Some(1));
+
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with(b"/boids/polarization\0"));
+        assert!(messages[1].starts_with(b"/boids/count\0"));
+        assert!(messages[2].starts_with(b"/boids/selected\0"));
+        assert!(messages[2].ends_with(&[30.0f32.to_be_bytes(), 20.0f32.to_be_bytes()].concat()));
+
+        // Nothing to report without metrics or a selection
+        assert_eq!(collect_messages(&params, &flock, None, None).len(), 1);
+    }
+
+    #[test]
+    fn batches_are_rate_limited() {
+        let mut sender = OscSender::start("127.0.0.1", 9, 10.0).unwrap();
+        let start = Instant::now();
+
+        assert!(sender.due(start));
+        assert!(!sender.due(start + Duration::from_millis(50)));
+        assert!(sender.due(start + Duration::from_millis(150)));
+    }
+}
@@ -0,0 +1,205 @@
+/*
+ * Palette Module
+ * 
+ * Colors of the scene: the background, the base boid color, the selection highlight,
+ * the debug overlays and the world boundary (and walls). A few built-in palettes set all
+ * of them at once; editing any color switches to the custom palette.
+ * 
+ * The boid color is the color of the species that haven't been given their own color:
+ * switching palettes recolors every species still drawn in the previous boid color.
+ */
+
+use nannou::prelude::*;
+
+// What the boids are colored by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Species,  // Their species' color, shaded by their state (see Boid::display_color)
+    GridCell, // A color per spatial grid cell, to see the partitioning (needs the spatial grid)
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 2] = [ColorMode::Species, ColorMode::GridCell];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorMode::Species => "Species",
+            ColorMode::GridCell => "Grid Cell",
+        }
+    }
+}
+
+// Color of a spatial grid cell in the grid cell color mode. The hues repeat every three
+// cells in each direction, so a cell never shares its color with any of the eight around
+// it (except across the world edge when the grid size isn't a multiple of three).
+pub fn cell_color(cell_index: usize, grid_size: usize) -> Rgb<u8> {
+    let (column, row) = (cell_index % grid_size.max(1), cell_index / grid_size.max(1));
+    // Consecutive tiles are four ninths of the color wheel apart
+    let tile = (column % 3 + 3 * (row % 3)) * 4 % 9;
+    hsv_to_rgb(tile as f32 / 9.0, 0.65, 0.95)
+}
+
+// Convert a hue (0 to 1, around the color wheel), saturation and value to a color
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Rgb<u8> {
+    let sector = hue.rem_euclid(1.0) * 6.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (red, green, blue) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let minimum = value - chroma;
+    let byte = |channel: f32| ((channel + minimum) * 255.0).round() as u8;
+    rgb(byte(red), byte(green), byte(blue))
+}
+
+// Named palettes, plus Custom for hand-picked colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Palette {
+    Dark,
+    Light,
+    Neon,
+    Pastel,
+    Custom,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Palette::Dark,
+        Palette::Light,
+        Palette::Neon,
+        Palette::Pastel,
+        Palette::Custom,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Dark => "Dark",
+            Palette::Light => "Light",
+            Palette::Neon => "Neon",
+            Palette::Pastel => "Pastel",
+            Palette::Custom => "Custom",
+        }
+    }
+    
+    // The palette's colors (None for Custom, which keeps whatever colors are set)
+    pub fn colors(self) -> Option<PaletteColors> {
+        let colors = match self {
+            Palette::Dark => PaletteColors {
+                background: [14, 16, 22],
+                boid: [220, 220, 220],
+                selection: [255, 255, 255],
+                debug: [255, 160, 40],
+                boundary: [128, 128, 128],
+            },
+            Palette::Light => PaletteColors {
+                background: [238, 236, 230],
+                boid: [44, 48, 60],
+                selection: [0, 0, 0],
+                debug: [200, 80, 0],
+                boundary: [150, 150, 150],
+            },
+            Palette::Neon => PaletteColors {
+                background: [8, 6, 20],
+                boid: [60, 255, 200],
+                selection: [255, 60, 200],
+                debug: [255, 230, 0],
+                boundary: [90, 60, 160],
+            },
+            Palette::Pastel => PaletteColors {
+                background: [42, 40, 54],
+                boid: [190, 225, 240],
+                selection: [255, 215, 170],
+                debug: [200, 235, 180],
+                boundary: [110, 105, 130],
+            },
+            Palette::Custom => return None,
+        };
+        Some(colors)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaletteColors {
+    pub background: [u8; 3],
+    pub boid: [u8; 3],      // Base color of the species
+    pub selection: [u8; 3], // Outline of the boid under the cursor
+    pub debug: [u8; 3],     // Frustum, cursor crosshair and grid cell, combined force, debug text
+    pub boundary: [u8; 3],  // World boundary and walls
+}
+
+impl Default for PaletteColors {
+    fn default() -> Self {
+        Palette::Dark.colors().unwrap()
+    }
+}
+
+// A palette color for the draw API
+pub fn color(rgb: [u8; 3]) -> Rgb8 {
+    rgb8(rgb[0], rgb[1], rgb[2])
+}
+
+// Edge band and the tint of boids near the world edge, the same in every palette
+pub const EDGE_WARNING: [u8; 3] = [255, 140, 40];
+
+// A palette color with an opacity from 0 to 1
+pub fn color_alpha(rgb: [u8; 3], alpha: f32) -> Rgba8 {
+    rgba8(rgb[0], rgb[1], rgb[2], (alpha.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+// Format a color for a preset file as "r, g, b"
+pub fn format_color(rgb: [u8; 3]) -> String {
+    format!("\"{}, {}, {}\"", rgb[0], rgb[1], rgb[2])
+}
+
+// Parse a color written by format_color
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let mut components = value.split(',').map(|component| component.trim().parse::<u8>());
+    let color = [components.next()?.ok()?, components.next()?.ok()?, components.next()?.ok()?];
+    components.next().is_none().then_some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_survive_the_preset_format() {
+        let color = [12, 200, 255];
+        let formatted = format_color(color);
+
+        assert_eq!(parse_color(formatted.trim_matches('"')), Some(color));
+        assert_eq!(parse_color("1,2"), None);
+        assert_eq!(parse_color("1, 2, 3, 4"), None);
+        assert_eq!(parse_color("1, 2, 300"), None);
+    }
+
+    #[test]
+    fn grid_cells_differ_in_color_from_their_neighbors() {
+        let grid_size = 9;
+        for row in 1..grid_size - 1 {
+            for column in 1..grid_size - 1 {
+                let color = cell_color(row * grid_size + column, grid_size);
+                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    let neighbor = (row as i32 + dy) as usize * grid_size + (column as i32 + dx) as usize;
+                    assert_ne!(cell_color(neighbor, grid_size), color);
+                }
+            }
+        }
+
+        // Primary hues come out exactly
+        assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), rgb(255, 0, 0));
+        assert_eq!(hsv_to_rgb(1.0 / 3.0, 1.0, 1.0), rgb(0, 255, 0));
+    }
+
+    #[test]
+    fn the_dark_palette_keeps_the_first_species_color() {
+        // Species created before any palette is chosen use the dark palette's boid color
+        assert_eq!(PaletteColors::default().boid, [220, 220, 220]);
+        assert!(Palette::ALL.iter().all(|palette| palette.colors().is_some() != (*palette == Palette::Custom)));
+    }
+}
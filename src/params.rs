@@ -9,9 +9,121 @@
  * Optimized for performance with spatial partitioning and adaptive settings.
  */
 
+use rand::Rng;
+
+use crate::boid::BoidShape;
+use crate::export::ExportFormat;
+use crate::palette::{self, ColorMode, Palette, PaletteColors};
+use crate::timeline::Keyframe;
+
+// Colors assigned to newly added species (cycled)
+const SPECIES_PALETTE: [[u8; 3]; 6] = [
+    [220, 220, 220],
+    [230, 90, 80],
+    [90, 180, 240],
+    [120, 220, 110],
+    [240, 200, 80],
+    [200, 120, 230],
+];
+
+// Steering force limit of the default species
+pub const DEFAULT_MAX_FORCE: f32 = 0.1;
+
+// Flocking parameters for a single species (a breed, when loaded from a breeds file)
+#[derive(Clone, PartialEq)]
+pub struct SpeciesParams {
+    pub name: String,
+    pub color: [u8; 3],
+    pub size: f32,      // Drawn size relative to boid_size
+    pub max_force: f32, // Steering force limit of its boids
+    pub share: f32,     // Percentage of the flock (the shares of all species add up to 100)
+    pub separation_weight: f32,
+    pub alignment_weight: f32,
+    pub cohesion_weight: f32,
+    pub separation_radius: f32,
+    pub alignment_radius: f32,
+    pub cohesion_radius: f32,
+    pub max_speed: f32,
+}
+
+// How the boids are placed when the flock is reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InitialLayout {
+    RandomUniform,
+    Ring,         // Evenly spaced on a circle, flying around it
+    Grid,         // On a square lattice, with random headings
+    TwoStreams,   // Two groups flying head-on into each other
+    ClusterBurst, // Packed at the center, flying outward
+}
+
+impl InitialLayout {
+    pub const ALL: [InitialLayout; 5] = [
+        InitialLayout::RandomUniform,
+        InitialLayout::Ring,
+        InitialLayout::Grid,
+        InitialLayout::TwoStreams,
+        InitialLayout::ClusterBurst,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            InitialLayout::RandomUniform => "Random",
+            InitialLayout::Ring => "Ring",
+            InitialLayout::Grid => "Grid",
+            InitialLayout::TwoStreams => "Two Streams",
+            InitialLayout::ClusterBurst => "Cluster Burst",
+        }
+    }
+}
+
+// How strongly a neighbor inside the separation radius pushes a boid away, by distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeparationFalloff {
+    InverseSquare, // 1/d²
+    Inverse,       // 1/d (the classic offset / d²)
+    Linear,        // From full strength at the boid to zero at the separation radius
+    Constant,      // Every neighbor pushes equally hard
+    Power,         // 1/d^p, with p = separation_falloff_power
+}
+
+impl SeparationFalloff {
+    pub const ALL: [SeparationFalloff; 5] = [
+        SeparationFalloff::InverseSquare,
+        SeparationFalloff::Inverse,
+        SeparationFalloff::Linear,
+        SeparationFalloff::Constant,
+        SeparationFalloff::Power,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            SeparationFalloff::InverseSquare => "Inverse Square",
+            SeparationFalloff::Inverse => "Inverse",
+            SeparationFalloff::Linear => "Linear",
+            SeparationFalloff::Constant => "Constant",
+            SeparationFalloff::Power => "Power",
+        }
+    }
+    
+    // What the curve does, for the UI tooltip
+    pub fn description(self) -> &'static str {
+        match self {
+            SeparationFalloff::InverseSquare => "Push falls off with the square of the distance: very strong at close range, barely felt at mid range",
+            SeparationFalloff::Inverse => "Push falls off with the distance (the classic weighting): strong up close, still felt at mid range",
+            SeparationFalloff::Linear => "Push falls linearly from full strength up close to nothing at the separation radius",
+            SeparationFalloff::Constant => "Every neighbor inside the separation radius pushes equally hard, however close",
+            SeparationFalloff::Power => "Push falls off with the distance raised to the falloff power (2 is inverse square, 1 inverse, 0 constant)",
+        }
+    }
+}
+
 // Parameters for the simulation that can be adjusted via UI
+#[derive(Clone)]
 pub struct SimulationParams {
-    pub num_boids: usize,
+    pub num_boids: usize, // Target population, which the flock grows or shrinks toward
+    pub instant_resize: bool, // Apply num_boids changes at once instead of gradually
+    pub initial_layout: InitialLayout, // Placement of the boids on reset
+    pub min_spawn_spacing: f32, // Smallest distance between new boids when they are placed (0 = no check)
     pub separation_weight: f32,
     pub alignment_weight: f32,
     pub cohesion_weight: f32,
@@ -20,25 +132,161 @@ pub struct SimulationParams {
     pub cohesion_radius: f32,
     pub max_speed: f32,
     pub world_size: f32,  // Added world size parameter
+    pub scale_radii_with_world: bool, // Scale the species' radii with world size changes so the flock keeps its look
+    pub scale_speed_with_world: bool, // Scale the max speeds along with the radii
+    // Wind tunnel mode: the left and right edges are open, boids enter at the left edge
+    // and are removed once they leave the world (the top and bottom edges still wrap)
+    pub wind_tunnel_mode: bool,
+    pub wind_tunnel_rate: f32, // Boids per second entering at the left edge while the flock is below num_boids
+    // Disabled rules are skipped entirely by the physics (unlike a zero weight)
+    pub enable_separation: bool,
+    pub enable_alignment: bool,
+    pub enable_cohesion: bool,
+    pub separation_falloff: SeparationFalloff,
+    pub separation_falloff_power: f32, // Exponent of the Power falloff
+    // Arrival: boids brake when approaching their cohesion target instead of overshooting it
+    pub enable_arrival: bool,
+    pub slowing_radius: f32, // Distance from the target at which boids start to slow down
+    // Far-field cohesion: a weak pull toward the boids far beyond the perception radii, so
+    // separate groups find each other (spatial grid only)
+    pub far_cohesion_weight: f32, // 0 = off
+    pub far_cohesion_radius: f32,
+    // Vicsek-style noise: each step every velocity is turned by a random angle in [-noise/2, noise/2]
+    pub angular_noise: f32, // Degrees (0 = no noise)
+    // Per-species parameters. The weight/radius/speed fields above always mirror
+    // the active species, so the sliders edit whichever species is selected.
+    pub species: Vec<SpeciesParams>,
+    pub active_species: usize,
+    pub spawn_at_cursor: bool, // Clicking empty space spawns a burst of boids instead of panning
+    pub spawn_burst_size: usize, // Number of boids added per spawn burst
+    pub emission_rate: f32, // Boids per second emitted while holding the right mouse button
+    pub edit_mode: bool, // Clicking a boid drags it instead of following it (same as holding Ctrl)
+    pub wall_mode: bool, // Clicks place wall endpoints instead of selecting boids
+    pub wall_avoid_radius: f32, // Distance at which boids start steering away from walls
+    pub wall_avoid_weight: f32,
+    pub randomize_sane_ranges: bool, // Constrain "Randomize" to ranges that tend to produce flocking
+    // Infection mode: boids of the infected species convert everyone they touch
+    pub infection_mode: bool, // Clicking a boid infects it, and infected boids spread the infection
+    pub infected_species: usize, // Index into species
+    pub contact_radius: f32, // Distance at which an infected boid converts another
+    // Energy mode: boids starve unless they flock, and dead boids are replaced after a delay
+    pub enable_energy: bool,
+    pub energy_drain_rate: f32, // Energy lost per second at rest (twice as much at full speed)
+    pub energy_gain_rate: f32, // Energy regained per second while flocking
+    pub energy_neighbor_threshold: usize, // A boid is flocking with more neighbors than this
+    pub respawn_delay: f32, // Seconds before a dead boid is replaced at a random location
+    // Pheromones: boids leave a decaying scent trail on a coarse grid and can steer up its gradient
+    pub enable_pheromones: bool,
+    pub pheromone_deposit: f32, // Pheromone each boid adds per step
+    pub pheromone_decay: f32, // Fraction of the pheromone that evaporates per step
+    pub pheromone_weight: f32, // Strength of the steering up the gradient
+    pub show_pheromones: bool, // Draw the field as a heatmap
+    // Perching: boids flying down into a ground band at the bottom of the world may land and rest
+    pub enable_perching: bool,
+    pub perch_band_height: f32, // Height of the ground band above the bottom edge of the world
+    pub perch_chance: f32, // Probability that a boid entering the band from above lands
+    pub perch_min_rest: f32, // Seconds a landed boid rests, picked between the min and max
+    pub perch_max_rest: f32,
+    pub perch_takeoff_speed: f32, // Speed at which a rested boid takes off upward
+    pub perched_influence: bool, // Perched boids still count as neighbors in the flocking rules
+    // Camera settings
+    pub zoom_sensitivity: f32, // Zoom change per mouse wheel line
+    pub invert_zoom: bool,     // Reverse the scroll direction for zooming
+    pub preserve_view_on_resize: bool, // Zoom with window resizes so the same amount of world stays visible
+    pub follow_deadzone: f32,  // Fraction of the window a followed target may drift from the center before the camera moves
+    pub follow_look_ahead: bool, // Lead a followed boid so there is more space in front of it than behind
     pub show_debug: bool,
+    pub compute_metrics: bool, // Compute the flock metrics even while the debug overlay is hidden
+    pub show_velocity_vectors: bool, // Draw velocity arrows for all visible boids (debug)
+    pub show_cursor_info: bool, // Cursor world position and grid cell readout (plus a crosshair in debug mode)
+    // Perception circles drawn around the selected (or first) boid in debug mode
+    pub show_separation_radius: bool,
+    pub show_alignment_radius: bool,
+    pub show_cohesion_radius: bool,
+    pub boid_size: f32, // Drawn length of a boid in world units
+    pub boid_shape: BoidShape,
+    pub spawn_effects: bool, // Fade boids in when they appear and out when they are removed
+    pub show_edge_band: bool, // Shade a band inside the world edge, strongest at the edge
+    pub edge_band_width: f32, // Width of the edge band in world units
+    pub tint_edge_boids: bool, // Tint boids inside the edge band, more the closer they are to the edge
+    pub tint_tagged_boids: bool, // Draw tagged boids in their tag's color (see the tags module)
+    pub palette: Palette,
+    pub colors: PaletteColors, // The palette's colors, or the custom ones
+    pub color_mode: ColorMode,
     pub pause_simulation: bool,
     // Performance settings
     pub enable_parallel: bool,
+    pub num_threads: usize, // Worker threads for the parallel physics (0 = rayon's default, one per core)
     pub enable_spatial_grid: bool,
-    pub cell_size_factor: f32,  // Multiplier for cell size relative to perception radius
-    pub enable_squared_distance: bool, // Use squared distance calculations to avoid sqrt operations
+    pub cell_size_factor: f32,  // Multiplier for cell size relative to perception radius (speed only, not results)
+    pub fast_math: bool, // Approximate the square roots in the flocking rules (slightly changes the results)
     pub enable_frustum_culling: bool, // Enable frustum culling optimization
+    pub culling_margin_multiplier: f32, // Scales the culling margin (one snapshot's movement plus the boid size)
+    pub instanced_rendering: bool, // Draw the boids in one instanced GPU draw call instead of one polygon each
+    pub enable_render_budget: bool, // Draw only a stable subset of the visible boids while drawing takes too long
+    pub render_budget_ms: f32, // Draw time per frame above which the boids are subsampled
     pub adaptive_cell_sizing: bool, // Dynamically adjust cell size based on boid density
+    pub enable_spatial_sorting: bool, // Reorder the boids by grid cell so neighbors are near in memory (grid only)
+    pub spatial_sort_interval: usize, // Physics steps between reorderings
+    pub neighbor_list_interval: usize, // Physics steps each gathered neighbor list is used for (grid only)
     // Timing settings
     pub fixed_physics_fps: f32, // Fixed physics update rate (updates per second)
     pub target_render_fps: f32, // Target rendering framerate (0 = unlimited)
     pub enable_interpolation: bool, // Enable interpolation between physics updates
+    pub time_scale: f32, // Simulation speed multiplier (slow motion / fast forward)
+    pub max_steps_per_frame: usize, // Cap on physics steps per frame to avoid a death spiral
+    pub physics_substeps: u32, // Force/integrate passes per physics step, each over a fraction of it
+    // Export settings
+    pub export_format: ExportFormat,
+    pub export_stride: usize, // Physics steps between rows in continuous export mode
+    // Network streaming settings
+    pub enable_streaming: bool, // Send boid positions to UDP subscribers
+    pub stream_port: u16,
+    pub stream_stride: usize,    // Physics steps between streamed frames
+    pub stream_max_boids: usize, // Larger flocks are subsampled to this many boids per frame
+    // OSC output of the flock metrics (see the osc module for the messages)
+    pub enable_osc: bool,
+    pub osc_host: String,
+    pub osc_port: u16,
+    pub osc_rate: f32, // Message batches per second
+    pub osc_send_polarization: bool,
+    pub osc_send_centroid: bool,
+    pub osc_send_count: bool,
+    pub osc_send_selected: bool, // Position of the selected boid
+    
+    // Parameter animation (see the timeline module), one `keyframe` line each in presets
+    pub keyframes: Vec<Keyframe>,
     
     // Internal state for tracking changes
     previous_values: Option<ParamSnapshot>,
+    world_scale_base: Option<WorldScaleBase>,
+}
+
+// The species' radii and max speeds at a reference world size, from which the values
+// for other world sizes are computed (so scaling back and forth doesn't compound)
+#[derive(Clone)]
+struct WorldScaleBase {
+    world_size: f32,
+    values: Vec<[f32; 4]>, // Separation, alignment and cohesion radius, max speed per species
+}
+
+impl WorldScaleBase {
+    // The species' values at a world size (the max speeds only change if `scale_speed` is set)
+    fn values_at(&self, world_size: f32, scale_speed: bool) -> Vec<[f32; 4]> {
+        let factor = world_size / self.world_size;
+        self.values.iter()
+            .map(|&[separation, alignment, cohesion, speed]| [
+                separation * factor,
+                alignment * factor,
+                cohesion * factor,
+                if scale_speed { speed * factor } else { speed },
+            ])
+            .collect()
+    }
 }
 
 // A snapshot of parameter values used for change detection
+#[derive(Clone)]
 struct ParamSnapshot {
     num_boids: usize,
     separation_weight: f32,
@@ -49,10 +297,49 @@ struct ParamSnapshot {
     cohesion_radius: f32,
     max_speed: f32,
     world_size: f32,  // Added world size parameter
+    enable_separation: bool,
+    enable_alignment: bool,
+    enable_cohesion: bool,
+    enable_arrival: bool,
+    slowing_radius: f32,
+    angular_noise: f32,
+    species: Vec<SpeciesParams>,
+    infection_mode: bool,
+    infected_species: usize,
+    contact_radius: f32,
+    enable_energy: bool,
+    energy_drain_rate: f32,
+    energy_gain_rate: f32,
+    energy_neighbor_threshold: usize,
+    respawn_delay: f32,
+    enable_pheromones: bool,
+    pheromone_deposit: f32,
+    pheromone_decay: f32,
+    pheromone_weight: f32,
+    show_pheromones: bool,
+    enable_perching: bool,
+    wall_avoid_radius: f32,
+    wall_avoid_weight: f32,
     show_debug: bool,
-    enable_squared_distance: bool,
+    show_velocity_vectors: bool,
+    show_cursor_info: bool,
+    show_separation_radius: bool,
+    show_alignment_radius: bool,
+    show_cohesion_radius: bool,
+    boid_size: f32,
+    boid_shape: BoidShape,
+    show_edge_band: bool,
+    edge_band_width: f32,
+    tint_edge_boids: bool,
+    tint_tagged_boids: bool,
+    fast_math: bool,
     enable_frustum_culling: bool,
+    culling_margin_multiplier: f32,
+    instanced_rendering: bool,
     adaptive_cell_sizing: bool,
+    enable_spatial_sorting: bool,
+    spatial_sort_interval: usize,
+    neighbor_list_interval: usize,
     fixed_physics_fps: f32,
     target_render_fps: f32,
     enable_interpolation: bool,
@@ -60,8 +347,11 @@ struct ParamSnapshot {
 
 impl Default for SimulationParams {
     fn default() -> Self {
-        Self {
+        let mut params = Self {
             num_boids: 500, // Increased default number of boids for the larger world
+            instant_resize: false,
+            initial_layout: InitialLayout::RandomUniform,
+            min_spawn_spacing: 0.0,
             separation_weight: 1.5,
             alignment_weight: 1.0,
             cohesion_weight: 1.0,
@@ -70,26 +360,765 @@ impl Default for SimulationParams {
             cohesion_radius: 150.0,
             max_speed: 50.0,
             world_size: 5000.0, // Default world size (same as the constant)
+            scale_radii_with_world: false,
+            scale_speed_with_world: false,
+            wind_tunnel_mode: false,
+            wind_tunnel_rate: 200.0,
+            enable_separation: true,
+            enable_alignment: true,
+            enable_cohesion: true,
+            separation_falloff: SeparationFalloff::Inverse,
+            separation_falloff_power: 2.0,
+            enable_arrival: false,
+            slowing_radius: 50.0,
+            far_cohesion_weight: 0.0,
+            far_cohesion_radius: 2000.0,
+            angular_noise: 0.0,
+            species: Vec::new(),
+            active_species: 0,
+            spawn_at_cursor: false,
+            edit_mode: false,
+            wall_mode: false,
+            wall_avoid_radius: 40.0,
+            wall_avoid_weight: 2.0,
+            spawn_burst_size: 50,
+            emission_rate: 100.0,
+            randomize_sane_ranges: true,
+            infection_mode: false,
+            infected_species: 1,
+            contact_radius: 15.0,
+            enable_energy: false,
+            energy_drain_rate: 0.05,
+            energy_gain_rate: 0.2,
+            energy_neighbor_threshold: 3,
+            respawn_delay: 2.0,
+            enable_pheromones: false,
+            pheromone_deposit: 1.0,
+            pheromone_decay: 0.02,
+            pheromone_weight: 1.0,
+            show_pheromones: false,
+            enable_perching: false,
+            perch_band_height: 40.0,
+            perch_chance: 0.3,
+            perch_min_rest: 1.0,
+            perch_max_rest: 5.0,
+            perch_takeoff_speed: 3.0,
+            perched_influence: true,
+            zoom_sensitivity: 0.1,
+            invert_zoom: false,
+            preserve_view_on_resize: false,
+            follow_deadzone: 0.15,
+            follow_look_ahead: false,
             show_debug: false,
+            compute_metrics: false,
+            show_velocity_vectors: false,
+            show_cursor_info: false,
+            show_separation_radius: true,
+            show_alignment_radius: true,
+            show_cohesion_radius: true,
+            boid_size: crate::BOID_SIZE,
+            boid_shape: BoidShape::Triangle,
+            spawn_effects: true,
+            show_edge_band: false,
+            edge_band_width: 300.0,
+            tint_edge_boids: false,
+            tint_tagged_boids: true,
+            palette: Palette::Dark,
+            colors: PaletteColors::default(),
+            color_mode: ColorMode::Species,
             pause_simulation: false,
             // Default performance settings
             enable_parallel: true,
+            num_threads: 0,
             enable_spatial_grid: true,
-            cell_size_factor: 0.1,
-            enable_squared_distance: true, // Enable by default for better performance
+            cell_size_factor: 1.0,
+            fast_math: false,
             enable_frustum_culling: true,  // Enable frustum culling by default
+            culling_margin_multiplier: 1.0,
+            instanced_rendering: true,
+            enable_render_budget: true,
+            render_budget_ms: 20.0,
             adaptive_cell_sizing: true,    // Enable adaptive cell sizing by default
+            enable_spatial_sorting: false,
+            spatial_sort_interval: 30,
+            neighbor_list_interval: 1,
             // Default timing settings
             fixed_physics_fps: 30.0, // 60 physics updates per second
             target_render_fps: 0.0,  // Unlimited rendering by default
             enable_interpolation: true, // Enable interpolation by default
+            time_scale: 1.0, // Real-time by default
+            max_steps_per_frame: 5,
+            physics_substeps: 1,
+            export_format: ExportFormat::Csv,
+            export_stride: 1,
+            enable_streaming: false,
+            stream_port: 9000,
+            stream_stride: 2,
+            stream_max_boids: 4096,
+            enable_osc: false,
+            osc_host: "127.0.0.1".to_string(),
+            osc_port: 8000,
+            osc_rate: 30.0,
+            osc_send_polarization: true,
+            osc_send_centroid: true,
+            osc_send_count: true,
+            osc_send_selected: true,
+            keyframes: Vec::new(),
             // Initialize with no previous values
             previous_values: None,
-        }
+            world_scale_base: None,
+        };
+        
+        // A single species using the default parameters behaves exactly like an unspeciated flock
+        params.species.push(SpeciesParams {
+            name: "Species 1".to_string(),
+            color: SPECIES_PALETTE[0],
+            size: 1.0,
+            max_force: DEFAULT_MAX_FORCE,
+            share: 100.0,
+            separation_weight: params.separation_weight,
+            alignment_weight: params.alignment_weight,
+            cohesion_weight: params.cohesion_weight,
+            separation_radius: params.separation_radius,
+            alignment_radius: params.alignment_radius,
+            cohesion_radius: params.cohesion_radius,
+            max_speed: params.max_speed,
+        });
+        params
     }
 }
 
 impl SimulationParams {
+    // Reset all parameters to their defaults, keeping the debug and pause flags.
+    // The previous snapshot is kept so change detection fires for everything that changed.
+    pub fn reset_to_defaults(&mut self) {
+        *self = Self {
+            show_debug: self.show_debug,
+            show_velocity_vectors: self.show_velocity_vectors,
+            show_cursor_info: self.show_cursor_info,
+            show_separation_radius: self.show_separation_radius,
+            show_alignment_radius: self.show_alignment_radius,
+            show_cohesion_radius: self.show_cohesion_radius,
+            pause_simulation: self.pause_simulation,
+            keyframes: std::mem::take(&mut self.keyframes),
+            previous_values: self.previous_values.take(),
+            ..Self::default()
+        };
+    }
+    
+    // Copy every setting from another set of parameters, keeping this one's change
+    // detection snapshot so the differences are detected on the next check
+    pub fn copy_settings_from(&mut self, other: &SimulationParams) {
+        *self = Self {
+            previous_values: self.previous_values.take(),
+            ..other.clone()
+        };
+    }
+    
+    // Build species parameters from the current slider values (the rest from `base`)
+    fn active_species_from_globals(&self, base: &SpeciesParams) -> SpeciesParams {
+        SpeciesParams {
+            separation_weight: self.separation_weight,
+            alignment_weight: self.alignment_weight,
+            cohesion_weight: self.cohesion_weight,
+            separation_radius: self.separation_radius,
+            alignment_radius: self.alignment_radius,
+            cohesion_radius: self.cohesion_radius,
+            max_speed: self.max_speed,
+            ..base.clone()
+        }
+    }
+    
+    // Copy the slider values into the active species (called once per frame after the UI)
+    pub fn sync_active_species(&mut self) {
+        self.species[self.active_species] = self.active_species_from_globals(&self.species[self.active_species]);
+    }
+    
+    // With scale_radii_with_world set, scale the species' radii (and optionally max speeds)
+    // by how much the world size changed since the last snapshot. The values are computed
+    // from the ones at a reference world size, so growing and then shrinking the world
+    // restores them exactly. Call after sync_active_species and before take_snapshot.
+    pub fn scale_with_world_size(&mut self) {
+        let Some(prev) = &self.previous_values else {
+            return;
+        };
+        if !self.scale_radii_with_world || self.world_size == prev.world_size {
+            return;
+        }
+        
+        let values: Vec<[f32; 4]> = self.species.iter()
+            .map(|species| [species.separation_radius, species.alignment_radius, species.cohesion_radius, species.max_speed])
+            .collect();
+        
+        // Values changed along with the world size (a preset or a reset) are meant for the new size
+        if self.species != prev.species {
+            self.world_scale_base = Some(WorldScaleBase { world_size: self.world_size, values });
+            return;
+        }
+        
+        // Start from the current values unless they are still the ones scaled from the base
+        // (they were edited, species were added, or the world wasn't scaled before)
+        let prev_world_size = prev.world_size;
+        let base = match self.world_scale_base.take() {
+            Some(base) if base.values_at(prev_world_size, self.scale_speed_with_world) == values => base,
+            _ => WorldScaleBase { world_size: prev_world_size, values },
+        };
+        
+        for (species, [separation, alignment, cohesion, speed]) in self.species.iter_mut()
+            .zip(base.values_at(self.world_size, self.scale_speed_with_world))
+        {
+            species.separation_radius = separation;
+            species.alignment_radius = alignment;
+            species.cohesion_radius = cohesion;
+            species.max_speed = speed;
+        }
+        self.world_scale_base = Some(base);
+        self.load_active_species();
+    }
+    
+    // How much the radii are scaled up from their values at the reference world size
+    // (the sliders' ranges are scaled by this, so they can show the effective radii)
+    pub fn world_radius_scale(&self) -> f32 {
+        match &self.world_scale_base {
+            Some(base) if self.scale_radii_with_world => self.world_size / base.world_size,
+            _ => 1.0,
+        }
+    }
+    
+    // The same for the max speeds, which are only scaled if scale_speed_with_world is set
+    pub fn world_speed_scale(&self) -> f32 {
+        if self.scale_speed_with_world { self.world_radius_scale() } else { 1.0 }
+    }
+    
+    // Load the active species into the slider values (after switching species)
+    pub fn load_active_species(&mut self) {
+        let species = self.species[self.active_species].clone();
+        self.separation_weight = species.separation_weight;
+        self.alignment_weight = species.alignment_weight;
+        self.cohesion_weight = species.cohesion_weight;
+        self.separation_radius = species.separation_radius;
+        self.alignment_radius = species.alignment_radius;
+        self.cohesion_radius = species.cohesion_radius;
+        self.max_speed = species.max_speed;
+    }
+    
+    // Add a new species copied from the active one and make it active
+    pub fn add_species(&mut self) {
+        if self.species.len() >= u8::MAX as usize {
+            return; // Species ids are stored as u8 on each boid
+        }
+        
+        // The first palette slot is the boid color of the current palette
+        let color = match self.species.len() % SPECIES_PALETTE.len() {
+            0 => self.colors.boid,
+            slot => SPECIES_PALETTE[slot],
+        };
+        let new_species = SpeciesParams {
+            name: format!("Species {}", self.species.len() + 1),
+            color,
+            ..self.species[self.active_species].clone()
+        };
+        self.species.push(new_species);
+        self.active_species = self.species.len() - 1;
+        
+        // The new species gets an even share, taken from the others in proportion
+        self.set_species_share(self.active_species, 100.0 / self.species.len() as f32);
+    }
+    
+    // Replace the species with breeds loaded from a file (see the breeds module)
+    pub fn set_breeds(&mut self, breeds: Vec<SpeciesParams>) {
+        if breeds.is_empty() {
+            return;
+        }
+        
+        self.species = breeds;
+        self.normalize_species_shares();
+        self.active_species = 0;
+        self.load_active_species();
+    }
+    
+    // Set one species' percentage of the flock, scaling the others so the shares still
+    // add up to 100
+    pub fn set_species_share(&mut self, index: usize, percent: f32) {
+        let percent = if self.species.len() == 1 { 100.0 } else { percent.clamp(0.0, 100.0) };
+        let others: f32 = self.species.iter().enumerate()
+            .filter(|&(i, _)| i != index)
+            .map(|(_, species)| species.share)
+            .sum();
+        let even_share = (100.0 - percent) / (self.species.len() - 1).max(1) as f32;
+        
+        for (i, species) in self.species.iter_mut().enumerate() {
+            species.share = if i == index {
+                percent
+            } else if others > 0.0 {
+                species.share * (100.0 - percent) / others
+            } else {
+                even_share
+            };
+        }
+    }
+    
+    // Scale the shares to add up to 100 (even shares if they are all 0)
+    fn normalize_species_shares(&mut self) {
+        let total: f32 = self.species.iter().map(|species| species.share).sum();
+        let count = self.species.len() as f32;
+        for species in &mut self.species {
+            species.share = if total > 0.0 { species.share * 100.0 / total } else { 100.0 / count };
+        }
+    }
+    
+    // Species of the boid at a flock index. With even shares the species take turns;
+    // otherwise the indices are spread over the shares along a low-discrepancy sequence,
+    // so any run of consecutive boids comes close to the mixture.
+    pub fn species_for_index(&self, index: usize) -> u8 {
+        let first_share = self.species[0].share;
+        if self.species.iter().all(|species| (species.share - first_share).abs() < 1e-3) {
+            return (index % self.species.len()) as u8;
+        }
+        
+        // Fractional part of index times the golden ratio, scaled to the shares
+        let mut position = (index as f64 * 0.618_033_988_749_895).fract() as f32 * 100.0;
+        for (i, species) in self.species.iter().enumerate() {
+            if position < species.share {
+                return i as u8;
+            }
+            position -= species.share;
+        }
+        (self.species.len() - 1) as u8
+    }
+    
+    // Name of a species for the UI ("?" for one that no longer exists)
+    pub fn species_name(&self, index: usize) -> &str {
+        self.species.get(index).map(|species| species.name.as_str()).unwrap_or("?")
+    }
+    
+    // Drawn length of a boid of a species in world units
+    pub fn species_boid_size(&self, species: u8) -> f32 {
+        let scale = self.species.get(species as usize).map(|species| species.size).unwrap_or(1.0);
+        self.boid_size * scale
+    }
+    
+    // Switch to a palette, recoloring the species drawn in the previous boid color
+    // (Custom keeps the current colors)
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        if let Some(colors) = palette.colors() {
+            self.set_boid_color(colors.boid);
+            self.colors = colors;
+        }
+    }
+    
+    // Change the base boid color, recoloring the species drawn in the previous one
+    pub fn set_boid_color(&mut self, color: [u8; 3]) {
+        let previous = self.colors.boid;
+        for species in self.species.iter_mut().filter(|species| species.color == previous) {
+            species.color = color;
+        }
+        self.colors.boid = color;
+    }
+    
+    // Remove the active species (at least one species is always kept)
+    pub fn remove_active_species(&mut self) {
+        if self.species.len() <= 1 {
+            return;
+        }
+        
+        self.species.remove(self.active_species);
+        self.active_species = self.active_species.min(self.species.len() - 1);
+        self.normalize_species_shares();
+        self.load_active_species();
+    }
+    
+    // Copy the active species' weights, radii and speed to all species (keeping their
+    // names, colors, sizes, forces and shares)
+    pub fn apply_active_to_all_species(&mut self) {
+        let active = self.species[self.active_species].clone();
+        for species in &mut self.species {
+            *species = SpeciesParams {
+                name: std::mem::take(&mut species.name),
+                color: species.color,
+                size: species.size,
+                max_force: species.max_force,
+                share: species.share,
+                ..active.clone()
+            };
+        }
+    }
+    
+    // Species id that spreads the infection, or None if infection mode is off
+    // (or the infected species was removed)
+    pub fn infected_species_id(&self) -> Option<u8> {
+        if self.infection_mode && self.infected_species < self.species.len() {
+            Some(self.infected_species as u8)
+        } else {
+            None
+        }
+    }
+    
+    // Whether the physics pass measures the flock (see physics::FlockMetrics)
+    pub fn metrics_enabled(&self) -> bool {
+        self.show_debug || self.compute_metrics || (self.enable_osc && self.osc_send_polarization)
+    }
+    
+    // Largest perception radius over all species (used to size the spatial grid)
+    pub fn max_perception_radius(&self) -> f32 {
+        self.species.iter()
+            .map(|species| species.separation_radius.max(species.alignment_radius).max(species.cohesion_radius))
+            .fold(0.0, f32::max)
+    }
+    
+    // Pick random values for the flocking weights, radii and max speed.
+    // Values are applied directly, so the normal change detection picks them up next frame.
+    pub fn randomize_physics(&mut self) {
+        let mut rng = rand::thread_rng();
+        
+        if self.randomize_sane_ranges {
+            // Sub-ranges that usually produce recognizable flocking rather than chaos
+            self.separation_weight = rng.gen_range(0.8..=2.0);
+            self.alignment_weight = rng.gen_range(0.5..=1.5);
+            self.cohesion_weight = rng.gen_range(0.5..=1.5);
+            self.separation_radius = rng.gen_range(15.0..=60.0);
+            self.alignment_radius = rng.gen_range(40.0..=150.0);
+            self.cohesion_radius = rng.gen_range(40.0..=150.0);
+            self.max_speed = rng.gen_range(2.0..=8.0);
+        } else {
+            // Anywhere within the slider ranges
+            self.separation_weight = rng.gen_range(Self::get_weight_range());
+            self.alignment_weight = rng.gen_range(Self::get_weight_range());
+            self.cohesion_weight = rng.gen_range(Self::get_weight_range());
+            self.separation_radius = rng.gen_range(Self::get_radius_range());
+            self.alignment_radius = rng.gen_range(Self::get_radius_range());
+            self.cohesion_radius = rng.gen_range(Self::get_radius_range());
+            self.max_speed = rng.gen_range(Self::get_max_speed_range());
+        }
+        
+        self.print_physics("Randomized");
+    }
+    
+    // Perturb each flocking weight, radius and max speed by up to +/-10%
+    pub fn nudge_physics(&mut self) {
+        let mut rng = rand::thread_rng();
+        let mut nudge = |value: f32, range: std::ops::RangeInclusive<f32>| {
+            (value * rng.gen_range(0.9..=1.1)).clamp(*range.start(), *range.end())
+        };
+        
+        self.separation_weight = nudge(self.separation_weight, Self::get_weight_range());
+        self.alignment_weight = nudge(self.alignment_weight, Self::get_weight_range());
+        self.cohesion_weight = nudge(self.cohesion_weight, Self::get_weight_range());
+        self.separation_radius = nudge(self.separation_radius, Self::get_radius_range());
+        self.alignment_radius = nudge(self.alignment_radius, Self::get_radius_range());
+        self.cohesion_radius = nudge(self.cohesion_radius, Self::get_radius_range());
+        self.max_speed = nudge(self.max_speed, Self::get_max_speed_range());
+        
+        self.print_physics("Nudged");
+    }
+    
+    // Print the flocking parameters so interesting combinations can be saved
+    fn print_physics(&self, label: &str) {
+        println!(
+            "{} parameters: separation_weight={:.2} alignment_weight={:.2} cohesion_weight={:.2} \
+             separation_radius={:.1} alignment_radius={:.1} cohesion_radius={:.1} max_speed={:.2}",
+            label,
+            self.separation_weight, self.alignment_weight, self.cohesion_weight,
+            self.separation_radius, self.alignment_radius, self.cohesion_radius,
+            self.max_speed
+        );
+    }
+    
+    // Apply a preset in a flat TOML subset: one `key = value` per line, `#` comments,
+    // `[section]` headers ignored. Keys are the parameter field names. A preset with
+    // keyframes replaces the animation; one without keeps it.
+    pub fn apply_preset(&mut self, text: &str) -> Result<(), String> {
+        let keyframes = std::mem::take(&mut self.keyframes);
+        for (line_number, line) in text.lines().enumerate() {
+            let Some((key, value)) = parse_preset_line(line)
+                .map_err(|err| format!("line {}: {}", line_number + 1, err))? else {
+                continue;
+            };
+            
+            self.set_preset_value(key, value)
+                .map_err(|err| format!("line {}: {}", line_number + 1, err))?;
+        }
+        
+        if self.keyframes.is_empty() {
+            self.keyframes = keyframes;
+        }
+        self.finish_preset();
+        
+        Ok(())
+    }
+    
+    // Set a single parameter from a preset or config file
+    pub fn set_preset_value(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let parse_f32 = |value: &str| value.parse::<f32>()
+            .map_err(|_| format!("invalid number for {}: {}", key, value));
+        let parse_usize = |value: &str| value.parse::<usize>()
+            .map_err(|_| format!("invalid integer for {}: {}", key, value));
+        let parse_bool = |value: &str| value.parse::<bool>()
+            .map_err(|_| format!("invalid boolean for {}: {}", key, value));
+        let parse_color = |value: &str| palette::parse_color(value)
+            .ok_or_else(|| format!("invalid color for {}: {} (expected r, g, b)", key, value));
+        
+        match key {
+            "num_boids" => self.num_boids = parse_usize(value)?,
+            "instant_resize" => self.instant_resize = parse_bool(value)?,
+            "initial_layout" => self.initial_layout = InitialLayout::ALL.into_iter()
+                .find(|layout| layout.label().eq_ignore_ascii_case(value))
+                .ok_or_else(|| format!("invalid layout for {}: {}", key, value))?,
+            "separation_falloff" => self.separation_falloff = SeparationFalloff::ALL.into_iter()
+                .find(|falloff| falloff.label().eq_ignore_ascii_case(value))
+                .ok_or_else(|| format!("invalid falloff for {}: {}", key, value))?,
+            "separation_falloff_power" => self.separation_falloff_power = parse_f32(value)?,
+            "min_spawn_spacing" => self.min_spawn_spacing = parse_f32(value)?,
+            "separation_weight" => self.separation_weight = parse_f32(value)?,
+            "alignment_weight" => self.alignment_weight = parse_f32(value)?,
+            "cohesion_weight" => self.cohesion_weight = parse_f32(value)?,
+            "separation_radius" => self.separation_radius = parse_f32(value)?,
+            "alignment_radius" => self.alignment_radius = parse_f32(value)?,
+            "cohesion_radius" => self.cohesion_radius = parse_f32(value)?,
+            "max_speed" => self.max_speed = parse_f32(value)?,
+            "world_size" => self.world_size = parse_f32(value)?,
+            "scale_radii_with_world" => self.scale_radii_with_world = parse_bool(value)?,
+            "scale_speed_with_world" => self.scale_speed_with_world = parse_bool(value)?,
+            "wind_tunnel_mode" => self.wind_tunnel_mode = parse_bool(value)?,
+            "wind_tunnel_rate" => self.wind_tunnel_rate = parse_f32(value)?,
+            "enable_separation" => self.enable_separation = parse_bool(value)?,
+            "enable_alignment" => self.enable_alignment = parse_bool(value)?,
+            "enable_cohesion" => self.enable_cohesion = parse_bool(value)?,
+            "enable_arrival" => self.enable_arrival = parse_bool(value)?,
+            "slowing_radius" => self.slowing_radius = parse_f32(value)?,
+            "far_cohesion_weight" => self.far_cohesion_weight = parse_f32(value)?,
+            "far_cohesion_radius" => self.far_cohesion_radius = parse_f32(value)?,
+            "angular_noise" => self.angular_noise = parse_f32(value)?,
+            "spawn_burst_size" => self.spawn_burst_size = parse_usize(value)?,
+            "emission_rate" => self.emission_rate = parse_f32(value)?,
+            "randomize_sane_ranges" => self.randomize_sane_ranges = parse_bool(value)?,
+            "infection_mode" => self.infection_mode = parse_bool(value)?,
+            "infected_species" => self.infected_species = parse_usize(value)?,
+            "contact_radius" => self.contact_radius = parse_f32(value)?,
+            "enable_energy" => self.enable_energy = parse_bool(value)?,
+            "energy_drain_rate" => self.energy_drain_rate = parse_f32(value)?,
+            "energy_gain_rate" => self.energy_gain_rate = parse_f32(value)?,
+            "energy_neighbor_threshold" => self.energy_neighbor_threshold = parse_usize(value)?,
+            "respawn_delay" => self.respawn_delay = parse_f32(value)?,
+            "enable_pheromones" => self.enable_pheromones = parse_bool(value)?,
+            "pheromone_deposit" => self.pheromone_deposit = parse_f32(value)?,
+            "pheromone_decay" => self.pheromone_decay = parse_f32(value)?,
+            "pheromone_weight" => self.pheromone_weight = parse_f32(value)?,
+            "enable_perching" => self.enable_perching = parse_bool(value)?,
+            "perch_band_height" => self.perch_band_height = parse_f32(value)?,
+            "perch_chance" => self.perch_chance = parse_f32(value)?,
+            "perch_min_rest" => self.perch_min_rest = parse_f32(value)?,
+            "perch_max_rest" => self.perch_max_rest = parse_f32(value)?,
+            "perch_takeoff_speed" => self.perch_takeoff_speed = parse_f32(value)?,
+            "perched_influence" => self.perched_influence = parse_bool(value)?,
+            "wall_avoid_radius" => self.wall_avoid_radius = parse_f32(value)?,
+            "wall_avoid_weight" => self.wall_avoid_weight = parse_f32(value)?,
+            "zoom_sensitivity" => self.zoom_sensitivity = parse_f32(value)?,
+            "invert_zoom" => self.invert_zoom = parse_bool(value)?,
+            "preserve_view_on_resize" => self.preserve_view_on_resize = parse_bool(value)?,
+            "follow_deadzone" => self.follow_deadzone = parse_f32(value)?,
+            "follow_look_ahead" => self.follow_look_ahead = parse_bool(value)?,
+            "boid_size" => self.boid_size = parse_f32(value)?,
+            "boid_shape" => self.boid_shape = BoidShape::ALL.into_iter()
+                .find(|shape| shape.label().eq_ignore_ascii_case(value))
+                .ok_or_else(|| format!("invalid shape for {}: {}", key, value))?,
+            "spawn_effects" => self.spawn_effects = parse_bool(value)?,
+            "show_edge_band" => self.show_edge_band = parse_bool(value)?,
+            "edge_band_width" => self.edge_band_width = parse_f32(value)?,
+            "tint_edge_boids" => self.tint_edge_boids = parse_bool(value)?,
+            "tint_tagged_boids" => self.tint_tagged_boids = parse_bool(value)?,
+            "palette" => self.set_palette(Palette::ALL.into_iter()
+                .find(|palette| palette.label().eq_ignore_ascii_case(value))
+                .ok_or_else(|| format!("invalid palette for {}: {}", key, value))?),
+            "background_color" => self.colors.background = parse_color(value)?,
+            "color_mode" => self.color_mode = ColorMode::ALL.into_iter()
+                .find(|mode| mode.label().eq_ignore_ascii_case(value))
+                .ok_or_else(|| format!("invalid color mode for {}: {}", key, value))?,
+            "boid_color" => self.set_boid_color(parse_color(value)?),
+            "selection_color" => self.colors.selection = parse_color(value)?,
+            "debug_color" => self.colors.debug = parse_color(value)?,
+            "boundary_color" => self.colors.boundary = parse_color(value)?,
+            "compute_metrics" => self.compute_metrics = parse_bool(value)?,
+            "enable_parallel" => self.enable_parallel = parse_bool(value)?,
+            "num_threads" => self.num_threads = parse_usize(value)?,
+            "enable_spatial_grid" => self.enable_spatial_grid = parse_bool(value)?,
+            "cell_size_factor" => self.cell_size_factor = parse_f32(value)?,
+            "fast_math" => self.fast_math = parse_bool(value)?,
+            "enable_squared_distance" => {} // Had no effect; superseded by fast_math
+            "enable_frustum_culling" => self.enable_frustum_culling = parse_bool(value)?,
+            "culling_margin_multiplier" => self.culling_margin_multiplier = parse_f32(value)?,
+            "instanced_rendering" => self.instanced_rendering = parse_bool(value)?,
+            "enable_render_budget" => self.enable_render_budget = parse_bool(value)?,
+            "render_budget_ms" => self.render_budget_ms = parse_f32(value)?,
+            "adaptive_cell_sizing" => self.adaptive_cell_sizing = parse_bool(value)?,
+            "enable_spatial_sorting" => self.enable_spatial_sorting = parse_bool(value)?,
+            "spatial_sort_interval" => self.spatial_sort_interval = parse_usize(value)?,
+            "neighbor_list_interval" => self.neighbor_list_interval = parse_usize(value)?,
+            "fixed_physics_fps" => self.fixed_physics_fps = parse_f32(value)?,
+            "target_render_fps" => self.target_render_fps = parse_f32(value)?,
+            "enable_interpolation" => self.enable_interpolation = parse_bool(value)?,
+            "time_scale" => self.time_scale = parse_f32(value)?,
+            "max_steps_per_frame" => self.max_steps_per_frame = parse_usize(value)?,
+            "physics_substeps" => self.physics_substeps = value.parse::<u32>()
+                .map_err(|_| format!("invalid integer for {}: {}", key, value))?,
+            "export_stride" => self.export_stride = parse_usize(value)?,
+            "enable_streaming" => self.enable_streaming = parse_bool(value)?,
+            "stream_port" => self.stream_port = value.parse::<u16>()
+                .map_err(|_| format!("invalid port for {}: {}", key, value))?,
+            "stream_stride" => self.stream_stride = parse_usize(value)?,
+            "stream_max_boids" => self.stream_max_boids = parse_usize(value)?,
+            "enable_osc" => self.enable_osc = parse_bool(value)?,
+            "osc_host" => self.osc_host = value.to_string(),
+            "osc_port" => self.osc_port = value.parse::<u16>()
+                .map_err(|_| format!("invalid port for {}: {}", key, value))?,
+            "osc_rate" => self.osc_rate = parse_f32(value)?,
+            "osc_send_polarization" => self.osc_send_polarization = parse_bool(value)?,
+            "osc_send_centroid" => self.osc_send_centroid = parse_bool(value)?,
+            "osc_send_count" => self.osc_send_count = parse_bool(value)?,
+            "osc_send_selected" => self.osc_send_selected = parse_bool(value)?,
+            "pause_simulation" => self.pause_simulation = parse_bool(value)?,
+            "keyframe" => self.keyframes.push(Keyframe::parse(value)?),
+            _ => return Err(format!("unknown parameter {}", key)),
+        }
+        
+        Ok(())
+    }
+    
+    // Clamp values set from a preset to the slider ranges and apply them to the active species
+    pub fn finish_preset(&mut self) {
+        let num_boids_range = Self::get_num_boids_range();
+        self.num_boids = self.num_boids.clamp(*num_boids_range.start(), *num_boids_range.end());
+        let world_size_range = Self::get_world_size_range();
+        self.world_size = self.world_size.clamp(*world_size_range.start(), *world_size_range.end());
+        let substeps_range = Self::get_physics_substeps_range();
+        self.physics_substeps = self.physics_substeps.clamp(*substeps_range.start(), *substeps_range.end());
+        let interval_range = Self::get_neighbor_list_interval_range();
+        self.neighbor_list_interval = self.neighbor_list_interval.clamp(*interval_range.start(), *interval_range.end());
+        let spacing_range = Self::get_min_spawn_spacing_range();
+        self.min_spawn_spacing = self.min_spawn_spacing.clamp(*spacing_range.start(), *spacing_range.end());
+        // A preset from a machine with more cores asks for more threads than this one has
+        self.num_threads = self.num_threads.min(*Self::get_num_threads_range().end());
+        
+        // The flocking values above edit the active species
+        self.sync_active_species();
+    }
+    
+    // Write the persistent settings in the preset format, readable by apply_preset.
+    // Session state (pause, debug overlays) and species other than the active one are not included.
+    pub fn to_preset(&self) -> String {
+        let values = [
+            ("num_boids", self.num_boids.to_string()),
+            ("instant_resize", self.instant_resize.to_string()),
+            ("initial_layout", format!("\"{}\"", self.initial_layout.label())),
+            ("min_spawn_spacing", self.min_spawn_spacing.to_string()),
+            ("separation_falloff", format!("\"{}\"", self.separation_falloff.label())),
+            ("separation_falloff_power", self.separation_falloff_power.to_string()),
+            ("separation_weight", self.separation_weight.to_string()),
+            ("alignment_weight", self.alignment_weight.to_string()),
+            ("cohesion_weight", self.cohesion_weight.to_string()),
+            ("separation_radius", self.separation_radius.to_string()),
+            ("alignment_radius", self.alignment_radius.to_string()),
+            ("cohesion_radius", self.cohesion_radius.to_string()),
+            ("max_speed", self.max_speed.to_string()),
+            ("world_size", self.world_size.to_string()),
+            ("scale_radii_with_world", self.scale_radii_with_world.to_string()),
+            ("scale_speed_with_world", self.scale_speed_with_world.to_string()),
+            ("wind_tunnel_mode", self.wind_tunnel_mode.to_string()),
+            ("wind_tunnel_rate", self.wind_tunnel_rate.to_string()),
+            ("enable_separation", self.enable_separation.to_string()),
+            ("enable_alignment", self.enable_alignment.to_string()),
+            ("enable_cohesion", self.enable_cohesion.to_string()),
+            ("enable_arrival", self.enable_arrival.to_string()),
+            ("slowing_radius", self.slowing_radius.to_string()),
+            ("far_cohesion_weight", self.far_cohesion_weight.to_string()),
+            ("far_cohesion_radius", self.far_cohesion_radius.to_string()),
+            ("angular_noise", self.angular_noise.to_string()),
+            ("spawn_burst_size", self.spawn_burst_size.to_string()),
+            ("emission_rate", self.emission_rate.to_string()),
+            ("randomize_sane_ranges", self.randomize_sane_ranges.to_string()),
+            ("infected_species", self.infected_species.to_string()),
+            ("contact_radius", self.contact_radius.to_string()),
+            ("enable_energy", self.enable_energy.to_string()),
+            ("energy_drain_rate", self.energy_drain_rate.to_string()),
+            ("energy_gain_rate", self.energy_gain_rate.to_string()),
+            ("energy_neighbor_threshold", self.energy_neighbor_threshold.to_string()),
+            ("respawn_delay", self.respawn_delay.to_string()),
+            ("enable_pheromones", self.enable_pheromones.to_string()),
+            ("pheromone_deposit", self.pheromone_deposit.to_string()),
+            ("pheromone_decay", self.pheromone_decay.to_string()),
+            ("pheromone_weight", self.pheromone_weight.to_string()),
+            ("enable_perching", self.enable_perching.to_string()),
+            ("perch_band_height", self.perch_band_height.to_string()),
+            ("perch_chance", self.perch_chance.to_string()),
+            ("perch_min_rest", self.perch_min_rest.to_string()),
+            ("perch_max_rest", self.perch_max_rest.to_string()),
+            ("perch_takeoff_speed", self.perch_takeoff_speed.to_string()),
+            ("perched_influence", self.perched_influence.to_string()),
+            ("wall_avoid_radius", self.wall_avoid_radius.to_string()),
+            ("wall_avoid_weight", self.wall_avoid_weight.to_string()),
+            ("zoom_sensitivity", self.zoom_sensitivity.to_string()),
+            ("invert_zoom", self.invert_zoom.to_string()),
+            ("preserve_view_on_resize", self.preserve_view_on_resize.to_string()),
+            ("follow_deadzone", self.follow_deadzone.to_string()),
+            ("follow_look_ahead", self.follow_look_ahead.to_string()),
+            ("boid_size", self.boid_size.to_string()),
+            ("boid_shape", format!("\"{}\"", self.boid_shape.label())),
+            ("spawn_effects", self.spawn_effects.to_string()),
+            ("show_edge_band", self.show_edge_band.to_string()),
+            ("edge_band_width", self.edge_band_width.to_string()),
+            ("tint_edge_boids", self.tint_edge_boids.to_string()),
+            ("tint_tagged_boids", self.tint_tagged_boids.to_string()),
+            ("palette", format!("\"{}\"", self.palette.label())),
+            ("background_color", palette::format_color(self.colors.background)),
+            ("color_mode", format!("\"{}\"", self.color_mode.label())),
+            ("boid_color", palette::format_color(self.colors.boid)),
+            ("selection_color", palette::format_color(self.colors.selection)),
+            ("debug_color", palette::format_color(self.colors.debug)),
+            ("boundary_color", palette::format_color(self.colors.boundary)),
+            ("compute_metrics", self.compute_metrics.to_string()),
+            ("enable_parallel", self.enable_parallel.to_string()),
+            ("num_threads", self.num_threads.to_string()),
+            ("enable_spatial_grid", self.enable_spatial_grid.to_string()),
+            ("cell_size_factor", self.cell_size_factor.to_string()),
+            ("fast_math", self.fast_math.to_string()),
+            ("enable_frustum_culling", self.enable_frustum_culling.to_string()),
+            ("culling_margin_multiplier", self.culling_margin_multiplier.to_string()),
+            ("instanced_rendering", self.instanced_rendering.to_string()),
+            ("enable_render_budget", self.enable_render_budget.to_string()),
+            ("render_budget_ms", self.render_budget_ms.to_string()),
+            ("adaptive_cell_sizing", self.adaptive_cell_sizing.to_string()),
+            ("enable_spatial_sorting", self.enable_spatial_sorting.to_string()),
+            ("spatial_sort_interval", self.spatial_sort_interval.to_string()),
+            ("neighbor_list_interval", self.neighbor_list_interval.to_string()),
+            ("fixed_physics_fps", self.fixed_physics_fps.to_string()),
+            ("target_render_fps", self.target_render_fps.to_string()),
+            ("enable_interpolation", self.enable_interpolation.to_string()),
+            ("time_scale", self.time_scale.to_string()),
+            ("max_steps_per_frame", self.max_steps_per_frame.to_string()),
+            ("physics_substeps", self.physics_substeps.to_string()),
+            ("export_stride", self.export_stride.to_string()),
+            ("enable_streaming", self.enable_streaming.to_string()),
+            ("stream_port", self.stream_port.to_string()),
+            ("stream_stride", self.stream_stride.to_string()),
+            ("stream_max_boids", self.stream_max_boids.to_string()),
+            ("enable_osc", self.enable_osc.to_string()),
+            ("osc_host", format!("\"{}\"", self.osc_host)),
+            ("osc_port", self.osc_port.to_string()),
+            ("osc_rate", self.osc_rate.to_string()),
+            ("osc_send_polarization", self.osc_send_polarization.to_string()),
+            ("osc_send_centroid", self.osc_send_centroid.to_string()),
+            ("osc_send_count", self.osc_send_count.to_string()),
+            ("osc_send_selected", self.osc_send_selected.to_string()),
+        ];
+        
+        let mut text: String = values.iter().map(|(key, value)| format!("{} = {}\n", key, value)).collect();
+        for keyframe in &self.keyframes {
+            text.push_str(&format!("keyframe = {}\n", keyframe.to_preset()));
+        }
+        text
+    }
+    
     // Take a snapshot of current parameter values for change detection
     pub fn take_snapshot(&mut self) {
         self.previous_values = Some(ParamSnapshot {
@@ -102,10 +1131,49 @@ impl SimulationParams {
             cohesion_radius: self.cohesion_radius,
             max_speed: self.max_speed,
             world_size: self.world_size,  // Added world size parameter
+            enable_separation: self.enable_separation,
+            enable_alignment: self.enable_alignment,
+            enable_cohesion: self.enable_cohesion,
+            enable_arrival: self.enable_arrival,
+            slowing_radius: self.slowing_radius,
+            angular_noise: self.angular_noise,
+            species: self.species.clone(),
+            infection_mode: self.infection_mode,
+            infected_species: self.infected_species,
+            contact_radius: self.contact_radius,
+            enable_energy: self.enable_energy,
+            energy_drain_rate: self.energy_drain_rate,
+            energy_gain_rate: self.energy_gain_rate,
+            energy_neighbor_threshold: self.energy_neighbor_threshold,
+            respawn_delay: self.respawn_delay,
+            enable_pheromones: self.enable_pheromones,
+            pheromone_deposit: self.pheromone_deposit,
+            pheromone_decay: self.pheromone_decay,
+            pheromone_weight: self.pheromone_weight,
+            show_pheromones: self.show_pheromones,
+            enable_perching: self.enable_perching,
+            wall_avoid_radius: self.wall_avoid_radius,
+            wall_avoid_weight: self.wall_avoid_weight,
             show_debug: self.show_debug,
-            enable_squared_distance: self.enable_squared_distance,
+            show_velocity_vectors: self.show_velocity_vectors,
+            show_cursor_info: self.show_cursor_info,
+            show_separation_radius: self.show_separation_radius,
+            show_alignment_radius: self.show_alignment_radius,
+            show_cohesion_radius: self.show_cohesion_radius,
+            boid_size: self.boid_size,
+            boid_shape: self.boid_shape,
+            show_edge_band: self.show_edge_band,
+            edge_band_width: self.edge_band_width,
+            tint_edge_boids: self.tint_edge_boids,
+            tint_tagged_boids: self.tint_tagged_boids,
+            fast_math: self.fast_math,
             enable_frustum_culling: self.enable_frustum_culling,
+            culling_margin_multiplier: self.culling_margin_multiplier,
+            instanced_rendering: self.instanced_rendering,
             adaptive_cell_sizing: self.adaptive_cell_sizing,
+            enable_spatial_sorting: self.enable_spatial_sorting,
+            spatial_sort_interval: self.spatial_sort_interval,
+            neighbor_list_interval: self.neighbor_list_interval,
             fixed_physics_fps: self.fixed_physics_fps,
             target_render_fps: self.target_render_fps,
             enable_interpolation: self.enable_interpolation,
@@ -126,12 +1194,51 @@ impl SimulationParams {
                 self.alignment_radius != prev.alignment_radius ||
                 self.cohesion_radius != prev.cohesion_radius ||
                 self.max_speed != prev.max_speed ||
-                self.enable_squared_distance != prev.enable_squared_distance ||
-                self.adaptive_cell_sizing != prev.adaptive_cell_sizing;
+                self.enable_separation != prev.enable_separation ||
+                self.enable_alignment != prev.enable_alignment ||
+                self.enable_cohesion != prev.enable_cohesion ||
+                self.enable_arrival != prev.enable_arrival ||
+                self.slowing_radius != prev.slowing_radius ||
+                self.angular_noise != prev.angular_noise ||
+                self.species != prev.species ||
+                self.infection_mode != prev.infection_mode ||
+                self.infected_species != prev.infected_species ||
+                self.contact_radius != prev.contact_radius ||
+                self.enable_energy != prev.enable_energy ||
+                self.energy_drain_rate != prev.energy_drain_rate ||
+                self.energy_gain_rate != prev.energy_gain_rate ||
+                self.energy_neighbor_threshold != prev.energy_neighbor_threshold ||
+                self.respawn_delay != prev.respawn_delay ||
+                self.enable_pheromones != prev.enable_pheromones ||
+                self.pheromone_deposit != prev.pheromone_deposit ||
+                self.pheromone_decay != prev.pheromone_decay ||
+                self.pheromone_weight != prev.pheromone_weight ||
+                self.enable_perching != prev.enable_perching ||
+                self.wall_avoid_radius != prev.wall_avoid_radius ||
+                self.wall_avoid_weight != prev.wall_avoid_weight ||
+                self.fast_math != prev.fast_math ||
+                self.adaptive_cell_sizing != prev.adaptive_cell_sizing ||
+                self.enable_spatial_sorting != prev.enable_spatial_sorting ||
+                self.spatial_sort_interval != prev.spatial_sort_interval ||
+                self.neighbor_list_interval != prev.neighbor_list_interval;
             
             let rendering_changed = 
                 self.show_debug != prev.show_debug ||
+                self.show_velocity_vectors != prev.show_velocity_vectors ||
+                self.show_cursor_info != prev.show_cursor_info ||
+                self.show_separation_radius != prev.show_separation_radius ||
+                self.show_alignment_radius != prev.show_alignment_radius ||
+                self.show_cohesion_radius != prev.show_cohesion_radius ||
+                self.show_pheromones != prev.show_pheromones ||
+                self.boid_size != prev.boid_size ||
+                self.boid_shape != prev.boid_shape ||
+                self.show_edge_band != prev.show_edge_band ||
+                self.edge_band_width != prev.edge_band_width ||
+                self.tint_edge_boids != prev.tint_edge_boids ||
+                self.tint_tagged_boids != prev.tint_tagged_boids ||
                 self.enable_frustum_culling != prev.enable_frustum_culling ||
+                self.culling_margin_multiplier != prev.culling_margin_multiplier ||
+                self.instanced_rendering != prev.instanced_rendering ||
                 self.fixed_physics_fps != prev.fixed_physics_fps ||
                 self.target_render_fps != prev.target_render_fps ||
                 self.enable_interpolation != prev.enable_interpolation;
@@ -151,10 +1258,131 @@ impl SimulationParams {
         10..=200000
     }
     
+    pub fn get_spawn_burst_size_range() -> std::ops::RangeInclusive<usize> {
+        1..=1000
+    }
+    
+    pub fn get_emission_rate_range() -> std::ops::RangeInclusive<f32> {
+        1.0..=2000.0
+    }
+    
+    pub fn get_wind_tunnel_rate_range() -> std::ops::RangeInclusive<f32> {
+        1.0..=2000.0
+    }
+    
+    pub fn get_contact_radius_range() -> std::ops::RangeInclusive<f32> {
+        1.0..=50.0
+    }
+    
+    pub fn get_min_spawn_spacing_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=50.0
+    }
+    
+    pub fn get_wall_avoid_radius_range() -> std::ops::RangeInclusive<f32> {
+        5.0..=200.0
+    }
+    
+    pub fn get_energy_rate_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=1.0
+    }
+    
+    pub fn get_energy_neighbor_threshold_range() -> std::ops::RangeInclusive<usize> {
+        0..=30
+    }
+    
+    pub fn get_respawn_delay_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=30.0
+    }
+    
+    pub fn get_pheromone_deposit_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=5.0
+    }
+    
+    pub fn get_pheromone_decay_range() -> std::ops::RangeInclusive<f32> {
+        0.001..=0.5
+    }
+    
+    pub fn get_perch_band_height_range() -> std::ops::RangeInclusive<f32> {
+        5.0..=500.0
+    }
+    
+    pub fn get_perch_chance_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=1.0
+    }
+    
+    pub fn get_perch_rest_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=60.0
+    }
+    
+    pub fn get_perch_takeoff_speed_range() -> std::ops::RangeInclusive<f32> {
+        0.5..=10.0
+    }
+    
+    pub fn get_export_stride_range() -> std::ops::RangeInclusive<usize> {
+        1..=1000
+    }
+    
+    pub fn get_stream_stride_range() -> std::ops::RangeInclusive<usize> {
+        1..=120
+    }
+    
+    // Frames must fit in a single UDP datagram
+    pub fn get_osc_rate_range() -> std::ops::RangeInclusive<f32> {
+        1.0..=120.0
+    }
+    
+    pub fn get_stream_max_boids_range() -> std::ops::RangeInclusive<usize> {
+        100..=crate::stream::MAX_BOIDS_PER_FRAME
+    }
+    
+    pub fn get_zoom_sensitivity_range() -> std::ops::RangeInclusive<f32> {
+        0.01..=0.5
+    }
+    
+    pub fn get_boid_size_range() -> std::ops::RangeInclusive<f32> {
+        1.0..=20.0
+    }
+    
+    pub fn get_edge_band_width_range() -> std::ops::RangeInclusive<f32> {
+        10.0..=2000.0
+    }
+    
+    pub fn get_render_budget_ms_range() -> std::ops::RangeInclusive<f32> {
+        5.0..=100.0
+    }
+    
+    pub fn get_culling_margin_multiplier_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=4.0
+    }
+    
+    pub fn get_follow_deadzone_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=0.45
+    }
+    
     pub fn get_max_speed_range() -> std::ops::RangeInclusive<f32> {
         1.0..=10.0
     }
     
+    pub fn get_separation_falloff_power_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=4.0
+    }
+    
+    pub fn get_far_cohesion_radius_range() -> std::ops::RangeInclusive<f32> {
+        100.0..=50000.0
+    }
+    
+    pub fn get_angular_noise_range() -> std::ops::RangeInclusive<f32> {
+        0.0..=360.0
+    }
+    
+    pub fn get_species_size_range() -> std::ops::RangeInclusive<f32> {
+        0.25..=4.0
+    }
+    
+    pub fn get_max_force_range() -> std::ops::RangeInclusive<f32> {
+        0.01..=1.0
+    }
+    
     pub fn get_weight_range() -> std::ops::RangeInclusive<f32> {
         0.0..=3.0
     }
@@ -175,7 +1403,132 @@ impl SimulationParams {
         30.0..=240.0
     }
     
+    pub fn get_time_scale_range() -> std::ops::RangeInclusive<f32> {
+        0.1..=10.0
+    }
+    
+    pub fn get_max_steps_per_frame_range() -> std::ops::RangeInclusive<usize> {
+        1..=64
+    }
+    
+    pub fn get_physics_substeps_range() -> std::ops::RangeInclusive<u32> {
+        1..=8
+    }
+    
+    // 0 uses rayon's global pool; otherwise up to one thread per core
+    pub fn get_num_threads_range() -> std::ops::RangeInclusive<usize> {
+        let cores = std::thread::available_parallelism().map_or(16, |cores| cores.get());
+        0..=cores
+    }
+    
+    pub fn get_spatial_sort_interval_range() -> std::ops::RangeInclusive<usize> {
+        1..=300
+    }
+    
+    pub fn get_neighbor_list_interval_range() -> std::ops::RangeInclusive<usize> {
+        1..=10
+    }
+    
     pub fn get_render_fps_range() -> std::ops::RangeInclusive<f32> {
         0.0..=240.0
     }
-} 
\ No newline at end of file
+}
+
+// Split a preset line into its key and value. Returns None for blank lines,
+// comments and `[section]` headers.
+pub fn parse_preset_line(line: &str) -> Result<Option<(&str, &str)>, String> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    if line.is_empty() || line.starts_with('[') {
+        return Ok(None);
+    }
+    
+    let (key, value) = line.split_once('=').ok_or("expected `key = value`")?;
+    Ok(Some((key.trim(), value.trim().trim_matches('"'))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Change the world size the way the UI does, one frame at a time
+    fn resize_world(params: &mut SimulationParams, world_size: f32) {
+        params.world_size = world_size;
+        params.sync_active_species();
+        params.detect_changes();
+        params.scale_with_world_size();
+        params.take_snapshot();
+    }
+
+    #[test]
+    fn radii_scaled_with_the_world_return_to_their_values() {
+        let mut params = SimulationParams { scale_radii_with_world: true, ..SimulationParams::default() };
+        params.add_species();
+        params.species[1].cohesion_radius = 80.0;
+        params.load_active_species();
+        params.take_snapshot();
+        let original = params.species.clone();
+
+        resize_world(&mut params, 50000.0);
+        assert_eq!(params.cohesion_radius, original[1].cohesion_radius * 10.0);
+        assert_eq!(params.species[0].separation_radius, original[0].separation_radius * 10.0);
+        assert_eq!(params.species[0].max_speed, original[0].max_speed);
+        assert_eq!(params.world_radius_scale(), 10.0);
+
+        // Many small steps back down don't compound rounding errors
+        for world_size in (5000..50000).rev().step_by(1234).map(|size| size as f32) {
+            resize_world(&mut params, world_size);
+        }
+        resize_world(&mut params, 5000.0);
+        assert!(params.species == original);
+    }
+
+    #[test]
+    fn edited_radii_become_the_new_base() {
+        let mut params = SimulationParams {
+            scale_radii_with_world: true,
+            scale_speed_with_world: true,
+            ..SimulationParams::default()
+        };
+        params.take_snapshot();
+
+        resize_world(&mut params, 10000.0);
+        params.alignment_radius = 300.0;
+        params.sync_active_species();
+        params.take_snapshot();
+
+        resize_world(&mut params, 5000.0);
+        assert_eq!(params.alignment_radius, 150.0);
+        assert_eq!(params.max_speed, SimulationParams::default().max_speed);
+
+        // Without the option the world size leaves the radii alone
+        params.scale_radii_with_world = false;
+        resize_world(&mut params, 20000.0);
+        assert_eq!(params.alignment_radius, 150.0);
+    }
+
+    #[test]
+    fn species_shares_set_the_mixture_and_keep_adding_up_to_100() {
+        let mut params = SimulationParams::default();
+        params.add_species();
+        // Even shares take turns, as before species had shares
+        let first: Vec<u8> = (0..4).map(|i| params.species_for_index(i)).collect();
+        assert_eq!(first, vec![0, 1, 0, 1]);
+
+        params.add_species();
+        params.set_species_share(0, 70.0);
+        let total: f32 = params.species.iter().map(|species| species.share).sum();
+        assert!((total - 100.0).abs() < 1e-3);
+        assert!((params.species[1].share - 15.0).abs() < 1e-3);
+
+        let mut counts = [0i32; 3];
+        for i in 0..1000 {
+            counts[params.species_for_index(i) as usize] += 1;
+        }
+        assert!((counts[0] - 700).abs() <= 5 && (counts[1] - 150).abs() <= 5, "{:?}", counts);
+
+        // Removing a species hands its share to the others
+        params.remove_active_species();
+        assert!((params.species[0].share - 70.0 / 0.85).abs() < 1e-3);
+        assert!((params.species[1].share - 15.0 / 0.85).abs() < 1e-3);
+    }
+}
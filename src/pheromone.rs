@@ -0,0 +1,159 @@
+/*
+ * Pheromone Module
+ * 
+ * This module defines the pheromone field: a coarse grid over the world into which
+ * every boid deposits a little scent each step. The scent fades by a fixed fraction
+ * per step, so the field traces where the flock has recently been, and boids can
+ * steer up its gradient to follow each other's trails.
+ * 
+ * The field wraps around the world edges like the boids do. Updating it costs
+ * O(cells + boids) per step; while pheromones are disabled no field exists at all.
+ */
+
+use nannou::prelude::*;
+
+// Cells along each side of the field
+pub const PHEROMONE_RESOLUTION: usize = 128;
+
+// Scalar field over the world, stored as a square grid of cell values
+#[derive(Clone, Debug)]
+pub struct PheromoneField {
+    resolution: usize,
+    world_size: f32,
+    cell_size: f32,
+    cells: Vec<f32>, // Row-major, starting at the bottom left of the world
+}
+
+impl PheromoneField {
+    // An empty field of resolution x resolution cells covering the world
+    pub fn new(resolution: usize, world_size: f32) -> Self {
+        let resolution = resolution.max(1);
+        
+        Self {
+            resolution,
+            world_size,
+            cell_size: world_size / resolution as f32,
+            cells: vec![0.0; resolution * resolution],
+        }
+    }
+    
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+    
+    pub fn world_size(&self) -> f32 {
+        self.world_size
+    }
+    
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+    
+    // Cell values, row by row from the bottom of the world
+    pub fn values(&self) -> &[f32] {
+        &self.cells
+    }
+    
+    // Center of the cell at (column, row), in world coordinates
+    pub fn cell_center(&self, column: usize, row: usize) -> Point2 {
+        let half_world = self.world_size / 2.0;
+        pt2(
+            (column as f32 + 0.5) * self.cell_size - half_world,
+            (row as f32 + 0.5) * self.cell_size - half_world,
+        )
+    }
+    
+    pub fn clear(&mut self) {
+        self.cells.fill(0.0);
+    }
+    
+    // Index of a cell, wrapping around the world edges
+    #[inline]
+    fn index(&self, column: isize, row: isize) -> usize {
+        let resolution = self.resolution as isize;
+        (row.rem_euclid(resolution) * resolution + column.rem_euclid(resolution)) as usize
+    }
+    
+    // The four cells whose centers surround a position, with their bilinear weights
+    #[inline]
+    fn bilinear(&self, position: Point2) -> [(usize, f32); 4] {
+        // Continuous cell coordinates, with the cell centers at whole numbers
+        let half_world = self.world_size / 2.0;
+        let x = (position.x + half_world) / self.cell_size - 0.5;
+        let y = (position.y + half_world) / self.cell_size - 0.5;
+        let (column, row) = (x.floor(), y.floor());
+        let (fx, fy) = (x - column, y - row);
+        let (column, row) = (column as isize, row as isize);
+        
+        [
+            (self.index(column, row), (1.0 - fx) * (1.0 - fy)),
+            (self.index(column + 1, row), fx * (1.0 - fy)),
+            (self.index(column, row + 1), (1.0 - fx) * fy),
+            (self.index(column + 1, row + 1), fx * fy),
+        ]
+    }
+    
+    // Add pheromone at a position, spread over the four nearest cells
+    pub fn deposit(&mut self, position: Point2, amount: f32) {
+        for (index, weight) in self.bilinear(position) {
+            self.cells[index] += amount * weight;
+        }
+    }
+    
+    // Remove the given fraction (0..=1) of the pheromone in every cell
+    pub fn decay(&mut self, rate: f32) {
+        let keep = (1.0 - rate).clamp(0.0, 1.0);
+        if keep < 1.0 {
+            self.cells.iter_mut().for_each(|value| *value *= keep);
+        }
+    }
+    
+    // Bilinearly interpolated pheromone level at a position
+    pub fn sample(&self, position: Point2) -> f32 {
+        self.bilinear(position).iter().map(|&(index, weight)| self.cells[index] * weight).sum()
+    }
+    
+    // Gradient of the interpolated field at a position (central differences one cell apart).
+    // Points toward increasing pheromone; zero on a flat field.
+    pub fn gradient(&self, position: Point2) -> Vec2 {
+        let dx = vec2(self.cell_size, 0.0);
+        let dy = vec2(0.0, self.cell_size);
+        vec2(
+            self.sample(position + dx) - self.sample(position - dx),
+            self.sample(position + dy) - self.sample(position - dy),
+        ) / (2.0 * self.cell_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deposits_are_conserved_and_decay() {
+        let mut field = PheromoneField::new(16, 1600.0);
+        field.deposit(pt2(123.0, -456.0), 2.0);
+        field.deposit(pt2(799.0, 799.0), 1.0); // Spreads across both wrapped edges
+
+        let total: f32 = field.values().iter().sum();
+        assert!((total - 3.0).abs() < 1e-4);
+
+        field.decay(0.25);
+        let total: f32 = field.values().iter().sum();
+        assert!((total - 2.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gradient_points_toward_the_deposit_across_the_edge() {
+        let mut field = PheromoneField::new(16, 1600.0);
+        field.deposit(field.cell_center(0, 8), 1.0);
+
+        // Right of the deposit the gradient points back to it...
+        assert!(field.gradient(field.cell_center(1, 8)).x < 0.0);
+        // ...and from the right edge of the world it points across the wrap
+        let gradient = field.gradient(field.cell_center(15, 8));
+        assert!(gradient.x > 0.0);
+        assert!(gradient.y.abs() < 1e-6);
+        assert_eq!(field.gradient(field.cell_center(8, 0)), Vec2::ZERO);
+    }
+}
@@ -12,559 +12,1732 @@
  * - Parallel processing for large numbers of boids
  * - Optimized world wrapping with efficient distance calculations
  * - Adaptive cell sizing based on perception radii
+ * 
+ * The functions here only operate on a Simulation, so they have no dependency
+ * on the window, camera or UI.
  */
 
 use nannou::prelude::*;
 use rand::Rng;
 use rayon::prelude::*;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::boid::{self, WorldWrap, MAX_ENERGY};
+use crate::debug::SelectedBoidDebug;
+use crate::far_field::FarField;
+use crate::flock::{BoidProperties, Flock};
+use crate::params::{SeparationFalloff, SimulationParams};
+use crate::pheromone::PheromoneField;
+use crate::region::{self, ParamOverrides};
+use crate::simulation::Simulation;
+use crate::spatial_grid::{NeighborEntry, SpatialGrid};
+use crate::wall::{Wall, WallGrid, WALL_MARGIN};
+
+// Time spent in each physics phase, summed over the steps of one frame.
+// Only measured while the debug overlay is shown.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhysicsTimings {
+    pub store_previous: Duration,
+    pub grid_rebuild: Duration, // Grid clear + insert
+    pub neighbor_gather: Duration, // Grid path only; without the grid it is part of forces
+    pub forces: Duration, // Force calculation and integration, including the per-boid edge wrap
+    pub spatial_sort: Duration, // Reordering the flock by grid cell (see Simulation::sort_by_cell)
+    pub substeps: u32, // Substeps of the last step (not summed)
+}
+
+// Start timing a phase, or do nothing if timing is disabled
+fn phase_start(enabled: bool) -> Option<Instant> {
+    if enabled {
+        Some(Instant::now())
+    } else {
+        None
+    }
+}
+
+// Add the time since phase_start to a phase total
+fn phase_end(start: Option<Instant>, total: &mut Duration) {
+    if let Some(start) = start {
+        *total += start.elapsed();
+    }
+}
+
+// Pre-computed per-species values for the force calculations
+#[derive(Clone, Copy)]
+struct SpeciesConstants {
+    separation_radius: f32,
+    sep_radius_sq: f32,
+    align_radius_sq: f32,
+    cohesion_radius_sq: f32,
+    separation_weight: f32,
+    alignment_weight: f32,
+    cohesion_weight: f32,
+    cohesion_slowing_radius: Option<f32>, // Set when arrival is enabled
+    separation_falloff: SeparationFalloff,
+    separation_falloff_power: f32,
+    // Rules switched off in the parameters (their neighbors aren't even checked)
+    separation_enabled: bool,
+    alignment_enabled: bool,
+    cohesion_enabled: bool,
+    fast_math: bool, // Approximate the square roots in the rules (see SimulationParams::fast_math)
+    ignore_perched: bool, // Perched boids aren't neighbors (see SimulationParams::perched_influence)
+}
 
-use crate::app::Model;
-use crate::boid::Boid;
-use crate::spatial_grid::SpatialGrid;
+// Build the per-species constants, indexed by species id, with a region's overrides applied
+fn species_constants(params: &SimulationParams, overrides: &ParamOverrides) -> Vec<SpeciesConstants> {
+    params.species.iter().map(|species| {
+        let separation_radius = overrides.separation_radius.unwrap_or(species.separation_radius);
+        let alignment_radius = overrides.alignment_radius.unwrap_or(species.alignment_radius);
+        let cohesion_radius = overrides.cohesion_radius.unwrap_or(species.cohesion_radius);
+        
+        SpeciesConstants {
+            separation_radius,
+            sep_radius_sq: separation_radius * separation_radius,
+            align_radius_sq: alignment_radius * alignment_radius,
+            cohesion_radius_sq: cohesion_radius * cohesion_radius,
+            separation_weight: overrides.separation_weight.unwrap_or(species.separation_weight),
+            alignment_weight: overrides.alignment_weight.unwrap_or(species.alignment_weight),
+            cohesion_weight: overrides.cohesion_weight.unwrap_or(species.cohesion_weight),
+            cohesion_slowing_radius: params.enable_arrival.then_some(params.slowing_radius),
+            separation_falloff: params.separation_falloff,
+            separation_falloff_power: params.separation_falloff_power,
+            separation_enabled: params.enable_separation,
+            alignment_enabled: params.enable_alignment,
+            cohesion_enabled: params.enable_cohesion,
+            fast_math: params.fast_math,
+            ignore_perched: params.enable_perching && !params.perched_influence,
+        }
+    }).collect()
+}
 
-// Reset boids to random positions
-pub fn reset_boids(model: &mut Model) {
-    let mut rng = rand::thread_rng();
+// The species constants outside any region, followed by the constants inside each region
+struct ConstantsTable {
+    tables: Vec<Vec<SpeciesConstants>>,
+}
+
+impl ConstantsTable {
+    fn new(sim: &Simulation) -> Self {
+        let outside = std::iter::once(species_constants(&sim.params, &ParamOverrides::default()));
+        let inside = sim.regions.iter().map(|region| species_constants(&sim.params, &region.overrides));
+        Self { tables: outside.chain(inside).collect() }
+    }
     
-    // Resize the boids vector if needed
-    model.boids.resize_with(model.params.num_boids, || {
-        // Use the world size from params for boid positioning
-        let half_world = model.params.world_size / 2.0;
-        let x = rng.gen_range(-half_world..half_world);
-        let y = rng.gen_range(-half_world..half_world);
-        Boid::new(x, y)
-    });
+    // The constants for a boid of a species in a region (or outside every region)
+    #[inline]
+    fn get(&self, region: Option<usize>, species: u8) -> &SpeciesConstants {
+        let table = &self.tables[region.map_or(0, |index| index + 1)];
+        &table[(species as usize).min(table.len() - 1)]
+    }
+}
+
+// Find the region each boid is in. A boid only scans the regions when it has left
+// the one it was in during the last step.
+fn resolve_regions(sim: &mut Simulation) {
+    let world_size = sim.params.world_size;
+    let regions = &sim.regions;
+    let positions = &sim.flock.positions;
+    let boid_regions = &mut sim.boid_regions;
+    boid_regions.resize(positions.len(), None);
+    
+    if regions.is_empty() {
+        boid_regions.fill(None);
+        return;
+    }
+    
+    let resolve = |(boid_region, &position): (&mut Option<usize>, &Point2)| {
+        *boid_region = region::region_at(regions, *boid_region, position, world_size);
+    };
     
-    // Update max speed for all boids
-    for boid in &mut model.boids {
-        boid.max_speed = model.params.max_speed;
+    if sim.params.enable_parallel {
+        boid_regions.par_iter_mut().zip(positions.par_iter()).for_each(resolve);
+    } else {
+        boid_regions.iter_mut().zip(positions.iter()).for_each(resolve);
     }
 }
 
-// Update boid positions and behaviors
-pub fn update_boids(model: &mut Model) {
-    // Only use spatial grid if enabled
-    if model.params.enable_spatial_grid {
-        update_boids_with_spatial_grid(model);
+// Compute the centroid of a set of positions on the wrapped (toroidal) world.
+// A naive average is wrong when the flock straddles an edge, so each axis is
+// mapped onto a circle and the circular mean (average of angles) is used instead.
+// Returns None if there are no positions or if they are spread so evenly along an
+// axis that the mean direction is undefined.
+pub fn wrapped_centroid<I: Iterator<Item = Point2>>(positions: I, world_size: f32) -> Option<Point2> {
+    let mut sum_cos = Vec2::ZERO;
+    let mut sum_sin = Vec2::ZERO;
+    let mut count = 0;
+    
+    for pos in positions {
+        // Map [-world_size/2, world_size/2] onto [-PI, PI]
+        let angle_x = pos.x / world_size * TAU;
+        let angle_y = pos.y / world_size * TAU;
+        
+        sum_cos += vec2(angle_x.cos(), angle_y.cos());
+        sum_sin += vec2(angle_x.sin(), angle_y.sin());
+        count += 1;
+    }
+    
+    if count == 0 {
+        return None;
+    }
+    
+    // The mean resultant length tells us how concentrated the angles are
+    let n = count as f32;
+    let resultant_x = (sum_cos.x * sum_cos.x + sum_sin.x * sum_sin.x).sqrt() / n;
+    let resultant_y = (sum_cos.y * sum_cos.y + sum_sin.y * sum_sin.y).sqrt() / n;
+    if resultant_x < 1e-4 || resultant_y < 1e-4 {
+        return None;
+    }
+    
+    // Map the mean angles back into world coordinates
+    let mean_x = sum_sin.x.atan2(sum_cos.x) / TAU * world_size;
+    let mean_y = sum_sin.y.atan2(sum_cos.y) / TAU * world_size;
+    
+    Some(pt2(mean_x, mean_y))
+}
+
+// Vicsek order parameter: the length of the mean normalized velocity, from 0 (headings
+// cancel out) to 1 (every boid flies the same way). Returns None if no boid is moving.
+pub fn order_parameter<I: Iterator<Item = Vec2>>(velocities: I) -> Option<f32> {
+    let mut sum = Vec2::ZERO;
+    let mut count = 0;
+    
+    for velocity in velocities {
+        let speed_squared = velocity.length_squared();
+        if speed_squared > 0.0 {
+            sum += velocity / speed_squared.sqrt();
+            count += 1;
+        }
+    }
+    
+    if count == 0 {
+        None
     } else {
-        update_boids_without_spatial_grid(model);
+        Some(sum.length() / count as f32)
     }
 }
 
-// Update boids using spatial grid for optimization
-fn update_boids_with_spatial_grid(model: &mut Model) {
-    // Ensure the spatial grid has appropriate cell size
-    let max_radius = f32::max(
-        model.params.separation_radius,
-        f32::max(model.params.alignment_radius, model.params.cohesion_radius)
-    );
+// Flock-wide measures of order, computed once per physics step while enabled
+// (see SimulationParams::metrics_enabled)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlockMetrics {
+    pub polarization: f32, // The order parameter: 0 = disordered, 1 = fully aligned
+    pub mean_nearest_neighbor_distance: Option<f32>, // Wrapped; over boids with a neighbor in reach
+    // Mean normalized rotation about the centroid: 1 = every boid circles it counter-clockwise,
+    // -1 = clockwise, 0 = no milling
+    pub angular_momentum: f32,
+}
+
+// Compute the flock metrics from the positions and velocities and each boid's squared
+// distance to its nearest neighbor (infinite if it has none within reach)
+pub fn flock_metrics<I: Iterator<Item = f32>>(
+    positions: &[Point2],
+    velocities: &[Vec2],
+    nearest_distances_squared: I,
+    world_size: f32,
+) -> FlockMetrics {
+    let (nearest_sum, nearest_count) = nearest_distances_squared
+        .filter(|distance_squared| distance_squared.is_finite())
+        .fold((0.0, 0), |(sum, count), distance_squared| (sum + distance_squared.sqrt(), count + 1));
     
-    // Apply the cell size factor
-    let cell_size = max_radius * model.params.cell_size_factor;
+    FlockMetrics {
+        polarization: order_parameter(velocities.iter().copied()).unwrap_or(0.0),
+        mean_nearest_neighbor_distance: (nearest_count > 0).then(|| nearest_sum / nearest_count as f32),
+        angular_momentum: angular_momentum(positions, velocities, world_size),
+    }
+}
+
+// Mean of (r x v) / (|r| |v|) over the boids, with r the wrapped offset from the centroid
+pub fn angular_momentum(positions: &[Point2], velocities: &[Vec2], world_size: f32) -> f32 {
+    let Some(centroid) = wrapped_centroid(positions.iter().copied(), world_size) else {
+        return 0.0;
+    };
+    let half_world = world_size / 2.0;
     
-    // Recreate grid if perception radii have changed significantly
-    if (cell_size - model.spatial_grid.cell_size).abs() > 5.0 {
-        model.spatial_grid = SpatialGrid::new(cell_size, model.params.world_size);
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (&position, &velocity) in positions.iter().zip(velocities) {
+        let mut offset = position - centroid;
+        if offset.x.abs() > half_world {
+            offset.x -= world_size.copysign(offset.x);
+        }
+        if offset.y.abs() > half_world {
+            offset.y -= world_size.copysign(offset.y);
+        }
+        
+        let lengths = offset.length() * velocity.length();
+        if lengths > 0.0 {
+            sum += offset.perp_dot(velocity) / lengths;
+            count += 1;
+        }
     }
     
-    // Store previous state for interpolation
-    for boid in &mut model.boids {
-        boid.store_previous_state();
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+// Squared distance to the closest of a boid's neighbors, or infinity without neighbors
+#[inline]
+fn nearest_distance_squared(neighbors: &[NeighborEntry]) -> f32 {
+    neighbors.iter().map(|neighbor| neighbor.distance_squared).fold(f32::INFINITY, f32::min)
+}
+
+// Weighted forces of the three flocking rules for one boid, and how many
+// neighbors contributed to each rule
+#[derive(Default)]
+struct RuleForces {
+    separation: Vec2,
+    alignment: Vec2,
+    cohesion: Vec2,
+    separation_count: usize,
+    alignment_count: usize,
+    cohesion_count: usize,
+}
+
+impl RuleForces {
+    // The force applied to the boid
+    fn combined(&self) -> Vec2 {
+        self.separation + self.alignment + self.cohesion
     }
     
-    // Clear the spatial grid
-    model.spatial_grid.clear();
+    // The per-rule forces as shown by the inspector
+    fn debug(&self, index: usize) -> SelectedBoidDebug {
+        SelectedBoidDebug {
+            index,
+            separation_force: self.separation,
+            alignment_force: self.alignment,
+            cohesion_force: self.cohesion,
+            combined_force: self.combined(),
+            separation_count: self.separation_count,
+            alignment_count: self.alignment_count,
+            cohesion_count: self.cohesion_count,
+        }
+    }
+}
+
+// Reynolds steering (Steering = Desired - Velocity): the force that turns the velocity
+// toward `direction` at full speed, limited to max_force. Zero if there is no direction.
+#[inline]
+fn steer(direction: Vec2, velocity: Vec2, properties: &BoidProperties) -> Vec2 {
+    steer_with(direction, velocity, properties, false)
+}
+
+// steer, with the normalizations approximated in the fast math mode
+#[inline]
+fn steer_with(direction: Vec2, velocity: Vec2, properties: &BoidProperties, fast_math: bool) -> Vec2 {
+    let direction_length_squared = direction.length_squared();
+    if direction_length_squared <= 0.0 {
+        return Vec2::ZERO;
+    }
+    
+    // Scale to maximum speed (only normalize if needed)
+    let desired = direction * boid::div_sqrt(properties.max_speed, direction_length_squared, fast_math);
+    let mut steering = desired - velocity;
+    
+    // Limit force
+    let force_squared = steering.length_squared();
+    let max_force_squared = properties.max_force * properties.max_force;
+    
+    if force_squared > max_force_squared {
+        steering *= boid::div_sqrt(properties.max_force, force_squared, fast_math);
+    }
     
-    // Insert all boids into the spatial grid
-    for (i, boid) in model.boids.iter().enumerate() {
-        model.spatial_grid.insert(i, boid.position, model.params.world_size);
+    steering
+}
+
+// The flock's arrays, as the flocking rules read them
+#[derive(Clone, Copy)]
+struct FlockState<'a> {
+    positions: &'a [Point2],
+    velocities: &'a [Vec2],
+    properties: &'a [BoidProperties],
+}
+
+// The flocking forces on boid `index` from its neighbors (with their squared distances,
+// measured the short way around the world edges), all three rules in a single pass over
+// the neighbors. Every physics path, with or without the grid and in parallel or not,
+// computes the rules here.
+fn compute_flocking_force(
+    index: usize,
+    neighbors: impl Iterator<Item = NeighborEntry>,
+    flock: FlockState,
+    constants: &SpeciesConstants,
+    wrap: WorldWrap,
+) -> RuleForces {
+    // With every rule disabled the boid just coasts
+    if !constants.separation_enabled && !constants.alignment_enabled && !constants.cohesion_enabled {
+        return RuleForces::default();
     }
     
-    // Update spatial grid statistics for adaptive optimizations
-    model.spatial_grid.update_statistics();
+    let position = flock.positions[index];
+    let velocity = flock.velocities[index];
+    let properties = &flock.properties[index];
+    
+    let mut separation = Vec2::ZERO;
+    let mut alignment = Vec2::ZERO;
+    let mut cohesion = Vec2::ZERO;
+    let mut separation_count = 0;
+    let mut alignment_count = 0;
+    let mut cohesion_count = 0;
     
-    // Update debug info with grid statistics if debug is enabled
-    if model.params.show_debug {
-        let (occupied_cells, total_cells, occupancy_percentage, max_cell_population) = 
-            model.spatial_grid.get_statistics();
+    for neighbor in neighbors {
+        if constants.ignore_perched && flock.properties[neighbor.index].perched_until.is_some() {
+            continue;
+        }
         
-        unsafe {
-            (*model.debug_info.get()).update_grid_stats(
-                occupied_cells, 
-                total_cells, 
-                occupancy_percentage, 
-                max_cell_population
+        let d_squared = neighbor.distance_squared;
+        
+        // Alignment
+        if constants.alignment_enabled && d_squared < constants.align_radius_sq {
+            alignment += flock.velocities[neighbor.index];
+            alignment_count += 1;
+        }
+        
+        let near_separation = constants.separation_enabled && d_squared < constants.sep_radius_sq;
+        let near_cohesion = constants.cohesion_enabled && d_squared < constants.cohesion_radius_sq;
+        if !near_separation && !near_cohesion {
+            continue;
+        }
+        
+        // Vector from the neighbor to this boid, taking the shorter path around the world edges
+        let offset = wrap.offset(flock.positions[neighbor.index], position);
+        
+        // Separation
+        if near_separation {
+            // Weight by distance along the falloff curve
+            separation += offset * boid::separation_factor(
+                constants.separation_falloff,
+                constants.separation_falloff_power,
+                d_squared,
+                constants.separation_radius,
+                constants.fast_math,
             );
+            separation_count += 1;
+        }
+        
+        // Cohesion: average the (wrapped) offsets to the neighbors, not their positions, so
+        // neighbors on both sides of a world edge don't average out to a spot between them
+        if near_cohesion {
+            cohesion -= offset;
+            cohesion_count += 1;
         }
     }
     
-    // Pre-calculate weights to avoid multiplication in the inner loop
-    let separation_weight = model.params.separation_weight;
-    let alignment_weight = model.params.alignment_weight;
-    let cohesion_weight = model.params.cohesion_weight;
+    if separation_count > 0 {
+        separation = steer_with(separation / separation_count as f32, velocity, properties, constants.fast_math);
+    }
     
-    // Extract positions and velocities for the spatial grid's calculations
-    let boid_positions: Vec<Point2> = model.boids.iter().map(|boid| boid.position).collect();
-    let boid_velocities: Vec<Vec2> = model.boids.iter().map(|boid| boid.velocity).collect();
+    if alignment_count > 0 {
+        alignment = steer_with(alignment / alignment_count as f32, velocity, properties, constants.fast_math);
+    }
     
-    // Pre-calculate squared radii
-    let sep_radius_sq = model.params.separation_radius * model.params.separation_radius;
-    let align_radius_sq = model.params.alignment_radius * model.params.alignment_radius;
-    let cohesion_radius_sq = model.params.cohesion_radius * model.params.cohesion_radius;
+    if cohesion_count > 0 {
+        cohesion = boid::arrive(
+            cohesion / cohesion_count as f32,
+            velocity,
+            properties.max_speed,
+            properties.max_force,
+            constants.cohesion_slowing_radius,
+        );
+    }
     
-    // Choose between parallel and sequential processing based on the setting
-    if model.params.enable_parallel {
-        // For parallel processing, we need to pre-compute all neighbor data
-        let mut neighbor_data = Vec::with_capacity(model.boids.len());
+    RuleForces {
+        separation: separation * constants.separation_weight,
+        alignment: alignment * constants.alignment_weight,
+        cohesion: cohesion * constants.cohesion_weight,
+        separation_count,
+        alignment_count,
+        cohesion_count,
+    }
+}
+
+// Every other boid of the flock as a neighbor of boid `index` (the original O(n²) approach,
+// without the grid). A boid exactly on top of it is skipped like the boid itself.
+fn every_other_boid(positions: &[Point2], index: usize, wrap: WorldWrap) -> impl Iterator<Item = NeighborEntry> + '_ {
+    let position = positions[index];
+    positions.iter().enumerate().filter_map(move |(other_index, &other)| {
+        let distance_squared = wrap.offset(position, other).length_squared();
+        (distance_squared > 0.0).then_some(NeighborEntry { index: other_index, distance_squared })
+    })
+}
+
+// One flocking force pass over the flock: everything but how the neighbors are found
+struct ForcePass<'a> {
+    flock: FlockState<'a>,
+    constants_table: &'a ConstantsTable,
+    boid_regions: &'a [Option<usize>],
+    wrap: WorldWrap,
+    selected_index: Option<usize>, // Only this boid records its per-rule forces for the inspector
+}
+
+impl ForcePass<'_> {
+    // Add boid i's flocking force to its acceleration and store its cohesion neighbor count.
+    // Returns the per-rule forces if it is the selected boid.
+    #[inline]
+    fn apply(
+        &self,
+        i: usize,
+        neighbors: impl Iterator<Item = NeighborEntry>,
+        acceleration: &mut Vec2,
+        neighbor_count: &mut usize,
+    ) -> Option<SelectedBoidDebug> {
+        let boid_properties = &self.flock.properties[i];
         
-        // First pass: gather all neighbor data
-        for (_i, boid) in model.boids.iter().enumerate() {
-            let nearby_with_distances = model.spatial_grid.get_nearby_with_distances(
-                boid.position, 
-                &boid_positions, 
-                model.params.world_size
-            );
-            
-            // Clone the data to avoid borrowing issues
-            let neighbors: Vec<_> = nearby_with_distances.iter().map(|&n| n).collect();
-            neighbor_data.push(neighbors);
+        // Frozen and perched boids keep their position but may still influence neighbors
+        if boid_properties.is_stationary() {
+            return None;
         }
         
-        // Second pass: calculate and apply forces in parallel
-        // Use par_chunks_mut instead of par_iter_mut.enumerate() to reduce synchronization overhead
-        // This processes boids in chunks, reducing the number of parallel tasks and synchronization points
-        let chunk_size = std::cmp::max(model.boids.len() / rayon::current_num_threads(), 1);
+        // Look up this boid's species parameters, as changed by the region it is in
+        let constants = self.constants_table.get(self.boid_regions[i], boid_properties.species);
         
-        // Update debug info with chunk size if debug is enabled
-        if model.params.show_debug {
-            unsafe {
-                (*model.debug_info.get()).chunk_size = Some(chunk_size);
+        let forces = compute_flocking_force(i, neighbors, self.flock, constants, self.wrap);
+        *acceleration += forces.combined();
+        *neighbor_count = forces.cohesion_count;
+        
+        (self.selected_index == Some(i)).then(|| forces.debug(i))
+    }
+    
+    // Run the pass over the whole flock, where neighbors(i) lists boid i's neighbors: in
+    // chunks of chunk_size boids on the thread pool, or in order without a chunk size.
+    // Returns the selected boid's per-rule forces.
+    fn run<N, I>(
+        &self,
+        accelerations: &mut [Vec2],
+        neighbor_counts: &mut [usize],
+        neighbors: N,
+        chunk_size: Option<usize>,
+    ) -> Option<SelectedBoidDebug>
+    where
+        N: Fn(usize) -> I + Sync,
+        I: Iterator<Item = NeighborEntry>,
+    {
+        let chunk_size = match chunk_size {
+            Some(chunk_size) => chunk_size,
+            None => {
+                let mut selected_debug = None;
+                for (i, (acceleration, neighbor_count)) in accelerations.iter_mut().zip(neighbor_counts.iter_mut()).enumerate() {
+                    selected_debug = self.apply(i, neighbors(i), acceleration, neighbor_count).or(selected_debug);
+                }
+                return selected_debug;
             }
-        }
+        };
+        
+        // Written from at most one thread (the one processing the selected boid)
+        let selected_debug = Mutex::new(None);
         
-        model.boids.par_chunks_mut(chunk_size).enumerate().for_each(|(chunk_idx, boid_chunk)| {
-            // Process each boid in the chunk sequentially
-            for (i_in_chunk, boid) in boid_chunk.iter_mut().enumerate() {
+        // Chunks rather than single boids keep the number of parallel tasks and
+        // synchronization points down
+        let chunks = accelerations.par_chunks_mut(chunk_size).zip(neighbor_counts.par_chunks_mut(chunk_size));
+        chunks.enumerate().for_each(|(chunk_idx, (acceleration_chunk, count_chunk))| {
+            for (i_in_chunk, (acceleration, neighbor_count)) in acceleration_chunk.iter_mut().zip(count_chunk.iter_mut()).enumerate() {
                 let i = chunk_idx * chunk_size + i_in_chunk;
-                if i >= neighbor_data.len() {
-                    break; // Safety check for the last chunk which might be smaller
+                if let Some(debug) = self.apply(i, neighbors(i), acceleration, neighbor_count) {
+                    *selected_debug.lock().unwrap() = Some(debug);
                 }
-                
-                let neighbors = &neighbor_data[i];
-                
-                // Calculate forces
-                let mut separation = Vec2::ZERO;
-                let mut alignment = Vec2::ZERO;
-                let mut cohesion = Vec2::ZERO;
-                let mut sep_count = 0;
-                let mut align_count = 0;
-                let mut cohesion_count = 0;
-                
-                // Process all neighbors in a single pass
-                for &neighbor in neighbors {
-                    let d_squared = neighbor.distance_squared;
-                    let other_idx = neighbor.index;
-                    
-                    // Separation
-                    if d_squared < sep_radius_sq {
-                        // Calculate vector pointing away from neighbor
-                        let dx = boid.position.x - boid_positions[other_idx].x;
-                        let dy = boid.position.y - boid_positions[other_idx].y;
-                        
-                        // Handle wrapping for separation vector
-                        let half_world = model.params.world_size / 2.0;
-                        let mut wrapped_dx = dx;
-                        let mut wrapped_dy = dy;
-                        
-                        // Check if wrapping around provides a shorter path
-                        if dx.abs() > half_world {
-                            wrapped_dx = if dx > 0.0 { dx - model.params.world_size } else { dx + model.params.world_size };
-                        }
-                        
-                        if dy.abs() > half_world {
-                            wrapped_dy = if dy > 0.0 { dy - model.params.world_size } else { dy + model.params.world_size };
-                        }
-                        
-                        // Only calculate actual distance if needed for weighting
-                        let d = d_squared.sqrt();
-                        
-                        // Weight by distance (closer boids have more influence)
-                        separation.x += (wrapped_dx / d) / d;
-                        separation.y += (wrapped_dy / d) / d;
-                        sep_count += 1;
-                    }
-                    
-                    // Alignment
-                    if d_squared < align_radius_sq {
-                        alignment += boid_velocities[other_idx];
-                        align_count += 1;
-                    }
-                    
-                    // Cohesion
-                    if d_squared < cohesion_radius_sq {
-                        // Handle wrapping for cohesion target
-                        let other_pos = boid_positions[other_idx];
-                        let half_world = model.params.world_size / 2.0;
-                        let mut target_x = other_pos.x;
-                        let mut target_y = other_pos.y;
-                        
-                        // Calculate direct distance components
-                        let dx = boid.position.x - other_pos.x;
-                        let dy = boid.position.y - other_pos.y;
-                        
-                        // Check if wrapping around provides a shorter path
-                        if dx.abs() > half_world {
-                            target_x += if dx > 0.0 { model.params.world_size } else { -model.params.world_size };
-                        }
-                        
-                        if dy.abs() > half_world {
-                            target_y += if dy > 0.0 { model.params.world_size } else { -model.params.world_size };
-                        }
-                        
-                        cohesion.x += target_x;
-                        cohesion.y += target_y;
-                        cohesion_count += 1;
-                    }
-                }
-                
-                // Process separation
-                if sep_count > 0 {
-                    separation /= sep_count as f32;
-                    
-                    let separation_length_squared = separation.length_squared();
-                    if separation_length_squared > 0.0 {
-                        // Implement Reynolds: Steering = Desired - Velocity
-                        let separation_length = separation_length_squared.sqrt();
-                        let desired = separation * (boid.max_speed / separation_length);
-                        
-                        separation = desired - boid.velocity;
-                        
-                        // Limit force
-                        let force_squared = separation.length_squared();
-                        let max_force_squared = boid.max_force * boid.max_force;
-                        
-                        if force_squared > max_force_squared {
-                            let force_length = force_squared.sqrt();
-                            separation *= boid.max_force / force_length;
-                        }
-                    }
-                }
-                
-                // Process alignment
-                if align_count > 0 {
-                    alignment /= align_count as f32;
-                    
-                    let alignment_length_squared = alignment.length_squared();
-                    if alignment_length_squared > 0.0 {
-                        // Implement Reynolds: Steering = Desired - Velocity
-                        let alignment_length = alignment_length_squared.sqrt();
-                        let desired = alignment * (boid.max_speed / alignment_length);
-                        
-                        alignment = desired - boid.velocity;
-                        
-                        // Limit force
-                        let force_squared = alignment.length_squared();
-                        let max_force_squared = boid.max_force * boid.max_force;
-                        
-                        if force_squared > max_force_squared {
-                            let force_length = force_squared.sqrt();
-                            alignment *= boid.max_force / force_length;
-                        }
-                    }
-                }
-                
-                // Process cohesion
-                if cohesion_count > 0 {
-                    cohesion.x /= cohesion_count as f32;
-                    cohesion.y /= cohesion_count as f32;
-                    
-                    // Calculate steering vector towards center
-                    let cohesion_target = pt2(cohesion.x, cohesion.y);
-                    let desired = cohesion_target - boid.position;
-                    
-                    let desired_length_squared = desired.length_squared();
-                    if desired_length_squared > 0.0 {
-                        // Scale to maximum speed
-                        let desired_length = desired_length_squared.sqrt();
-                        let desired_normalized = desired * (boid.max_speed / desired_length);
-                        
-                        // Steering = Desired - Velocity
-                        cohesion = desired_normalized - boid.velocity;
-                        
-                        // Limit force
-                        let force_squared = cohesion.length_squared();
-                        let max_force_squared = boid.max_force * boid.max_force;
-                        
-                        if force_squared > max_force_squared {
-                            let force_length = force_squared.sqrt();
-                            cohesion *= boid.max_force / force_length;
-                        }
-                    }
-                }
-                
-                // Combine forces with weights (avoid creating intermediate vectors)
-                let mut combined_force = Vec2::ZERO;
-                combined_force.x = separation.x * separation_weight + alignment.x * alignment_weight + cohesion.x * cohesion_weight;
-                combined_force.y = separation.y * separation_weight + alignment.y * alignment_weight + cohesion.y * cohesion_weight;
-                
-                // Apply the calculated acceleration
-                boid.apply_force(combined_force);
-                
-                // Update position
-                boid.update();
-                
-                // Wrap around edges
-                boid.wrap_edges(model.params.world_size);
             }
         });
+        
+        selected_debug.into_inner().unwrap()
+    }
+}
+
+// Boids per parallel chunk: one chunk per thread
+fn parallel_chunk_size(len: usize) -> usize {
+    std::cmp::max(len / rayon::current_num_threads(), 1)
+}
+
+// Collect the neighbors within the contact radius of an infected boid that aren't infected yet
+fn infection_contacts(
+    neighbors: &[NeighborEntry],
+    properties: &[BoidProperties],
+    infected_species: u8,
+    contact_radius_sq: f32,
+    contacts: &mut Vec<usize>,
+) {
+    for neighbor in neighbors {
+        if neighbor.distance_squared < contact_radius_sq && properties[neighbor.index].species != infected_species {
+            contacts.push(neighbor.index);
+        }
+    }
+}
+
+// Move one boid by its accumulated acceleration and wrap it around the world edges
+#[inline]
+fn integrate_boid(
+    position: &mut Point2,
+    velocity: &mut Vec2,
+    acceleration: &mut Vec2,
+    prev_position: &mut Point2,
+    properties: &BoidProperties,
+    wrap: WorldWrap,
+    fraction: f32,
+) {
+    // Frozen and perched boids keep their position
+    if properties.is_stationary() {
+        return;
+    }
+    
+    boid::integrate_fraction(position, velocity, acceleration, properties.max_speed, fraction);
+    boid::wrap_edges(position, prev_position, wrap.world_size, wrap.wrap_x);
+}
+
+// Steering force away from the walls within the avoidance radius, stronger the closer the wall
+fn wall_avoidance(
+    position: Point2,
+    velocity: Vec2,
+    properties: &BoidProperties,
+    walls: &[Wall],
+    wall_grid: &WallGrid,
+    radius: f32,
+    nearby: &mut Vec<usize>,
+) -> Vec2 {
+    wall_grid.walls_near(position, nearby);
+    let radius_sq = radius * radius;
+    
+    let mut away = Vec2::ZERO;
+    for &index in nearby.iter() {
+        let offset = position - walls[index].closest_point(position);
+        let d_squared = offset.length_squared();
+        if d_squared >= radius_sq || d_squared <= 0.0 {
+            continue;
+        }
+        
+        // Weight by how far inside the avoidance radius the boid is
+        let d = d_squared.sqrt();
+        away += offset / d * (1.0 - d / radius);
+    }
+    
+    steer(away, velocity, properties)
+}
+
+// Add the wall avoidance force to every boid's acceleration
+fn apply_wall_forces(sim: &mut Simulation) {
+    let radius = sim.params.wall_avoid_radius;
+    let weight = sim.params.wall_avoid_weight;
+    let (walls, wall_grid) = (&sim.walls, &sim.wall_grid);
+    let Flock { positions, velocities, accelerations, properties, .. } = &mut sim.flock;
+    
+    let apply = |nearby: &mut Vec<usize>, (acceleration, position, velocity, properties): (&mut Vec2, &Point2, &Vec2, &BoidProperties)| {
+        // Frozen and perched boids don't move, so they don't need to avoid anything
+        if !properties.is_stationary() {
+            *acceleration += wall_avoidance(*position, *velocity, properties, walls, wall_grid, radius, nearby) * weight;
+        }
+    };
+    
+    if sim.params.enable_parallel {
+        (
+            accelerations.par_iter_mut(),
+            positions.par_iter(),
+            velocities.par_iter(),
+            properties.par_iter(),
+        ).into_par_iter().for_each_init(Vec::new, apply);
     } else {
-        // Sequential processing for when parallel is disabled
-        for boid in &mut model.boids {
-            // Get nearby boids with pre-computed distances
-            let nearby_with_distances = model.spatial_grid.get_nearby_with_distances(
-                boid.position, 
-                &boid_positions, 
-                model.params.world_size
-            );
-            
-            // Calculate forces
-            let mut separation = Vec2::ZERO;
-            let mut alignment = Vec2::ZERO;
-            let mut cohesion = Vec2::ZERO;
-            let mut sep_count = 0;
-            let mut align_count = 0;
-            let mut cohesion_count = 0;
-            
-            // Process all neighbors in a single pass
-            for &neighbor in nearby_with_distances {
-                let d_squared = neighbor.distance_squared;
-                let other_idx = neighbor.index;
-                
-                // Separation
-                if d_squared < sep_radius_sq {
-                    // Calculate vector pointing away from neighbor
-                    let dx = boid.position.x - boid_positions[other_idx].x;
-                    let dy = boid.position.y - boid_positions[other_idx].y;
-                    
-                    // Handle wrapping for separation vector
-                    let half_world = model.params.world_size / 2.0;
-                    let mut wrapped_dx = dx;
-                    let mut wrapped_dy = dy;
-                    
-                    // Check if wrapping around provides a shorter path
-                    if dx.abs() > half_world {
-                        wrapped_dx = if dx > 0.0 { dx - model.params.world_size } else { dx + model.params.world_size };
-                    }
-                    
-                    if dy.abs() > half_world {
-                        wrapped_dy = if dy > 0.0 { dy - model.params.world_size } else { dy + model.params.world_size };
-                    }
-                    
-                    // Only calculate actual distance if needed for weighting
-                    let d = d_squared.sqrt();
-                    
-                    // Weight by distance (closer boids have more influence)
-                    separation.x += (wrapped_dx / d) / d;
-                    separation.y += (wrapped_dy / d) / d;
-                    sep_count += 1;
-                }
-                
-                // Alignment
-                if d_squared < align_radius_sq {
-                    alignment += boid_velocities[other_idx];
-                    align_count += 1;
-                }
-                
-                // Cohesion
-                if d_squared < cohesion_radius_sq {
-                    // Handle wrapping for cohesion target
-                    let other_pos = boid_positions[other_idx];
-                    let half_world = model.params.world_size / 2.0;
-                    let mut target_x = other_pos.x;
-                    let mut target_y = other_pos.y;
-                    
-                    // Calculate direct distance components
-                    let dx = boid.position.x - other_pos.x;
-                    let dy = boid.position.y - other_pos.y;
-                    
-                    // Check if wrapping around provides a shorter path
-                    if dx.abs() > half_world {
-                        target_x += if dx > 0.0 { model.params.world_size } else { -model.params.world_size };
-                    }
-                    
-                    if dy.abs() > half_world {
-                        target_y += if dy > 0.0 { model.params.world_size } else { -model.params.world_size };
-                    }
-                    
-                    cohesion.x += target_x;
-                    cohesion.y += target_y;
-                    cohesion_count += 1;
-                }
-            }
-            
-            // Process separation
-            if sep_count > 0 {
-                separation /= sep_count as f32;
-                
-                let separation_length_squared = separation.length_squared();
-                if separation_length_squared > 0.0 {
-                    // Implement Reynolds: Steering = Desired - Velocity
-                    let separation_length = separation_length_squared.sqrt();
-                    let desired = separation * (boid.max_speed / separation_length);
-                    
-                    separation = desired - boid.velocity;
-                    
-                    // Limit force
-                    let force_squared = separation.length_squared();
-                    let max_force_squared = boid.max_force * boid.max_force;
-                    
-                    if force_squared > max_force_squared {
-                        let force_length = force_squared.sqrt();
-                        separation *= boid.max_force / force_length;
-                    }
-                }
+        let mut nearby = Vec::new();
+        let boids = accelerations.iter_mut()
+            .zip(positions.iter())
+            .zip(velocities.iter())
+            .zip(properties.iter());
+        
+        for (((acceleration, position), velocity), properties) in boids {
+            apply(&mut nearby, (acceleration, position, velocity, properties));
+        }
+    }
+}
+
+// Steer every boid up the pheromone gradient. The gradient is sensed one field cell
+// ahead of the boid, so it follows the trails in front of it rather than being held
+// back by the scent it has just left behind.
+fn apply_pheromone_forces(sim: &mut Simulation, field: &PheromoneField) {
+    let weight = sim.params.pheromone_weight;
+    let sensor_distance = field.cell_size();
+    let Flock { positions, velocities, accelerations, properties, .. } = &mut sim.flock;
+    
+    let apply = |(acceleration, position, velocity, properties): (&mut Vec2, &Point2, &Vec2, &BoidProperties)| {
+        if !properties.is_stationary() {
+            let sensor = *position + velocity.normalize_or_zero() * sensor_distance;
+            *acceleration += steer(field.gradient(sensor), *velocity, properties) * weight;
+        }
+    };
+    
+    if sim.params.enable_parallel {
+        (
+            accelerations.par_iter_mut(),
+            positions.par_iter(),
+            velocities.par_iter(),
+            properties.par_iter(),
+        ).into_par_iter().for_each(apply);
+    } else {
+        accelerations.iter_mut()
+            .zip(positions.iter())
+            .zip(velocities.iter())
+            .zip(properties.iter())
+            .for_each(|(((acceleration, position), velocity), properties)| apply((acceleration, position, velocity, properties)));
+    }
+}
+
+// Pull every boid toward the centroid of the boids within the far cohesion radius, using
+// the cell centroids of the freshly filled grid. Steers like the cohesion rule (arriving
+// when arrival is enabled), only at a much larger scale.
+fn apply_far_cohesion(sim: &mut Simulation) {
+    let weight = sim.params.far_cohesion_weight;
+    let radius = sim.params.far_cohesion_radius;
+    let slowing_radius = sim.params.enable_arrival.then_some(sim.params.slowing_radius);
+    let field = FarField::new(&sim.spatial_grid, radius, sim.params.world_size);
+    let Flock { positions, velocities, accelerations, properties, .. } = &mut sim.flock;
+    
+    let apply = |(acceleration, position, velocity, properties): (&mut Vec2, &Point2, &Vec2, &BoidProperties)| {
+        if !properties.is_stationary() {
+            if let Some(offset) = field.mean_offset(*position, radius) {
+                *acceleration += boid::arrive(offset, *velocity, properties.max_speed, properties.max_force, slowing_radius) * weight;
             }
-            
-            // Process alignment
-            if align_count > 0 {
-                alignment /= align_count as f32;
-                
-                let alignment_length_squared = alignment.length_squared();
-                if alignment_length_squared > 0.0 {
-                    // Implement Reynolds: Steering = Desired - Velocity
-                    let alignment_length = alignment_length_squared.sqrt();
-                    let desired = alignment * (boid.max_speed / alignment_length);
-                    
-                    alignment = desired - boid.velocity;
-                    
-                    // Limit force
-                    let force_squared = alignment.length_squared();
-                    let max_force_squared = boid.max_force * boid.max_force;
-                    
-                    if force_squared > max_force_squared {
-                        let force_length = force_squared.sqrt();
-                        alignment *= boid.max_force / force_length;
-                    }
+        }
+    };
+    
+    if sim.params.enable_parallel {
+        (
+            accelerations.par_iter_mut(),
+            positions.par_iter(),
+            velocities.par_iter(),
+            properties.par_iter(),
+        ).into_par_iter().for_each(apply);
+    } else {
+        accelerations.iter_mut()
+            .zip(positions.iter())
+            .zip(velocities.iter())
+            .zip(properties.iter())
+            .for_each(|(((acceleration, position), velocity), properties)| apply((acceleration, position, velocity, properties)));
+    }
+}
+
+// Let the pheromone evaporate, then have every boid deposit some at its new position
+fn update_pheromones(sim: &mut Simulation) {
+    if let Some(field) = &mut sim.pheromones {
+        field.decay(sim.params.pheromone_decay);
+        
+        let amount = sim.params.pheromone_deposit;
+        for &position in &sim.flock.positions {
+            field.deposit(position, amount);
+        }
+    }
+}
+
+// Stop a boid whose last step crossed a wall just short of it, and remove the part of its
+// velocity going into the wall so it slides along it
+fn resolve_wall_crossing(
+    position: &mut Point2,
+    velocity: &mut Vec2,
+    from: Point2,
+    walls: &[Wall],
+    wall_grid: &WallGrid,
+    nearby: &mut Vec<usize>,
+) {
+    let movement = *position - from;
+    let length_squared = movement.length_squared();
+    if length_squared <= 0.0 {
+        return;
+    }
+    
+    // A step longer than a cell could cross a wall outside the cells around the new
+    // position, so fast boids test every wall
+    if length_squared > wall_grid.cell_size() * wall_grid.cell_size() {
+        nearby.clear();
+        nearby.extend(0..walls.len());
+    } else {
+        wall_grid.walls_near(*position, nearby);
+    }
+    
+    // The first wall along the movement is the one that stops the boid
+    let hit = nearby.iter()
+        .filter_map(|&index| walls[index].crossing(from, *position).map(|t| (t, index)))
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    
+    if let Some((t, index)) = hit {
+        let stop = (t - WALL_MARGIN / length_squared.sqrt()).max(0.0);
+        *position = from + movement * stop;
+        
+        let normal = walls[index].normal_towards(from);
+        let into_wall = velocity.dot(normal);
+        if into_wall < 0.0 {
+            *velocity -= normal * into_wall;
+        }
+    }
+}
+
+// Undo any step that went through a wall (after integration)
+fn resolve_wall_crossings(sim: &mut Simulation) {
+    let (walls, wall_grid) = (&sim.walls, &sim.wall_grid);
+    let Flock { positions, velocities, prev_positions, .. } = &mut sim.flock;
+    
+    if sim.params.enable_parallel {
+        (
+            positions.par_iter_mut(),
+            velocities.par_iter_mut(),
+            prev_positions.par_iter(),
+        ).into_par_iter().for_each_init(Vec::new, |nearby, (position, velocity, &from)| {
+            resolve_wall_crossing(position, velocity, from, walls, wall_grid, nearby);
+        });
+    } else {
+        let mut nearby = Vec::new();
+        for ((position, velocity), &from) in positions.iter_mut().zip(velocities.iter_mut()).zip(prev_positions.iter()) {
+            resolve_wall_crossing(position, velocity, from, walls, wall_grid, &mut nearby);
+        }
+    }
+}
+
+// A boid's energy after one step: it drains faster the faster the boid flies, and
+// refills while the boid has more than the threshold number of neighbors
+#[inline]
+fn next_energy(energy: f32, speed_fraction: f32, flocking: bool, drain_rate: f32, gain_rate: f32, dt: f32) -> f32 {
+    let mut change = -drain_rate * (1.0 + speed_fraction);
+    if flocking {
+        change += gain_rate;
+    }
+    
+    (energy + change * dt).clamp(0.0, MAX_ENERGY)
+}
+
+// Update every boid's energy from its speed and the neighbor counts of this step.
+// Boids left with zero energy are dead until Simulation::remove_dead removes them.
+fn update_energy(sim: &mut Simulation) {
+    let dt = sim.physics_step_size.as_secs_f32();
+    let drain_rate = sim.params.energy_drain_rate;
+    let gain_rate = sim.params.energy_gain_rate;
+    let threshold = sim.params.energy_neighbor_threshold;
+    let neighbor_counts = &sim.neighbor_counts;
+    let Flock { velocities, properties, energies, .. } = &mut sim.flock;
+    
+    let update = |(energy, velocity, properties, &neighbors): (&mut f32, &Vec2, &BoidProperties, &usize)| {
+        // Frozen boids are paused, so they neither starve nor feed
+        if !properties.frozen {
+            let speed_fraction = (velocity.length() / properties.max_speed.max(f32::EPSILON)).min(1.0);
+            *energy = next_energy(*energy, speed_fraction, neighbors > threshold, drain_rate, gain_rate, dt);
+        }
+    };
+    
+    if sim.params.enable_parallel {
+        (
+            energies.par_iter_mut(),
+            velocities.par_iter(),
+            properties.par_iter(),
+            neighbor_counts.par_iter(),
+        ).into_par_iter().for_each(update);
+    } else {
+        energies.iter_mut()
+            .zip(velocities.iter())
+            .zip(properties.iter())
+            .zip(neighbor_counts.iter())
+            .for_each(|(((energy, velocity), properties), neighbors)| update((energy, velocity, properties, neighbors)));
+    }
+}
+
+// Largest angle between a takeoff and straight up (perching)
+const TAKEOFF_SPREAD: f32 = PI / 4.0;
+
+// Velocity of a boid taking off: upward, within TAKEOFF_SPREAD of straight up
+fn takeoff_velocity<R: Rng>(rng: &mut R, speed: f32) -> Vec2 {
+    let angle = PI / 2.0 + rng.gen_range(-TAKEOFF_SPREAD..=TAKEOFF_SPREAD);
+    vec2(angle.cos(), angle.sin()) * speed
+}
+
+// Land the boids whose step crossed the top of the ground band on their way down (each with
+// the perch chance), and launch the boids whose rest is over. Runs after integration, so a
+// boid lands where its step ended. Rests are counted in steps, so seeded runs stay reproducible.
+fn update_perching(sim: &mut Simulation) {
+    let step = sim.step_count;
+    let band_top = -sim.params.world_size / 2.0 + sim.params.perch_band_height;
+    let chance = sim.params.perch_chance;
+    let min_rest = sim.params.perch_min_rest;
+    let max_rest = sim.params.perch_max_rest.max(min_rest);
+    let takeoff_speed = sim.params.perch_takeoff_speed;
+    let step_seconds = sim.physics_step_size.as_secs_f32();
+    let rng = &mut sim.rng;
+    let Flock { positions, prev_positions, velocities, properties, .. } = &mut sim.flock;
+    
+    for (((position, prev_position), velocity), properties) in positions.iter().zip(prev_positions.iter()).zip(velocities.iter_mut()).zip(properties.iter_mut()) {
+        match properties.perched_until {
+            Some(until) => {
+                if step >= until {
+                    properties.perched_until = None;
+                    *velocity = takeoff_velocity(rng, takeoff_speed);
                 }
             }
-            
-            // Process cohesion
-            if cohesion_count > 0 {
-                cohesion.x /= cohesion_count as f32;
-                cohesion.y /= cohesion_count as f32;
-                
-                // Calculate steering vector towards center
-                let cohesion_target = pt2(cohesion.x, cohesion.y);
-                let desired = cohesion_target - boid.position;
-                
-                let desired_length_squared = desired.length_squared();
-                if desired_length_squared > 0.0 {
-                    // Scale to maximum speed
-                    let desired_length = desired_length_squared.sqrt();
-                    let desired_normalized = desired * (boid.max_speed / desired_length);
-                    
-                    // Steering = Desired - Velocity
-                    cohesion = desired_normalized - boid.velocity;
-                    
-                    // Limit force
-                    let force_squared = cohesion.length_squared();
-                    let max_force_squared = boid.max_force * boid.max_force;
-                    
-                    if force_squared > max_force_squared {
-                        let force_length = force_squared.sqrt();
-                        cohesion *= boid.max_force / force_length;
-                    }
+            None => {
+                if prev_position.y >= band_top && position.y < band_top && rng.gen::<f32>() < chance {
+                    let rest_steps = (rng.gen_range(min_rest..=max_rest) / step_seconds).round().max(1.0) as u64;
+                    properties.perched_until = Some(step + rest_steps);
+                    *velocity = Vec2::ZERO;
                 }
             }
-            
-            // Combine forces with weights (avoid creating intermediate vectors)
-            let mut combined_force = Vec2::ZERO;
-            combined_force.x = separation.x * separation_weight + alignment.x * alignment_weight + cohesion.x * cohesion_weight;
-            combined_force.y = separation.y * separation_weight + alignment.y * alignment_weight + cohesion.y * cohesion_weight;
-            
-            // Apply the calculated acceleration
-            boid.apply_force(combined_force);
-            
-            // Update position
-            boid.update();
-            
-            // Wrap around edges
-            boid.wrap_edges(model.params.world_size);
         }
     }
+}
+
+// Launch every perched boid at once (when perching is switched off)
+pub fn release_perched(sim: &mut Simulation) {
+    let takeoff_speed = sim.params.perch_takeoff_speed;
+    let rng = &mut sim.rng;
+    let Flock { velocities, properties, .. } = &mut sim.flock;
     
-    // Wrap boids around the edges of the world
-    for boid in &mut model.boids {
-        boid.wrap_edges(model.params.world_size);
+    for (velocity, properties) in velocities.iter_mut().zip(properties.iter_mut()) {
+        if properties.perched_until.take().is_some() {
+            *velocity = takeoff_velocity(rng, takeoff_speed);
+        }
     }
 }
 
-// Update boids without spatial grid (original O(n²) approach)
-fn update_boids_without_spatial_grid(model: &mut Model) {
-    // Create a copy of boids for the calculations
-    let boids_clone = model.boids.clone();
-    
-    // Pre-calculate weights to avoid multiplication in the inner loop
-    let separation_weight = model.params.separation_weight;
-    let alignment_weight = model.params.alignment_weight;
-    let cohesion_weight = model.params.cohesion_weight;
-    
-    // Use parallel processing if enabled
-    if model.params.enable_parallel {
-        // Calculate optimal chunk size based on available threads
-        let chunk_size = std::cmp::max(model.boids.len() / rayon::current_num_threads(), 1);
+// Turn every boid's steered velocity (velocity + acceleration) by a random angle uniform in
+// [-noise/2, noise/2] before it moves (Vicsek-style noise). The angles come from the
+// simulation RNG in index order, so seeded runs stay reproducible. Does nothing without noise.
+// A substep steers and turns by its fraction of the step.
+fn apply_angular_noise(sim: &mut Simulation, fraction: f32) {
+    if sim.params.angular_noise <= 0.0 {
+        return;
+    }
+    
+    let half_angle = sim.params.angular_noise.to_radians() / 2.0 * fraction;
+    let rng = &mut sim.rng;
+    let Flock { velocities, accelerations, properties, .. } = &mut sim.flock;
+    
+    for ((velocity, acceleration), properties) in velocities.iter_mut().zip(accelerations.iter_mut()).zip(properties.iter()) {
+        if properties.is_stationary() {
+            continue;
+        }
         
-        // Update debug info with chunk size if debug is enabled
-        if model.params.show_debug {
-            unsafe {
-                (*model.debug_info.get()).chunk_size = Some(chunk_size);
-            }
+        // The steering is folded into the velocity, which integration then limits as usual
+        let steered = *velocity + *acceleration * fraction;
+        let (sin, cos) = rng.gen_range(-half_angle..=half_angle).sin_cos();
+        *velocity = vec2(steered.x * cos - steered.y * sin, steered.x * sin + steered.y * cos);
+        *acceleration = Vec2::ZERO;
+    }
+}
+
+// Integrate every boid over a fraction of the step once all accelerations of the substep are
+// known (in wind tunnel mode boids that cross the left or right edge stay outside until the
+// simulation removes them)
+fn integrate_flock(flock: &mut Flock, world_size: f32, wind_tunnel: bool, parallel: bool, fraction: f32) {
+    let wrap = WorldWrap { world_size, wrap_x: !wind_tunnel };
+    let Flock { positions, velocities, accelerations, prev_positions, properties, .. } = flock;
+    
+    if parallel {
+        (
+            positions.par_iter_mut(),
+            velocities.par_iter_mut(),
+            accelerations.par_iter_mut(),
+            prev_positions.par_iter_mut(),
+            properties.par_iter(),
+        ).into_par_iter().for_each(|(position, velocity, acceleration, prev_position, properties)| {
+            integrate_boid(position, velocity, acceleration, prev_position, properties, wrap, fraction);
+        });
+    } else {
+        let boids = positions.iter_mut()
+            .zip(velocities.iter_mut())
+            .zip(accelerations.iter_mut())
+            .zip(prev_positions.iter_mut())
+            .zip(properties.iter());
+        
+        for ((((position, velocity), acceleration), prev_position), properties) in boids {
+            integrate_boid(position, velocity, acceleration, prev_position, properties, wrap, fraction);
+        }
+    }
+}
+
+// One force/integrate pass of a physics step
+#[derive(Clone, Copy)]
+struct Substep {
+    first: bool,   // The grid is built, the neighbors gathered and the flock measured in the first only
+    fraction: f32, // Share of the step the boids move over
+}
+
+// Update boid positions and behaviors (one fixed physics step).
+// Forces are calculated from the state at the start of the step and accumulated into
+// the acceleration array; the boids are only moved once every force is known. With
+// substeps this repeats over equal fractions of the step, so fast boids can't skip past
+// their neighbors and walls, reusing the neighbors found in the first substep.
+pub fn update_boids(sim: &mut Simulation) {
+    // Store previous state for interpolation exactly once per step, so that after
+    // several steps in one frame the renderer interpolates across the last step only
+    let timer = phase_start(sim.params.show_debug);
+    sim.flock.store_previous_state();
+    phase_end(timer, &mut sim.physics_timings.store_previous);
+    
+    // Regions change the flocking parameters of the boids inside them
+    resolve_regions(sim);
+    
+    let substeps_range = SimulationParams::get_physics_substeps_range();
+    let substeps = sim.params.physics_substeps.clamp(*substeps_range.start(), *substeps_range.end());
+    sim.physics_timings.substeps = substeps;
+    
+    for index in 0..substeps {
+        let substep = Substep { first: index == 0, fraction: 1.0 / substeps as f32 };
+        
+        // Walls push nearby boids away; the flocking rules add to the same accelerations
+        if !sim.walls.is_empty() {
+            let timer = phase_start(sim.params.show_debug);
+            apply_wall_forces(sim);
+            phase_end(timer, &mut sim.physics_timings.forces);
         }
         
-        // Process boids in parallel chunks to reduce synchronization overhead
-        model.boids.par_chunks_mut(chunk_size).for_each(|boid_chunk| {
-            for boid in boid_chunk {
-                // Calculate forces
-                let separation = boid.separation_original(&boids_clone, model.params.separation_radius, model.params.enable_squared_distance);
-                let alignment = boid.alignment_original(&boids_clone, model.params.alignment_radius, model.params.enable_squared_distance);
-                let cohesion = boid.cohesion_original(&boids_clone, model.params.cohesion_radius, model.params.enable_squared_distance);
-                
-                // Combine forces with weights (avoid creating intermediate vectors)
-                let mut combined_force = Vec2::ZERO;
-                combined_force.x = separation.x * separation_weight + alignment.x * alignment_weight + cohesion.x * cohesion_weight;
-                combined_force.y = separation.y * separation_weight + alignment.y * alignment_weight + cohesion.y * cohesion_weight;
-                
-                // Apply the calculated acceleration
-                boid.apply_force(combined_force);
-                
-                // Update position
-                boid.update();
-                
-                // Wrap around edges
-                boid.wrap_edges(model.params.world_size);
+        // Boids follow the pheromone trails (the field is taken out so the flock can be borrowed)
+        if sim.params.pheromone_weight > 0.0 {
+            if let Some(field) = sim.pheromones.take() {
+                let timer = phase_start(sim.params.show_debug);
+                apply_pheromone_forces(sim, &field);
+                phase_end(timer, &mut sim.physics_timings.forces);
+                sim.pheromones = Some(field);
             }
-        });
+        }
+        
+        // Only use spatial grid if enabled
+        if sim.params.enable_spatial_grid {
+            update_boids_with_spatial_grid(sim, substep);
+        } else {
+            update_boids_without_spatial_grid(sim, substep);
+        }
+        
+        // No boid may pass through a wall in a single step (checked from where the step
+        // started, so a boid stopped in an earlier substep stays stopped)
+        if !sim.walls.is_empty() {
+            let timer = phase_start(sim.params.show_debug);
+            resolve_wall_crossings(sim);
+            phase_end(timer, &mut sim.physics_timings.forces);
+        }
+    }
+    
+    // Boids that flew down into the ground band may land, and rested boids take off
+    if sim.params.enable_perching {
+        update_perching(sim);
+    }
+    
+    // Mark the positions the boids ended the step at
+    if sim.pheromones.is_some() {
+        let timer = phase_start(sim.params.show_debug);
+        update_pheromones(sim);
+        phase_end(timer, &mut sim.physics_timings.forces);
+    }
+    
+    // Boids that run out of energy are only marked here; the simulation removes them after the step
+    if sim.params.enable_energy {
+        let timer = phase_start(sim.params.show_debug);
+        update_energy(sim);
+        phase_end(timer, &mut sim.physics_timings.forces);
+    }
+}
+
+// Update boids using spatial grid for optimization
+fn update_boids_with_spatial_grid(sim: &mut Simulation, substep: Substep) {
+    let timing = sim.params.show_debug;
+    let world_size = sim.params.world_size;
+    let compute_metrics = sim.params.metrics_enabled();
+    
+    // The neighbor lists gathered in one of the last neighbor_list_interval steps may be
+    // used again, which then don't need the grid rebuilt either
+    let query_radius = sim.max_query_radius();
+    let interval = sim.params.neighbor_list_interval.max(1) as u64;
+    let reuse_lists = sim.neighbor_lists.reusable(sim.step_count, interval, query_radius, sim.flock.len());
+    
+    // The grid is built once per step; later substeps look up the neighbors in it
+    if substep.first && !reuse_lists {
+        // Ensure the spatial grid has appropriate cell size (regions may widen the radii)
+        let cell_size = sim.base_cell_size();
+        
+        // Recreate grid if perception radii have changed significantly (cells grown past the
+        // minimum by adaptive sizing are kept)
+        let adapted = sim.params.adaptive_cell_sizing && sim.spatial_grid.min_cell_size > cell_size;
+        if !adapted && (cell_size - sim.spatial_grid.min_cell_size).abs() > 5.0 {
+            sim.spatial_grid = SpatialGrid::new(cell_size, sim.params.world_size);
+        }
+        sim.spatial_grid.wrap_x = !sim.params.wind_tunnel_mode;
+        
+        // Clear the spatial grid
+        let timer = phase_start(timing);
+        sim.spatial_grid.clear();
+        
+        // Insert all boids into the spatial grid
+        for (i, &position) in sim.flock.positions.iter().enumerate() {
+            sim.spatial_grid.insert(i, position, world_size);
+        }
+        
+        // Cells narrower than the radii (a small cell size factor) are searched in more rings
+        sim.spatial_grid.set_query_radius(query_radius);
+        phase_end(timer, &mut sim.physics_timings.grid_rebuild);
+    }
+    
+    // Boids drift toward the mass far beyond their perception radii
+    if sim.params.far_cohesion_weight > 0.0 {
+        let timer = phase_start(timing);
+        apply_far_cohesion(sim);
+        phase_end(timer, &mut sim.physics_timings.forces);
+    }
+    
+    // Pre-calculate per-species (and per-region) weights and squared radii to avoid work in the inner loop
+    let constants_table = ConstantsTable::new(sim);
+    
+    // Boids converted by the infected boids this step (contacts are checked once per step)
+    let infected_species = sim.params.infected_species_id().filter(|_| substep.first);
+    let contact_radius_sq = sim.params.contact_radius * sim.params.contact_radius;
+    let mut contacts = Vec::new();
+    
+    // The force pass reads the flock's arrays directly and only writes accelerations
+    // (and the neighbor counts used by energy mode)
+    let Flock { positions, velocities, accelerations, properties, .. } = &mut sim.flock;
+    let (positions, velocities, properties): (&[Point2], &[Vec2], &[BoidProperties]) = (positions, velocities, properties);
+    let neighbor_counts = &mut sim.neighbor_counts;
+    neighbor_counts.clear();
+    neighbor_counts.resize(positions.len(), 0);
+    
+    // Every boid's neighbors are gathered into lists first (into buffers kept from the last
+    // step, so this doesn't allocate once they are large enough), which the force pass,
+    // the metrics and the infection then read
+    let timer = phase_start(timing);
+    let neighbor_data = &mut sim.neighbor_lists;
+    if substep.first && !reuse_lists {
+        neighbor_data.clear();
+        for &position in positions {
+            neighbor_data.push(sim.spatial_grid.get_nearby_with_distances(position, positions, world_size));
+        }
+        neighbor_data.mark_gathered(sim.step_count, query_radius);
     } else {
-        // Sequential processing for when parallel is disabled
-        for boid in &mut model.boids {
-            // Calculate forces
-            let separation = boid.separation_original(&boids_clone, model.params.separation_radius, model.params.enable_squared_distance);
-            let alignment = boid.alignment_original(&boids_clone, model.params.alignment_radius, model.params.enable_squared_distance);
-            let cohesion = boid.cohesion_original(&boids_clone, model.params.cohesion_radius, model.params.enable_squared_distance);
-            
-            // Combine forces with weights (avoid creating intermediate vectors)
-            let mut combined_force = Vec2::ZERO;
-            combined_force.x = separation.x * separation_weight + alignment.x * alignment_weight + cohesion.x * cohesion_weight;
-            combined_force.y = separation.y * separation_weight + alignment.y * alignment_weight + cohesion.y * cohesion_weight;
-            
-            // Apply the calculated acceleration
-            boid.apply_force(combined_force);
-            
-            // Update position
-            boid.update();
-            
-            // Wrap around edges
-            boid.wrap_edges(model.params.world_size);
-        }
-    }
-} 
\ No newline at end of file
+        // Later substeps and reusing steps keep the neighbors and only measure them again
+        // (boids that came within range since the lists were gathered are missed until then)
+        neighbor_data.refresh_distances(positions, world_size, sim.spatial_grid.wrap_x);
+    }
+    let neighbor_data = &*neighbor_data;
+    phase_end(timer, &mut sim.physics_timings.neighbor_gather);
+    sim.grid_neighbor_counts.clear();
+    sim.grid_neighbor_counts.extend((0..neighbor_data.len()).map(|i| neighbor_data.get(i).len()));
+    
+    // Measure the flock at the start of the step, from the neighbor distances gathered above
+    if substep.first {
+        sim.metrics = compute_metrics.then(|| {
+            let nearest = (0..neighbor_data.len()).map(|i| nearest_distance_squared(neighbor_data.get(i)));
+            flock_metrics(positions, velocities, nearest, world_size)
+        });
+    }
+    
+    let timer = phase_start(timing);
+    let pass = ForcePass {
+        flock: FlockState { positions, velocities, properties },
+        constants_table: &constants_table,
+        boid_regions: &sim.boid_regions,
+        wrap: WorldWrap { world_size, wrap_x: sim.spatial_grid.wrap_x },
+        selected_index: sim.tracked_boid,
+    };
+    let chunk_size = sim.params.enable_parallel.then(|| parallel_chunk_size(positions.len()));
+    if chunk_size.is_some() {
+        // Record the chunk size for the debug display
+        sim.last_chunk_size = chunk_size;
+    }
+    if let Some(debug) = pass.run(accelerations, neighbor_counts, move |i| neighbor_data.get(i).iter().copied(), chunk_size) {
+        sim.tracked_boid_forces = Some(debug);
+    }
+    
+    // Reuse the neighbor lists gathered above for the infected boids
+    if let Some(species) = infected_species {
+        for &i in &sim.infected {
+            infection_contacts(neighbor_data.get(i), properties, species, contact_radius_sq, &mut contacts);
+        }
+    }
+    
+    apply_angular_noise(sim, substep.fraction);
+    integrate_flock(&mut sim.flock, world_size, sim.params.wind_tunnel_mode, sim.params.enable_parallel, substep.fraction);
+    phase_end(timer, &mut sim.physics_timings.forces);
+    
+    sim.infect(&contacts);
+}
+
+// Update boids without spatial grid (original O(n²) approach)
+fn update_boids_without_spatial_grid(sim: &mut Simulation, substep: Substep) {
+    let timer = phase_start(sim.params.show_debug);
+    
+    // Pre-calculate per-species (and per-region) weights and radii
+    let constants_table = ConstantsTable::new(sim);
+    
+    // Boids converted by the infected boids this step (contacts are checked once per step)
+    let infected_species = sim.params.infected_species_id().filter(|_| substep.first);
+    let contact_radius_sq = sim.params.contact_radius * sim.params.contact_radius;
+    let mut contacts = Vec::new();
+    
+    // Every boid reads the same arrays, so no copy of the flock is needed
+    let Flock { positions, velocities, accelerations, properties, .. } = &mut sim.flock;
+    let (positions, velocities, properties): (&[Point2], &[Vec2], &[BoidProperties]) = (positions, velocities, properties);
+    let neighbor_counts = &mut sim.neighbor_counts;
+    neighbor_counts.clear();
+    neighbor_counts.resize(positions.len(), 0);
+    sim.grid_neighbor_counts.clear();
+    
+    // Measure the flock at the start of the step, checking every pair like the rules below
+    let world_size = sim.params.world_size;
+    if substep.first {
+        sim.metrics = sim.params.metrics_enabled().then(|| {
+            let nearest = positions.iter().enumerate().map(|(i, &position)| {
+                positions.iter().enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, &other)| SpatialGrid::wrapped_distance_squared(position, other, world_size))
+                    .fold(f32::INFINITY, f32::min)
+            });
+            flock_metrics(positions, velocities, nearest, world_size)
+        });
+    }
+    
+    // Neighbors are found across the world edges, like in the grid lookups
+    let wrap = WorldWrap { world_size, wrap_x: !sim.params.wind_tunnel_mode };
+    
+    let pass = ForcePass {
+        flock: FlockState { positions, velocities, properties },
+        constants_table: &constants_table,
+        boid_regions: &sim.boid_regions,
+        wrap,
+        selected_index: sim.tracked_boid,
+    };
+    let chunk_size = sim.params.enable_parallel.then(|| parallel_chunk_size(positions.len()));
+    if chunk_size.is_some() {
+        // Record the chunk size for the debug display
+        sim.last_chunk_size = chunk_size;
+    }
+    if let Some(debug) = pass.run(accelerations, neighbor_counts, move |i| every_other_boid(positions, i, wrap), chunk_size) {
+        sim.tracked_boid_forces = Some(debug);
+    }
+    
+    // Check every boid against the infected ones, across the world edges like the grid's
+    // neighbor distances
+    if let Some(species) = infected_species {
+        for &i in &sim.infected {
+            let infected_position = positions[i];
+            for (j, &position) in positions.iter().enumerate() {
+                let distance_squared = SpatialGrid::wrapped_distance_squared(position, infected_position, world_size);
+                if distance_squared < contact_radius_sq && properties[j].species != species {
+                    contacts.push(j);
+                }
+            }
+        }
+    }
+    
+    apply_angular_noise(sim, substep.fraction);
+    integrate_flock(&mut sim.flock, sim.params.world_size, sim.params.wind_tunnel_mode, sim.params.enable_parallel, substep.fraction);
+    phase_end(timer, &mut sim.physics_timings.forces);
+    
+    sim.infect(&contacts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boid::Boid;
+    use crate::region::ParamRegion;
+
+    const WORLD_SIZE: f32 = 1000.0;
+
+    // A simulation containing a single boid with the given position and velocity
+    fn single_boid_simulation(position: Point2, velocity: Vec2) -> Simulation {
+        let mut params = SimulationParams::default();
+        params.num_boids = 1;
+        params.world_size = WORLD_SIZE;
+        let mut sim = Simulation::new(params);
+
+        sim.flock.positions[0] = position;
+        sim.flock.velocities[0] = velocity;
+        sim.flock.store_previous_state();
+
+        sim
+    }
+
+    // Every rule on at unit weight, with the inverse separation falloff and no arrival
+    fn rule_constants(separation_radius: f32, alignment_radius: f32, cohesion_radius: f32) -> SpeciesConstants {
+        SpeciesConstants {
+            separation_radius,
+            sep_radius_sq: separation_radius * separation_radius,
+            align_radius_sq: alignment_radius * alignment_radius,
+            cohesion_radius_sq: cohesion_radius * cohesion_radius,
+            separation_weight: 1.0,
+            alignment_weight: 1.0,
+            cohesion_weight: 1.0,
+            cohesion_slowing_radius: None,
+            separation_falloff: SeparationFalloff::Inverse,
+            separation_falloff_power: 2.0,
+            separation_enabled: true,
+            alignment_enabled: true,
+            cohesion_enabled: true,
+            fast_math: false,
+            ignore_perched: false,
+        }
+    }
+
+    // The rule forces on the first boid of a hand-placed flock, with every other boid as a
+    // candidate neighbor. The boids have a max speed of 4.
+    fn forces_on_first(boids: &[(Point2, Vec2)], max_force: f32, constants: &SpeciesConstants, wrap: WorldWrap) -> RuleForces {
+        let mut flock = Flock::with_capacity(boids.len());
+        for &(position, velocity) in boids {
+            let mut boid = Boid::new(position.x, position.y);
+            boid.velocity = velocity;
+            boid.max_force = max_force;
+            flock.push(boid);
+        }
+
+        let state = FlockState { positions: &flock.positions, velocities: &flock.velocities, properties: &flock.properties };
+        compute_flocking_force(0, every_other_boid(state.positions, 0, wrap), state, constants, wrap)
+    }
+
+    fn assert_force(force: Vec2, pinned: (f32, f32)) {
+        assert!((force - vec2(pinned.0, pinned.1)).length() < 1e-5, "force {:?}, pinned {:?}", force, pinned);
+    }
+
+    fn counts(forces: &RuleForces) -> (usize, usize, usize) {
+        (forces.separation_count, forces.alignment_count, forces.cohesion_count)
+    }
+
+    #[test]
+    fn flocking_forces_match_the_pinned_values() {
+        let wrap = WorldWrap { world_size: WORLD_SIZE, wrap_x: true };
+
+        // Three neighbors inside every radius
+        let boids = [
+            (pt2(0.0, 0.0), vec2(1.0, 0.0)),
+            (pt2(10.0, 0.0), vec2(0.0, 2.0)),
+            (pt2(-20.0, 5.0), vec2(2.0, 0.0)),
+            (pt2(0.0, -30.0), vec2(1.0, 1.0)),
+        ];
+        let forces = forces_on_first(&boids, 1.0, &rule_constants(50.0, 50.0, 50.0), wrap);
+        assert_eq!(counts(&forces), (3, 3, 3));
+        assert_force(forces.separation, (-0.952_201_3, 0.305_471_4));
+        assert_force(forces.alignment, (0.542_888_8, 0.839_804_6));
+        assert_force(forces.cohesion, (-0.556_190_4, -0.831_054_9));
+        assert_force(forces.combined(), (-0.965_502_8, 0.314_221));
+
+        // Each rule only counts the neighbors inside its own radius, and is weighted on its own
+        let mut constants = rule_constants(20.0, 50.0, 100.0);
+        constants.separation_weight = 1.5;
+        constants.cohesion_weight = 0.5;
+        let boids = [
+            (pt2(0.0, 0.0), vec2(0.0, 3.0)),
+            (pt2(5.0, 5.0), vec2(3.0, 0.0)),
+            (pt2(30.0, 0.0), vec2(0.0, -3.0)),
+            (pt2(0.0, -80.0), vec2(-3.0, 0.0)),
+        ];
+        let forces = forces_on_first(&boids, 1.0, &constants, wrap);
+        assert_eq!(counts(&forces), (1, 2, 3));
+        assert_force(forces.separation, (-0.654_883_2, -1.349_491_7));
+        assert_force(forces.alignment, (0.436_588_8, -0.899_661_2));
+        assert_force(forces.cohesion, (0.123_7, -0.484_456_7));
+        assert_force(forces.combined(), (-0.094_594_5, -2.733_609_7));
+
+        // Neighbors across the right edge of the world are measured the short way
+        let boids = [
+            (pt2(490.0, 0.0), vec2(2.0, 0.0)),
+            (pt2(-495.0, 10.0), vec2(0.0, 2.0)),
+            (pt2(470.0, -5.0), vec2(2.0, 2.0)),
+        ];
+        let forces = forces_on_first(&boids, 1.0, &rule_constants(30.0, 40.0, 50.0), wrap);
+        assert_eq!(counts(&forces), (2, 2, 2));
+        assert_force(forces.separation, (-0.412_596_2, -0.910_914));
+        assert_force(forces.alignment, (-0.058_914_5, 0.998_263));
+        assert_force(forces.cohesion, (-0.862_856_2, 0.505_449_5));
+        assert_force(forces.combined(), (-1.334_366_9, 0.592_798_5));
+    }
+
+    #[test]
+    fn every_physics_path_computes_the_same_forces() {
+        let run = |enable_spatial_grid: bool, enable_parallel: bool| {
+            let mut params = SimulationParams::default();
+            params.num_boids = 200;
+            params.world_size = 600.0;
+            params.enable_spatial_grid = enable_spatial_grid;
+            params.enable_parallel = enable_parallel;
+            let mut sim = Simulation::with_seed(params, 3);
+            sim.tracked_boid = Some(0);
+            update_boids(&mut sim);
+            (sim.flock.velocities.clone(), sim.tracked_boid_forces.unwrap())
+        };
+
+        // The grid visits the neighbors in a different order, so the sums differ in the last bits
+        let (brute_force, brute_force_debug) = run(false, false);
+        for (enable_spatial_grid, enable_parallel) in [(false, true), (true, false), (true, true)] {
+            let (velocities, debug) = run(enable_spatial_grid, enable_parallel);
+            for (index, (velocity, expected)) in velocities.iter().zip(&brute_force).enumerate() {
+                assert!((*velocity - *expected).length() < 1e-4,
+                    "grid {}, parallel {}: boid {} moved at {:?}, not {:?}", enable_spatial_grid, enable_parallel, index, velocity, expected);
+            }
+            assert_eq!(
+                (debug.separation_count, debug.alignment_count, debug.cohesion_count),
+                (brute_force_debug.separation_count, brute_force_debug.alignment_count, brute_force_debug.cohesion_count)
+            );
+            assert!((debug.combined_force - brute_force_debug.combined_force).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn separation_points_away_from_the_neighbor() {
+        let boids = [(pt2(0.0, 0.0), Vec2::ZERO), (pt2(10.0, 0.0), Vec2::ZERO)];
+        let force = forces_on_first(&boids, 0.1, &rule_constants(50.0, 50.0, 50.0), WorldWrap { world_size: WORLD_SIZE, wrap_x: true }).separation;
+
+        assert!(force.x < 0.0, "force {:?} should point away from the neighbor", force);
+        assert!(force.y.abs() < 1e-6);
+        assert!((force.length() - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn alignment_steers_toward_the_neighbors_heading() {
+        let velocity = vec2(0.0, 2.0);
+        let heading = vec2(1.0, 0.0);
+        let boids = [(pt2(0.0, 0.0), velocity), (pt2(10.0, 0.0), heading * 2.0), (pt2(-10.0, 5.0), heading * 2.0)];
+        let force = forces_on_first(&boids, 0.1, &rule_constants(50.0, 50.0, 50.0), WorldWrap { world_size: WORLD_SIZE, wrap_x: true }).alignment;
+
+        // Applying the force turns the velocity toward the shared heading
+        let turned = velocity + force;
+        assert!(turned.normalize().dot(heading) > velocity.normalize().dot(heading), "velocity should turn toward {:?}", heading);
+        assert!(force.length() <= 0.1 + 1e-6);
+    }
+
+    #[test]
+    fn cohesion_steers_toward_the_centroid() {
+        let boids = [
+            (pt2(0.0, 0.0), Vec2::ZERO),
+            (pt2(10.0, 10.0), Vec2::ZERO),
+            (pt2(10.0, -10.0), Vec2::ZERO),
+            (pt2(20.0, 0.0), Vec2::ZERO),
+        ];
+        let force = forces_on_first(&boids, 0.1, &rule_constants(50.0, 50.0, 50.0), WorldWrap { world_size: WORLD_SIZE, wrap_x: true }).cohesion;
+
+        // The centroid is on the positive x axis
+        assert!(force.x > 0.0, "force {:?} should point toward the centroid", force);
+        assert!(force.y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn fast_separation_matches_the_exact_one_with_every_falloff() {
+        let boids = [(pt2(0.0, 0.0), vec2(0.0, 1.0)), (pt2(10.0, 3.0), Vec2::ZERO), (pt2(-4.0, 20.0), Vec2::ZERO)];
+        let wrap = WorldWrap { world_size: WORLD_SIZE, wrap_x: true };
+        for falloff in SeparationFalloff::ALL {
+            let mut constants = rule_constants(50.0, 50.0, 50.0);
+            constants.separation_falloff = falloff;
+            constants.separation_falloff_power = 1.5;
+            let exact = forces_on_first(&boids, 0.1, &constants, wrap).separation;
+            constants.fast_math = true;
+            let fast = forces_on_first(&boids, 0.1, &constants, wrap).separation;
+            assert!((fast - exact).length() <= exact.length() * 1e-4, "{:?}", falloff);
+        }
+    }
+
+    #[test]
+    fn rules_measure_across_the_world_edges() {
+        let wrap = WorldWrap { world_size: 5000.0, wrap_x: true };
+        let boids = [
+            (pt2(2490.0, 0.0), Vec2::ZERO),
+            (pt2(2495.0, 0.0), vec2(0.0, 1.0)),
+            (pt2(-2495.0, 0.0), vec2(0.0, 1.0)),
+        ];
+        let constants = rule_constants(50.0, 50.0, 50.0);
+
+        // The neighbors are 5 and 15 units ahead, so the boid steers across the seam
+        // rather than back toward the origin
+        let forces = forces_on_first(&boids, 0.1, &constants, wrap);
+        assert!(forces.cohesion.x > 0.0, "cohesion {:?} should point across the seam", forces.cohesion);
+        assert!(forces.cohesion.y.abs() < 1e-6);
+        assert!(forces.separation.x < 0.0, "separation {:?} should point away from both neighbors", forces.separation);
+        assert!(forces.alignment.y > 0.0);
+        assert_eq!(counts(&forces), (2, 2, 2));
+
+        // With the x axis open, the neighbor across the edge is out of reach
+        let open = WorldWrap { wrap_x: false, ..wrap };
+        assert_eq!(counts(&forces_on_first(&boids, 0.1, &constants, open)), (1, 1, 1));
+    }
+
+    #[test]
+    fn rules_ignore_boids_outside_the_perception_radius() {
+        let boids = [(pt2(0.0, 0.0), vec2(1.0, 0.0)), (pt2(100.0, 0.0), vec2(0.0, 1.0))];
+        let forces = forces_on_first(&boids, 0.1, &rule_constants(50.0, 50.0, 50.0), WorldWrap { world_size: WORLD_SIZE, wrap_x: true });
+
+        assert_eq!(forces.combined(), Vec2::ZERO);
+        assert_eq!(counts(&forces), (0, 0, 0));
+    }
+
+    #[test]
+    fn fast_math_keeps_the_flock_statistics() {
+        // A dense flock that settles into flying together
+        let run = |fast_math: bool| {
+            let mut params = SimulationParams::default();
+            params.num_boids = 150;
+            params.world_size = 600.0;
+            params.fast_math = fast_math;
+            let mut sim = Simulation::with_seed(params, 7);
+            for _ in 0..1000 {
+                sim.step_once();
+            }
+
+            let velocities = &sim.flock.velocities;
+            let polarization = order_parameter(velocities.iter().copied()).unwrap();
+            let mean_speed = velocities.iter().map(|velocity| velocity.length()).sum::<f32>() / velocities.len() as f32;
+            (polarization, mean_speed)
+        };
+
+        let (exact_polarization, exact_speed) = run(false);
+        let (fast_polarization, fast_speed) = run(true);
+
+        assert!((fast_polarization - exact_polarization).abs() <= exact_polarization * 0.01,
+            "polarization {} vs {}", fast_polarization, exact_polarization);
+        assert!((fast_speed - exact_speed).abs() <= exact_speed * 0.01,
+            "mean speed {} vs {}", fast_speed, exact_speed);
+    }
+
+    #[test]
+    fn grid_update_wraps_boid_past_the_edge_once() {
+        for enable_parallel in [false, true] {
+            let mut sim = single_boid_simulation(pt2(WORLD_SIZE / 2.0 + 1.0, 0.0), vec2(2.0, 0.0));
+            sim.params.enable_spatial_grid = true;
+            sim.params.enable_parallel = enable_parallel;
+
+            update_boids(&mut sim);
+
+            // The previous position jumps with it, past the opposite edge
+            assert_eq!(sim.flock.positions[0], pt2(-WORLD_SIZE / 2.0, 0.0));
+            assert_eq!(sim.flock.prev_positions[0].x, -WORLD_SIZE / 2.0 - 2.0);
+        }
+    }
+
+    #[test]
+    fn previous_state_is_stored_once_per_step() {
+        for enable_spatial_grid in [false, true] {
+            let mut sim = single_boid_simulation(pt2(0.0, 0.0), vec2(2.0, 0.0));
+            sim.params.enable_spatial_grid = enable_spatial_grid;
+
+            update_boids(&mut sim);
+            let after_first_step = sim.flock.positions[0];
+            update_boids(&mut sim);
+
+            // Interpolation runs between the last two physics states
+            assert_eq!(sim.flock.prev_positions[0], after_first_step);
+            assert_eq!(sim.flock.positions[0], pt2(4.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn boids_stop_at_walls_instead_of_crossing_them() {
+        for enable_parallel in [false, true] {
+            let mut sim = single_boid_simulation(pt2(0.0, 0.0), vec2(20.0, 5.0));
+            sim.params.enable_parallel = enable_parallel;
+            sim.params.wall_avoid_weight = 0.0; // Only the hard correction
+            sim.set_walls(vec![Wall::new(pt2(10.0, -50.0), pt2(10.0, 50.0))]);
+
+            update_boids(&mut sim);
+
+            let position = sim.flock.positions[0];
+            assert!(position.x < 10.0 && position.x > 9.0, "stopped at {:?}", position);
+            // The velocity into the wall is removed, the rest slides along it
+            assert_eq!(sim.flock.velocities[0], vec2(0.0, 5.0));
+        }
+    }
+
+    #[test]
+    fn substeps_let_fast_boids_turn_before_reaching_a_wall() {
+        // A boid covering most of the avoidance radius in one step, heading straight at a wall
+        let run = |substeps: u32, enable_parallel: bool| {
+            let mut sim = single_boid_simulation(pt2(0.0, 0.0), vec2(40.0, 0.0));
+            sim.params.enable_parallel = enable_parallel;
+            sim.params.physics_substeps = substeps;
+            sim.params.wall_avoid_radius = 38.0;
+            sim.params.wall_avoid_weight = 2.0;
+            sim.flock.properties[0].max_speed = 40.0;
+            sim.flock.properties[0].max_force = 15.0;
+            sim.set_walls(vec![Wall::new(pt2(45.0, -200.0), pt2(45.0, 200.0))]);
+
+            let mut closest = f32::INFINITY;
+            for _ in 0..5 {
+                update_boids(&mut sim);
+                closest = closest.min(45.0 - sim.flock.positions[0].x);
+            }
+            (closest, sim.flock.velocities[0])
+        };
+
+        for enable_parallel in [false, true] {
+            // One step jumps from outside the radius to just short of the wall, and the
+            // next would carry it through: only the hard stop keeps it out
+            let (closest, _) = run(1, enable_parallel);
+            assert!(closest < 1.0, "closest {}", closest);
+
+            // Substeps feel the wall on the way in and turn the boid around well before it
+            for substeps in [4, 8] {
+                let (closest, velocity) = run(substeps, enable_parallel);
+                assert!(closest > 5.0, "{} substeps: closest {}", substeps, closest);
+                assert!(velocity.x < 0.0, "{} substeps: velocity {:?}", substeps, velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn boids_inside_a_region_use_its_overrides() {
+        for enable_spatial_grid in [false, true] {
+            let mut params = SimulationParams::default();
+            params.num_boids = 2;
+            params.world_size = WORLD_SIZE;
+            params.enable_spatial_grid = enable_spatial_grid;
+            let mut sim = Simulation::new(params);
+
+            // Boid 0 is just across the right edge, inside a region that straddles the
+            // edge and switches every rule off; boid 1 is its neighbor outside the region
+            sim.flock.positions[0] = pt2(-495.0, 0.0);
+            sim.flock.positions[1] = pt2(-475.0, 0.0);
+            sim.flock.velocities.fill(vec2(1.0, 0.0));
+            let mut region = ParamRegion::new(Rect::from_corners(pt2(480.0, -20.0), pt2(520.0, 20.0)), 0);
+            region.overrides.separation_weight = Some(0.0);
+            region.overrides.alignment_weight = Some(0.0);
+            region.overrides.cohesion_weight = Some(0.0);
+            sim.set_regions(vec![region]);
+
+            update_boids(&mut sim);
+
+            assert_eq!(sim.boid_regions, vec![Some(0), None]);
+            assert_eq!(sim.flock.velocities[0], vec2(1.0, 0.0));
+            assert_ne!(sim.flock.velocities[1], vec2(1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn boids_steer_toward_pheromone_ahead_and_leave_their_own() {
+        for enable_parallel in [false, true] {
+            let mut sim = single_boid_simulation(pt2(0.0, 0.0), vec2(2.0, 0.0));
+            sim.params.enable_parallel = enable_parallel;
+            sim.params.enable_pheromones = true;
+            sim.apply_param_changes();
+
+            // A trail ahead and to the left of the boid
+            sim.pheromones.as_mut().unwrap().deposit(pt2(10.0, 10.0), 5.0);
+
+            update_boids(&mut sim);
+
+            assert!(sim.flock.velocities[0].y > 0.0);
+            let field = sim.pheromones.as_ref().unwrap();
+            let total: f32 = field.values().iter().sum();
+            let expected = 5.0 * (1.0 - sim.params.pheromone_decay) + sim.params.pheromone_deposit;
+            assert!((total - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn disabled_rules_are_skipped() {
+        for enable_spatial_grid in [false, true] {
+            let mut params = SimulationParams::default();
+            params.num_boids = 2;
+            params.world_size = WORLD_SIZE;
+            params.enable_spatial_grid = enable_spatial_grid;
+            params.enable_separation = false;
+            params.enable_alignment = false;
+            params.enable_cohesion = false;
+            let mut sim = Simulation::new(params);
+            sim.tracked_boid = Some(0);
+
+            // Two boids close enough for every rule, heading different ways
+            sim.flock.positions[0] = pt2(0.0, 0.0);
+            sim.flock.positions[1] = pt2(10.0, 0.0);
+            sim.flock.velocities[0] = vec2(2.0, 0.0);
+            sim.flock.velocities[1] = vec2(0.0, 2.0);
+
+            // With every rule off the boids coast
+            update_boids(&mut sim);
+            assert_eq!(sim.flock.velocities[0], vec2(2.0, 0.0));
+            assert_eq!(sim.flock.velocities[1], vec2(0.0, 2.0));
+
+            // Only alignment contributes once it is the only rule on
+            sim.params.enable_alignment = true;
+            update_boids(&mut sim);
+            let forces = sim.tracked_boid_forces.unwrap();
+            assert_eq!((forces.separation_count, forces.alignment_count, forces.cohesion_count), (0, 1, 0));
+            assert_eq!(forces.separation_force, Vec2::ZERO);
+            assert_ne!(forces.alignment_force, Vec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn order_parameter_measures_alignment() {
+        let aligned = [vec2(2.0, 0.0), vec2(5.0, 0.0), vec2(0.5, 0.0)];
+        let opposed = [vec2(2.0, 0.0), vec2(-5.0, 0.0), Vec2::ZERO];
+
+        assert!((order_parameter(aligned.into_iter()).unwrap() - 1.0).abs() < 1e-6);
+        assert!(order_parameter(opposed.into_iter()).unwrap().abs() < 1e-6);
+        assert_eq!(order_parameter([Vec2::ZERO].into_iter()), None);
+    }
+
+    #[test]
+    fn metrics_tell_milling_from_marching() {
+        // Four boids on a circle around the origin, either circling it or all heading +x
+        let positions = [pt2(10.0, 0.0), pt2(0.0, 10.0), pt2(-10.0, 0.0), pt2(0.0, -10.0)];
+        let circling = [vec2(0.0, 1.0), vec2(-1.0, 0.0), vec2(0.0, -1.0), vec2(1.0, 0.0)];
+        let marching = [vec2(1.0, 0.0); 4];
+        let nearest = || [200.0, 200.0, 200.0, f32::INFINITY].into_iter();
+
+        let milling = flock_metrics(&positions, &circling, nearest(), WORLD_SIZE);
+        assert!(milling.polarization.abs() < 1e-6);
+        assert!((milling.angular_momentum - 1.0).abs() < 1e-4);
+        // Boids without a neighbor in reach are left out of the mean
+        assert!((milling.mean_nearest_neighbor_distance.unwrap() - 200.0_f32.sqrt()).abs() < 1e-4);
+
+        let school = flock_metrics(&positions, &marching, nearest(), WORLD_SIZE);
+        assert!((school.polarization - 1.0).abs() < 1e-6);
+        assert!(school.angular_momentum.abs() < 1e-4);
+    }
+
+    #[test]
+    fn nearest_neighbor_metric_uses_wrapped_distances() {
+        for enable_spatial_grid in [false, true] {
+            let mut params = SimulationParams::default();
+            params.num_boids = 2;
+            params.world_size = WORLD_SIZE;
+            params.enable_spatial_grid = enable_spatial_grid;
+            params.compute_metrics = true;
+            let mut sim = Simulation::new(params);
+
+            // 10 units apart across the edge
+            sim.flock.positions[0] = pt2(-WORLD_SIZE / 2.0 + 4.0, 0.0);
+            sim.flock.positions[1] = pt2(WORLD_SIZE / 2.0 - 6.0, 0.0);
+
+            update_boids(&mut sim);
+
+            let distance = sim.metrics.unwrap().mean_nearest_neighbor_distance.unwrap();
+            assert!((distance - 10.0).abs() < 1e-3, "nearest neighbor at {}", distance);
+        }
+    }
+
+    #[test]
+    fn angular_noise_is_reproducible_with_a_seed() {
+        let run = |noise: f32| {
+            let mut params = SimulationParams::default();
+            params.num_boids = 50;
+            params.angular_noise = noise;
+            let mut sim = Simulation::with_seed(params, 7);
+            for _ in 0..10 {
+                update_boids(&mut sim);
+            }
+            sim.flock.velocities
+        };
+
+        let noisy = run(90.0);
+
+        assert_eq!(noisy, run(90.0));
+        assert_ne!(noisy, run(0.0));
+    }
+
+    #[test]
+    fn only_flocking_boids_regain_energy() {
+        for enable_spatial_grid in [false, true] {
+            let mut params = SimulationParams::default();
+            params.num_boids = 3;
+            params.world_size = WORLD_SIZE;
+            params.enable_spatial_grid = enable_spatial_grid;
+            params.enable_energy = true;
+            params.energy_neighbor_threshold = 0;
+            let mut sim = Simulation::new(params);
+
+            // Two boids within each other's cohesion radius, one on its own
+            sim.flock.positions[0] = pt2(0.0, 0.0);
+            sim.flock.positions[1] = pt2(10.0, 0.0);
+            sim.flock.positions[2] = pt2(400.0, 400.0);
+            sim.flock.energies.fill(0.5);
+
+            update_boids(&mut sim);
+
+            assert_eq!(sim.neighbor_counts, vec![1, 1, 0]);
+            assert!(sim.flock.energies[0] > 0.5 && sim.flock.energies[1] > 0.5);
+            assert!(sim.flock.energies[2] < 0.5);
+        }
+    }
+}
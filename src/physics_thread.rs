@@ -0,0 +1,675 @@
+/*
+ * Physics Thread Module
+ * 
+ * This module runs the simulation on a dedicated thread so that a physics step
+ * taking longer than a frame never freezes the window or the UI.
+ * 
+ * - The physics thread owns the Simulation; the render thread never touches it
+ * - Boid state is published as snapshots, triple buffered so that neither thread
+ *   holds the lock for longer than a buffer swap (or the render thread's copy)
+ * - The render thread keeps the last two snapshots and interpolates between them
+ *   using their timestamps
+ * - Parameter changes and interaction (selection, spawning, dragging, resets,
+ *   exports) are sent as commands and applied between physics steps. Commands
+ *   wake the thread immediately, so pausing, stepping and resetting stay
+ *   responsive even while paused.
+ */
+
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::boid::Boid;
+use crate::debug::{NeighborHistogram, SelectedBoidDebug, SpeedStats};
+use crate::effects::Effects;
+use crate::export::Exporter;
+use crate::flock::BoidId;
+use crate::osc::OscStatus;
+use crate::stream::StreamStatus;
+use crate::wall::Wall;
+use crate::region::ParamRegion;
+use crate::palette::{self, ColorMode};
+use crate::params::SimulationParams;
+use crate::pheromone::PheromoneField;
+use crate::physics::{FlockMetrics, PhysicsTimings};
+use crate::simulation::Simulation;
+use crate::spatial_grid::SpatialGrid;
+use crate::tags::{TagMetrics, TAG_COLORS, TAG_COUNT};
+
+// How often the physics thread adapts the spatial grid cell size
+const CELL_SIZE_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// The part of a boid's state that the render thread needs
+#[derive(Clone, Copy)]
+pub struct BoidRenderState {
+    pub position: Point2,
+    pub velocity: Vec2,
+    pub color: Rgb<u8>,
+    pub species: u8,
+    pub frozen: bool,
+    pub perched_until: Option<u64>,
+    pub energy: f32,
+    pub id: BoidId,
+    pub tag: Option<u8>,
+}
+
+// Statistics from the physics iteration that produced a snapshot
+#[derive(Clone, Default)]
+pub struct PhysicsStats {
+    pub steps: usize, // Physics steps run since the previous snapshot
+    pub simulation_step: u64, // Physics steps since the last reset (see Simulation::step_count)
+    pub simulation_time: f64, // Simulated seconds since the last reset
+    pub steps_capped: bool, // Whether the last iteration hit max_steps_per_frame
+    pub dropped_time: Duration, // Simulated time discarded by the step cap, in total
+    pub step_duration: Option<Duration>, // Measured time of one physics step
+    pub step_size: Duration, // Budget for one step (1 / fixed_physics_fps)
+    pub timings: PhysicsTimings, // Per-phase timings (only measured with show_debug)
+    pub chunk_size: Option<usize>,
+    pub threads: usize, // Threads the last steps ran on (1 without parallel processing)
+    pub grid_statistics: Option<(usize, usize, f32, usize)>, // See SpatialGrid::get_statistics
+    pub cell_size: f32, // Current (possibly adapted) spatial grid cell size, see SpatialGrid::min_cell_size
+    pub tracked_boid_forces: Option<SelectedBoidDebug>,
+    pub species_counts: Vec<usize>, // Boids per species (only counted with show_debug)
+    pub average_energy: Option<f32>, // Only computed with show_debug in energy mode
+    pub speeds: Option<SpeedStats>, // Only computed with show_debug
+    pub respawning_boids: usize, // Dead boids waiting to be replaced
+    pub target_boids: usize, // num_boids as the physics thread has it (spawning and deleting move it)
+    pub neighbor_histogram: Option<NeighborHistogram>, // Only computed with show_debug and the spatial grid
+    pub metrics: Option<FlockMetrics>, // Only computed while SimulationParams::metrics_enabled
+    pub tag_metrics: [Option<TagMetrics>; TAG_COUNT], // Measured every TAG_METRICS_INTERVAL steps
+    pub stream: Option<StreamStatus>, // Running network stream, if any
+    pub stream_error: Option<String>, // Why streaming couldn't start
+    pub osc: Option<OscStatus>, // Running OSC output, if any
+    pub osc_error: Option<String>, // Why the OSC output couldn't start
+}
+
+// Boid state published by the physics thread
+pub struct Snapshot {
+    pub boids: Vec<BoidRenderState>,
+    pub time: Instant, // When the snapshot was taken
+    pub sequence: u64, // Increases with every published snapshot (0 = nothing published yet)
+    pub layout_version: u64, // Changes when boids jump or change index, so they aren't interpolated
+    pub order_version: u64, // Changes when the boids are reordered, so they are interpolated by id
+    pub stats: PhysicsStats,
+    pub pheromones: Option<PheromoneField>, // Only copied while the heatmap is shown
+}
+
+impl Snapshot {
+    fn empty() -> Self {
+        Self {
+            boids: Vec::new(),
+            time: Instant::now(),
+            sequence: 0,
+            layout_version: 0,
+            order_version: 0,
+            stats: PhysicsStats::default(),
+            pheromones: None,
+        }
+    }
+}
+
+// Requests from the render thread, applied between physics steps
+pub enum PhysicsCommand {
+    // New parameter values. The boid count is only taken over when `resize` is set,
+    // since the physics thread changes it itself when boids are spawned or deleted.
+    SetParams { params: Box<SimulationParams>, resize: bool },
+    SetEmitter(Option<Point2>),
+    TrackBoid(Option<usize>),
+    Step, // Run a single physics step (e.g. while paused)
+    Reset,
+    RedistributeSpecies,
+    Spawn { center: Point2, count: usize },
+    Delete(usize),
+    ToggleFrozen(usize),
+    Infect(usize), // Seed the infection at a boid (infection mode)
+    CureInfection, // Return every boid to the base species
+    MoveBoid { index: usize, position: Point2 },
+    TagBoids { area: Rect, tag: u8 }, // Tag every boid in an area (wrapping around the world edges)
+    ClearTags,
+    SetWalls(Vec<Wall>),
+    SetRegions(Vec<ParamRegion>),
+    Export(Exporter),
+    StopExport,
+    Shutdown,
+}
+
+// The two most recent snapshots, shared between the threads
+struct SharedSnapshots {
+    latest: Snapshot,
+    previous: Snapshot,
+}
+
+// Handle to the physics thread, owned by the render thread
+pub struct PhysicsThread {
+    commands: Sender<PhysicsCommand>,
+    snapshots: Arc<Mutex<SharedSnapshots>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PhysicsThread {
+    // Move the simulation onto a new physics thread
+    pub fn spawn(sim: Simulation) -> Self {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let snapshots = Arc::new(Mutex::new(SharedSnapshots {
+            latest: Snapshot::empty(),
+            previous: Snapshot::empty(),
+        }));
+        
+        let worker = Worker {
+            sim,
+            snapshots: Arc::clone(&snapshots),
+            back: Snapshot::empty(),
+            stats: PhysicsStats::default(),
+            sequence: 0,
+            layout_version: 0,
+            order_version: 0,
+            changed: true,
+            steps: 0,
+            last_cell_size_update: Instant::now(),
+        };
+        
+        let handle = thread::Builder::new()
+            .name("physics".to_string())
+            .spawn(move || worker.run(command_receiver))
+            .expect("failed to start the physics thread");
+        
+        Self {
+            commands: command_sender,
+            snapshots,
+            handle: Some(handle),
+        }
+    }
+    
+    // Queue a command (ignored once the thread has shut down)
+    pub fn send(&self, command: PhysicsCommand) {
+        let _ = self.commands.send(command);
+    }
+    
+    // Stop the physics thread, waiting for it to finish the step it is running
+    pub fn shutdown(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = self.commands.send(PhysicsCommand::Shutdown);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for PhysicsThread {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// State owned by the physics thread
+struct Worker {
+    sim: Simulation,
+    snapshots: Arc<Mutex<SharedSnapshots>>,
+    back: Snapshot, // Filled outside the lock, then swapped in as the latest snapshot
+    stats: PhysicsStats, // Statistics of the last physics iteration
+    sequence: u64,
+    layout_version: u64,
+    order_version: u64,
+    changed: bool, // Boid state changed since the last published snapshot
+    steps: usize, // Physics steps run since the last published snapshot
+    last_cell_size_update: Instant,
+}
+
+impl Worker {
+    fn run(mut self, commands: Receiver<PhysicsCommand>) {
+        // Publish the initial state so the window has something to draw
+        self.publish();
+        
+        loop {
+            let paused = self.sim.params.pause_simulation;
+            
+            // Wait for a command, or (while running) until the next physics step is due
+            let first = if paused {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            } else {
+                match commands.recv_timeout(self.time_until_next_step()) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            };
+            
+            // Apply everything that is queued before stepping
+            for command in first.into_iter().chain(commands.try_iter()) {
+                if !self.handle_command(command) {
+                    return;
+                }
+            }
+            
+            let now = Instant::now();
+            
+            // Time spent paused (including waiting for the command that resumed) is not simulated
+            let steps = self.sim.advance(now);
+            
+            if !self.sim.params.pause_simulation {
+                if steps > 0 {
+                    self.record_steps(steps);
+                    self.report_removed_boids();
+                    self.report_reordering();
+                    self.stats.steps_capped = self.sim.steps_capped;
+                    self.stats.step_duration = self.sim.last_step_duration;
+                    self.stats.timings = self.sim.physics_timings;
+                }
+                
+                // Adapt the grid cell size to the boid density
+                if self.sim.params.adaptive_cell_sizing &&
+                   now.duration_since(self.last_cell_size_update) >= CELL_SIZE_UPDATE_INTERVAL {
+                    self.sim.update_adaptive_cell_size();
+                    self.last_cell_size_update = now;
+                }
+            }
+            
+            if self.changed {
+                self.publish();
+            }
+        }
+    }
+    
+    // Time until the accumulator holds a full physics step
+    fn time_until_next_step(&self) -> Duration {
+        let remaining = self.sim.physics_step_size.saturating_sub(self.sim.physics_accumulator);
+        remaining.div_f32(self.sim.params.time_scale.max(0.01))
+    }
+    
+    // Apply a command. Returns false when the thread should shut down.
+    fn handle_command(&mut self, command: PhysicsCommand) -> bool {
+        match command {
+            PhysicsCommand::SetParams { params, resize } => {
+                let num_boids = self.sim.params.num_boids;
+                self.sim.params.copy_settings_from(&params);
+                if !resize {
+                    self.sim.params.num_boids = num_boids;
+                }
+                
+                let (boids_changed, physics_changed, rendering_changed, world_size_changed) =
+                    self.sim.apply_param_changes();
+                if world_size_changed {
+                    // Boids outside the new world were moved
+                    self.layout_changed();
+                } else if boids_changed || physics_changed {
+                    // New boids, or new species colors
+                    self.changed = true;
+                } else if rendering_changed && self.sim.pheromones.is_some() {
+                    // The heatmap may have been switched on, even while paused
+                    self.changed = true;
+                }
+            }
+            PhysicsCommand::SetEmitter(position) => {
+                // Start each emission burst from scratch
+                if self.sim.emitter.is_none() {
+                    self.sim.emission_accumulator = 0.0;
+                }
+                self.sim.emitter = position;
+            }
+            PhysicsCommand::TrackBoid(index) => {
+                if self.sim.tracked_boid != index {
+                    self.sim.tracked_boid = index;
+                    self.stats.tracked_boid_forces = None;
+                }
+            }
+            PhysicsCommand::Step => {
+                self.sim.removed_boids.clear();
+                self.sim.step_once();
+                self.record_steps(1);
+                self.report_removed_boids();
+                self.report_reordering();
+            }
+            PhysicsCommand::Reset => {
+                self.sim.reset();
+                self.layout_changed();
+            }
+            PhysicsCommand::RedistributeSpecies => {
+                self.sim.redistribute_species();
+                self.changed = true;
+            }
+            PhysicsCommand::Spawn { center, count } => {
+                if self.sim.spawn_at(center, count) > 0 {
+                    self.changed = true;
+                }
+            }
+            PhysicsCommand::Delete(index) => {
+                if index < self.sim.flock.len() {
+                    self.sim.delete(index);
+                    
+                    // Another boid now has the deleted boid's index
+                    self.layout_changed();
+                }
+            }
+            PhysicsCommand::ToggleFrozen(index) => {
+                self.sim.toggle_frozen(index);
+                self.changed = true;
+            }
+            PhysicsCommand::Infect(index) => {
+                self.sim.infect(&[index]);
+                self.changed = true;
+            }
+            PhysicsCommand::CureInfection => {
+                self.sim.cure_infection();
+                self.changed = true;
+            }
+            PhysicsCommand::MoveBoid { index, position } => {
+                self.sim.move_boid_to(index, position);
+                self.changed = true;
+            }
+            PhysicsCommand::TagBoids { area, tag } => {
+                self.sim.tag_boids_in(area, tag);
+                self.changed = true;
+            }
+            PhysicsCommand::ClearTags => {
+                self.sim.clear_tags();
+                self.changed = true;
+            }
+            PhysicsCommand::SetWalls(walls) => {
+                self.sim.set_walls(walls);
+            }
+            PhysicsCommand::SetRegions(regions) => {
+                self.sim.set_regions(regions);
+            }
+            PhysicsCommand::Export(exporter) => {
+                self.sim.attach_exporter(exporter);
+            }
+            PhysicsCommand::StopExport => {
+                self.sim.stop_export();
+            }
+            PhysicsCommand::Shutdown => return false,
+        }
+        
+        true
+    }
+    
+    // Account for physics steps that were just run
+    fn record_steps(&mut self, steps: usize) {
+        self.steps += steps;
+        self.changed = true;
+        self.stats.step_size = self.sim.physics_step_size;
+        self.stats.chunk_size = self.sim.last_chunk_size;
+        self.stats.threads = if self.sim.params.enable_parallel { self.sim.thread_count() } else { 1 };
+        if let Some(forces) = self.sim.tracked_boid_forces.take() {
+            self.stats.tracked_boid_forces = Some(forces);
+        }
+    }
+    
+    // Boids that starved during the last steps were replaced by others at their indices
+    fn report_removed_boids(&mut self) {
+        if self.sim.removed_boids.is_empty() {
+            return;
+        }
+        
+        self.sim.removed_boids.clear();
+        self.layout_changed();
+    }
+    
+    // Boids that were sorted by grid cell during the last steps kept their state but not
+    // their indices
+    fn report_reordering(&mut self) {
+        if self.sim.reordered {
+            self.sim.reordered = false;
+            self.order_version += 1;
+        }
+    }
+    
+    // Boids jumped or changed index, so the next snapshot must not be interpolated from the last
+    fn layout_changed(&mut self) {
+        self.layout_version += 1;
+        self.changed = true;
+    }
+    
+    // Copy the boids into the back buffer and swap it in as the latest snapshot
+    fn publish(&mut self) {
+        self.back.boids.clear();
+        let flock = &self.sim.flock;
+        self.back.boids.extend(flock.positions.iter().zip(&flock.velocities).zip(&flock.properties).zip(&flock.energies).zip(&flock.ids).map(
+            |((((&position, &velocity), properties), &energy), &id)| BoidRenderState {
+                position,
+                velocity,
+                color: properties.color,
+                species: properties.species,
+                frozen: properties.frozen,
+                perched_until: properties.perched_until,
+                energy,
+                id,
+                tag: properties.tag,
+            },
+        ));
+        
+        self.sequence += 1;
+        self.back.time = Instant::now();
+        self.back.sequence = self.sequence;
+        self.back.layout_version = self.layout_version;
+        self.back.order_version = self.order_version;
+        self.back.stats = self.stats.clone();
+        self.back.stats.steps = self.steps;
+        self.back.stats.simulation_step = self.sim.step_count;
+        self.back.stats.simulation_time = self.sim.simulation_time;
+        self.back.stats.dropped_time = self.sim.dropped_time;
+        self.back.stats.cell_size = self.sim.spatial_grid.min_cell_size;
+        if self.sim.params.show_debug {
+            self.back.stats.species_counts = self.sim.species_counts();
+        }
+        self.back.stats.metrics = self.sim.metrics;
+        self.back.stats.tag_metrics = self.sim.tag_metrics;
+        self.back.stats.average_energy = if self.sim.params.show_debug && self.sim.params.enable_energy {
+            self.sim.average_energy()
+        } else {
+            None
+        };
+        self.back.stats.speeds = if self.sim.params.show_debug {
+            SpeedStats::from_velocities(&self.sim.flock.velocities, self.sim.tracked_boid)
+        } else {
+            None
+        };
+        self.back.stats.respawning_boids = self.sim.respawn_timers.len();
+        self.back.stats.target_boids = self.sim.params.num_boids;
+        self.back.stats.neighbor_histogram = if self.sim.params.show_debug && self.sim.params.enable_spatial_grid {
+            NeighborHistogram::from_counts(&self.sim.grid_neighbor_counts)
+        } else {
+            None
+        };
+        self.back.stats.stream = self.sim.streamer.as_ref().map(|streamer| streamer.status());
+        self.back.stats.stream_error = self.sim.stream_error.as_ref()
+            .map(|(port, err)| format!("Failed to bind UDP port {}: {}", port, err));
+        self.back.stats.osc = self.sim.osc.as_ref().map(|osc| osc.status());
+        self.back.stats.osc_error = self.sim.osc_error.as_ref()
+            .map(|err| format!("Failed to start the OSC output: {}", err));
+        self.back.stats.grid_statistics = if self.sim.params.enable_spatial_grid {
+            Some(self.sim.spatial_grid.get_statistics())
+        } else {
+            None
+        };
+        self.back.pheromones = self.sim.pheromones.as_ref()
+            .filter(|_| self.sim.params.show_pheromones)
+            .cloned();
+        
+        // latest -> previous, back -> latest, and the old previous becomes the new back buffer
+        {
+            let mut shared = self.snapshots.lock().unwrap();
+            let shared = &mut *shared;
+            std::mem::swap(&mut shared.previous, &mut shared.latest);
+            std::mem::swap(&mut shared.latest, &mut self.back);
+        }
+        
+        self.steps = 0;
+        self.changed = false;
+    }
+}
+
+// The render thread's view of the simulation, rebuilt from the physics snapshots
+pub struct SimulationView {
+    // Boids whose previous state comes from the previous snapshot, so the usual
+    // interpolation methods blend between the last two snapshots
+    pub boids: Vec<Boid>,
+    // Grid of the latest positions, used for frustum culling
+    pub spatial_grid: SpatialGrid,
+    pub interpolation_alpha: f32,
+    // Statistics of the physics iteration that produced the latest snapshot
+    pub stats: PhysicsStats,
+    // Pheromone field of the latest snapshot, while the heatmap is shown
+    pub pheromones: Option<PheromoneField>,
+    indices: HashMap<BoidId, usize>, // Index of each boid id in the latest snapshot
+    layout_version: u64, // Of the latest snapshot; indices is rebuilt when it changes
+    order_version: u64, // Likewise
+    sequence: u64,
+    latest_time: Instant,
+    interval: Duration, // Time between the last two snapshots
+    grid_world_size: f32,
+    color_mode: ColorMode, // Applied to the boids by the last sync
+    tint_tagged_boids: bool, // Likewise
+}
+
+impl SimulationView {
+    pub fn new(params: &SimulationParams) -> Self {
+        let cell_size = params.max_perception_radius() * params.cell_size_factor;
+        
+        Self {
+            boids: Vec::with_capacity(params.num_boids),
+            spatial_grid: SpatialGrid::new(cell_size, params.world_size),
+            interpolation_alpha: 1.0,
+            stats: PhysicsStats::default(),
+            pheromones: None,
+            indices: HashMap::new(),
+            layout_version: 0,
+            order_version: 0,
+            sequence: 0,
+            latest_time: Instant::now(),
+            interval: Duration::ZERO,
+            grid_world_size: params.world_size,
+            color_mode: ColorMode::Species,
+            tint_tagged_boids: params.tint_tagged_boids,
+        }
+    }
+    
+    // Pick up the latest snapshot from the physics thread, starting the fade effects of the
+    // boids it adds or removes. Returns whether there was a new one.
+    pub fn sync(&mut self, physics: &PhysicsThread, params: &SimulationParams, effects: &mut Effects) -> bool {
+        {
+            let shared = physics.snapshots.lock().unwrap();
+            let latest = &shared.latest;
+            let previous = &shared.previous;
+            
+            // A new color mode is applied to the latest snapshot again (while paused there is no other)
+            if latest.sequence == self.sequence && params.color_mode == self.color_mode &&
+               params.tint_tagged_boids == self.tint_tagged_boids {
+                return false;
+            }
+            
+            // Only interpolate while boids keep their indices and don't jump
+            let interpolate = previous.sequence > 0 && previous.layout_version == latest.layout_version;
+            
+            // After a reordering each boid's previous state is found by its id
+            let previous_indices: Option<HashMap<BoidId, usize>> = (interpolate && previous.order_version != latest.order_version)
+                .then(|| previous.boids.iter().enumerate().map(|(index, prev)| (prev.id, index)).collect());
+            
+            // Compared with the boids drawn so far, before they are overwritten
+            let ids = latest.boids.iter().map(|state| state.id);
+            if params.spawn_effects {
+                effects.observe(&self.boids, ids);
+            } else {
+                effects.clear(ids);
+            }
+            
+            self.boids.resize_with(latest.boids.len(), || Boid::new(0.0, 0.0));
+            for (i, (boid, state)) in self.boids.iter_mut().zip(&latest.boids).enumerate() {
+                let prev_index = match &previous_indices {
+                    Some(indices) => indices.get(&state.id).copied(),
+                    None => Some(i),
+                };
+                
+                // Boids that wrapped around the world edge are interpolated the short way
+                // around (see Boid::get_interpolated_position)
+                let prev = prev_index.and_then(|index| previous.boids.get(index))
+                    .filter(|_| interpolate)
+                    .unwrap_or(state);
+                
+                boid.position = state.position;
+                boid.velocity = state.velocity;
+                boid.prev_position = prev.position;
+                boid.prev_velocity = prev.velocity;
+                boid.color = state.color;
+                boid.species = state.species;
+                boid.frozen = state.frozen;
+                boid.perched_until = state.perched_until;
+                boid.energy = state.energy;
+                boid.id = state.id;
+                boid.tag = state.tag;
+            }
+            
+            // Boids only change index (or id) with the layout or order, or when added or removed at the end
+            if latest.layout_version != self.layout_version || latest.order_version != self.order_version ||
+               self.indices.len() != self.boids.len() {
+                self.indices.clear();
+                self.indices.extend(self.boids.iter().enumerate().map(|(index, boid)| (boid.id, index)));
+                self.layout_version = latest.layout_version;
+                self.order_version = latest.order_version;
+            }
+            
+            self.sequence = latest.sequence;
+            self.latest_time = latest.time;
+            self.interval = latest.time.saturating_duration_since(previous.time);
+            self.stats = latest.stats.clone();
+            self.pheromones.clone_from(&latest.pheromones);
+        }
+        
+        // Rebuild the culling grid from the latest positions, with the physics grid's cell size
+        if params.enable_spatial_grid {
+            let cell_size = self.stats.cell_size;
+            if cell_size != self.spatial_grid.min_cell_size || params.world_size != self.grid_world_size {
+                self.spatial_grid = SpatialGrid::new(cell_size, params.world_size);
+                self.grid_world_size = params.world_size;
+            }
+            
+            self.spatial_grid.clear();
+            for (i, boid) in self.boids.iter().enumerate() {
+                self.spatial_grid.insert(i, boid.position, params.world_size);
+            }
+        }
+        
+        // Debug coloring replaces the snapshot's colors until the next sync, and costs
+        // nothing in the species mode
+        if params.color_mode == ColorMode::GridCell && params.enable_spatial_grid {
+            let grid_size = self.spatial_grid.grid_size;
+            for boid in &mut self.boids {
+                let cell_index = self.spatial_grid.pos_to_cell_index(boid.position, params.world_size);
+                boid.color = palette::cell_color(cell_index, grid_size);
+            }
+        } else if params.tint_tagged_boids {
+            for boid in &mut self.boids {
+                if let Some(tag) = boid.tag {
+                    let [red, green, blue] = TAG_COLORS[tag as usize];
+                    boid.color = rgb(red, green, blue);
+                }
+            }
+        }
+        self.color_mode = params.color_mode;
+        self.tint_tagged_boids = params.tint_tagged_boids;
+        
+        true
+    }
+    
+    // Index of the boid with this id in the latest snapshot, or None if it no longer exists
+    pub fn index_of(&self, id: BoidId) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+    
+    // Update the interpolation factor for a frame drawn at `now`. The view runs one
+    // snapshot interval behind the physics, blending from the previous snapshot
+    // (alpha 0) to the latest one (alpha 1). While paused the boids are shown at their
+    // true (latest) state, not wherever the blend was when the pause started.
+    pub fn update_interpolation(&mut self, now: Instant, enable_interpolation: bool, paused: bool) {
+        self.interpolation_alpha = if enable_interpolation && !paused && !self.interval.is_zero() {
+            let elapsed = now.saturating_duration_since(self.latest_time);
+            (elapsed.as_secs_f32() / self.interval.as_secs_f32()).min(1.0)
+        } else {
+            1.0
+        };
+    }
+}
@@ -0,0 +1,133 @@
+/*
+ * Placement Module
+ * 
+ * Keeps new boids apart when they are placed (see SimulationParams::min_spawn_spacing).
+ * Boids placed uniformly at random land almost on top of each other at high densities,
+ * and their separation forces then throw them apart on the first step. With a spacing
+ * set, each candidate position is checked against the boids placed so far (looked up in
+ * a temporary spatial grid, so placing n boids stays O(n)) and drawn again while it is
+ * too close, like Poisson disk sampling.
+ * 
+ * When the world is too crowded for the spacing, a boid that finds no room within
+ * MAX_ATTEMPTS candidates is placed at its last one, and the rest of the batch is placed
+ * without the check.
+ */
+
+use nannou::prelude::*;
+
+use crate::boid::Boid;
+use crate::spatial_grid::SpatialGrid;
+
+// Candidates drawn for a boid before giving up on the spacing
+pub const MAX_ATTEMPTS: usize = 30;
+
+// A batch of boids being placed at least `spacing` apart (wrapped distances)
+pub struct SpacedPlacement {
+    spacing: f32,
+    world_size: f32,
+    grid: Option<SpatialGrid>, // None without a spacing, or once it couldn't be kept
+    positions: Vec<Point2>, // Boids already in the grid: the existing ones, then the placed ones
+}
+
+impl SpacedPlacement {
+    // A placement of `count` boids among the existing ones (only those that can be too
+    // close to a new boid are needed). A spacing of 0 places every boid at its first candidate.
+    pub fn new(spacing: f32, world_size: f32, existing: impl Iterator<Item = Point2>, count: usize) -> Self {
+        if spacing <= 0.0 {
+            return Self { spacing, world_size, grid: None, positions: Vec::new() };
+        }
+        
+        let positions: Vec<Point2> = existing.collect();
+        
+        // About one boid per cell, so a sparse spacing doesn't allocate a huge grid
+        let total = (positions.len() + count).max(1);
+        let cell_size = spacing.max(world_size / (total as f32).sqrt());
+        let mut grid = SpatialGrid::new(cell_size, world_size);
+        grid.rebuild(&positions, world_size);
+        
+        Self { spacing, world_size, grid: Some(grid), positions }
+    }
+    
+    // Draw candidates until one is far enough from every boid placed so far, and record it
+    pub fn place(&mut self, mut candidate: impl FnMut() -> Boid) -> Boid {
+        let mut boid = candidate();
+        let Some(grid) = &mut self.grid else {
+            return boid;
+        };
+        
+        let spacing_sq = self.spacing * self.spacing;
+        let mut attempts = 1;
+        loop {
+            let mut clear = true;
+            grid.for_each_in_radius(boid.position, self.spacing, &self.positions, self.world_size, |_, distance_squared| {
+                clear &= distance_squared >= spacing_sq;
+            });
+            if clear {
+                break;
+            }
+            
+            if attempts == MAX_ATTEMPTS {
+                // Too crowded: place the rest of the batch at random
+                self.grid = None;
+                return boid;
+            }
+            boid = candidate();
+            attempts += 1;
+        }
+        
+        grid.insert(self.positions.len(), boid.position, self.world_size);
+        self.positions.push(boid.position);
+        boid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const WORLD_SIZE: f32 = 1000.0;
+
+    fn random_boid(rng: &mut StdRng) -> Boid {
+        let half_world = WORLD_SIZE / 2.0;
+        Boid::new_with_rng(rng.gen_range(-half_world..half_world), rng.gen_range(-half_world..half_world), rng)
+    }
+
+    fn closest_pair(positions: &[Point2]) -> f32 {
+        let mut closest = f32::INFINITY;
+        for (i, &a) in positions.iter().enumerate() {
+            for &b in &positions[i + 1..] {
+                closest = closest.min(SpatialGrid::wrapped_distance_squared(a, b, WORLD_SIZE).sqrt());
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn boids_are_placed_at_least_the_spacing_apart() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let existing = [pt2(0.0, 0.0), pt2(490.0, 490.0)];
+        let mut placement = SpacedPlacement::new(20.0, WORLD_SIZE, existing.iter().copied(), 400);
+
+        let mut positions = existing.to_vec();
+        for _ in 0..400 {
+            positions.push(placement.place(|| random_boid(&mut rng)).position);
+        }
+
+        // 400 discs of radius 10 cover an eighth of the world, so the spacing fits easily
+        assert!(placement.grid.is_some());
+        assert!(closest_pair(&positions) >= 20.0);
+    }
+
+    #[test]
+    fn an_impossible_spacing_falls_back_to_random_placement() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut placement = SpacedPlacement::new(400.0, WORLD_SIZE, std::iter::empty(), 100);
+
+        let positions: Vec<Point2> = (0..100).map(|_| placement.place(|| random_boid(&mut rng)).position).collect();
+
+        assert_eq!(positions.len(), 100);
+        assert!(placement.grid.is_none());
+    }
+}
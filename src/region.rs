@@ -0,0 +1,156 @@
+/*
+ * Region Module
+ * 
+ * This module defines parameter regions: rectangles of the world inside which boids
+ * use different flocking weights and radii than their species' own, e.g. a calm zone
+ * with high cohesion next to a turbulent zone with high separation.
+ * 
+ * Regions wrap around the world edges like the boids do, so a region dragged across
+ * one edge continues on the opposite side. Where regions overlap, a boid keeps the
+ * region it entered first until it leaves it.
+ */
+
+use nannou::prelude::*;
+
+// Whether a position is inside a rectangle of the world, wrapping around the world edges
+// (a rectangle at least as large as the world contains every position)
+pub fn wrapped_rect_contains(rect: &Rect, position: Point2, world_size: f32) -> bool {
+    let offset = position - pt2(rect.left(), rect.bottom());
+    offset.x.rem_euclid(world_size) <= rect.w() && offset.y.rem_euclid(world_size) <= rect.h()
+}
+
+// Tints assigned to new regions (cycled)
+const REGION_PALETTE: [[u8; 3]; 4] = [
+    [90, 180, 240],
+    [230, 90, 80],
+    [120, 220, 110],
+    [240, 200, 80],
+];
+
+// Flocking parameters replaced inside a region (None keeps the species' value)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParamOverrides {
+    pub separation_weight: Option<f32>,
+    pub alignment_weight: Option<f32>,
+    pub cohesion_weight: Option<f32>,
+    pub separation_radius: Option<f32>,
+    pub alignment_radius: Option<f32>,
+    pub cohesion_radius: Option<f32>,
+}
+
+impl ParamOverrides {
+    // The largest overridden perception radius (0 if no radius is overridden)
+    pub fn max_radius(&self) -> f32 {
+        [self.separation_radius, self.alignment_radius, self.cohesion_radius]
+            .into_iter()
+            .flatten()
+            .fold(0.0, f32::max)
+    }
+}
+
+// A rectangle of the world with its own flocking parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamRegion {
+    pub rect: Rect,
+    pub overrides: ParamOverrides,
+    pub color: [u8; 3],
+}
+
+impl ParamRegion {
+    // A region without overrides, tinted by its position in the region list
+    pub fn new(rect: Rect, index: usize) -> Self {
+        Self {
+            rect,
+            overrides: ParamOverrides::default(),
+            color: REGION_PALETTE[index % REGION_PALETTE.len()],
+        }
+    }
+    
+    // Whether a position is inside the region, wrapping around the world edges
+    pub fn contains(&self, position: Point2, world_size: f32) -> bool {
+        wrapped_rect_contains(&self.rect, position, world_size)
+    }
+    
+    // The region's corners, counter-clockwise from the bottom left
+    pub fn corners(&self) -> [Point2; 4] {
+        let rect = &self.rect;
+        [
+            pt2(rect.left(), rect.bottom()),
+            pt2(rect.right(), rect.bottom()),
+            pt2(rect.right(), rect.top()),
+            pt2(rect.left(), rect.top()),
+        ]
+    }
+    
+    // The copies of the region's rectangle that overlap the world, one per wrapped
+    // position (a region straddling an edge has up to four)
+    pub fn wrapped_rects(&self, world_size: f32) -> Vec<Rect> {
+        let half_world = world_size / 2.0;
+        let overlaps_world = |low: f32, high: f32| low < half_world && high > -half_world;
+        let shifts = [-world_size, 0.0, world_size];
+        let rect = &self.rect;
+        
+        let mut rects = Vec::new();
+        for &dx in &shifts {
+            for &dy in &shifts {
+                if overlaps_world(rect.left() + dx, rect.right() + dx) && overlaps_world(rect.bottom() + dy, rect.top() + dy) {
+                    rects.push(Rect::from_corners(pt2(rect.left() + dx, rect.bottom() + dy), pt2(rect.right() + dx, rect.top() + dy)));
+                }
+            }
+        }
+        rects
+    }
+}
+
+// The region containing a position, or None if it is outside every region. The cached
+// region from the last step is kept while the position is still inside it, so the
+// regions are only scanned for boids that left theirs.
+#[inline]
+pub fn region_at(regions: &[ParamRegion], cached: Option<usize>, position: Point2, world_size: f32) -> Option<usize> {
+    let still_inside = |&index: &usize| regions.get(index).is_some_and(|region| region.contains(position, world_size));
+    cached.filter(still_inside)
+        .or_else(|| regions.iter().position(|region| region.contains(position, world_size)))
+}
+
+// The largest perception radius set by any region
+pub fn max_override_radius(regions: &[ParamRegion]) -> f32 {
+    regions.iter().map(|region| region.overrides.max_radius()).fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD_SIZE: f32 = 1000.0;
+
+    #[test]
+    fn region_straddling_an_edge_wraps_around() {
+        // Spans x from 450 to 550, i.e. 450..500 and -500..-450 after wrapping
+        let region = ParamRegion::new(Rect::from_corners(pt2(450.0, -50.0), pt2(550.0, 50.0)), 0);
+
+        assert!(region.contains(pt2(480.0, 0.0), WORLD_SIZE));
+        assert!(region.contains(pt2(-480.0, 0.0), WORLD_SIZE));
+        assert!(!region.contains(pt2(-440.0, 0.0), WORLD_SIZE));
+        assert!(!region.contains(pt2(480.0, 60.0), WORLD_SIZE));
+        assert_eq!(region.wrapped_rects(WORLD_SIZE).len(), 2);
+    }
+
+    #[test]
+    fn cached_region_is_kept_until_the_boid_leaves_it() {
+        let regions = [
+            ParamRegion::new(Rect::from_corners(pt2(0.0, 0.0), pt2(100.0, 100.0)), 0),
+            ParamRegion::new(Rect::from_corners(pt2(50.0, 50.0), pt2(150.0, 150.0)), 1),
+        ];
+
+        // In the overlap the first region wins, unless the boid came from the second one
+        assert_eq!(region_at(&regions, None, pt2(75.0, 75.0), WORLD_SIZE), Some(0));
+        assert_eq!(region_at(&regions, Some(1), pt2(75.0, 75.0), WORLD_SIZE), Some(1));
+
+        // Leaving the cached region finds the one it is in now, if any
+        assert_eq!(region_at(&regions, Some(1), pt2(25.0, 25.0), WORLD_SIZE), Some(0));
+        assert_eq!(region_at(&regions, Some(0), pt2(-25.0, 25.0), WORLD_SIZE), None);
+
+        // A cached index past the end (the regions were replaced) is ignored
+        assert_eq!(region_at(&regions, Some(5), pt2(125.0, 125.0), WORLD_SIZE), Some(1));
+    }
+}
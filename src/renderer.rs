@@ -2,7 +2,8 @@
  * Renderer Module
  * 
  * This module handles the rendering of the boid simulation.
- * It draws the boids, world boundaries, and debug information.
+ * It draws the boids, walls, parameter regions, the pheromone heatmap, world boundaries,
+ * and debug information (including the forces acting on the selected boid).
  * 
  * Optimized for performance by:
  * - Only rendering visible boids (frustum culling)
@@ -11,15 +12,101 @@
  */
 
 use nannou::prelude::*;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
 use crate::app::Model;
-use crate::culling;
-use crate::ui;
+use crate::boid::{boid_render_position, boid_render_velocity, Boid};
+use crate::culling::{self, RenderBudget};
+use crate::instanced::{self, InstancedRenderer};
+use crate::palette;
+use crate::params::SimulationParams;
+use crate::region::{self, ParamRegion};
+
+// Maximum number of velocity arrows drawn per frame in debug mode
+const MAX_VELOCITY_ARROWS: usize = 4000;
+// Wall thickness in world units (never drawn thinner than 2 pixels)
+const WALL_THICKNESS: f32 = 6.0;
+// Opacity of the region fills, so the boids inside stay visible
+const REGION_FILL_ALPHA: f32 = 0.12;
+// Size of the corner handles drawn in edit mode, in screen pixels
+const REGION_HANDLE_SIZE: f32 = 8.0;
+// Radius of the outline around the boid under the cursor, relative to the boid size
+const HOVER_OUTLINE_SCALE: f32 = 1.8;
+// Opacity of the strongest pheromone cell in the heatmap
+const PHEROMONE_MAX_ALPHA: f32 = 0.5;
+// Cells weaker than this fraction of the strongest one are not drawn
+const PHEROMONE_MIN_LEVEL: f32 = 0.02;
+// Length of the selected boid's force arrows per unit of force, in world units
+const FORCE_ARROW_SCALE: f32 = 300.0;
+// Half the length of the cursor crosshair's arms, in screen pixels
+const CROSSHAIR_SIZE: f32 = 10.0;
+// Opacity of the edge band right at the world edge
+const EDGE_BAND_OPACITY: f32 = 0.35;
+// Opacity of the perception circles of rules that are off or have no weight
+const INACTIVE_CIRCLE_ALPHA: f32 = 0.25;
+
+// One of the rule radii drawn around the debug boid
+pub struct PerceptionCircle {
+    pub label: &'static str,
+    pub radius: f32, // In world units
+    pub color: Srgb<u8>,
+    pub active: bool, // The rule is enabled and has a nonzero weight, so it affects the boid
+}
+
+// The boid whose perception radii are drawn: the selected one, or else the first
+pub fn perception_boid(boids: &[Boid], selected_index: Option<usize>) -> Option<&Boid> {
+    selected_index
+        .and_then(|index| boids.get(index))
+        .or_else(|| boids.first())
+}
+
+// The rule radii of a boid that are switched on for drawing, with its species' values and
+// the overrides of the region it is in, as the physics uses them
+pub fn perception_circles(params: &SimulationParams, regions: &[ParamRegion], alpha: f32, boid: &Boid) -> Vec<PerceptionCircle> {
+    let species = match params.species.get(boid.species as usize) {
+        Some(species) => species,
+        None => return Vec::new(),
+    };
+    let position = boid_render_position(boid, params, alpha);
+    let overrides = region::region_at(regions, None, position, params.world_size)
+        .map(|index| regions[index].overrides)
+        .unwrap_or_default();
+    
+    let circles = [
+        (params.show_separation_radius, PerceptionCircle {
+            label: "Separation",
+            radius: overrides.separation_radius.unwrap_or(species.separation_radius),
+            color: RED,
+            active: params.enable_separation && overrides.separation_weight.unwrap_or(species.separation_weight) != 0.0,
+        }),
+        (params.show_alignment_radius, PerceptionCircle {
+            label: "Alignment",
+            radius: overrides.alignment_radius.unwrap_or(species.alignment_radius),
+            color: GREEN,
+            active: params.enable_alignment && overrides.alignment_weight.unwrap_or(species.alignment_weight) != 0.0,
+        }),
+        (params.show_cohesion_radius, PerceptionCircle {
+            label: "Cohesion",
+            radius: overrides.cohesion_radius.unwrap_or(species.cohesion_radius),
+            color: BLUE,
+            active: params.enable_cohesion && overrides.cohesion_weight.unwrap_or(species.cohesion_weight) != 0.0,
+        }),
+    ];
+    
+    let mut shown = Vec::new();
+    for (show, circle) in circles {
+        if show {
+            shown.push(circle);
+        }
+    }
+    shown
+}
 
 // Render the model
 pub fn view(app: &App, model: &Model, frame: Frame) {
     // Skip rendering if not needed (when paused and nothing has changed)
-    let render_needed = unsafe { *model.render_needed.get() };
+    let render_needed = model.render_needed.load(Ordering::Relaxed);
     if !render_needed {
         // Only draw the UI
         model.egui.draw_to_frame(&frame).unwrap();
@@ -30,7 +117,7 @@ pub fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
     
     // Clear the background
-    draw.background().color(BLACK);
+    draw.background().color(palette::color(model.params.colors.background));
     
     // Get the window rectangle
     let window_rect = app.window_rect();
@@ -50,75 +137,112 @@ pub fn view(app: &App, model: &Model, frame: Frame) {
         .wh(world_rect.wh())
         .no_fill()
         .stroke_weight(1.0)
-        .stroke(GRAY);
-    
-    // Calculate the visible area in world space for culling
-    let visible_area = Rect::from_corners(
-        pt2(
-            model.camera.screen_to_world(pt2(window_rect.left(), window_rect.bottom()), window_rect).x,
-            model.camera.screen_to_world(pt2(window_rect.left(), window_rect.bottom()), window_rect).y
-        ),
-        pt2(
-            model.camera.screen_to_world(pt2(window_rect.right(), window_rect.top()), window_rect).x,
-            model.camera.screen_to_world(pt2(window_rect.right(), window_rect.top()), window_rect).y
-        )
-    );
+        .stroke(palette::color(model.params.colors.boundary));
     
-    // Add a margin to the visible area (scaled by zoom level)
-    let margin = crate::BOID_SIZE * 2.0 / model.camera.zoom;
-    let visible_area_with_margin = Rect::from_corners(
-        pt2(visible_area.left() - margin, visible_area.bottom() - margin),
-        pt2(visible_area.right() + margin, visible_area.top() + margin)
-    );
+    draw_edge_band(&draw, model, window_rect);
+    draw_pheromones(&draw, model, window_rect);
+    draw_regions(&draw, model, window_rect, app.keys.mods.ctrl());
+    draw_walls(&draw, model, window_rect);
     
-    // Calculate frustum area ratio for debug info
-    if model.params.show_debug {
-        let world_area = world_size * world_size;
-        let frustum_area = visible_area_with_margin.w() * visible_area_with_margin.h();
-        let area_ratio = frustum_area / world_area;
-        
-        unsafe {
-            (*model.debug_info.get()).frustum_area_ratio = Some(area_ratio);
-        }
-    }
+    // The visible area in world space, grown so that boids drawn partly or (interpolated)
+    // entirely inside the window aren't culled by their stored position
+    let steps = if model.params.enable_interpolation { model.view.stats.steps } else { 0 };
+    let margin = culling::culling_margin(&model.params, steps);
+    let visible_area_with_margin = culling::visible_area(model, window_rect, margin);
+    
+    // Time the culling and draw sections for the debug breakdown (only with show_debug)
+    let timing = model.params.show_debug;
     
     // Get visible boids based on culling settings
+    let culling_start = timing.then(Instant::now);
     let visible_boids_indices = if model.params.enable_frustum_culling {
         // Get visible boids using the most efficient method available
         culling::get_visible_boids(model, visible_area_with_margin)
     } else {
         // If culling is disabled, render all boids
-        (0..model.boids.len()).collect()
+        (0..model.view.boids.len()).collect()
     };
+    let culling_time = culling_start.map(|start| start.elapsed());
     
     // Track visible boid count and calculate culling efficiency for debug info
     if model.params.show_debug {
         let visible_count = visible_boids_indices.len();
-        let total_count = model.boids.len();
+        let total_count = model.view.boids.len();
         
         // Update visible boid count
-        unsafe {
-            (*model.debug_info.get()).visible_boids_count = Some(visible_count);
-            
-            // Calculate and update culling efficiency
-            if total_count > 0 {
-                let efficiency = (1.0 - (visible_count as f32 / total_count as f32)) * 100.0;
-                (*model.debug_info.get()).culling_efficiency = Some(efficiency);
-            }
+        let mut debug_info = model.debug_info.lock().unwrap();
+        debug_info.visible_boids_count = Some(visible_count);
+        
+        // Calculate and update culling efficiency
+        if total_count > 0 {
+            let efficiency = (1.0 - (visible_count as f32 / total_count as f32)) * 100.0;
+            debug_info.culling_efficiency = Some(efficiency);
         }
     }
     
+    // Over the render budget, draw only a stable subset of the visible boids (always
+    // including the selected and hovered ones)
+    let render_budget = *model.render_budget.lock().unwrap();
+    let visible_boids_indices: Vec<usize> = if render_budget.stride > 1 {
+        let always_drawn = |index| model.selected_boid_index == Some(index) || model.hovered_boid_index == Some(index);
+        visible_boids_indices.into_iter()
+            .filter(|&index| render_budget.includes(model.view.boids[index].id) || always_drawn(index))
+            .collect()
+    } else {
+        visible_boids_indices
+    };
+    
     // Draw each visible boid with interpolation
-    for &i in &visible_boids_indices {
-        // Check if this is the selected boid
-        let is_selected = model.selected_boid_index.map_or(false, |selected| selected == i);
+    let budget_start = Instant::now();
+    let draw_start = timing.then(Instant::now);
+    let draw = if model.params.instanced_rendering && instanced::supports_shape(model.params.boid_shape) {
+        // Render the layers below the boids, draw all boids in one instanced pass on top,
+        // and continue with a fresh Draw for the overlays
+        draw.to_frame(app, &frame).unwrap();
+        draw_boids_instanced(model, &frame, &visible_boids_indices, window_rect);
+        app.draw()
+    } else {
+        for &i in &visible_boids_indices {
+            // Check if this is the selected boid
+            let is_selected = model.selected_boid_index == Some(i);
+            
+            // Draw the boid, passing the selection state (boids that just appeared fade in)
+            let boid = &model.view.boids[i];
+            let fade = model.effects.spawn_fade(boid.id);
+            if fade < 1.0 {
+                boid.draw_faded(&draw, &model.camera, window_rect, model.view.interpolation_alpha, &model.params, fade);
+            } else {
+                boid.draw(&draw, &model.camera, window_rect, model.view.interpolation_alpha, is_selected, &model.params);
+            }
+        }
         
-        // Draw the boid, passing the selection state
-        model.boids[i].draw(&draw, &model.camera, window_rect, model.interpolation_alpha, is_selected);
+        // Removed boids fade out where they were last seen
+        for dying in &model.effects.dying {
+            if visible_area_with_margin.contains(dying.boid.position) {
+                dying.boid.draw_faded(&draw, &model.camera, window_rect, 1.0, &model.params, dying.fade());
+            }
+        }
+        draw
+    };
+    
+    // Outline the boid under the cursor
+    if let Some(boid) = model.hovered_boid_index.and_then(|index| model.view.boids.get(index)) {
+        let position = boid_render_position(boid, &model.params, model.view.interpolation_alpha);
+        draw.ellipse()
+            .xy(model.camera.world_to_screen(position, window_rect))
+            .radius(model.params.boid_size * HOVER_OUTLINE_SCALE * model.camera.zoom)
+            .no_fill()
+            .stroke_weight(1.0)
+            .stroke(palette::color_alpha(model.params.colors.selection, 0.5));
     }
     
     // Draw debug visualization if enabled
     if model.params.show_debug {
+        // Draw velocity vectors for all visible boids if enabled
+        if model.params.show_velocity_vectors {
+            draw_velocity_vectors(&draw, model, &visible_boids_indices, window_rect);
+        }
+        
         // Draw frustum culling visualization if enabled
         if model.params.enable_frustum_culling {
             // Convert the visible area with margin to screen space for visualization
@@ -137,80 +261,322 @@ pub fn view(app: &App, model: &Model, frame: Frame) {
                 .wh(vec2(bottom_right.x - top_left.x, bottom_right.y - top_left.y))
                 .no_fill()
                 .stroke_weight(2.0)
-                .stroke(rgba(1.0, 0.5, 0.0, 0.7)); // Orange for frustum boundary
+                .stroke(palette::color_alpha(model.params.colors.debug, 0.7));
         }
         
-        // Draw perception radius for the first boid if it's visible
-        if !model.boids.is_empty() {
-            let first_boid = &model.boids[0];
-            
-            // Get interpolated position for debug visualization
-            let interpolated_pos = if model.params.enable_interpolation {
-                first_boid.get_interpolated_position(model.interpolation_alpha)
-            } else {
-                first_boid.position
-            };
-            
-            if visible_area_with_margin.contains(Vec2::new(interpolated_pos.x, interpolated_pos.y)) {
-                let screen_pos = model.camera.world_to_screen(Vec2::new(interpolated_pos.x, interpolated_pos.y), window_rect);
-                
-                // Scale radii based on zoom level
-                let sep_radius = model.params.separation_radius * model.camera.zoom;
-                let align_radius = model.params.alignment_radius * model.camera.zoom;
-                let cohesion_radius = model.params.cohesion_radius * model.camera.zoom;
-                
-                // Separation radius
-                draw.ellipse()
-                    .xy(pt2(screen_pos.x, screen_pos.y))
-                    .radius(sep_radius)
-                    .no_fill()
-                    .stroke(RED)
-                    .stroke_weight(1.0);
-                
-                // Alignment radius
-                draw.ellipse()
-                    .xy(pt2(screen_pos.x, screen_pos.y))
-                    .radius(align_radius)
-                    .no_fill()
-                    .stroke(GREEN)
-                    .stroke_weight(1.0);
-                
-                // Cohesion radius
-                draw.ellipse()
-                    .xy(pt2(screen_pos.x, screen_pos.y))
-                    .radius(cohesion_radius)
-                    .no_fill()
-                    .stroke(BLUE)
-                    .stroke_weight(1.0);
-                
-                // Get interpolated velocity for debug visualization
-                let interpolated_vel = if model.params.enable_interpolation {
-                    first_boid.get_interpolated_velocity(model.interpolation_alpha)
-                } else {
-                    first_boid.velocity
-                };
-                
-                // Velocity vector
-                draw.arrow()
-                    .start(pt2(screen_pos.x, screen_pos.y))
-                    .end(pt2(
-                        screen_pos.x + interpolated_vel.x * 5.0 * model.camera.zoom,
-                        screen_pos.y + interpolated_vel.y * 5.0 * model.camera.zoom
-                    ))
-                    .color(YELLOW)
-                    .stroke_weight(2.0);
-            }
+        draw_perception_circles(&draw, model, window_rect, visible_area_with_margin);
+        
+        draw_selected_boid_forces(&draw, model, window_rect);
+        
+        if model.params.show_cursor_info {
+            draw_cursor_crosshair(&draw, model, window_rect);
         }
     }
     
     // Finish drawing
     draw.to_frame(app, &frame).unwrap();
     
+    // Adjust the subsampling for the next frame (back to every boid while the budget is off)
+    let render_stride = {
+        let mut render_budget = model.render_budget.lock().unwrap();
+        if model.params.enable_render_budget {
+            render_budget.update(budget_start.elapsed(), Duration::from_secs_f32(model.params.render_budget_ms / 1000.0));
+        } else {
+            *render_budget = RenderBudget::default();
+        }
+        render_budget.stride
+    };
+    model.debug_info.lock().unwrap().render_stride = render_stride;
+    
+    if let (Some(culling_time), Some(draw_start)) = (culling_time, draw_start) {
+        model.debug_info.lock().unwrap().phase_breakdown.record_render(culling_time, draw_start.elapsed());
+    }
+    
     // If simulation is paused, mark rendering as complete
     if model.params.pause_simulation {
-        unsafe { *model.render_needed.get() = false; }
+        model.render_needed.store(false, Ordering::Relaxed);
     }
     
     // Draw the egui UI
     model.egui.draw_to_frame(&frame).unwrap();
-} 
\ No newline at end of file
+} 
+
+// Draw the boids with the instanced pipeline, creating it on first use (or when the
+// window's sample count changed)
+fn draw_boids_instanced(model: &Model, frame: &Frame, indices: &[usize], window_rect: Rect) {
+    let mut renderer = model.instanced_renderer.lock().unwrap();
+    let sample_count = frame.texture_msaa_samples();
+    if !renderer.as_ref().is_some_and(|renderer| renderer.matches(sample_count)) {
+        let device = frame.device_queue_pair().device();
+        *renderer = Some(InstancedRenderer::new(device, frame.texture_format(), sample_count));
+    }
+    
+    if let Some(renderer) = renderer.as_mut() {
+        renderer.draw(frame, model, indices, window_rect);
+    }
+}
+
+// Shade a band inside each world edge, fading out away from the edge. Each band is one
+// quad with per-corner opacity, clipped to the visible part of the world, so the cost
+// doesn't depend on the zoom. The bands overlap in the corners, which end up darker.
+fn draw_edge_band(draw: &Draw, model: &Model, window_rect: Rect) {
+    if !model.params.show_edge_band {
+        return;
+    }
+    
+    let half_world = model.params.world_size / 2.0;
+    let width = model.params.edge_band_width.clamp(1.0, half_world);
+    let visible = culling::window_area(&model.camera, window_rect);
+    
+    // Each band with whether it fades along x (the left and right edges) and its edge
+    let bands = [
+        (Rect::from_corners(pt2(-half_world, -half_world), pt2(-half_world + width, half_world)), true, -half_world),
+        (Rect::from_corners(pt2(half_world - width, -half_world), pt2(half_world, half_world)), true, half_world),
+        (Rect::from_corners(pt2(-half_world, -half_world), pt2(half_world, -half_world + width)), false, -half_world),
+        (Rect::from_corners(pt2(-half_world, half_world - width), pt2(half_world, half_world)), false, half_world),
+    ];
+    for (band, along_x, edge) in bands.iter() {
+        let band = match band.overlap(visible) {
+            Some(band) => band,
+            None => continue,
+        };
+        
+        let corners = [band.bottom_left(), band.bottom_right(), band.top_right(), band.top_left()];
+        let vertices = corners.iter().map(|&corner| {
+            let distance = (if *along_x { corner.x - edge } else { corner.y - edge }).abs();
+            let opacity = EDGE_BAND_OPACITY * (1.0 - distance / width).clamp(0.0, 1.0);
+            let screen = model.camera.world_to_screen(corner, window_rect);
+            (pt3(screen.x, screen.y, 0.0), palette::color_alpha(palette::EDGE_WARNING, opacity))
+        });
+        draw.mesh().indexed_colored(vertices, [0, 1, 2, 0, 2, 3]);
+    }
+}
+
+// Draw the perception circles and the velocity of the debug boid (see perception_boid) if
+// it is in view. Circles of rules that don't affect it are dimmed.
+fn draw_perception_circles(draw: &Draw, model: &Model, window_rect: Rect, visible_area: Rect) {
+    let boid = match perception_boid(&model.view.boids, model.selected_boid_index) {
+        Some(boid) => boid,
+        None => return,
+    };
+    
+    let alpha = model.view.interpolation_alpha;
+    let position = boid_render_position(boid, &model.params, alpha);
+    if !visible_area.contains(position) {
+        return;
+    }
+    
+    let screen_pos = model.camera.world_to_screen(position, window_rect);
+    for circle in perception_circles(&model.params, &model.regions, alpha, boid) {
+        let opacity = if circle.active { 1.0 } else { INACTIVE_CIRCLE_ALPHA };
+        draw.ellipse()
+            .xy(screen_pos)
+            .radius(circle.radius * model.camera.zoom)
+            .no_fill()
+            .stroke(srgba(circle.color.red, circle.color.green, circle.color.blue, (opacity * 255.0) as u8))
+            .stroke_weight(1.0);
+    }
+    
+    // Velocity vector
+    let velocity = boid_render_velocity(boid, &model.params, alpha);
+    draw.arrow()
+        .start(screen_pos)
+        .end(screen_pos + velocity * 5.0 * model.camera.zoom)
+        .color(YELLOW)
+        .stroke_weight(2.0);
+}
+
+// Draw the walls as thick lines, plus a preview of the wall being drawn
+fn draw_walls(draw: &Draw, model: &Model, window_rect: Rect) {
+    let weight = (WALL_THICKNESS * model.camera.zoom).max(2.0);
+    
+    for wall in &model.walls {
+        draw.line()
+            .start(model.camera.world_to_screen(wall.a, window_rect))
+            .end(model.camera.world_to_screen(wall.b, window_rect))
+            .weight(weight)
+            .caps_round()
+            .color(palette::color(model.params.colors.boundary));
+    }
+    
+    // The second endpoint follows the cursor until it is placed
+    if let Some(start) = model.wall_start {
+        draw.line()
+            .start(model.camera.world_to_screen(start, window_rect))
+            .end(model.mouse_position)
+            .weight(weight)
+            .caps_round()
+            .color(palette::color_alpha(model.params.colors.boundary, 0.5));
+    }
+}
+
+// Draw the pheromone field as a translucent heatmap, scaled to its strongest cell.
+// Only the cells in view that hold a noticeable amount are drawn.
+fn draw_pheromones(draw: &Draw, model: &Model, window_rect: Rect) {
+    let field = match &model.view.pheromones {
+        Some(field) if model.params.show_pheromones => field,
+        _ => return,
+    };
+    
+    let max_level = field.values().iter().fold(0.0, |max: f32, &value| max.max(value));
+    if max_level <= 0.0 {
+        return;
+    }
+    
+    // Range of cell indices covering the window along one axis
+    let resolution = field.resolution();
+    let cell_size = field.cell_size();
+    let half_world = field.world_size() / 2.0;
+    let cell_range = |low: f32, high: f32| {
+        let first = ((low + half_world) / cell_size).floor().max(0.0) as usize;
+        let last = (((high + half_world) / cell_size).ceil().max(0.0) as usize).min(resolution);
+        first..last
+    };
+    
+    let bottom_left = model.camera.screen_to_world(pt2(window_rect.left(), window_rect.bottom()), window_rect);
+    let top_right = model.camera.screen_to_world(pt2(window_rect.right(), window_rect.top()), window_rect);
+    let screen_cell_size = cell_size * model.camera.zoom;
+    
+    for row in cell_range(bottom_left.y, top_right.y) {
+        for column in cell_range(bottom_left.x, top_right.x) {
+            let level = field.values()[row * resolution + column] / max_level;
+            if level < PHEROMONE_MIN_LEVEL {
+                continue;
+            }
+            
+            draw.rect()
+                .xy(model.camera.world_to_screen(field.cell_center(column, row), window_rect))
+                .w_h(screen_cell_size, screen_cell_size)
+                .color(rgba(1.0, 0.55, 0.1, level * PHEROMONE_MAX_ALPHA));
+        }
+    }
+}
+
+// Draw the parameter regions as translucent tinted rectangles (with every wrapped copy
+// of a region straddling a world edge), plus corner handles while they can be dragged
+fn draw_regions(draw: &Draw, model: &Model, window_rect: Rect, ctrl_held: bool) {
+    let world_size = model.params.world_size;
+    let edit_mode = model.params.edit_mode || ctrl_held;
+    
+    for (index, region) in model.regions.iter().enumerate() {
+        let [r, g, b] = region.color;
+        let tint = rgba8(r, g, b, (REGION_FILL_ALPHA * 255.0) as u8);
+        let selected = model.selected_region == Some(index);
+        let outline = rgba8(r, g, b, if selected { 255 } else { 120 });
+        
+        for rect in region.wrapped_rects(world_size) {
+            let corner_a = model.camera.world_to_screen(pt2(rect.left(), rect.bottom()), window_rect);
+            let corner_b = model.camera.world_to_screen(pt2(rect.right(), rect.top()), window_rect);
+            let screen_rect = Rect::from_corners(corner_a, corner_b);
+            
+            draw.rect()
+                .xy(screen_rect.xy())
+                .wh(screen_rect.wh())
+                .color(tint)
+                .stroke_weight(if selected { 2.0 } else { 1.0 })
+                .stroke(outline);
+        }
+        
+        if edit_mode {
+            for corner in region.corners() {
+                draw.rect()
+                    .xy(model.camera.world_to_screen(corner, window_rect))
+                    .w_h(REGION_HANDLE_SIZE, REGION_HANDLE_SIZE)
+                    .color(outline);
+            }
+        }
+    }
+}
+
+// Draw a velocity arrow for each visible boid, subsampling uniformly if there are too many
+// Crosshair at the cursor and the outline of the spatial grid cell under it, matching
+// the cursor readout
+fn draw_cursor_crosshair(draw: &Draw, model: &Model, window_rect: Rect) {
+    let cursor = model.mouse_position;
+    let color = palette::color_alpha(model.params.colors.debug, 0.8);
+    draw.line().start(cursor - vec2(CROSSHAIR_SIZE, 0.0)).end(cursor + vec2(CROSSHAIR_SIZE, 0.0)).weight(1.0).color(color);
+    draw.line().start(cursor - vec2(0.0, CROSSHAIR_SIZE)).end(cursor + vec2(0.0, CROSSHAIR_SIZE)).weight(1.0).color(color);
+    
+    if !model.params.enable_spatial_grid {
+        return;
+    }
+    
+    let world_size = model.params.world_size;
+    let grid = &model.view.spatial_grid;
+    let cursor_world = model.camera.screen_to_world(cursor, window_rect);
+    if let Some((column, row)) = grid.cell_at(cursor_world, world_size) {
+        let bottom_left = pt2(column as f32, row as f32) * grid.cell_size - world_size / 2.0;
+        let bottom_left = model.camera.world_to_screen(bottom_left, window_rect);
+        let size = grid.cell_size * model.camera.zoom;
+        draw.rect()
+            .xy(bottom_left + vec2(size, size) / 2.0)
+            .w_h(size, size)
+            .no_fill()
+            .stroke_weight(1.0)
+            .stroke(palette::color_alpha(model.params.colors.debug, 0.6));
+    }
+}
+
+// Arrows for the per-rule forces on the selected boid in the last physics step
+fn draw_selected_boid_forces(draw: &Draw, model: &Model, window_rect: Rect) {
+    // Forces recorded for a previous selection are not drawn
+    let selected = model.selected_boid_index.and_then(|index| model.view.boids.get(index).map(|boid| (index, boid)));
+    let (boid, forces) = match (selected, model.selected_boid_debug) {
+        (Some((index, boid)), Some(forces)) if forces.index == index => (boid, forces),
+        _ => return,
+    };
+    
+    let position = boid_render_position(boid, &model.params, model.view.interpolation_alpha);
+    let start = model.camera.world_to_screen(position, window_rect);
+    
+    let arrows = [
+        (forces.separation_force, RED, 2.0),
+        (forces.alignment_force, GREEN, 2.0),
+        (forces.cohesion_force, BLUE, 2.0),
+        (forces.combined_force, palette::color(model.params.colors.debug), 3.0),
+    ];
+    for (force, color, weight) in arrows {
+        let end = start + force * FORCE_ARROW_SCALE * model.camera.zoom;
+        
+        // Skip rules that didn't contribute
+        if (end - start).length_squared() < 1.0 {
+            continue;
+        }
+        
+        draw.arrow()
+            .start(start)
+            .end(end)
+            .color(color)
+            .stroke_weight(weight);
+    }
+}
+
+fn draw_velocity_vectors(draw: &Draw, model: &Model, visible_boids_indices: &[usize], window_rect: Rect) {
+    // Step through the visible list so that at most MAX_VELOCITY_ARROWS are drawn
+    let step = visible_boids_indices.len().div_ceil(MAX_VELOCITY_ARROWS);
+    
+    for &i in visible_boids_indices.iter().step_by(step.max(1)) {
+        let boid = &model.view.boids[i];
+        
+        // Use interpolated state so the arrows move smoothly with the boids
+        let alpha = model.view.interpolation_alpha;
+        let (pos, vel) = (boid_render_position(boid, &model.params, alpha), boid_render_velocity(boid, &model.params, alpha));
+        
+        let screen_pos = model.camera.world_to_screen(Vec2::new(pos.x, pos.y), window_rect);
+        
+        // Arrow length is proportional to speed and scaled by zoom level
+        let end = screen_pos + vel * 5.0 * model.camera.zoom;
+        
+        // Skip degenerate arrows for stationary boids
+        if (end - screen_pos).length_squared() < 1.0 {
+            continue;
+        }
+        
+        // Use the boid's own color so heading and color stay consistent
+        draw.arrow()
+            .start(pt2(screen_pos.x, screen_pos.y))
+            .end(pt2(end.x, end.y))
+            .color(boid.color)
+            .stroke_weight(1.0);
+    }
+}
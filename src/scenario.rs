@@ -0,0 +1,144 @@
+/*
+ * Scenario Module
+ * 
+ * A scenario is a fixed workload for comparing performance (and behavior) across
+ * branches: a seed, a number of steps and the simulation parameters. The windowed app
+ * (--scenario), the headless mode and the criterion benches all build the same world
+ * from it, since the seed decides the boid placement and every later random choice.
+ * 
+ * Scenario files use the preset format plus two extra keys:
+ * 
+ *   seed = 42        # Seed of the simulation's random number generator (default 0)
+ *   steps = 1000     # Physics steps to run headless and in verify mode (default 1000)
+ *   num_boids = 5000 # Any other key is a preset parameter
+ * 
+ * The state hash summarizes the flock after the steps, so a change in behavior shows up
+ * as a different hash (see --verify).
+ */
+
+use std::path::Path;
+
+use crate::cli::DEFAULT_BENCH_STEPS;
+use crate::flock::Flock;
+use crate::params::{self, SimulationParams};
+use crate::simulation::Simulation;
+
+#[derive(Clone)]
+pub struct Scenario {
+    pub seed: u64,
+    pub steps: usize,
+    pub params: SimulationParams,
+}
+
+impl Scenario {
+    // Parse a scenario; parameters not in it keep their defaults, so the file alone decides the world
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut scenario = Self {
+            seed: 0,
+            steps: DEFAULT_BENCH_STEPS,
+            params: SimulationParams::default(),
+        };
+        
+        for (line_number, line) in text.lines().enumerate() {
+            let Some((key, value)) = params::parse_preset_line(line)
+                .map_err(|err| format!("line {}: {}", line_number + 1, err))? else {
+                continue;
+            };
+            
+            let result = match key {
+                "seed" => value.parse().map(|seed| scenario.seed = seed)
+                    .map_err(|_| format!("invalid integer for {}: {}", key, value)),
+                "steps" => value.parse().map(|steps| scenario.steps = steps)
+                    .map_err(|_| format!("invalid integer for {}: {}", key, value)),
+                _ => scenario.params.set_preset_value(key, value),
+            };
+            result.map_err(|err| format!("line {}: {}", line_number + 1, err))?;
+        }
+        
+        scenario.params.finish_preset();
+        Ok(scenario)
+    }
+    
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read scenario {}: {}", path.display(), err))?;
+        Self::parse(&text).map_err(|err| format!("invalid scenario {}: {}", path.display(), err))
+    }
+    
+    // The scenario's world before its first step
+    pub fn build_simulation(&self) -> Simulation {
+        Simulation::with_seed(self.params.clone(), self.seed)
+    }
+}
+
+// Hash of every boid's id, position and velocity (FNV-1a over the exact bits), so any
+// change in behavior changes it. Stable across runs, platforms and Rust versions.
+pub fn state_hash(flock: &Flock) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    
+    let mut hash = OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+        }
+    };
+    
+    write(&(flock.len() as u64).to_le_bytes());
+    for ((id, position), velocity) in flock.ids.iter().zip(&flock.positions).zip(&flock.velocities) {
+        write(&id.to_le_bytes());
+        for value in [position.x, position.y, velocity.x, velocity.y] {
+            write(&value.to_bits().to_le_bytes());
+        }
+    }
+    
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO: &str = "seed = 7\nsteps = 20\nnum_boids = 200\nworld_size = 1000\nenable_parallel = false\n";
+
+    #[test]
+    fn scenarios_set_the_seed_steps_and_parameters() {
+        let scenario = Scenario::parse(SCENARIO).unwrap();
+
+        assert_eq!(scenario.seed, 7);
+        assert_eq!(scenario.steps, 20);
+        assert_eq!(scenario.params.num_boids, 200);
+        assert_eq!(scenario.params.world_size, 1000.0);
+        assert!(!scenario.params.enable_parallel);
+
+        assert!(Scenario::parse("seed = -1").is_err());
+        assert!(Scenario::parse("boids = 10").is_err());
+    }
+
+    #[test]
+    fn the_same_scenario_gives_the_same_state_hash() {
+        let scenario = Scenario::parse(SCENARIO).unwrap();
+        let run = || {
+            let mut sim = scenario.build_simulation();
+            sim.step_n(scenario.steps);
+            state_hash(&sim.flock)
+        };
+
+        let hash = run();
+        assert_eq!(run(), hash);
+
+        // A different seed is a different world
+        let mut reseeded = Scenario { seed: 8, ..scenario.clone() }.build_simulation();
+        reseeded.step_n(scenario.steps);
+        assert_ne!(state_hash(&reseeded.flock), hash);
+    }
+
+    #[test]
+    fn the_checked_in_scenarios_load() {
+        for name in ["small", "medium", "large"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios").join(format!("{}.toml", name));
+            let scenario = Scenario::load(&path).unwrap();
+            assert!(scenario.steps > 0, "{}", name);
+        }
+    }
+}
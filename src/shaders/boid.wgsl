@@ -0,0 +1,42 @@
+// Instanced boid shader: places the unit boid mesh at each boid's position, heading and
+// size, then applies the camera transform (see instanced.rs)
+
+[[block]]
+struct Camera {
+    // Camera position in world units, zoom in pixels per world unit, unused
+    view: vec4<f32>;
+    // Half the window size in pixels, unused, unused
+    half_window: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] corner: vec2<f32>,
+    [[location(1)]] position: vec2<f32>,
+    [[location(2)]] angle: f32,
+    [[location(3)]] scale: f32,
+    [[location(4)]] color: vec4<f32>
+) -> VertexOutput {
+    let c = cos(angle);
+    let s = sin(angle);
+    let world = position + vec2<f32>(corner.x * c - corner.y * s, corner.x * s + corner.y * c) * scale;
+    let screen = (world - camera.view.xy) * camera.view.z;
+    
+    var out: VertexOutput;
+    out.position = vec4<f32>(screen / camera.half_window.xy, 0.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return input.color;
+}
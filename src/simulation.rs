@@ -0,0 +1,1807 @@
+/*
+ * Simulation Module
+ * 
+ * This module contains the head-less flocking simulation: the boids, their parameters,
+ * the spatial grid and the fixed-timestep bookkeeping. It has no dependency on the
+ * window, UI or camera, so it can be driven from other programs and benchmarks.
+ * 
+ * The application's Model owns a Simulation and adds the interactive state
+ * (camera, selection, UI and rendering caches) on top of it.
+ */
+
+use nannou::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use rand::{Rng, SeedableRng};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::boid::{self, Boid, MAX_ENERGY};
+use crate::debug::{NeighborHistogram, SelectedBoidDebug};
+use crate::export::{self, Exporter};
+use crate::flock::{BoidProperties, Flock};
+use crate::params::{InitialLayout, SimulationParams};
+use crate::pheromone::{PheromoneField, PHEROMONE_RESOLUTION};
+use crate::physics;
+use crate::placement::SpacedPlacement;
+use crate::spatial_grid::{NeighborLists, SpatialGrid};
+use crate::osc::{self, OscSender};
+use crate::stream::Streamer;
+use crate::region::{self, ParamRegion};
+use crate::tags::{self, TagMetrics, TAG_COUNT, TAG_METRICS_INTERVAL};
+use crate::wall::{Wall, WallGrid};
+
+// Boids added or removed per second while the flock converges on num_boids
+const POPULATION_CHANGE_RATE: f32 = 2000.0;
+// Largest angle (radians) between a boid entering the wind tunnel and the +x axis
+const WIND_TUNNEL_SPREAD: f32 = 0.35;
+
+/// The flocking simulation state, independent of any window.
+///
+/// ```
+/// use boids::{Simulation, SimulationParams};
+/// use std::time::Duration;
+///
+/// let mut params = SimulationParams::default();
+/// params.num_boids = 200;
+/// let mut sim = Simulation::new(params);
+///
+/// // Advance 1000 frames at 60 FPS
+/// for _ in 0..1000 {
+///     sim.step(Duration::from_secs_f32(1.0 / 60.0));
+/// }
+///
+/// if let Some(centroid) = sim.centroid() {
+///     println!("Centroid: ({:.1}, {:.1})", centroid.x, centroid.y);
+/// }
+/// ```
+pub struct Simulation {
+    pub flock: Flock, // Boid state, one array per field
+    pub params: SimulationParams,
+    pub spatial_grid: SpatialGrid,
+    // Impassable walls, and the grid used to find the walls near a boid
+    pub walls: Vec<Wall>,
+    pub wall_grid: WallGrid,
+    // Regions with their own flocking parameters, and the region each boid was in
+    // during the last step (a cache: only valid while the boid is still inside it)
+    pub regions: Vec<ParamRegion>,
+    pub boid_regions: Vec<Option<usize>>,
+    // Scent left by the boids (None while pheromones are disabled)
+    pub pheromones: Option<PheromoneField>,
+    // Fixed timestep physics variables
+    pub physics_accumulator: Duration,
+    pub physics_step_size: Duration,
+    pub interpolation_alpha: f32,
+    pub steps_capped: bool, // Whether the last step() hit max_steps_per_frame
+    pub dropped_time: Duration, // Simulated time discarded by the step cap since the simulation was created
+    pub step_count: u64, // Physics steps taken since the simulation was created or last reset
+    pub simulation_time: f64, // Simulated seconds over those steps (the step size may have changed along the way)
+    pub last_step_duration: Option<Duration>, // Measured time of one physics step, averaged over the last step() call
+    pub last_advance: Option<Instant>, // Wall-clock time of the last advance() (None while paused)
+    // Boid emission (e.g. at the cursor while the right mouse button is held)
+    pub emitter: Option<Point2>,
+    pub emission_accumulator: f32, // Fractional boids carried over between physics steps
+    pub wind_tunnel_accumulator: f32, // Same for the boids entering the wind tunnel
+    // Indices of the boids spreading the infection (only kept while infection mode is on),
+    // so the contact check doesn't have to scan the whole flock
+    pub infected: Vec<usize>,
+    // Energy mode: neighbors within each boid's cohesion radius during the last step
+    pub neighbor_counts: Vec<usize>,
+    // Boids in each boid's 3x3 grid neighborhood during the last step (empty without the grid)
+    pub grid_neighbor_counts: Vec<usize>,
+    // Neighbor lists of the parallel grid pass: the buffers are reused every step, and the
+    // lists themselves for neighbor_list_interval steps while the flock keeps its layout
+    pub neighbor_lists: NeighborLists,
+    pub respawn_timers: Vec<f32>, // Seconds until each dead boid is replaced
+    // Dead boids removed by the last step() (or since the last step() for step_once),
+    // as the (index, moved_from) of each swap_remove in order, so the new layout is published
+    pub removed_boids: Vec<(usize, Option<usize>)>,
+    // Whether the last step() (or step_once since) reordered the flock (see sort_by_cell)
+    pub reordered: bool,
+    // Per-rule force recording for a single boid (the inspector's selected boid)
+    pub tracked_boid: Option<usize>,
+    pub tracked_boid_forces: Option<SelectedBoidDebug>,
+    // Chunk size used by the last parallel physics step
+    pub last_chunk_size: Option<usize>,
+    // Order of the flock at the start of the last physics step (only while metrics are enabled)
+    pub metrics: Option<physics::FlockMetrics>,
+    // Measures of the tagged groups, updated every TAG_METRICS_INTERVAL steps while any boid is tagged
+    pub tag_metrics: [Option<TagMetrics>; TAG_COUNT],
+    // Time spent in each physics phase during the last step() (only measured with show_debug)
+    pub physics_timings: physics::PhysicsTimings,
+    // Source of randomness for boid placement, seedable for reproducible runs
+    pub rng: StdRng,
+    // Boid state export (continuous exports receive a batch every few physics steps)
+    pub exporter: Option<Exporter>,
+    // Network streaming of boid positions (see SimulationParams::enable_streaming)
+    pub streamer: Option<Streamer>,
+    pub stream_error: Option<(u16, String)>, // Port that failed to bind and why (retried when the port changes)
+    // OSC output of the flock metrics (see SimulationParams::enable_osc)
+    pub osc: Option<OscSender>,
+    pub osc_error: Option<String>, // Why the OSC sender couldn't start
+    // Dedicated worker threads for the parallel physics (None uses rayon's global pool,
+    // see SimulationParams::num_threads)
+    pub thread_pool: Option<rayon::ThreadPool>,
+}
+
+impl Simulation {
+    // Create a simulation with randomly placed boids
+    pub fn new(params: SimulationParams) -> Self {
+        Self::with_rng(params, StdRng::from_entropy())
+    }
+    
+    // Create a simulation whose boid placement is reproducible for a given seed
+    pub fn with_seed(params: SimulationParams, seed: u64) -> Self {
+        Self::with_rng(params, StdRng::seed_from_u64(seed))
+    }
+    
+    fn with_rng(params: SimulationParams, rng: StdRng) -> Self {
+        // Cells as wide as the largest perception radius (by default) keep lookups to 3x3 cells
+        let cell_size = params.max_perception_radius() * params.cell_size_factor;
+        let spatial_grid = SpatialGrid::new(cell_size, params.world_size);
+        
+        // Calculate physics step size based on fixed FPS
+        let physics_step_size = Duration::from_secs_f32(1.0 / params.fixed_physics_fps);
+        
+        let wall_grid = WallGrid::new(&[], params.wall_avoid_radius, params.world_size);
+        
+        let mut sim = Self {
+            flock: Flock::with_capacity(params.num_boids),
+            params,
+            spatial_grid,
+            walls: Vec::new(),
+            wall_grid,
+            regions: Vec::new(),
+            boid_regions: Vec::new(),
+            pheromones: None,
+            physics_accumulator: Duration::from_secs(0),
+            physics_step_size,
+            interpolation_alpha: 0.0,
+            steps_capped: false,
+            dropped_time: Duration::ZERO,
+            step_count: 0,
+            simulation_time: 0.0,
+            last_step_duration: None,
+            last_advance: None,
+            emitter: None,
+            emission_accumulator: 0.0,
+            wind_tunnel_accumulator: 0.0,
+            infected: Vec::new(),
+            neighbor_counts: Vec::new(),
+            grid_neighbor_counts: Vec::new(),
+            neighbor_lists: NeighborLists::default(),
+            respawn_timers: Vec::new(),
+            removed_boids: Vec::new(),
+            reordered: false,
+            tracked_boid: None,
+            tracked_boid_forces: None,
+            last_chunk_size: None,
+            metrics: None,
+            tag_metrics: [None; TAG_COUNT],
+            physics_timings: physics::PhysicsTimings::default(),
+            rng,
+            exporter: None,
+            streamer: None,
+            stream_error: None,
+            osc: None,
+            osc_error: None,
+            thread_pool: None,
+        };
+        sim.update_pheromone_field();
+        sim.reset();
+        sim.update_streaming();
+        sim.update_osc();
+        sim.update_thread_pool();
+        
+        // Take initial snapshot of parameters for change detection
+        sim.params.take_snapshot();
+        
+        sim
+    }
+    
+    // Advance the simulation by dt of wall-clock time (scaled by time_scale), running
+    // as many fixed physics steps as fit, up to max_steps_per_frame.
+    // Returns the number of physics steps taken.
+    pub fn step(&mut self, dt: Duration) -> usize {
+        // Add to accumulator, scaled by the simulation speed multiplier
+        self.physics_accumulator += dt.mul_f32(self.params.time_scale);
+        
+        // Phase timings are summed over this frame's steps
+        self.physics_timings = physics::PhysicsTimings::default();
+        
+        // Removals and reorderings are reported per step() call
+        self.removed_boids.clear();
+        self.reordered = false;
+        
+        // Perform fixed timestep updates, up to the per-frame cap
+        let max_steps = self.params.max_steps_per_frame;
+        let mut steps = 0;
+        let mut physics_time = Duration::ZERO;
+        while self.physics_accumulator >= self.physics_step_size && steps < max_steps {
+            // Update boids (through step_n, like the tests)
+            let step_start = Instant::now();
+            self.step_n(1);
+            physics_time += step_start.elapsed();
+            
+            // Subtract step size from accumulator
+            self.physics_accumulator -= self.physics_step_size;
+            steps += 1;
+        }
+        
+        self.last_step_duration = if steps > 0 {
+            Some(physics_time / steps as u32)
+        } else {
+            None
+        };
+        
+        // If we hit the cap, drop the backlog (keeping the sub-step remainder)
+        // so the simulation slows down instead of falling further behind
+        self.steps_capped = self.physics_accumulator >= self.physics_step_size;
+        if self.steps_capped {
+            let remainder = Duration::from_nanos((self.physics_accumulator.as_nanos() % self.physics_step_size.as_nanos()) as u64);
+            self.dropped_time += self.physics_accumulator - remainder;
+            self.physics_accumulator = remainder;
+        }
+        
+        // Calculate interpolation alpha
+        if self.params.enable_interpolation {
+            self.interpolation_alpha = self.physics_accumulator.as_secs_f32() / self.physics_step_size.as_secs_f32();
+        } else {
+            self.interpolation_alpha = 0.0;
+        }
+        
+        steps
+    }
+    
+    // Advance to wall-clock time `now`, running the physics steps that are due since the
+    // last call. Time spent paused is not simulated: the first call after a pause (or the
+    // very first call) only restarts the clock. Returns the number of physics steps taken.
+    pub fn advance(&mut self, now: Instant) -> usize {
+        if self.params.pause_simulation {
+            self.last_advance = None;
+            return 0;
+        }
+        
+        let steps = match self.last_advance {
+            Some(last) => self.step(now.saturating_duration_since(last)),
+            None => {
+                self.resume();
+                0
+            }
+        };
+        self.last_advance = Some(now);
+        steps
+    }
+    
+    // Discard time accumulated before a pause, so resuming doesn't start with a burst of catch-up steps
+    pub fn resume(&mut self) {
+        self.physics_accumulator = Duration::ZERO;
+        self.interpolation_alpha = 0.0;
+    }
+    
+    // Run exactly n fixed physics steps, including the emitter, without any of the wall-clock
+    // bookkeeping of step() (the accumulator, the per-frame cap and the step timing are left
+    // alone). step() runs its steps through here, so tests exercise the same code as the app.
+    pub fn step_n(&mut self, n: usize) {
+        for _ in 0..n {
+            // Emit boids at a framerate-independent rate
+            if let Some(position) = self.emitter {
+                self.emission_accumulator += self.params.emission_rate * self.physics_step_size.as_secs_f32();
+                let count = self.emission_accumulator.floor();
+                if count >= 1.0 {
+                    self.emission_accumulator -= count;
+                    self.spawn_at(position, count as usize);
+                }
+            }
+            
+            self.step_once();
+        }
+    }
+    
+    // A digest of every boid's position and velocity, from the bit patterns of the floats
+    // (FNV-1a, which unlike the std hashers is fixed across Rust versions), for comparing
+    // runs against each other or against golden values
+    pub fn hash_state(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        
+        let values = self.flock.positions.iter().zip(&self.flock.velocities)
+            .flat_map(|(position, velocity)| [position.x, position.y, velocity.x, velocity.y]);
+        let mut hash = FNV_OFFSET;
+        for value in values {
+            for byte in value.to_bits().to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+    
+    // Run exactly one physics step, feeding a continuous export if one is running
+    pub fn step_once(&mut self) {
+        // The pool is taken out so the step can borrow the simulation inside it
+        match self.thread_pool.take() {
+            Some(pool) => {
+                pool.install(|| self.run_step());
+                self.thread_pool = Some(pool);
+            }
+            None => self.run_step(),
+        }
+    }
+    
+    // Threads the parallel physics runs on
+    pub fn thread_count(&self) -> usize {
+        self.thread_pool.as_ref().map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads())
+    }
+    
+    // Body of step_once, on whichever thread pool is current
+    fn run_step(&mut self) {
+        if self.flock.len() + self.respawn_timers.len() != self.params.num_boids {
+            self.converge_population();
+        }
+        
+        if !self.respawn_timers.is_empty() {
+            self.respawn();
+        }
+        
+        if self.params.wind_tunnel_mode {
+            self.feed_wind_tunnel();
+        }
+        
+        // Keep boids that are near in the world near in memory, before the grid is rebuilt
+        let sort_interval = self.params.spatial_sort_interval.max(1) as u64;
+        if self.params.enable_spatial_sorting && self.params.enable_spatial_grid && self.step_count.is_multiple_of(sort_interval) {
+            let sort_start = self.params.show_debug.then(Instant::now);
+            self.sort_by_cell();
+            if let Some(start) = sort_start {
+                self.physics_timings.spatial_sort += start.elapsed();
+            }
+        }
+        
+        physics::update_boids(self);
+        self.step_count += 1;
+        self.simulation_time += self.physics_step_size.as_secs_f64();
+        
+        // Dead boids are removed between steps, so indices never change within one
+        if self.params.enable_energy {
+            self.remove_dead();
+        }
+        if self.params.wind_tunnel_mode {
+            self.remove_exited();
+        }
+        
+        if let Some(exporter) = &self.exporter {
+            if exporter.wants_step(self.step_count) {
+                let records = export::collect_records(self.step_count, self.simulation_time, &self.flock, &mut self.spatial_grid, &self.params);
+                exporter.send(records);
+            }
+        }
+        
+        if self.step_count.is_multiple_of(TAG_METRICS_INTERVAL) {
+            self.update_tag_metrics();
+        }
+        
+        if let Some(streamer) = &self.streamer {
+            if streamer.wants_step(self.step_count) {
+                streamer.send_frame(self.step_count, &self.flock, self.params.world_size);
+            }
+        }
+        
+        if let Some(osc) = &mut self.osc {
+            if osc.due(Instant::now()) {
+                osc.send(osc::collect_messages(&self.params, &self.flock, self.metrics.as_ref(), self.tracked_boid));
+            }
+        }
+    }
+    
+    // Export the current state of every boid to a new file (written in the background)
+    pub fn export_frame(&mut self, path: &Path, format: export::ExportFormat) -> io::Result<()> {
+        self.attach_exporter(Exporter::start(path, format, false, 1)?);
+        Ok(())
+    }
+    
+    // Start appending every boid's state every `stride` physics steps until stop_export
+    pub fn start_continuous_export(&mut self, path: &Path, format: export::ExportFormat, stride: u64) -> io::Result<()> {
+        self.attach_exporter(Exporter::start(path, format, true, stride)?);
+        Ok(())
+    }
+    
+    // Use an already started exporter: a single-frame exporter receives the current
+    // state and is stopped, a continuous one receives batches from now on
+    pub fn attach_exporter(&mut self, mut exporter: Exporter) {
+        if !exporter.is_continuous() {
+            exporter.send(export::collect_records(self.step_count, self.simulation_time, &self.flock, &mut self.spatial_grid, &self.params));
+            exporter.stop();
+        }
+        
+        self.exporter = Some(exporter);
+    }
+    
+    // Stop a continuous export; queued rows are still written
+    pub fn stop_export(&mut self) {
+        if let Some(exporter) = &mut self.exporter {
+            exporter.stop();
+        }
+    }
+    
+    // Apply parameter changes made since the last call (e.g. from the UI) and take a
+    // new snapshot. Returns (boids_changed, physics_changed, rendering_changed, world_size_changed).
+    pub fn apply_param_changes(&mut self) -> (bool, bool, bool, bool) {
+        let changes = self.params.detect_changes();
+        let (boids_changed, physics_changed, _rendering_changed, world_size_changed) = changes;
+        
+        if boids_changed && self.params.instant_resize {
+            // Add or remove boids to match the new count, keeping the existing flock intact
+            // (otherwise step_once converges on it)
+            self.resize();
+        }
+        
+        if physics_changed || world_size_changed {
+            // Update max speed and color for all boids from their species
+            self.apply_species_params();
+            
+            // Update physics step size if FPS changed
+            self.physics_step_size = Duration::from_secs_f32(1.0 / self.params.fixed_physics_fps);
+            
+            // The infected species or the mode may have changed
+            self.rebuild_infected();
+            
+            // The wall grid's cells depend on the avoidance radius and the world size
+            self.rebuild_wall_grid();
+            
+            // The pheromone field covers the world, and only exists while enabled
+            self.update_pheromone_field();
+            
+            // Boids are fully fed whenever energy mode is off
+            if !self.params.enable_energy {
+                self.flock.energies.fill(MAX_ENERGY);
+            }
+            
+            // Perched boids take off when perching is switched off
+            if !self.params.enable_perching {
+                physics::release_perched(self);
+            }
+        }
+        
+        // If world size changed, we need to recreate the spatial grid
+        if world_size_changed {
+            self.spatial_grid = SpatialGrid::new(self.base_cell_size(), self.params.world_size);
+            
+            // Ensure all boids are within the new world bounds
+            // Boids already inside (e.g. when growing the world) are left where they are
+            let world_size = self.params.world_size;
+            let half_world = world_size / 2.0;
+            let Flock { positions, prev_positions, .. } = &mut self.flock;
+            for (position, prev_position) in positions.iter_mut().zip(prev_positions.iter_mut()) {
+                if position.x > half_world || position.x < -half_world ||
+                   position.y > half_world || position.y < -half_world {
+                    boid::wrap_into_world(position, prev_position, world_size);
+                }
+            }
+        }
+        
+        self.update_streaming();
+        self.update_osc();
+        self.update_thread_pool();
+        
+        self.params.take_snapshot();
+        
+        changes
+    }
+    
+    // Start, stop or reconfigure the network stream to match the parameters
+    pub fn update_streaming(&mut self) {
+        if !self.params.enable_streaming {
+            self.streamer = None;
+            self.stream_error = None;
+            return;
+        }
+        
+        let port = self.params.stream_port;
+        let failed_port = self.stream_error.as_ref().map(|(failed_port, _)| *failed_port);
+        if self.streamer.as_ref().is_none_or(|streamer| streamer.port() != port) && failed_port != Some(port) {
+            // Release the old port before binding the new one
+            self.streamer = None;
+            match Streamer::start(port, self.params.stream_stride as u64, self.params.stream_max_boids) {
+                Ok(streamer) => {
+                    self.streamer = Some(streamer);
+                    self.stream_error = None;
+                }
+                Err(err) => {
+                    eprintln!("warning: failed to stream on UDP port {}: {}", port, err);
+                    self.stream_error = Some((port, err.to_string()));
+                }
+            }
+        }
+        
+        if let Some(streamer) = &mut self.streamer {
+            streamer.configure(self.params.stream_stride as u64, self.params.stream_max_boids);
+        }
+    }
+    
+    // Build or drop the dedicated thread pool to match num_threads
+    pub fn update_thread_pool(&mut self) {
+        let num_threads = self.params.num_threads;
+        if num_threads == 0 {
+            self.thread_pool = None;
+            return;
+        }
+        
+        if !matches!(&self.thread_pool, Some(pool) if pool.current_num_threads() == num_threads) {
+            // The old pool's threads exit on their own once it is dropped
+            self.thread_pool = None;
+            match rayon::ThreadPoolBuilder::new().num_threads(num_threads).build() {
+                Ok(pool) => self.thread_pool = Some(pool),
+                Err(err) => eprintln!("warning: failed to start {} physics threads, using the global pool: {}", num_threads, err),
+            }
+        }
+    }
+    
+    // Start, stop or reconfigure the OSC output to match the parameters
+    pub fn update_osc(&mut self) {
+        if !self.params.enable_osc {
+            self.osc = None;
+            self.osc_error = None;
+            return;
+        }
+        
+        let (host, port) = (self.params.osc_host.trim(), self.params.osc_port);
+        if self.osc.as_ref().is_none_or(|osc| !osc.targets(host, port)) {
+            self.osc = None;
+            match OscSender::start(host, port, self.params.osc_rate) {
+                Ok(osc) => {
+                    self.osc = Some(osc);
+                    self.osc_error = None;
+                }
+                Err(err) => {
+                    // Retried with the next parameter change, but only logged once
+                    if self.osc_error.is_none() {
+                        eprintln!("warning: failed to start the OSC output: {}", err);
+                    }
+                    self.osc_error = Some(err.to_string());
+                }
+            }
+        }
+        
+        if let Some(osc) = &mut self.osc {
+            osc.configure(self.params.osc_rate);
+        }
+    }
+    
+    // Replace the walls
+    pub fn set_walls(&mut self, walls: Vec<Wall>) {
+        self.walls = walls;
+        self.rebuild_wall_grid();
+    }
+    
+    // Replace the parameter regions
+    pub fn set_regions(&mut self, regions: Vec<ParamRegion>) {
+        self.regions = regions;
+        
+        // Region indices may now mean different regions
+        self.boid_regions.clear();
+    }
+    
+    fn rebuild_wall_grid(&mut self) {
+        self.wall_grid = WallGrid::new(&self.walls, self.params.wall_avoid_radius, self.params.world_size);
+    }
+    
+    // Create or drop the pheromone field to match the parameters. A field for the
+    // right world size is kept, so toggling other parameters doesn't erase the trails.
+    fn update_pheromone_field(&mut self) {
+        let world_size = self.params.world_size;
+        if !self.params.enable_pheromones {
+            self.pheromones = None;
+        } else if self.pheromones.as_ref().is_none_or(|field| field.world_size() != world_size) {
+            self.pheromones = Some(PheromoneField::new(PHEROMONE_RESOLUTION, world_size));
+        }
+    }
+    
+    // Recreate the flock in the initial layout, with new random positions and velocities
+    pub fn reset(&mut self) {
+        let rng = &mut self.rng;
+        
+        // Recreate every boid so positions and velocities are all re-randomized
+        self.flock.clear();
+        self.neighbor_lists.invalidate();
+        self.respawn_timers.clear();
+        if let Some(field) = &mut self.pheromones {
+            field.clear();
+        }
+        // Only a reset restarts the simulation clock
+        self.step_count = 0;
+        self.simulation_time = 0.0;
+        
+        let (layout, count, world_size) = (self.params.initial_layout, self.params.num_boids, self.params.world_size);
+        let mut placement = SpacedPlacement::new(self.params.min_spawn_spacing, world_size, std::iter::empty(), count);
+        for index in 0..count {
+            self.flock.push(placement.place(|| place_boid(layout, index, count, world_size, rng)));
+        }
+        
+        // Assign species (which also sets color and max speed)
+        self.redistribute_species();
+        
+        // The new boids are untagged
+        self.tag_metrics = [None; TAG_COUNT];
+    }
+    
+    // Grow or shrink the flock to match num_boids without touching existing boids
+    pub fn resize(&mut self) {
+        self.set_flock_len(self.params.num_boids);
+    }
+    
+    // Move the flock toward num_boids by at most POPULATION_CHANGE_RATE boids per second.
+    // Boids waiting to respawn count as part of the flock; their respawns are cancelled
+    // before any live boid is removed.
+    fn converge_population(&mut self) {
+        let budget = (POPULATION_CHANGE_RATE * self.physics_step_size.as_secs_f32()).ceil() as usize;
+        let target = self.params.num_boids;
+        let len = self.flock.len();
+        let pending = self.respawn_timers.len();
+        
+        if len + pending < target {
+            // The wind tunnel fills the flock from its left edge at its own rate
+            if !self.params.wind_tunnel_mode {
+                self.set_flock_len(len + budget.min(target - len - pending));
+            }
+        } else {
+            let excess = len + pending - target;
+            let cancelled = excess.min(pending);
+            self.respawn_timers.truncate(pending - cancelled);
+            self.set_flock_len(len - budget.min(excess - cancelled));
+        }
+    }
+    
+    // Add random boids or remove boids from the end until the flock has `target` boids
+    fn set_flock_len(&mut self, target: usize) {
+        if target < self.flock.len() {
+            // Remove boids from the end
+            self.flock.truncate(target);
+            self.neighbor_lists.invalidate();
+            self.infected.retain(|&index| index < target);
+        } else {
+            // Append new randomly placed boids
+            self.push_random_boids(target - self.flock.len());
+        }
+    }
+    
+    // Add boids at random positions, kept min_spawn_spacing apart (at the left edge in
+    // wind tunnel mode)
+    fn push_random_boids(&mut self, count: usize) {
+        if self.params.wind_tunnel_mode {
+            for _ in 0..count {
+                self.push_wind_tunnel_boid();
+            }
+            return;
+        }
+        
+        let world_size = self.params.world_size;
+        let half_world = world_size / 2.0;
+        let mut placement = SpacedPlacement::new(self.params.min_spawn_spacing, world_size, self.flock.positions.iter().copied(), count);
+        for _ in 0..count {
+            let rng = &mut self.rng;
+            let mut boid = placement.place(|| {
+                let x = rng.gen_range(-half_world..half_world);
+                let y = rng.gen_range(-half_world..half_world);
+                Boid::new_with_rng(x, y, rng)
+            });
+            assign_species(&mut boid, self.flock.len(), &self.params);
+            self.push_boid(boid);
+        }
+    }
+    
+    // Add a boid on the left edge at a random height, heading right at its species' max speed
+    fn push_wind_tunnel_boid(&mut self) {
+        let half_world = self.params.world_size / 2.0;
+        let y = self.rng.gen_range(-half_world..half_world);
+        let angle = self.rng.gen_range(-WIND_TUNNEL_SPREAD..=WIND_TUNNEL_SPREAD);
+        let mut boid = Boid::new_with_rng(-half_world, y, &mut self.rng);
+        assign_species(&mut boid, self.flock.len(), &self.params);
+        boid.velocity = vec2(angle.cos(), angle.sin()) * boid.max_speed;
+        boid.prev_velocity = boid.velocity;
+        self.push_boid(boid);
+    }
+    
+    // Let boids into the wind tunnel at wind_tunnel_rate, as long as the flock (with the
+    // boids waiting to respawn) is below the target size
+    fn feed_wind_tunnel(&mut self) {
+        self.wind_tunnel_accumulator += self.params.wind_tunnel_rate * self.physics_step_size.as_secs_f32();
+        let due = self.wind_tunnel_accumulator.floor();
+        self.wind_tunnel_accumulator -= due;
+        
+        let room = self.params.num_boids.saturating_sub(self.flock.len() + self.respawn_timers.len());
+        for _ in 0..room.min(due as usize) {
+            self.push_wind_tunnel_boid();
+        }
+    }
+    
+    // Remove the boids that left the wind tunnel through its left or right edge. They
+    // aren't replaced directly: feed_wind_tunnel refills the flock at its own rate.
+    fn remove_exited(&mut self) {
+        let half_world = self.params.world_size / 2.0;
+        for index in (0..self.flock.len()).rev() {
+            if self.flock.positions[index].x.abs() > half_world {
+                let moved_from = self.remove(index);
+                self.removed_boids.push((index, moved_from));
+            }
+        }
+    }
+    
+    // Remove the boids that ran out of energy and schedule their replacements.
+    // Boids are removed from the highest index down, so the boid that swap_remove
+    // moves into a freed slot is always alive.
+    pub fn remove_dead(&mut self) {
+        for index in (0..self.flock.len()).rev() {
+            if self.flock.energies[index] <= 0.0 {
+                let moved_from = self.remove(index);
+                self.removed_boids.push((index, moved_from));
+                self.respawn_timers.push(self.params.respawn_delay);
+            }
+        }
+    }
+    
+    // Count down the respawn timers and add a new boid for each one that ran out,
+    // as long as the flock is below the target size
+    fn respawn(&mut self) {
+        let dt = self.physics_step_size.as_secs_f32();
+        let mut due = 0;
+        self.respawn_timers.retain_mut(|timer| {
+            *timer -= dt;
+            if *timer <= 0.0 {
+                due += 1;
+            }
+            *timer > 0.0
+        });
+        
+        let room = self.params.num_boids.saturating_sub(self.flock.len());
+        self.push_random_boids(due.min(room));
+    }
+    
+    // Add a burst of boids scattered around a world position. Returns how many were added.
+    pub fn spawn_at(&mut self, center: Point2, count: usize) -> usize {
+        let spread = self.params.boid_size * 4.0;
+        
+        // Don't exceed the maximum supported number of boids
+        let max_boids = *SimulationParams::get_num_boids_range().end();
+        let count = count.min(max_boids.saturating_sub(self.flock.len()));
+        
+        // Only the boids around the burst can be too close to a new boid
+        let (spacing, world_size) = (self.params.min_spawn_spacing, self.params.world_size);
+        let reach = spread * std::f32::consts::SQRT_2 + spacing;
+        let nearby = self.flock.positions.iter()
+            .copied()
+            .filter(|&position| SpatialGrid::wrapped_distance_squared(position, center, world_size) <= reach * reach);
+        let mut placement = SpacedPlacement::new(spacing, world_size, nearby, count);
+        
+        for _ in 0..count {
+            let rng = &mut self.rng;
+            let mut boid = placement.place(|| {
+                let x = center.x + rng.gen_range(-spread..spread);
+                let y = center.y + rng.gen_range(-spread..spread);
+                let mut boid = Boid::new_with_rng(x, y, rng);
+                boid.wrap_into_world(world_size);
+                boid
+            });
+            assign_species(&mut boid, self.flock.len(), &self.params);
+            self.push_boid(boid);
+        }
+        
+        // The spawned boids raise the target, so they aren't removed again
+        self.params.num_boids = (self.params.num_boids + count).min(max_boids);
+        
+        count
+    }
+    
+    // Remove a boid, moving the last boid into its slot (swap_remove).
+    // Returns the old index of the boid that was moved, if any, so callers can fix up indices.
+    pub fn delete(&mut self, index: usize) -> Option<usize> {
+        if index >= self.flock.len() {
+            return None;
+        }
+        
+        let moved_from = self.remove(index);
+        
+        // Lower the target so the boid isn't replaced
+        self.params.num_boids = self.params.num_boids.saturating_sub(1);
+        
+        moved_from
+    }
+    
+    // swap_remove a boid, keeping the indices of the infected and tracked boids pointing
+    // at the same boids. Returns the old index of the boid that was moved, if any.
+    fn remove(&mut self, index: usize) -> Option<usize> {
+        let last_index = self.flock.len() - 1;
+        self.flock.swap_remove(index);
+        self.neighbor_lists.invalidate();
+        
+        // The infected list follows the boid that moved into the deleted slot
+        self.infected.retain(|&infected| infected != index);
+        for infected in &mut self.infected {
+            if *infected == last_index {
+                *infected = index;
+            }
+        }
+        
+        if self.tracked_boid == Some(index) {
+            self.tracked_boid = None;
+        } else if self.tracked_boid == Some(last_index) {
+            self.tracked_boid = Some(index);
+        }
+        
+        if index != last_index {
+            Some(last_index)
+        } else {
+            None
+        }
+    }
+    
+    // Reorder the flock along the grid cells' Z-order curve, so the neighbor lookups of
+    // consecutive boids touch nearby memory. The infected and tracked boids and the
+    // per-boid caches follow their boids.
+    pub fn sort_by_cell(&mut self) {
+        let world_size = self.params.world_size;
+        let grid = &self.spatial_grid;
+        let keys: Vec<u64> = self.flock.positions.par_iter().map(|&position| grid.morton_key(position, world_size)).collect();
+        
+        // Stable, so boids in the same cell keep their relative order
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.par_sort_by_key(|&index| keys[index]);
+        if order.iter().enumerate().all(|(new_index, &index)| new_index == index) {
+            return;
+        }
+        
+        self.flock.permute(&order);
+        self.neighbor_lists.invalidate();
+        
+        // Per-boid values from the last step (cleared if they don't cover the flock)
+        for values in [&mut self.neighbor_counts, &mut self.grid_neighbor_counts] {
+            if values.len() == order.len() {
+                *values = order.iter().map(|&index| values[index]).collect();
+            } else {
+                values.clear();
+            }
+        }
+        if self.boid_regions.len() == order.len() {
+            self.boid_regions = order.iter().map(|&index| self.boid_regions[index]).collect();
+        } else {
+            self.boid_regions.clear();
+        }
+        
+        // Where each boid went
+        let mut new_indices = vec![0; order.len()];
+        for (new_index, &index) in order.iter().enumerate() {
+            new_indices[index] = new_index;
+        }
+        for infected in &mut self.infected {
+            *infected = new_indices[*infected];
+        }
+        self.tracked_boid = self.tracked_boid.map(|index| new_indices[index]);
+        
+        self.reordered = true;
+    }
+    
+    // Toggle whether a boid is frozen in place
+    pub fn toggle_frozen(&mut self, index: usize) {
+        if let Some(properties) = self.flock.properties.get_mut(index) {
+            properties.frozen = !properties.frozen;
+        }
+    }
+    
+    // Tag every boid inside an area of the world (wrapping around the edges), replacing
+    // their previous tags. Returns the number of boids tagged.
+    pub fn tag_boids_in(&mut self, area: Rect, tag: u8) -> usize {
+        let world_size = self.params.world_size;
+        let mut tagged = 0;
+        for (properties, &position) in self.flock.properties.iter_mut().zip(&self.flock.positions) {
+            if region::wrapped_rect_contains(&area, position, world_size) {
+                properties.tag = Some(tag);
+                tagged += 1;
+            }
+        }
+        
+        self.update_tag_metrics();
+        tagged
+    }
+    
+    pub fn clear_tags(&mut self) {
+        for properties in &mut self.flock.properties {
+            properties.tag = None;
+        }
+        self.tag_metrics = [None; TAG_COUNT];
+    }
+    
+    // Measure the tagged groups. Like an export, this refills the spatial grid with every
+    // boid's current position.
+    pub fn update_tag_metrics(&mut self) {
+        self.tag_metrics = if tags::any_tagged(&self.flock) {
+            let radius = self.params.max_perception_radius();
+            self.spatial_grid.rebuild(&self.flock.positions, self.params.world_size);
+            self.spatial_grid.set_query_radius(radius);
+            tags::tag_metrics(&self.flock, &mut self.spatial_grid, radius, self.params.world_size)
+        } else {
+            [None; TAG_COUNT]
+        };
+    }
+    
+    // Place a boid at a world position with zero velocity (used while dragging it).
+    // The previous state is snapped too so interpolation doesn't smear the jump.
+    // The spatial grid picks up the new position on its next rebuild.
+    pub fn move_boid_to(&mut self, index: usize, position: Point2) {
+        if index < self.flock.len() {
+            self.flock.positions[index] = position;
+            self.flock.velocities[index] = Vec2::ZERO;
+            self.flock.accelerations[index] = Vec2::ZERO;
+            self.flock.prev_velocities[index] = Vec2::ZERO;
+            self.flock.wrap_into_world(index, self.params.world_size);
+            // Its old neighbors are no longer near it
+            self.neighbor_lists.invalidate();
+        }
+    }
+    
+    // Re-apply species colors, max speeds and forces to all boids (after species parameters change)
+    pub fn apply_species_params(&mut self) {
+        for properties in &mut self.flock.properties {
+            apply_species(properties, &self.params);
+        }
+    }
+    
+    // Distribute all boids across the species by their shares (after species are added or
+    // removed, or the mixture changes)
+    pub fn redistribute_species(&mut self) {
+        for (i, properties) in self.flock.properties.iter_mut().enumerate() {
+            properties.species = self.params.species_for_index(i);
+            apply_species(properties, &self.params);
+        }
+        
+        self.rebuild_infected();
+    }
+    
+    // Add a boid at the end, tracking it if it belongs to the infected species
+    fn push_boid(&mut self, boid: Boid) {
+        if self.params.infected_species_id() == Some(boid.species) {
+            self.infected.push(self.flock.len());
+        }
+        self.flock.push(boid);
+        self.neighbor_lists.invalidate();
+    }
+    
+    // Find the boids of the infected species again (after species are reassigned)
+    pub fn rebuild_infected(&mut self) {
+        self.infected.clear();
+        if let Some(species) = self.params.infected_species_id() {
+            let infected = self.flock.properties.iter()
+                .enumerate()
+                .filter(|(_, properties)| properties.species == species)
+                .map(|(index, _)| index);
+            self.infected.extend(infected);
+        }
+    }
+    
+    // Convert boids to the infected species (a click seeding the infection, or contacts
+    // found by the physics pass). Boids that are already infected are skipped.
+    pub fn infect(&mut self, indices: &[usize]) {
+        let Some(species) = self.params.infected_species_id() else {
+            return;
+        };
+        
+        for &index in indices {
+            if let Some(properties) = self.flock.properties.get_mut(index) {
+                if properties.species != species {
+                    properties.species = species;
+                    apply_species(properties, &self.params);
+                    self.infected.push(index);
+                }
+            }
+        }
+    }
+    
+    // Cure the infection: every boid goes back to the base (first) species
+    pub fn cure_infection(&mut self) {
+        for properties in &mut self.flock.properties {
+            properties.species = 0;
+            apply_species(properties, &self.params);
+        }
+        
+        self.rebuild_infected();
+    }
+    
+    // Mean energy of the flock (energy mode)
+    pub fn average_energy(&self) -> Option<f32> {
+        if self.flock.is_empty() {
+            return None;
+        }
+        
+        Some(self.flock.energies.iter().sum::<f32>() / self.flock.len() as f32)
+    }
+    
+    // Number of boids in each species, indexed by species id
+    pub fn species_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.params.species.len()];
+        for properties in &self.flock.properties {
+            if let Some(count) = counts.get_mut(properties.species as usize) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+    
+    // Boid positions in physics (not interpolated) space
+    pub fn positions(&self) -> impl Iterator<Item = Point2> + '_ {
+        self.flock.positions.iter().copied()
+    }
+    
+    // Boid velocities in physics (not interpolated) space
+    pub fn velocities(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.flock.velocities.iter().copied()
+    }
+    
+    // Wrap-aware centroid of the whole flock
+    pub fn centroid(&self) -> Option<Point2> {
+        physics::wrapped_centroid(self.positions(), self.params.world_size)
+    }
+    
+    // Vicsek order parameter of the whole flock (see physics::order_parameter)
+    pub fn order_parameter(&self) -> Option<f32> {
+        physics::order_parameter(self.velocities())
+    }
+    
+    // Widest perception radius, including radii widened by regions: the grid's neighbor
+    // lookups must reach this far
+    pub fn max_query_radius(&self) -> f32 {
+        self.params.max_perception_radius().max(region::max_override_radius(&self.regions))
+    }
+    
+    // Grid cell size for the widest perception radius (the cell size factor applied)
+    pub fn base_cell_size(&self) -> f32 {
+        self.max_query_radius() * self.params.cell_size_factor
+    }
+    
+    // Update the spatial grid cell size based on boid density
+    pub fn update_adaptive_cell_size(&mut self) {
+        // Average number of neighbors per boid, over the whole flock's last grid lookups
+        let avg_neighbors = match NeighborHistogram::from_counts(&self.grid_neighbor_counts) {
+            Some(histogram) => histogram.mean,
+            None => return, // No boids (or no grid lookups) to measure
+        };
+        
+        // Adjust cell size based on average neighbors
+        // Target: around 10-20 neighbors per cell for optimal performance
+        let target_neighbors = 15.0;
+        let current_cell_size = self.spatial_grid.min_cell_size;
+        
+        let mut new_cell_size = if avg_neighbors > target_neighbors * 1.5 {
+            // Too many neighbors, decrease cell size
+            current_cell_size * 0.9
+        } else if avg_neighbors < target_neighbors * 0.5 {
+            // Too few neighbors, increase cell size
+            current_cell_size * 1.1
+        } else {
+            // Good range, keep current size
+            current_cell_size
+        };
+        
+        // Sparse flocks (down to a single boid) would grow the cells forever, so stop at three
+        // cells per side, where the 3x3 neighborhood still visits each cell once
+        new_cell_size = new_cell_size.min(self.params.world_size / 3.0);
+        
+        // Ensure cell size is at least the maximum perception radius, the same floor the
+        // physics uses, so the two never take turns recreating the grid
+        new_cell_size = f32::max(new_cell_size, self.base_cell_size());
+        
+        // Only recreate grid if cell size changed significantly (less than the 10% step,
+        // which f32 rounding can make come out at exactly 10%)
+        if (new_cell_size - current_cell_size).abs() > current_cell_size * 0.05 {
+            self.rebuild_spatial_grid(new_cell_size);
+        }
+    }
+    
+    // Replace the spatial grid with one of the given cell size holding every boid, so that
+    // anything reading it before the next step (e.g. the published grid statistics) still
+    // sees the whole flock
+    fn rebuild_spatial_grid(&mut self, cell_size: f32) {
+        let world_size = self.params.world_size;
+        self.spatial_grid = SpatialGrid::new(cell_size, world_size);
+        for (i, &position) in self.flock.positions.iter().enumerate() {
+            self.spatial_grid.insert(i, position, world_size);
+        }
+    }
+}
+
+// Speed of the boids placed by the layouts (the speed Boid::new gives them)
+const INITIAL_SPEED: f32 = 2.0;
+
+// Boid `index` of `count` in a freshly reset flock with the given layout
+fn place_boid<R: Rng>(layout: InitialLayout, index: usize, count: usize, world_size: f32, rng: &mut R) -> Boid {
+    let random_heading = |rng: &mut R| {
+        let angle = rng.gen_range(0.0..TAU);
+        vec2(angle.cos(), angle.sin())
+    };
+    
+    let (position, heading) = match layout {
+        InitialLayout::RandomUniform => {
+            // Anywhere in the world, with the random velocity Boid::new_with_rng gives it
+            let half_world = world_size / 2.0;
+            let x = rng.gen_range(-half_world..half_world);
+            let y = rng.gen_range(-half_world..half_world);
+            return Boid::new_with_rng(x, y, rng);
+        }
+        InitialLayout::Ring => {
+            // Counter-clockwise around the circle, with a little radial jitter
+            let angle = index as f32 / count as f32 * TAU;
+            let radius = world_size * 0.35 * rng.gen_range(0.98..1.02);
+            let outward = vec2(angle.cos(), angle.sin());
+            (outward * radius, outward.perp())
+        }
+        InitialLayout::Grid => {
+            // Rows of `side` boids filling the middle 80% of the world
+            let side = (count as f32).sqrt().ceil() as usize;
+            let spacing = world_size * 0.8 / side as f32;
+            let cell = |coordinate: usize| (coordinate as f32 + 0.5) * spacing - world_size * 0.4;
+            (pt2(cell(index % side), cell(index / side)), random_heading(rng))
+        }
+        InitialLayout::TwoStreams => {
+            // Even indices start in a band on the left heading right, odd ones on the right heading left
+            let side = if index.is_multiple_of(2) { -1.0 } else { 1.0 };
+            let x = side * rng.gen_range(world_size * 0.1..world_size * 0.4);
+            let y = rng.gen_range(-world_size * 0.15..world_size * 0.15);
+            (pt2(x, y), vec2(-side, 0.0))
+        }
+        InitialLayout::ClusterBurst => {
+            // Scattered within a small disc, each flying away from the center
+            let heading = random_heading(rng);
+            let distance = world_size * 0.02 * rng.gen_range(0.0f32..1.0).sqrt();
+            (heading * distance, heading)
+        }
+    };
+    
+    let mut boid = Boid::new_with_rng(position.x, position.y, rng);
+    boid.velocity = heading * INITIAL_SPEED;
+    boid.prev_velocity = boid.velocity;
+    boid
+}
+
+// Assign a new boid's species by index, following the species' shares, and apply that
+// species' color, max speed and max force
+fn assign_species(boid: &mut Boid, index: usize, params: &SimulationParams) {
+    boid.species = params.species_for_index(index);
+    let species = &params.species[boid.species as usize];
+    boid.color = rgb(species.color[0], species.color[1], species.color[2]);
+    boid.max_speed = species.max_speed;
+    boid.max_force = species.max_force;
+}
+
+// Apply the color, max speed and max force of the boid's species
+fn apply_species(properties: &mut BoidProperties, params: &SimulationParams) {
+    let species = &params.species[(properties.species as usize).min(params.species.len() - 1)];
+    properties.color = rgb(species.color[0], species.color[1], species.color[2]);
+    properties.max_speed = species.max_speed;
+    properties.max_force = species.max_force;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breeds;
+    use crate::culling;
+    use crate::params::DEFAULT_MAX_FORCE;
+
+    fn small_simulation() -> Simulation {
+        let mut params = SimulationParams::default();
+        params.num_boids = 10;
+        params.max_steps_per_frame = 5;
+        Simulation::with_seed(params, 1)
+    }
+
+    #[test]
+    fn step_is_capped_and_drops_the_backlog() {
+        let mut sim = small_simulation();
+        let step_size = sim.physics_step_size;
+
+        // A frame long enough for 20 steps only runs the capped number
+        let steps = sim.step(step_size * 20);
+
+        assert_eq!(steps, 5);
+        assert!(sim.steps_capped);
+        assert!(sim.physics_accumulator < step_size);
+        // The 15 steps that didn't run are dropped (up to f32 rounding of the scaled time)
+        let discarded = (sim.dropped_time + sim.physics_accumulator).as_secs_f64();
+        assert!((discarded - (step_size * 15).as_secs_f64()).abs() < 1e-5, "discarded {} s", discarded);
+    }
+
+    #[test]
+    fn resume_discards_accumulated_time() {
+        let mut sim = small_simulation();
+        sim.physics_accumulator = sim.physics_step_size * 3;
+
+        sim.resume();
+
+        assert_eq!(sim.step(Duration::ZERO), 0);
+        assert_eq!(sim.dropped_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn first_advance_after_a_long_pause_runs_at_most_one_step() {
+        let mut sim = small_simulation();
+        let step_size = sim.physics_step_size;
+        let start = Instant::now();
+
+        assert_eq!(sim.advance(start), 0);
+        assert_eq!(sim.advance(start + step_size * 5 / 2), 2);
+
+        // Pause for a minute, with part of a step left in the accumulator
+        sim.physics_accumulator = step_size / 2;
+        sim.params.pause_simulation = true;
+        assert_eq!(sim.advance(start + step_size * 4), 0);
+        sim.params.pause_simulation = false;
+        let resumed = start + Duration::from_secs(60);
+
+        // The paused minute is not caught up, nor is the time before the pause
+        assert!(sim.advance(resumed) <= 1);
+        assert_eq!(sim.advance(resumed + step_size * 3 / 2), 1);
+        assert!(!sim.steps_capped);
+        assert_eq!(sim.dropped_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn infection_spreads_only_within_the_contact_radius() {
+        let mut sim = small_simulation();
+        sim.params.add_species();
+        sim.params.infection_mode = true;
+        sim.params.infected_species = 1;
+        sim.cure_infection();
+
+        // Boid 1 touches boid 0; the rest are spread far apart
+        for i in 0..sim.flock.len() {
+            sim.flock.positions[i] = pt2(-2000.0 + i as f32 * 400.0, 1000.0);
+        }
+        sim.flock.positions[0] = pt2(0.0, 0.0);
+        sim.flock.positions[1] = pt2(5.0, 0.0);
+
+        sim.infect(&[0]);
+        sim.step_once();
+
+        assert_eq!(sim.species_counts(), vec![8, 2]);
+        assert_eq!(sim.flock.properties[1].species, 1);
+        assert_eq!(sim.infected, vec![0, 1]);
+
+        sim.cure_infection();
+
+        assert_eq!(sim.species_counts(), vec![10, 0]);
+        assert!(sim.infected.is_empty());
+    }
+
+    #[test]
+    fn breeds_set_the_mixture_and_each_boids_limits() {
+        let mut params = SimulationParams::default();
+        params.num_boids = 200;
+        params.set_breeds(breeds::parse("[sparrow]\nshare = 70\n[hawk]\nmax_speed = 7\nmax_force = 0.3\nshare = 30\n").unwrap());
+        let mut sim = Simulation::with_seed(params, 1);
+
+        let counts = sim.species_counts();
+        assert!((counts[0] as i32 - 140).abs() <= 3, "{:?}", counts);
+        assert_eq!(counts[0] + counts[1], 200);
+        let sparrow_speed = SimulationParams::default().max_speed;
+        for properties in &sim.flock.properties {
+            let expected = if properties.species == 1 { (7.0, 0.3) } else { (sparrow_speed, DEFAULT_MAX_FORCE) };
+            assert_eq!((properties.max_speed, properties.max_force), expected);
+        }
+
+        // Boids added later follow the mixture too
+        sim.params.num_boids = 400;
+        sim.params.instant_resize = true;
+        sim.apply_param_changes();
+        let counts = sim.species_counts();
+        assert!((counts[1] as i32 - 120).abs() <= 3, "{:?}", counts);
+    }
+
+    #[test]
+    fn tags_survive_parameter_changes_but_not_a_reset() {
+        let mut sim = small_simulation();
+        for i in 0..sim.flock.len() {
+            sim.flock.positions[i] = pt2(-450.0 + i as f32 * 100.0, 0.0);
+        }
+
+        // The left half of the world
+        let half_world = sim.params.world_size / 2.0;
+        let area = Rect::from_corners(pt2(-half_world, -half_world), pt2(0.0, half_world));
+        assert_eq!(sim.tag_boids_in(area, 1), 5);
+        assert_eq!(sim.tag_metrics[1].unwrap().count, 5);
+        let tagged_ids = |sim: &Simulation| {
+            let mut ids: Vec<_> = sim.flock.ids.iter().zip(&sim.flock.properties)
+                .filter(|(_, properties)| properties.tag == Some(1))
+                .map(|(&id, _)| id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        let tagged = tagged_ids(&sim);
+
+        sim.params.separation_weight *= 2.0;
+        sim.params.world_size *= 1.5;
+        sim.apply_param_changes();
+        sim.step_once();
+        assert_eq!(tagged_ids(&sim), tagged);
+
+        sim.reset();
+        assert!(sim.flock.properties.iter().all(|properties| properties.tag.is_none()));
+        assert_eq!(sim.tag_metrics, [None; TAG_COUNT]);
+    }
+
+    #[test]
+    fn sorting_by_cell_keeps_boids_and_their_references_together() {
+        let mut params = SimulationParams::default();
+        params.num_boids = 200;
+        let mut sim = Simulation::with_seed(params, 3);
+        sim.infected = vec![5, 120];
+        sim.tracked_boid = Some(42);
+        let id_at = |sim: &Simulation, index: usize| sim.flock.ids[index];
+        let (infected_ids, tracked_id) = ([id_at(&sim, 5), id_at(&sim, 120)], id_at(&sim, 42));
+        let position_of_tracked = sim.flock.positions[42];
+
+        sim.sort_by_cell();
+
+        // The flock is in Z-order, and every reference still points at the same boid
+        let keys: Vec<u64> = sim.flock.positions.iter().map(|&position| sim.spatial_grid.morton_key(position, sim.params.world_size)).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(sim.reordered);
+        assert_eq!([id_at(&sim, sim.infected[0]), id_at(&sim, sim.infected[1])], infected_ids);
+        let tracked = sim.tracked_boid.unwrap();
+        assert_eq!(id_at(&sim, tracked), tracked_id);
+        assert_eq!(sim.flock.positions[tracked], position_of_tracked);
+
+        // Sorting a sorted flock changes nothing
+        sim.reordered = false;
+        sim.sort_by_cell();
+        assert!(!sim.reordered);
+    }
+
+    // A freshly reset flock of 400 boids in the given layout
+    fn reset_in_layout(layout: InitialLayout, seed: u64) -> Simulation {
+        let mut params = SimulationParams::default();
+        params.num_boids = 400;
+        params.initial_layout = layout;
+        Simulation::with_seed(params, seed)
+    }
+
+    #[test]
+    fn every_layout_places_the_whole_flock_inside_the_world() {
+        for layout in InitialLayout::ALL {
+            let sim = reset_in_layout(layout, 3);
+            let half_world = sim.params.world_size / 2.0;
+
+            assert_eq!(sim.flock.len(), 400, "{:?}", layout);
+            for (position, velocity) in sim.flock.positions.iter().zip(&sim.flock.velocities) {
+                assert!(position.x.abs() <= half_world && position.y.abs() <= half_world, "{:?} placed a boid at {:?}", layout, position);
+                assert!((velocity.length() - INITIAL_SPEED).abs() < 1e-4);
+            }
+
+            // The same seed gives the same flock
+            assert_eq!(reset_in_layout(layout, 3).flock.positions, sim.flock.positions);
+        }
+    }
+
+    #[test]
+    fn structured_layouts_set_up_their_motion() {
+        let flock = reset_in_layout(InitialLayout::Ring, 1).flock;
+        for (position, velocity) in flock.positions.iter().zip(&flock.velocities) {
+            // Tangential, and all turning the same way
+            assert!(position.normalize().dot(*velocity).abs() < 1e-3);
+            assert!(position.perp_dot(*velocity) > 0.0);
+        }
+
+        let flock = reset_in_layout(InitialLayout::TwoStreams, 1).flock;
+        for (position, velocity) in flock.positions.iter().zip(&flock.velocities) {
+            // Each group heads toward the other
+            assert!(position.x * velocity.x < 0.0);
+            assert_eq!(velocity.y, 0.0);
+        }
+
+        let sim = reset_in_layout(InitialLayout::ClusterBurst, 1);
+        let cluster_radius = sim.params.world_size * 0.02;
+        for (position, velocity) in sim.flock.positions.iter().zip(&sim.flock.velocities) {
+            // Close to the center, flying away from it
+            assert!(position.length() <= cluster_radius + 1e-3);
+            assert!(position.dot(*velocity) >= 0.0);
+        }
+
+        // No two boids share a lattice point
+        let flock = reset_in_layout(InitialLayout::Grid, 1).flock;
+        let mut points: Vec<(i32, i32)> = flock.positions.iter().map(|position| (position.x as i32, position.y as i32)).collect();
+        points.sort_unstable();
+        points.dedup();
+        assert_eq!(points.len(), flock.len());
+    }
+
+    #[test]
+    fn the_flock_converges_on_a_new_target_gradually() {
+        let mut sim = small_simulation();
+        let budget = (POPULATION_CHANGE_RATE * sim.physics_step_size.as_secs_f32()).ceil() as usize;
+
+        sim.params.num_boids = 10 + 3 * budget;
+        sim.apply_param_changes();
+        assert_eq!(sim.flock.len(), 10);
+
+        sim.step_once();
+        assert_eq!(sim.flock.len(), 10 + budget);
+        sim.step_once();
+        sim.step_once();
+        assert_eq!(sim.flock.len(), 10 + 3 * budget);
+
+        // Shrinking cancels pending respawns before removing live boids
+        sim.respawn_timers = vec![1.0; 2];
+        sim.params.num_boids = 10 + 3 * budget + 1;
+        sim.apply_param_changes();
+        sim.step_once();
+        assert_eq!(sim.respawn_timers.len(), 1);
+        assert_eq!(sim.flock.len(), 10 + 3 * budget);
+
+        sim.params.num_boids = 10;
+        sim.apply_param_changes();
+        sim.step_once();
+        assert!(sim.respawn_timers.is_empty());
+        assert_eq!(sim.flock.len(), 10 + 2 * budget);
+    }
+
+    #[test]
+    fn instant_resize_applies_the_target_at_once() {
+        let mut sim = small_simulation();
+        sim.params.instant_resize = true;
+        sim.params.num_boids = 5000;
+        sim.apply_param_changes();
+
+        assert_eq!(sim.flock.len(), 5000);
+    }
+
+    #[test]
+    fn spawning_and_deleting_move_the_target() {
+        let mut sim = small_simulation();
+        sim.spawn_at(pt2(0.0, 0.0), 5);
+        sim.delete(0);
+        assert_eq!(sim.params.num_boids, 14);
+
+        // The flock stays at its new size
+        sim.step_once();
+        assert_eq!(sim.flock.len(), 14);
+    }
+
+    #[test]
+    fn boid_ids_survive_deletes_and_are_not_reused() {
+        let mut sim = small_simulation();
+        let last_id = sim.flock.ids[9];
+
+        sim.delete(3);
+
+        // The last boid moved into the deleted slot but kept its id
+        assert_eq!(sim.flock.ids[3], last_id);
+        assert_eq!(sim.flock.get(3).unwrap().id, last_id);
+
+        // Boids added later get ids no other boid had
+        sim.flock.push(sim.flock.get(0).unwrap());
+        assert!(sim.flock.ids[..9].iter().all(|&id| id < sim.flock.ids[9]));
+    }
+
+    #[test]
+    fn wind_tunnel_boids_enter_at_the_left_edge_and_leave_through_the_sides() {
+        let mut sim = small_simulation();
+        sim.params.wind_tunnel_mode = true;
+        sim.params.wind_tunnel_rate = 2000.0; // More than the 10 missing boids per step
+        sim.params.num_boids = 20;
+        sim.params.enable_separation = false;
+        sim.params.enable_alignment = false;
+        sim.params.enable_cohesion = false;
+        sim.apply_param_changes();
+        let half = sim.params.world_size / 2.0;
+
+        // About to leave through the right, left and top edges, and the rest at rest
+        let starts = [(pt2(half - 1.0, 0.0), vec2(5.0, 0.0)), (pt2(-half + 1.0, 0.0), vec2(-5.0, 0.0)), (pt2(0.0, half - 1.0), vec2(0.0, 5.0))];
+        for index in 0..sim.flock.len() {
+            let (position, velocity) = starts.get(index).copied().unwrap_or((pt2(0.0, index as f32 * 10.0), Vec2::ZERO));
+            sim.flock.positions[index] = position;
+            sim.flock.velocities[index] = velocity;
+        }
+        let (right_id, left_id, top_id) = (sim.flock.ids[0], sim.flock.ids[1], sim.flock.ids[2]);
+        let first_new_id = sim.flock.ids.iter().max().unwrap() + 1;
+
+        sim.step_once();
+
+        // Both boids that crossed a side edge are gone; the top edge still wraps
+        assert!(!sim.flock.ids.contains(&right_id));
+        assert!(!sim.flock.ids.contains(&left_id));
+        let top = sim.flock.ids.iter().position(|&id| id == top_id).unwrap();
+        assert!(sim.flock.positions[top].y < -half + 5.0);
+        assert_eq!(sim.removed_boids.len(), 2);
+
+        // The flock was filled up to the target from the left edge, heading right
+        assert_eq!(sim.flock.len(), 18);
+        let new_boids: Vec<usize> = (0..sim.flock.len()).filter(|&index| sim.flock.ids[index] >= first_new_id).collect();
+        assert_eq!(new_boids.len(), 10);
+        for index in new_boids {
+            assert!(sim.flock.positions[index].x <= -half + sim.flock.properties[index].max_speed);
+            assert!(sim.flock.velocities[index].x > 0.0);
+        }
+
+        // The boids that left are replaced on the next step
+        sim.step_once();
+        assert_eq!(sim.flock.len(), 20);
+    }
+
+    #[test]
+    fn perching_boids_land_in_the_ground_band_and_take_off_after_their_rest() {
+        let mut sim = small_simulation();
+        sim.params.enable_perching = true;
+        sim.params.perch_chance = 1.0;
+        sim.params.perch_min_rest = 0.5;
+        sim.params.perch_max_rest = 0.5;
+        sim.params.enable_separation = false;
+        sim.params.enable_alignment = false;
+        sim.params.enable_cohesion = false;
+        sim.apply_param_changes();
+        let band_top = -sim.params.world_size / 2.0 + sim.params.perch_band_height;
+        let rest_steps = (0.5 / sim.physics_step_size.as_secs_f32()).round() as u64;
+
+        // One boid is about to fly down into the band, the others hover above it
+        for index in 0..sim.flock.len() {
+            sim.flock.positions[index] = pt2(index as f32 * 20.0, band_top + 50.0);
+            sim.flock.velocities[index] = Vec2::ZERO;
+        }
+        sim.flock.positions[0] = pt2(0.0, band_top + 1.0);
+        sim.flock.velocities[0] = vec2(0.0, -3.0);
+
+        sim.step_once();
+        let landed = sim.flock.positions[0];
+        assert!(landed.y < band_top);
+        assert_eq!(sim.flock.velocities[0], Vec2::ZERO);
+        assert_eq!(sim.flock.properties[0].perched_until, Some(rest_steps));
+        assert!(sim.flock.properties[1..].iter().all(|properties| properties.perched_until.is_none()));
+
+        // It rests in place until its rest is over, then takes off upward
+        for _ in 1..rest_steps {
+            sim.step_once();
+        }
+        assert_eq!(sim.flock.positions[0], landed);
+        assert!(sim.flock.properties[0].is_stationary());
+        sim.step_once();
+        assert!(sim.flock.properties[0].perched_until.is_none());
+        assert!(sim.flock.velocities[0].y > 0.0);
+
+        // Switching perching off launches every perched boid at once
+        sim.flock.properties[1].perched_until = Some(sim.step_count + 1000);
+        sim.flock.velocities[1] = Vec2::ZERO;
+        sim.params.enable_perching = false;
+        sim.apply_param_changes();
+        assert!(sim.flock.properties[1].perched_until.is_none());
+        assert!(sim.flock.velocities[1].y > 0.0);
+    }
+
+    #[test]
+    fn starved_boids_are_removed_and_respawned_after_the_delay() {
+        let mut sim = small_simulation();
+        sim.params.enable_energy = true;
+        sim.params.energy_drain_rate = 0.1;
+        sim.params.energy_neighbor_threshold = 100; // Nobody is flocking
+        sim.params.respawn_delay = 0.5;
+        sim.flock.energies[3] = 0.001;
+        sim.tracked_boid = Some(9);
+
+        sim.step_once();
+
+        // The last boid took the starved boid's slot, and the target size is kept
+        assert_eq!(sim.flock.len(), 9);
+        assert_eq!(sim.removed_boids, vec![(3, Some(9))]);
+        assert_eq!(sim.tracked_boid, Some(3));
+        assert_eq!(sim.respawn_timers.len(), 1);
+        assert_eq!(sim.params.num_boids, 10);
+
+        let delay_steps = (0.5 / sim.physics_step_size.as_secs_f32()).ceil() as usize;
+        for _ in 0..delay_steps - 1 {
+            sim.step_once();
+        }
+        assert_eq!(sim.flock.len(), 9);
+
+        sim.step_once();
+        sim.step_once();
+
+        assert_eq!(sim.flock.len(), 10);
+        assert!(sim.respawn_timers.is_empty());
+        assert!(sim.flock.energies[9] > 0.95, "respawned with {}", sim.flock.energies[9]);
+    }
+
+    #[test]
+    fn the_simulation_clock_survives_parameter_changes_until_a_reset() {
+        let mut sim = small_simulation();
+        sim.step_n(10);
+
+        // Parameter changes keep the counters, and a new step size only changes the later steps
+        let step = sim.physics_step_size.as_secs_f64();
+        sim.params.separation_weight *= 2.0;
+        sim.apply_param_changes();
+        sim.physics_step_size /= 2;
+        sim.step_n(10);
+
+        assert_eq!(sim.step_count, 20);
+        let expected = 10.0 * step + 10.0 * sim.physics_step_size.as_secs_f64();
+        assert!((sim.simulation_time - expected).abs() < 1e-9, "{} vs {}", sim.simulation_time, expected);
+
+        sim.reset();
+        assert_eq!(sim.step_count, 0);
+        assert_eq!(sim.simulation_time, 0.0);
+    }
+
+    #[test]
+    fn stepping_by_count_matches_the_frame_loop() {
+        let mut stepped = small_simulation();
+        let mut framed = small_simulation();
+        assert_eq!(stepped.hash_state(), framed.hash_state());
+
+        // Four frames of a step each equal four counted steps (the frames are a hair longer,
+        // so the time scale's float rounding can't leave them a hair short)
+        stepped.step_n(4);
+        let step_size = framed.physics_step_size;
+        for _ in 0..4 {
+            assert_eq!(framed.step(step_size + Duration::from_micros(1)), 1);
+        }
+        assert_eq!(stepped.step_count, 4);
+        assert_eq!(stepped.hash_state(), framed.hash_state());
+
+        // Counted steps ignore the frame accumulator and cap
+        framed.physics_accumulator = step_size / 2;
+        framed.step_n(10);
+        stepped.step_n(10);
+        assert_eq!(framed.physics_accumulator, step_size / 2);
+        assert_eq!(stepped.hash_state(), framed.hash_state());
+
+        // Any change to a single bit of the state changes the digest
+        let hash = stepped.hash_state();
+        stepped.flock.velocities[3].x = f32::from_bits(stepped.flock.velocities[3].x.to_bits() ^ 1);
+        assert_ne!(stepped.hash_state(), hash);
+    }
+
+    #[test]
+    fn the_thread_pool_follows_the_thread_count() {
+        let mut sim = small_simulation();
+        assert!(sim.thread_pool.is_none());
+
+        sim.params.num_threads = 2;
+        sim.apply_param_changes();
+        assert_eq!(sim.thread_count(), 2);
+        sim.step_n(3);
+        assert_eq!(sim.step_count, 3);
+        assert!(sim.flock.positions.iter().all(|position| position.is_finite()));
+
+        // Back to rayon's global pool
+        sim.params.num_threads = 0;
+        sim.apply_param_changes();
+        assert!(sim.thread_pool.is_none());
+        assert_eq!(sim.thread_count(), rayon::current_num_threads());
+    }
+
+    #[test]
+    fn adapting_the_cell_size_keeps_the_boids_in_the_grid() {
+        let mut sim = small_simulation();
+        sim.step(sim.physics_step_size);
+        let cell_size = sim.spatial_grid.min_cell_size;
+
+        // Sparse neighborhoods grow the cells
+        sim.grid_neighbor_counts = vec![1; sim.flock.len()];
+        sim.update_adaptive_cell_size();
+        assert!(sim.spatial_grid.min_cell_size > cell_size);
+
+        // Culling right after the resize still finds every boid
+        let world_size = sim.params.world_size;
+        let boids: Vec<Boid> = sim.flock.iter().collect();
+        let world = Rect::from_w_h(world_size, world_size);
+        let mut visible = culling::cull_with_spatial_grid(&boids, &sim.spatial_grid, &sim.params, 1.0, world);
+        visible.sort_unstable();
+        assert_eq!(visible, (0..boids.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reset_and_spawning_keep_new_boids_the_spacing_apart() {
+        // Packed into a disc a fiftieth of the world wide, where random placement overlaps
+        let mut params = SimulationParams::default();
+        params.num_boids = 200;
+        params.initial_layout = InitialLayout::ClusterBurst;
+        params.min_spawn_spacing = 5.0;
+        let mut sim = Simulation::with_seed(params, 2);
+        assert_eq!(sim.spawn_at(pt2(1000.0, 1000.0), 10), 10);
+
+        let world_size = sim.params.world_size;
+        let positions = &sim.flock.positions;
+        for (i, &a) in positions.iter().enumerate() {
+            for &b in &positions[i + 1..] {
+                assert!(SpatialGrid::wrapped_distance_squared(a, b, world_size) >= 25.0);
+            }
+        }
+    }
+
+    // Distance between the centroids of two groups of 30 boids that start 1200 units apart,
+    // closest over `steps` steps
+    fn closest_group_approach(far_cohesion_weight: f32, steps: usize) -> f32 {
+        let mut params = SimulationParams::default();
+        params.num_boids = 60;
+        params.world_size = 4000.0;
+        params.far_cohesion_weight = far_cohesion_weight;
+        params.far_cohesion_radius = 1500.0;
+        let mut sim = Simulation::with_seed(params, 1);
+        for index in 0..60 {
+            let side = if index < 30 { -600.0 } else { 600.0 };
+            let slot = index % 30;
+            sim.flock.positions[index] = pt2(side + (slot % 6) as f32 * 10.0, (slot / 6) as f32 * 10.0);
+            sim.flock.velocities[index] = Vec2::ZERO;
+        }
+
+        let world_size = sim.params.world_size;
+        let mut closest = f32::MAX;
+        for _ in 0..steps {
+            sim.step_once();
+            let left = physics::wrapped_centroid(sim.flock.positions[..30].iter().copied(), world_size).unwrap();
+            let right = physics::wrapped_centroid(sim.flock.positions[30..].iter().copied(), world_size).unwrap();
+            closest = closest.min(SpatialGrid::wrapped_distance_squared(left, right, world_size).sqrt());
+        }
+        closest
+    }
+
+    #[test]
+    fn far_cohesion_merges_groups_that_cannot_see_each_other() {
+        // Without it the groups are far outside each other's perception radii
+        assert!(closest_group_approach(0.0, 500) > 1000.0);
+        let merged = closest_group_approach(1.0, 500);
+        assert!(merged < 200.0, "groups came within {}", merged);
+    }
+
+    #[test]
+    fn the_far_cohesion_preset_loads() {
+        let mut params = SimulationParams::default();
+        params.apply_preset(include_str!("../presets/far_cohesion.toml")).unwrap();
+        assert!(params.far_cohesion_weight > 0.0);
+        assert_eq!(params.num_boids, 100000);
+    }
+
+    #[test]
+    fn tiny_flocks_in_the_smallest_world_step_without_duplicate_neighbors() {
+        let world_size = *SimulationParams::get_world_size_range().start();
+        for num_boids in [0, 1, 2] {
+            for (enable_spatial_grid, enable_parallel) in [(true, true), (true, false), (false, false)] {
+                let mut params = SimulationParams::default();
+                params.num_boids = num_boids;
+                params.world_size = world_size;
+                params.show_debug = true;
+                params.adaptive_cell_sizing = true;
+                params.enable_spatial_grid = enable_spatial_grid;
+                params.enable_parallel = enable_parallel;
+                let mut sim = Simulation::with_seed(params, 1);
+
+                for _ in 0..20 {
+                    sim.step_once();
+                    sim.update_adaptive_cell_size();
+                }
+
+                assert_eq!(sim.flock.len(), num_boids);
+                assert!(sim.flock.positions.iter().all(|position| position.is_finite()));
+                // Each boid sees every other boid at most once
+                assert!(sim.grid_neighbor_counts.iter().all(|&count| count < num_boids.max(1)));
+                assert_eq!(sim.centroid().is_some(), num_boids > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn a_lone_boid_stops_growing_the_cells() {
+        let mut params = SimulationParams::default();
+        params.num_boids = 1;
+        params.world_size = *SimulationParams::get_world_size_range().start();
+        params.adaptive_cell_sizing = true;
+        let mut sim = Simulation::with_seed(params, 1);
+
+        // No neighbors asks for larger cells every time, up to three cells per side
+        for _ in 0..50 {
+            sim.step_once();
+            sim.update_adaptive_cell_size();
+        }
+        let cell_size = sim.spatial_grid.min_cell_size;
+        assert!(sim.spatial_grid.grid_size >= 3);
+        sim.step_once();
+        sim.update_adaptive_cell_size();
+        assert_eq!(sim.spatial_grid.min_cell_size, cell_size);
+    }
+}
@@ -14,11 +14,21 @@
  * - Supporting world wrapping for seamless edge transitions
  * - Using a more efficient cell lookup strategy for wrapped worlds
  * - Optimizing empty cell handling with occupancy tracking
- * - Using adaptive cell checking based on local density
+ * 
+ * The world is tiled by whole cells: when the world size isn't a multiple of the requested
+ * cell size the cells are widened rather than leaving a narrow partial cell at the seam,
+ * whose neighborhood wouldn't reach a full cell across the world edge.
+ * 
+ * Neighbor queries search as many rings of cells around a boid's cell as the query radius
+ * needs (one ring, the 3x3 neighborhood, while the cells are at least as wide as the
+ * radius), so the cell size only affects the speed of the lookups, never their results.
  */
 
 use nannou::prelude::*;
 
+// Most cells per side of the grid
+pub const MAX_GRID_SIZE: usize = 1024;
+
 /// A neighbor entry with pre-computed distance information
 #[derive(Clone, Copy)]
 pub struct NeighborEntry {
@@ -26,27 +36,118 @@ pub struct NeighborEntry {
     pub distance_squared: f32,
 }
 
+// The neighbor lists of a whole flock in one buffer: boid i's neighbors are
+// entries[offsets[i]..offsets[i + 1]]. Kept across steps, so gathering the lists
+// doesn't allocate once the buffers are large enough, and so the lists themselves can be
+// reused for a few steps (see SimulationParams::neighbor_list_interval).
+#[derive(Default)]
+pub struct NeighborLists {
+    entries: Vec<NeighborEntry>,
+    offsets: Vec<usize>,
+    // Step the lists were gathered at and the radius they were gathered within
+    // (None once they no longer match the flock)
+    gathered: Option<(u64, f32)>,
+}
+
+impl NeighborLists {
+    // Forget every list, keeping the buffers
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.offsets.clear();
+        self.offsets.push(0);
+        self.gathered = None;
+    }
+    
+    // Record that the lists were just gathered for a step, within a query radius
+    pub fn mark_gathered(&mut self, step: u64, query_radius: f32) {
+        self.gathered = Some((step, query_radius));
+    }
+    
+    // Stop the lists from being reused (boids were added, removed, reordered or moved by hand)
+    pub fn invalidate(&mut self) {
+        self.gathered = None;
+    }
+    
+    // Whether the lists may stand in for gathering them again at a step: they were gathered
+    // fewer than interval steps before it, for the same radius and number of boids
+    pub fn reusable(&self, step: u64, interval: u64, query_radius: f32, len: usize) -> bool {
+        let recent = matches!(self.gathered, Some((gathered_step, radius))
+            if step >= gathered_step && step - gathered_step < interval && radius == query_radius);
+        recent && self.len() == len
+    }
+    
+    // Add the next boid's neighbors
+    pub fn push(&mut self, neighbors: &[NeighborEntry]) {
+        if self.offsets.is_empty() {
+            self.offsets.push(0);
+        }
+        self.entries.extend_from_slice(neighbors);
+        self.offsets.push(self.entries.len());
+    }
+    
+    // Neighbors of the boid added index-th since the last clear
+    #[inline]
+    pub fn get(&self, index: usize) -> &[NeighborEntry] {
+        &self.entries[self.offsets[index]..self.offsets[index + 1]]
+    }
+    
+    // Measure every list's distances again from the current positions, keeping the
+    // neighbors themselves (physics substeps reuse the lists gathered at the step start)
+    pub fn refresh_distances(&mut self, positions: &[Point2], world_size: f32, wrap_x: bool) {
+        for (i, bounds) in self.offsets.windows(2).enumerate() {
+            let position = positions[i];
+            for entry in &mut self.entries[bounds[0]..bounds[1]] {
+                entry.distance_squared = SpatialGrid::distance_squared(position, positions[entry.index], world_size, wrap_x);
+            }
+        }
+    }
+    
+    // Number of boids with a list
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub struct SpatialGrid {
-    pub cell_size: f32,
+    pub cell_size: f32,     // Width of the cells, world_size / grid_size
+    pub min_cell_size: f32, // Cell size asked for (the cells are at least this wide)
     pub grid: Vec<Vec<usize>>,
     pub grid_size: usize,
+    // Whether distances take the shortcut across the left and right edges (off while the
+    // x axis is open in wind tunnel mode; the top and bottom edges always wrap)
+    pub wrap_x: bool,
     // Cache for nearby indices with distances to avoid reallocations
     nearby_with_distance_cache: Vec<NeighborEntry>,
-    // Lookup table for wrapped cell coordinates to avoid repeated calculations
+    // Neighbor lookups find every boid within this distance (see set_query_radius)
+    query_radius: f32,
+    // Rings of cells around the boid's cell that hold every boid within the query radius
+    query_rings: isize,
+    // Offsets of the cells a lookup visits around the boid's cell
     wrapped_cell_lookup: Vec<(isize, isize)>,
-    // Lookup table for dense areas (smaller neighborhood)
-    dense_area_lookup: Vec<(isize, isize)>,
     // Track which cells are occupied for quick empty cell checks
     cell_occupancy: Vec<bool>,
-    // Statistics for adaptive optimization
+    // Sum of the positions inserted into each cell, for the cell centroids (see far_field)
+    cell_position_sums: Vec<Vec2>,
+    // Statistics for the debug display
     empty_cell_count: usize,
     max_cell_population: usize,
-    avg_cell_population: f32,
 }
 
 impl SpatialGrid {
-    pub fn new(cell_size: f32, world_size: f32) -> Self {
-        let grid_size = (world_size / cell_size).ceil() as usize;
+    pub fn new(min_cell_size: f32, world_size: f32) -> Self {
+        // A zero or tiny cell size (e.g. a small cell size factor) would allocate billions
+        // of cells, and a cell larger than the world still needs one cell
+        let cells_across = world_size / min_cell_size;
+        let grid_size = if cells_across.is_finite() {
+            (cells_across.floor() as usize).clamp(1, MAX_GRID_SIZE)
+        } else {
+            MAX_GRID_SIZE
+        };
+        let cell_size = world_size / grid_size as f32;
         let total_cells = grid_size * grid_size;
         
         // Pre-allocate grid with capacity
@@ -64,60 +165,102 @@ impl SpatialGrid {
         // Pre-allocate caches for nearby indices (9 cells * estimated boids per cell)
         let estimated_capacity = 9 * estimated_boids_per_cell;
         
-        // Pre-compute wrapped cell offsets for a 3x3 neighborhood
-        let mut wrapped_cell_lookup = Vec::with_capacity(9);
-        for y_offset in -1..=1 {
-            for x_offset in -1..=1 {
-                wrapped_cell_lookup.push((x_offset, y_offset));
-            }
-        }
-        
-        // Pre-compute dense area lookup (smaller neighborhood - just adjacent cells)
-        let mut dense_area_lookup = Vec::with_capacity(5);
-        dense_area_lookup.push((0, 0)); // Center cell
-        dense_area_lookup.push((1, 0)); // Right
-        dense_area_lookup.push((-1, 0)); // Left
-        dense_area_lookup.push((0, 1)); // Top
-        dense_area_lookup.push((0, -1)); // Bottom
-        
         // Initialize cell occupancy tracking
         let cell_occupancy = vec![false; total_cells];
         
         Self {
             cell_size,
+            min_cell_size,
             grid,
             grid_size,
+            wrap_x: true,
             nearby_with_distance_cache: Vec::with_capacity(estimated_capacity),
-            wrapped_cell_lookup,
-            dense_area_lookup,
+            query_radius: cell_size,
+            query_rings: 1,
+            wrapped_cell_lookup: ring_offsets(1),
             cell_occupancy,
+            cell_position_sums: vec![Vec2::ZERO; total_cells],
             empty_cell_count: total_cells,
             max_cell_population: 0,
-            avg_cell_population: 0.0,
         }
     }
     
-    // Convert world coordinates to grid cell index
+    // Make the neighbor lookups find every boid within `radius`, searching as many rings of
+    // cells as that takes (at least the 3x3 neighborhood)
+    pub fn set_query_radius(&mut self, radius: f32) {
+        if radius == self.query_radius {
+            return;
+        }
+        
+        self.query_radius = radius;
+        let rings = self.rings(radius);
+        if rings != self.query_rings {
+            self.query_rings = rings;
+            // Rings that wrap around the world are never walked (see searches_whole_grid)
+            if !self.searches_whole_grid() {
+                self.wrapped_cell_lookup = ring_offsets(rings);
+            }
+        }
+    }
+    
+    // Rings of cells around a cell that hold every point within `radius` of a point in it
+    fn rings(&self, radius: f32) -> isize {
+        let rings = (radius / self.cell_size).ceil();
+        if rings.is_finite() {
+            (rings as isize).clamp(1, self.grid_size as isize)
+        } else {
+            self.grid_size as isize
+        }
+    }
+    
+    // Whether the query rings reach around the world, so the neighborhood would wrap onto
+    // itself and every cell is visited once instead
+    fn searches_whole_grid(&self) -> bool {
+        2 * self.query_rings + 1 >= self.grid_size as isize
+    }
+    
+    // Convert world coordinates to grid cell index. This is the cell the queries start
+    // from too; positions outside the world are wrapped into it.
     #[inline]
     pub fn pos_to_cell_index(&self, pos: Point2, world_size: f32) -> usize {
-        let half_world = world_size / 2.0;
-        // Convert from world space to grid space (0 to grid_size)
-        let grid_x = ((pos.x + half_world) / self.cell_size).clamp(0.0, self.grid_size as f32 - 1.0) as usize;
-        let grid_y = ((pos.y + half_world) / self.cell_size).clamp(0.0, self.grid_size as f32 - 1.0) as usize;
-        
-        // Convert 2D coordinates to 1D index
-        grid_y * self.grid_size + grid_x
+        let (grid_x, grid_y) = self.pos_to_cell_coords(pos, world_size);
+        self.cell_coords_to_index(grid_x, grid_y)
     }
     
-    // Convert world coordinates to grid cell coordinates
+    // Convert world coordinates to grid cell coordinates. Positions on the far edges
+    // (+half_world, where wrap_edges leaves boids) are in the last column or row.
     #[inline]
     pub fn pos_to_cell_coords(&self, pos: Point2, world_size: f32) -> (isize, isize) {
         let half_world = world_size / 2.0;
+        let last = self.grid_size as isize - 1;
+        
         // Convert from world space to grid space (0 to grid_size)
-        let grid_x = ((pos.x + half_world) / self.cell_size).floor() as isize;
-        let grid_y = ((pos.y + half_world) / self.cell_size).floor() as isize;
+        let to_cell = |coordinate: f32| {
+            let cell = ((coordinate + half_world) / self.cell_size).floor() as isize;
+            if cell > last && coordinate <= half_world {
+                last
+            } else {
+                cell
+            }
+        };
         
-        (grid_x, grid_y)
+        (to_cell(pos.x), to_cell(pos.y))
+    }
+    
+    // Position of the cell containing a position along a Z-order (Morton) curve through the
+    // grid. Sorting by it keeps boids in nearby cells, including the rows above and
+    // below, close together.
+    pub fn morton_key(&self, pos: Point2, world_size: f32) -> u64 {
+        let max_coord = self.grid_size.saturating_sub(1) as isize;
+        let (x, y) = self.pos_to_cell_coords(pos, world_size);
+        morton_code(x.clamp(0, max_coord) as u32, y.clamp(0, max_coord) as u32)
+    }
+    
+    // Column and row of the cell containing a position, or None outside the world
+    pub fn cell_at(&self, pos: Point2, world_size: f32) -> Option<(usize, usize)> {
+        let (x, y) = self.pos_to_cell_coords(pos, world_size);
+        let in_grid = |coord: isize| coord >= 0 && (coord as usize) < self.grid_size;
+        (in_grid(x) && in_grid(y)).then_some((x as usize, y as usize))
     }
     
     // Convert grid cell coordinates to 1D index, handling wrapping
@@ -137,13 +280,21 @@ impl SpatialGrid {
         let total_cells = self.grid.len();
         self.empty_cell_count = total_cells;
         self.max_cell_population = 0;
-        self.avg_cell_population = 0.0;
         
         // Clear all cells and reset occupancy
         for (i, cell) in self.grid.iter_mut().enumerate() {
             cell.clear();
             self.cell_occupancy[i] = false;
         }
+        self.cell_position_sums.fill(Vec2::ZERO);
+    }
+    
+    // Clear the grid and insert every position, with its index as the boid index
+    pub fn rebuild(&mut self, positions: &[Point2], world_size: f32) {
+        self.clear();
+        for (i, &position) in positions.iter().enumerate() {
+            self.insert(i, position, world_size);
+        }
     }
     
     // Insert a boid into the grid
@@ -160,32 +311,22 @@ impl SpatialGrid {
             
             cell.push(boid_index);
             self.cell_occupancy[cell_index] = true;
+            self.cell_position_sums[cell_index] += position;
             
             // Update statistics
             self.max_cell_population = self.max_cell_population.max(cell.len());
         }
     }
     
-    // Update statistics after all insertions
-    pub fn update_statistics(&mut self) {
-        let total_cells = self.grid.len();
-        let occupied_cells = total_cells - self.empty_cell_count;
-        
-        if occupied_cells > 0 {
-            let mut total_boids = 0;
-            for cell in &self.grid {
-                total_boids += cell.len();
-            }
-            
-            self.avg_cell_population = total_boids as f32 / occupied_cells as f32;
-        } else {
-            self.avg_cell_population = 0.0;
-        }
+    // Number of boids in a cell and the sum of their positions (their centroid times the count)
+    #[inline]
+    pub fn cell_mass(&self, cell_index: usize) -> (usize, Vec2) {
+        (self.grid[cell_index].len(), self.cell_position_sums[cell_index])
     }
     
     // Calculate the squared distance between two points, accounting for world wrapping
     #[inline]
-    fn wrapped_distance_squared(p1: Point2, p2: Point2, world_size: f32) -> f32 {
+    pub fn wrapped_distance_squared(p1: Point2, p2: Point2, world_size: f32) -> f32 {
         let half_size = world_size / 2.0;
         
         // Calculate direct distance components
@@ -205,6 +346,21 @@ impl SpatialGrid {
         dx * dx + dy * dy
     }
     
+    // Squared distance as the neighbor lookups measure it, with the x axis wrapped only if `wrap_x`
+    #[inline]
+    pub fn distance_squared(p1: Point2, p2: Point2, world_size: f32, wrap_x: bool) -> f32 {
+        if wrap_x {
+            return Self::wrapped_distance_squared(p1, p2, world_size);
+        }
+        
+        let dx = p1.x - p2.x;
+        let mut dy = (p1.y - p2.y).abs();
+        if dy > world_size / 2.0 {
+            dy = world_size - dy;
+        }
+        dx * dx + dy * dy
+    }
+    
     // Process a single cell and add its boids to the result
     #[inline]
     fn process_cell(&mut self, cell_index: usize, position: Point2, boids: &[Point2], world_size: f32) -> bool {
@@ -226,8 +382,9 @@ impl SpatialGrid {
                     continue;
                 }
                 
-                // Calculate squared distance with wrapping
-                let distance_squared = Self::wrapped_distance_squared(position, other_pos, world_size);
+                // Calculate squared distance with wrapping. Cells across an open edge are
+                // still visited, but their boids come out too far away to be neighbors.
+                let distance_squared = Self::distance_squared(position, other_pos, world_size, self.wrap_x);
                 
                 self.nearby_with_distance_cache.push(NeighborEntry {
                     index: boid_index,
@@ -239,38 +396,71 @@ impl SpatialGrid {
         true // Cell had boids
     }
     
+    // Call `f` with every boid in the cells within `radius` of a position: the cell
+    // containing it and at least the eight cells around it, wrapping around the world
+    // edges. Unlike get_nearby_with_distances this works on a shared grid and leaves the
+    // distance test to the caller.
+    pub fn for_each_nearby(&self, position: Point2, radius: f32, world_size: f32, mut f: impl FnMut(usize)) {
+        let (grid_x, grid_y) = self.pos_to_cell_coords(position, world_size);
+        let grid_size = self.grid_size as isize;
+        
+        // Search the whole grid rather than visit a cell twice when the rings wrap around
+        let reach = ((radius / self.cell_size).ceil() as isize).max(1);
+        let (x_cells, y_cells) = if 2 * reach + 1 >= grid_size {
+            (0..grid_size, 0..grid_size)
+        } else {
+            (grid_x - reach..grid_x + reach + 1, grid_y - reach..grid_y + reach + 1)
+        };
+        
+        for y in y_cells {
+            for x in x_cells.clone() {
+                let cell_index = self.cell_coords_to_index(x, y);
+                if cell_index < self.grid.len() && self.cell_occupancy[cell_index] {
+                    self.grid[cell_index].iter().copied().for_each(&mut f);
+                }
+            }
+        }
+    }
+    
+    // Call `f` with the index and squared distance of every boid within `radius` of `center`
+    // (inclusive), measured across the world edges like the neighbor lookups. Only the cells
+    // overlapping the radius are visited, in as many rings as it takes, so this is the broad
+    // phase for anything acting on the boids around a single point. `boids` must be the
+    // positions the grid was filled with.
+    pub fn for_each_in_radius(&self, center: Point2, radius: f32, boids: &[Point2], world_size: f32, mut f: impl FnMut(usize, f32)) {
+        let radius_sq = radius * radius;
+        self.for_each_nearby(center, radius, world_size, |index| {
+            if let Some(&position) = boids.get(index) {
+                let distance_squared = Self::distance_squared(center, position, world_size, self.wrap_x);
+                if distance_squared <= radius_sq {
+                    f(index, distance_squared);
+                }
+            }
+        });
+    }
+    
     // Get boid indices with pre-computed squared distances
     // This avoids redundant distance calculations in the force computations
     pub fn get_nearby_with_distances(&mut self, position: Point2, boids: &[nannou::prelude::Point2], world_size: f32) -> &[NeighborEntry] {
         // Clear the cache but keep its capacity
         self.nearby_with_distance_cache.clear();
         
+        // When the rings reach around the world the neighborhood wraps onto itself, so
+        // every cell is visited once instead
+        if self.searches_whole_grid() {
+            for cell_index in 0..self.grid.len() {
+                self.process_cell(cell_index, position, boids, world_size);
+            }
+            return &self.nearby_with_distance_cache;
+        }
+        
         // Get the cell coordinates
         let (grid_x, grid_y) = self.pos_to_cell_coords(position, world_size);
         
-        // Check center cell first
-        let center_index = self.cell_coords_to_index(grid_x, grid_y);
-        let center_has_boids = self.process_cell(center_index, position, boids, world_size);
-        
-        // Choose search pattern based on local density
-        // If center cell is dense, use smaller neighborhood to reduce checks
-        let search_pattern = if center_has_boids && 
-            self.grid.get(center_index).map_or(0, |cell| cell.len()) > (self.avg_cell_population as usize * 2) {
-            &self.dense_area_lookup
-        } else {
-            &self.wrapped_cell_lookup
-        };
-        
-        // Clone the search pattern to avoid borrowing issues
-        let search_pattern: Vec<(isize, isize)> = search_pattern.iter().cloned().collect();
-        
-        // Check the cell and its neighbors based on the selected pattern
-        for &(x_offset, y_offset) in &search_pattern {
-            // Skip center cell as we already processed it
-            if x_offset == 0 && y_offset == 0 {
-                continue;
-            }
-            
+        // Check the cell and the rings around it. The full neighborhood is always
+        // searched: skipping the diagonal cells drops neighbors that are within range.
+        for lookup_index in 0..self.wrapped_cell_lookup.len() {
+            let (x_offset, y_offset) = self.wrapped_cell_lookup[lookup_index];
             let check_x = grid_x + x_offset;
             let check_y = grid_y + y_offset;
             
@@ -292,4 +482,367 @@ impl SpatialGrid {
         
         (occupied_cells, total_cells, occupancy_percentage, self.max_cell_population)
     }
-} 
\ No newline at end of file
+} 
+// Cell offsets of a square neighborhood reaching `rings` cells out from the center cell
+fn ring_offsets(rings: isize) -> Vec<(isize, isize)> {
+    let mut offsets = Vec::with_capacity((2 * rings as usize + 1).pow(2));
+    for y_offset in -rings..=rings {
+        for x_offset in -rings..=rings {
+            offsets.push((x_offset, y_offset));
+        }
+    }
+    offsets
+}
+
+// Interleave the bits of two coordinates (x in the even bits, y in the odd ones)
+pub fn morton_code(x: u32, y: u32) -> u64 {
+    // Spread the 32 bits of a coordinate over the even bits of a u64
+    fn spread(value: u32) -> u64 {
+        let mut value = value as u64;
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        value = (value | (value << 1)) & 0x5555_5555_5555_5555;
+        value
+    }
+    
+    spread(x) | (spread(y) << 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
+
+    // Independent wrapped distance for the brute-force reference
+    fn brute_force_distance_squared(a: Point2, b: Point2, world_size: f32) -> f32 {
+        let dx = (a.x - b.x).abs();
+        let dy = (a.y - b.y).abs();
+        let dx = dx.min(world_size - dx);
+        let dy = dy.min(world_size - dy);
+        dx * dx + dy * dy
+    }
+
+    proptest! {
+        // With the query radius set, the rings searched cover the whole perception circle
+        // (several cells wide for radii above the cell size), so the grid must find exactly
+        // the brute-force neighbors. Whole-number cell sizes keep the world an exact
+        // multiple of the cell size.
+        #[test]
+        fn grid_query_matches_brute_force_radius_scan(
+            cell_size in (20u32..100).prop_map(|size| size as f32),
+            cells_per_side in 3usize..12,
+            radius_fraction in 0.05f32..4.0,
+            fractions in prop::collection::vec((0.0f32..1.0, 0.0f32..1.0), 1..200),
+        ) {
+            let world_size = cell_size * cells_per_side as f32;
+            let half = world_size / 2.0;
+            let radius = cell_size * radius_fraction;
+            let radius_sq = radius * radius;
+            let positions: Vec<Point2> = fractions.iter()
+                .map(|&(fx, fy)| pt2(fx * world_size - half, fy * world_size - half))
+                .collect();
+
+            let mut grid = SpatialGrid::new(cell_size, world_size);
+            for (i, &position) in positions.iter().enumerate() {
+                grid.insert(i, position, world_size);
+            }
+            grid.set_query_radius(radius);
+
+            for (i, &position) in positions.iter().enumerate() {
+                let from_grid: BTreeSet<usize> = grid.get_nearby_with_distances(position, &positions, world_size)
+                    .iter()
+                    .filter(|neighbor| neighbor.distance_squared < radius_sq)
+                    .map(|neighbor| neighbor.index)
+                    .collect();
+
+                let brute_force: BTreeSet<usize> = positions.iter().enumerate()
+                    .filter(|&(j, &other)| {
+                        let distance_squared = brute_force_distance_squared(position, other, world_size);
+                        j != i && distance_squared > 0.0 && distance_squared < radius_sq
+                    })
+                    .map(|(j, _)| j)
+                    .collect();
+
+                prop_assert_eq!(from_grid, brute_force, "neighbors of boid {} at {:?}", i, position);
+            }
+        }
+    }
+
+    proptest! {
+        // Radius queries reach as many rings of cells as the radius needs (up to the whole
+        // grid), and report each boid within it once with its wrapped distance
+        #[test]
+        fn radius_query_matches_brute_force(
+            cell_size in (20u32..100).prop_map(|size| size as f32),
+            cells_per_side in 3usize..12,
+            radius_cells in 0.1f32..4.0,
+            center in (0.0f32..1.0, 0.0f32..1.0),
+            fractions in prop::collection::vec((0.0f32..1.0, 0.0f32..1.0), 1..200),
+        ) {
+            let world_size = cell_size * cells_per_side as f32;
+            let half = world_size / 2.0;
+            let radius = cell_size * radius_cells;
+            let to_world = |(fx, fy): (f32, f32)| pt2(fx * world_size - half, fy * world_size - half);
+            let positions: Vec<Point2> = fractions.iter().copied().map(to_world).collect();
+            let center = to_world(center);
+
+            let mut grid = SpatialGrid::new(cell_size, world_size);
+            for (i, &position) in positions.iter().enumerate() {
+                grid.insert(i, position, world_size);
+            }
+
+            let mut from_grid = Vec::new();
+            grid.for_each_in_radius(center, radius, &positions, world_size, |index, distance_squared| {
+                from_grid.push((index, distance_squared));
+            });
+            from_grid.sort_by_key(|&(index, _)| index);
+
+            let brute_force: Vec<(usize, f32)> = positions.iter().enumerate()
+                .map(|(i, &position)| (i, brute_force_distance_squared(center, position, world_size)))
+                .filter(|&(_, distance_squared)| distance_squared <= radius * radius)
+                .collect();
+
+            prop_assert_eq!(from_grid.len(), brute_force.len(), "boids within {} of {:?}", radius, center);
+            for (&(index, distance_squared), &(expected_index, expected)) in from_grid.iter().zip(&brute_force) {
+                prop_assert_eq!(index, expected_index);
+                prop_assert!((distance_squared - expected).abs() <= expected.max(1.0) * 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn radius_queries_leave_the_open_edge_unwrapped() {
+        let (world_size, half) = (1000.0, 500.0);
+        let mut grid = SpatialGrid::new(100.0, world_size);
+        grid.wrap_x = false;
+        let positions = [pt2(half - 10.0, 0.0), pt2(-half + 10.0, 0.0), pt2(-half + 10.0, -half + 10.0)];
+        for (i, &position) in positions.iter().enumerate() {
+            grid.insert(i, position, world_size);
+        }
+
+        // Across the left and right edges the boids are far apart; across the bottom they are not
+        let mut found = Vec::new();
+        grid.for_each_in_radius(pt2(-half + 10.0, half - 10.0), 50.0, &positions, world_size, |index, _| found.push(index));
+        assert_eq!(found, vec![2]);
+
+        found.clear();
+        grid.for_each_in_radius(pt2(half - 20.0, 0.0), 50.0, &positions, world_size, |index, distance_squared| {
+            found.push(index);
+            assert_eq!(distance_squared, 100.0);
+        });
+        assert_eq!(found, vec![0]);
+    }
+
+    #[test]
+    fn cell_at_finds_the_cell_under_a_position() {
+        let grid = SpatialGrid::new(100.0, 1000.0);
+
+        assert_eq!(grid.cell_at(pt2(-500.0, -500.0), 1000.0), Some((0, 0)));
+        assert_eq!(grid.cell_at(pt2(150.0, -250.0), 1000.0), Some((6, 2)));
+        assert_eq!(grid.cell_at(pt2(499.0, 499.0), 1000.0), Some((9, 9)));
+        assert_eq!(grid.cell_at(pt2(510.0, 0.0), 1000.0), None);
+        assert_eq!(grid.cell_at(pt2(0.0, -501.0), 1000.0), None);
+    }
+
+    #[test]
+    fn morton_keys_follow_the_z_order_curve() {
+        // The first 2x2 block is visited in a Z, before moving on to the next block
+        assert_eq!(morton_code(0, 0), 0);
+        assert_eq!(morton_code(1, 0), 1);
+        assert_eq!(morton_code(0, 1), 2);
+        assert_eq!(morton_code(1, 1), 3);
+        assert_eq!(morton_code(2, 0), 4);
+        assert_eq!(morton_code(u32::MAX, u32::MAX), u64::MAX);
+
+        // Positions outside the world are clamped to the edge cells
+        let grid = SpatialGrid::new(100.0, 1000.0);
+        assert_eq!(grid.morton_key(pt2(150.0, -250.0), 1000.0), morton_code(6, 2));
+        assert_eq!(grid.morton_key(pt2(600.0, -600.0), 1000.0), morton_code(9, 0));
+    }
+
+    // Indices of the boids the grid reports near a position, with duplicates kept
+    fn nearby_indices(grid: &mut SpatialGrid, position: Point2, positions: &[Point2], world_size: f32) -> Vec<usize> {
+        let mut indices: Vec<usize> = grid.get_nearby_with_distances(position, positions, world_size)
+            .iter()
+            .map(|neighbor| neighbor.index)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn insertion_and_queries_agree_on_boundary_cells() {
+        let (world_size, half) = (1000.0, 500.0);
+        let grid = SpatialGrid::new(100.0, world_size);
+
+        // The far edges are in the last column and row, on both paths
+        let cases = [
+            (pt2(-half, -half), (0, 0)),
+            (pt2(half, -half), (9, 0)),
+            (pt2(-half, half), (0, 9)),
+            (pt2(half, half), (9, 9)),
+            (pt2(half, 0.0), (9, 5)),
+            (pt2(0.0, -half), (5, 0)),
+        ];
+        for (position, (x, y)) in cases {
+            assert_eq!(grid.pos_to_cell_coords(position, world_size), (x, y), "cell of {:?}", position);
+            assert_eq!(grid.pos_to_cell_index(position, world_size), y as usize * 10 + x as usize);
+            assert_eq!(grid.cell_coords_to_index(x, y), grid.pos_to_cell_index(position, world_size));
+        }
+
+        // Negative coordinates round down, and positions outside the world wrap
+        assert_eq!(grid.pos_to_cell_coords(pt2(-450.0, -350.0), world_size), (0, 1));
+        assert_eq!(grid.pos_to_cell_coords(pt2(-0.5, -0.5), world_size), (4, 4));
+        assert_eq!(grid.pos_to_cell_index(pt2(half + 50.0, -half - 50.0), world_size), 9 * 10);
+        assert_eq!(grid.cell_coords_to_index(-1, -1), 99);
+        assert_eq!(grid.cell_coords_to_index(10, -11), 9 * 10);
+    }
+
+    #[test]
+    fn corner_neighborhoods_wrap_on_both_axes() {
+        let (world_size, half) = (1000.0, 500.0);
+        let mut grid = SpatialGrid::new(100.0, world_size);
+        let positions = [
+            pt2(-half + 1.0, -half + 1.0),
+            pt2(half - 1.0, -half + 1.0),
+            pt2(-half + 1.0, half - 1.0),
+            pt2(half, half), // Exactly on the far corner
+        ];
+        for (i, &position) in positions.iter().enumerate() {
+            grid.insert(i, position, world_size);
+        }
+
+        // Each corner boid finds the other three across the seams
+        for (i, &position) in positions.iter().enumerate() {
+            let expected: Vec<usize> = (0..positions.len()).filter(|&j| j != i).collect();
+            assert_eq!(nearby_indices(&mut grid, position, &positions, world_size), expected, "neighbors of {:?}", position);
+        }
+
+        let diagonal = grid.get_nearby_with_distances(positions[0], &positions, world_size)
+            .iter()
+            .find(|neighbor| neighbor.index == 3)
+            .unwrap()
+            .distance_squared;
+        assert_eq!(diagonal, 2.0);
+    }
+
+    #[test]
+    fn cells_are_widened_to_tile_the_world() {
+        let world_size = 1000.0;
+        let mut grid = SpatialGrid::new(300.0, world_size);
+        assert_eq!(grid.grid_size, 3);
+        assert_eq!(grid.min_cell_size, 300.0);
+        assert!(grid.cell_size >= 300.0);
+        assert!((grid.cell_size * grid.grid_size as f32 - world_size).abs() < 1e-3);
+
+        // Within the requested cell size across the seam (a partial fourth cell
+        // between them would put the neighbor out of reach)
+        let positions = [pt2(-490.0, 0.0), pt2(250.0, 0.0)];
+        for (i, &position) in positions.iter().enumerate() {
+            grid.insert(i, position, world_size);
+        }
+        let neighbors = grid.get_nearby_with_distances(positions[0], &positions, world_size);
+        assert_eq!(neighbors.len(), 1);
+        assert_eq!(neighbors[0].distance_squared, 260.0 * 260.0);
+    }
+
+    #[test]
+    fn small_grids_report_each_boid_once() {
+        let world_size = 1000.0;
+        let positions = [pt2(-400.0, -400.0), pt2(100.0, 300.0), pt2(450.0, -20.0)];
+
+        // Two cells per side, and a single cell larger than the world
+        for min_cell_size in [400.0, 2000.0] {
+            let mut grid = SpatialGrid::new(min_cell_size, world_size);
+            assert!(grid.grid_size < 3);
+            for (i, &position) in positions.iter().enumerate() {
+                grid.insert(i, position, world_size);
+            }
+
+            assert_eq!(nearby_indices(&mut grid, positions[0], &positions, world_size), vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn neighbor_lists_keep_each_boid_apart_and_reuse_their_buffers() {
+        let entry = |index| NeighborEntry { index, distance_squared: index as f32 };
+        let mut lists = NeighborLists::default();
+        lists.clear();
+        lists.push(&[entry(1), entry(2)]);
+        lists.push(&[]);
+        lists.push(&[entry(0)]);
+
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists.get(0).iter().map(|neighbor| neighbor.index).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(lists.get(1).is_empty());
+        assert_eq!(lists.get(2)[0].index, 0);
+
+        let capacity = lists.entries.capacity();
+        lists.clear();
+        assert!(lists.is_empty());
+        lists.push(&[entry(3)]);
+        assert_eq!(lists.entries.capacity(), capacity);
+    }
+
+    #[test]
+    fn neighbor_lists_are_reused_only_within_the_interval() {
+        let mut lists = NeighborLists::default();
+        lists.clear();
+        lists.push(&[]);
+        lists.push(&[]);
+        assert!(!lists.reusable(0, 3, 100.0, 2));
+
+        lists.mark_gathered(10, 100.0);
+        assert!(lists.reusable(10, 3, 100.0, 2));
+        assert!(lists.reusable(12, 3, 100.0, 2));
+        assert!(!lists.reusable(13, 3, 100.0, 2));
+        // An interval of 1 gathers every step
+        assert!(!lists.reusable(11, 1, 100.0, 2));
+        // A new radius, a new flock size or an earlier step (a reset) need new lists
+        assert!(!lists.reusable(11, 3, 120.0, 2));
+        assert!(!lists.reusable(11, 3, 100.0, 3));
+        assert!(!lists.reusable(9, 3, 100.0, 2));
+
+        lists.invalidate();
+        assert!(!lists.reusable(11, 3, 100.0, 2));
+    }
+
+    #[test]
+    fn degenerate_cell_sizes_give_a_bounded_grid() {
+        // A zero cell size is capped instead of asking for infinitely many cells
+        let grid = SpatialGrid::new(0.0, 1000.0);
+        assert_eq!(grid.grid_size, MAX_GRID_SIZE);
+        assert_eq!(grid.grid.len(), MAX_GRID_SIZE * MAX_GRID_SIZE);
+
+        // A cell wider than the world becomes the whole world
+        let grid = SpatialGrid::new(1.0e6, 1000.0);
+        assert_eq!(grid.grid_size, 1);
+        assert_eq!(grid.cell_size, 1000.0);
+    }
+
+    #[test]
+    fn open_x_axis_measures_across_the_world_instead_of_the_seam() {
+        let world_size = 1000.0;
+        let mut grid = SpatialGrid::new(100.0, world_size);
+        grid.wrap_x = false;
+
+        // Neighbors across the left/right seam and across the top/bottom seam
+        let positions = [pt2(-495.0, 0.0), pt2(495.0, 0.0), pt2(-495.0, 495.0), pt2(-495.0, -495.0)];
+        for (i, &position) in positions.iter().enumerate() {
+            grid.insert(i, position, world_size);
+        }
+
+        let distance_to = |grid: &mut SpatialGrid, from: usize, to: usize| {
+            grid.get_nearby_with_distances(positions[from], &positions, world_size)
+                .iter()
+                .find(|neighbor| neighbor.index == to)
+                .unwrap()
+                .distance_squared
+        };
+        assert_eq!(distance_to(&mut grid, 0, 1), 990.0 * 990.0);
+        assert_eq!(distance_to(&mut grid, 2, 3), 100.0);
+    }
+}
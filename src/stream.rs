@@ -0,0 +1,318 @@
+/*
+ * Stream Module
+ * 
+ * This module streams boid positions over UDP so external programs (visualizers,
+ * robotics or audio projects) can follow the simulation live.
+ * 
+ * A client subscribes by sending any datagram to the stream port and keeps its
+ * subscription alive by sending another one at least every few seconds. Every
+ * `stride` physics steps the simulation thread encodes a frame and pushes it into
+ * a small bounded channel; a background thread sends it to every subscriber. When
+ * the sender falls behind, frames are dropped so the physics never waits on the network.
+ * 
+ * Frame layout (all values little-endian):
+ * 
+ *   u64  frame number (the physics step)
+ *   u32  number of boids in this frame
+ *   u32  total number of boids in the simulation
+ *   f32  world size
+ *   then for each boid: f32 x, f32 y, f32 heading (radians, 0 = +x)
+ * 
+ * A frame must fit in a single datagram, so large flocks are subsampled: every
+ * n-th boid is sent so that at most `max_boids` boids are in a frame.
+ */
+
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::flock::Flock;
+
+// Size of the frame header in bytes
+pub const HEADER_SIZE: usize = 20;
+
+// Size of one boid's data in bytes (x, y, heading)
+pub const BOID_RECORD_SIZE: usize = 12;
+
+// Most boids that fit in a single UDP datagram (65507 bytes of payload)
+pub const MAX_BOIDS_PER_FRAME: usize = (65507 - HEADER_SIZE) / BOID_RECORD_SIZE;
+
+// Subscribers that haven't sent a datagram for this long stop receiving frames
+pub const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Frames waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 4;
+
+// How often the sender thread checks for new subscribers while no frames arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// A decoded frame (see the module comment for the wire format)
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamFrame {
+    pub frame: u64,
+    pub total_boids: u32,
+    pub world_size: f32,
+    pub boids: Vec<(Point2, f32)>, // Position and heading
+}
+
+// Encode the flock into a frame, keeping every n-th boid so at most max_boids are sent
+pub fn encode_frame(frame: u64, flock: &Flock, world_size: f32, max_boids: usize) -> Vec<u8> {
+    let max_boids = max_boids.clamp(1, MAX_BOIDS_PER_FRAME);
+    let every = flock.len().div_ceil(max_boids).max(1);
+    let count = flock.len().div_ceil(every);
+    
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + count * BOID_RECORD_SIZE);
+    bytes.extend_from_slice(&frame.to_le_bytes());
+    bytes.extend_from_slice(&(count as u32).to_le_bytes());
+    bytes.extend_from_slice(&(flock.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&world_size.to_le_bytes());
+    
+    for (position, velocity) in flock.positions.iter().zip(&flock.velocities).step_by(every) {
+        bytes.extend_from_slice(&position.x.to_le_bytes());
+        bytes.extend_from_slice(&position.y.to_le_bytes());
+        bytes.extend_from_slice(&velocity.y.atan2(velocity.x).to_le_bytes());
+    }
+    
+    bytes
+}
+
+// Decode a frame produced by encode_frame. Returns None if the datagram is malformed.
+pub fn decode_frame(bytes: &[u8]) -> Option<StreamFrame> {
+    let read_u32 = |offset: usize| bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let read_f32 = |offset: usize| read_u32(offset).map(f32::from_bits);
+    
+    let frame = read_u32(0)? as u64 | ((read_u32(4)? as u64) << 32);
+    let count = read_u32(8)? as usize;
+    let total_boids = read_u32(12)?;
+    let world_size = read_f32(16)?;
+    
+    if bytes.len() != HEADER_SIZE + count * BOID_RECORD_SIZE {
+        return None;
+    }
+    
+    let boids = (0..count).map(|i| {
+        let offset = HEADER_SIZE + i * BOID_RECORD_SIZE;
+        let x = read_f32(offset)?;
+        let y = read_f32(offset + 4)?;
+        let heading = read_f32(offset + 8)?;
+        Some((pt2(x, y), heading))
+    }).collect::<Option<Vec<_>>>()?;
+    
+    Some(StreamFrame { frame, total_boids, world_size, boids })
+}
+
+pub struct Streamer {
+    port: u16,
+    stride: u64,
+    max_boids: usize,
+    sender: SyncSender<Vec<u8>>,
+    status: StreamStatus,
+}
+
+// Shared view of a stream's state, cheap to clone and safe to read from any thread
+#[derive(Clone)]
+pub struct StreamStatus {
+    port: u16,
+    running: Arc<AtomicBool>, // Sender thread hasn't exited
+    clients: Arc<AtomicUsize>,
+    frames_sent: Arc<AtomicUsize>,
+    frames_dropped: Arc<AtomicUsize>, // Frames skipped because the sender was behind
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Streamer {
+    // Bind the UDP port (on all interfaces) and start the sender thread.
+    // A frame is sent every `stride` physics steps with at most `max_boids` boids.
+    pub fn start(port: u16, stride: u64, max_boids: usize) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let port = socket.local_addr()?.port();
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUE_CAPACITY);
+        let status = StreamStatus {
+            port,
+            running: Arc::new(AtomicBool::new(true)),
+            clients: Arc::new(AtomicUsize::new(0)),
+            frames_sent: Arc::new(AtomicUsize::new(0)),
+            frames_dropped: Arc::new(AtomicUsize::new(0)),
+            error: Arc::new(Mutex::new(None)),
+        };
+        
+        let sender_status = status.clone();
+        thread::spawn(move || {
+            let result = (|| -> io::Result<()> {
+                socket.set_read_timeout(Some(Duration::from_millis(1)))?;
+                let mut subscribers: HashMap<SocketAddr, Instant> = HashMap::new();
+                
+                // Runs until the Streamer is dropped
+                loop {
+                    let frame = match receiver.recv_timeout(POLL_INTERVAL) {
+                        Ok(frame) => Some(frame),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                    };
+                    
+                    accept_subscribers(&socket, &mut subscribers)?;
+                    sender_status.clients.store(subscribers.len(), Ordering::Relaxed);
+                    
+                    if let Some(frame) = frame {
+                        // Unreachable clients are dropped; ones that stop listening time out
+                        subscribers.retain(|address, _| socket.send_to(&frame, address).is_ok());
+                        sender_status.frames_sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })();
+            
+            if let Err(err) = result {
+                *sender_status.error.lock().unwrap() = Some(err.to_string());
+            }
+            sender_status.running.store(false, Ordering::Relaxed);
+        });
+        
+        Ok(Self {
+            port,
+            stride: stride.max(1),
+            max_boids,
+            sender,
+            status,
+        })
+    }
+    
+    // Change how often and how many boids are sent, without rebinding the port
+    pub fn configure(&mut self, stride: u64, max_boids: usize) {
+        self.stride = stride.max(1);
+        self.max_boids = max_boids;
+    }
+    
+    // Whether a frame should be sent for this physics step
+    pub fn wants_step(&self, step: u64) -> bool {
+        step.is_multiple_of(self.stride)
+    }
+    
+    // Encode and queue a frame, dropping it if the sender thread is behind (never blocks)
+    pub fn send_frame(&self, step: u64, flock: &Flock, world_size: f32) {
+        let frame = encode_frame(step, flock, world_size, self.max_boids);
+        match self.sender.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.status.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // The sender only exits after a socket error, which is reported via error()
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+    
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    
+    // A handle for watching this stream from another thread
+    pub fn status(&self) -> StreamStatus {
+        self.status.clone()
+    }
+}
+
+impl StreamStatus {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+    
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::Relaxed)
+    }
+    
+    pub fn frames_sent(&self) -> usize {
+        self.frames_sent.load(Ordering::Relaxed)
+    }
+    
+    pub fn frames_dropped(&self) -> usize {
+        self.frames_dropped.load(Ordering::Relaxed)
+    }
+    
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+// Register or refresh everyone who sent a datagram since the last call and forget
+// subscribers that have been silent for too long
+fn accept_subscribers(socket: &UdpSocket, subscribers: &mut HashMap<SocketAddr, Instant>) -> io::Result<()> {
+    let mut buffer = [0u8; 64];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((_, address)) => {
+                subscribers.insert(address, Instant::now());
+            }
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            // Reported on some platforms after sending to a client that has gone away
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset
+                || err.kind() == io::ErrorKind::ConnectionRefused => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    
+    subscribers.retain(|_, last_seen| last_seen.elapsed() < SUBSCRIPTION_TIMEOUT);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boid::Boid;
+
+    fn flock_of(count: usize) -> Flock {
+        let mut flock = Flock::with_capacity(count);
+        for i in 0..count {
+            let mut boid = Boid::new(i as f32, -(i as f32));
+            boid.velocity = vec2(0.0, 1.0);
+            flock.push(boid);
+        }
+        flock
+    }
+
+    #[test]
+    fn frames_decode_to_the_encoded_boids() {
+        let flock = flock_of(3);
+
+        let bytes = encode_frame(42, &flock, 5000.0, 100);
+        let frame = decode_frame(&bytes).unwrap();
+
+        assert_eq!(bytes.len(), HEADER_SIZE + 3 * BOID_RECORD_SIZE);
+        assert_eq!(frame.frame, 42);
+        assert_eq!(frame.total_boids, 3);
+        assert_eq!(frame.world_size, 5000.0);
+        assert_eq!(frame.boids.len(), 3);
+        assert_eq!(frame.boids[2].0, pt2(2.0, -2.0));
+        assert!((frame.boids[2].1 - PI / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn large_flocks_are_subsampled_to_fit() {
+        let flock = flock_of(1000);
+
+        let frame = decode_frame(&encode_frame(1, &flock, 5000.0, 300)).unwrap();
+
+        assert_eq!(frame.total_boids, 1000);
+        assert!(frame.boids.len() <= 300);
+        assert_eq!(frame.boids[0].0, pt2(0.0, 0.0));
+        assert_eq!(frame.boids[1].0, pt2(4.0, -4.0));
+        assert!(encode_frame(1, &flock_of(10_000), 5000.0, usize::MAX).len() <= 65507);
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let bytes = encode_frame(7, &flock_of(2), 5000.0, 100);
+
+        assert!(decode_frame(&bytes[..bytes.len() - 1]).is_none());
+        assert!(decode_frame(&bytes[..10]).is_none());
+    }
+}
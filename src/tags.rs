@@ -0,0 +1,168 @@
+/*
+ * Tags Module
+ * 
+ * Tags mark groups of boids for experiments: the boids in an area (the selected region,
+ * or everything on screen) are tagged A, B or C, and the tagged groups are followed as
+ * they disperse through the flock. A tag stays with its boid through parameter changes;
+ * a reset creates a new, untagged flock.
+ * 
+ * For each tag the simulation periodically measures where the group is (the wrapped
+ * centroid), how far it has spread (the root mean square distance from the centroid)
+ * and how mixed it is with the untagged boids (the mean fraction of untagged boids among
+ * a tagged boid's neighbors: 0 while the group keeps to itself, 1 once each tagged boid
+ * is surrounded by untagged ones).
+ */
+
+use nannou::prelude::*;
+
+use crate::flock::Flock;
+use crate::physics;
+use crate::spatial_grid::SpatialGrid;
+
+// Number of tags, and their names in the UI and the exports
+pub const TAG_COUNT: usize = 3;
+pub const TAG_NAMES: [&str; TAG_COUNT] = ["A", "B", "C"];
+
+// Colors tagged boids are tinted in
+pub const TAG_COLORS: [[u8; 3]; TAG_COUNT] = [
+    [255, 90, 90],
+    [90, 200, 255],
+    [255, 210, 60],
+];
+
+// Physics steps between two measurements of the tagged groups
+pub const TAG_METRICS_INTERVAL: u64 = 30;
+
+// Measures of one tagged group
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TagMetrics {
+    pub count: usize,
+    // Wrapped centroid, None if the group is spread evenly around the world
+    pub centroid: Option<Point2>,
+    // Root mean square wrapped distance from the centroid (None without a centroid)
+    pub spread: Option<f32>,
+    // Mean fraction of untagged neighbors, over tagged boids with a neighbor in reach
+    pub mixing: Option<f32>,
+}
+
+// Whether any boid carries a tag
+pub fn any_tagged(flock: &Flock) -> bool {
+    flock.properties.iter().any(|properties| properties.tag.is_some())
+}
+
+// Measure every tagged group (None for tags no boid carries). The grid must hold every
+// boid and find the neighbors within `radius`.
+pub fn tag_metrics(flock: &Flock, grid: &mut SpatialGrid, radius: f32, world_size: f32) -> [Option<TagMetrics>; TAG_COUNT] {
+    let mut metrics = [None; TAG_COUNT];
+    
+    for (tag, group) in metrics.iter_mut().enumerate() {
+        let members: Vec<usize> = (0..flock.len())
+            .filter(|&index| flock.properties[index].tag == Some(tag as u8))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        
+        let centroid = physics::wrapped_centroid(members.iter().map(|&index| flock.positions[index]), world_size);
+        let spread = centroid.map(|centroid| {
+            let sum: f32 = members.iter()
+                .map(|&index| SpatialGrid::wrapped_distance_squared(flock.positions[index], centroid, world_size))
+                .sum();
+            (sum / members.len() as f32).sqrt()
+        });
+        
+        let radius_sq = radius * radius;
+        let (mut mixing_sum, mut mixed_count) = (0.0, 0);
+        for &index in &members {
+            let neighbors = grid.get_nearby_with_distances(flock.positions[index], &flock.positions, world_size);
+            let (mut total, mut untagged) = (0, 0);
+            for neighbor in neighbors.iter().filter(|neighbor| neighbor.index != index && neighbor.distance_squared < radius_sq) {
+                total += 1;
+                if flock.properties[neighbor.index].tag.is_none() {
+                    untagged += 1;
+                }
+            }
+            if total > 0 {
+                mixing_sum += untagged as f32 / total as f32;
+                mixed_count += 1;
+            }
+        }
+        
+        *group = Some(TagMetrics {
+            count: members.len(),
+            centroid,
+            spread,
+            mixing: (mixed_count > 0).then(|| mixing_sum / mixed_count as f32),
+        });
+    }
+    
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boid::Boid;
+
+    const WORLD_SIZE: f32 = 1000.0;
+    const RADIUS: f32 = 50.0;
+
+    fn flock_with_tags(boids: &[(f32, f32, Option<u8>)]) -> (Flock, SpatialGrid) {
+        let mut flock = Flock::default();
+        let mut grid = SpatialGrid::new(RADIUS, WORLD_SIZE);
+        for (i, &(x, y, tag)) in boids.iter().enumerate() {
+            let mut boid = Boid::new(x, y);
+            boid.tag = tag;
+            flock.push(boid);
+            grid.insert(i, pt2(x, y), WORLD_SIZE);
+        }
+        grid.set_query_radius(RADIUS);
+        (flock, grid)
+    }
+
+    #[test]
+    fn a_group_that_keeps_to_itself_is_unmixed() {
+        let (flock, mut grid) = flock_with_tags(&[
+            (0.0, 0.0, Some(0)),
+            (10.0, 0.0, Some(0)),
+            (300.0, 300.0, None),
+            (310.0, 300.0, None),
+        ]);
+        let metrics = tag_metrics(&flock, &mut grid, RADIUS, WORLD_SIZE);
+
+        let group = metrics[0].unwrap();
+        assert_eq!(group.count, 2);
+        let centroid = group.centroid.unwrap();
+        assert!((centroid.x - 5.0).abs() < 1e-3 && centroid.y.abs() < 1e-3);
+        assert!((group.spread.unwrap() - 5.0).abs() < 1e-3);
+        assert_eq!(group.mixing, Some(0.0));
+        assert_eq!(metrics[1], None);
+        assert_eq!(metrics[2], None);
+    }
+
+    #[test]
+    fn a_group_among_untagged_boids_is_mixed() {
+        // Each tagged boid has one untagged neighbor and no tagged one
+        let (flock, mut grid) = flock_with_tags(&[
+            (0.0, 0.0, Some(1)),
+            (10.0, 0.0, None),
+            (200.0, 0.0, Some(1)),
+            (210.0, 0.0, None),
+        ]);
+        let group = tag_metrics(&flock, &mut grid, RADIUS, WORLD_SIZE)[1].unwrap();
+        assert_eq!(group.mixing, Some(1.0));
+        assert!((group.spread.unwrap() - 100.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn the_centroid_of_a_group_across_the_world_edge_is_at_the_edge() {
+        let (flock, mut grid) = flock_with_tags(&[
+            (495.0, 0.0, Some(2)),
+            (-495.0, 0.0, Some(2)),
+        ]);
+        let group = tag_metrics(&flock, &mut grid, RADIUS, WORLD_SIZE)[2].unwrap();
+        assert!(group.centroid.unwrap().x.abs() > 490.0);
+        assert!((group.spread.unwrap() - 5.0).abs() < 0.1);
+        assert_eq!(group.mixing, Some(0.0));
+    }
+}
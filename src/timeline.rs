@@ -0,0 +1,315 @@
+/*
+ * Timeline Module
+ * 
+ * Keyframed parameter animation, e.g. for recording videos: each keyframe sets one
+ * flocking parameter to a value at a time in simulated seconds, and between two
+ * keyframes of the same parameter the value is interpolated with the later keyframe's
+ * easing. Before a parameter's first keyframe it holds that keyframe's value, and after
+ * its last one it holds the last value.
+ * 
+ * The keyframes are part of the parameters, so presets and the config file carry them
+ * (one `keyframe = "time, parameter, value, easing"` line each). The playhead has its own
+ * play/stop state and advances with the simulated time, so it stands still while the
+ * physics is paused. While it plays (or after a seek) the animated values are written
+ * into the sliders before change detection, so the physics thread picks them up like
+ * any slider change.
+ */
+
+use crate::modulation::ModTarget;
+use crate::params::SimulationParams;
+
+// Shape of the interpolation into a keyframe from the one before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    Step, // Hold the previous value until the keyframe's time
+}
+
+impl Easing {
+    pub const ALL: [Easing; 5] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Step];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            Easing::Linear => "Linear",
+            Easing::EaseIn => "Ease In",
+            Easing::EaseOut => "Ease Out",
+            Easing::EaseInOut => "Ease In/Out",
+            Easing::Step => "Step",
+        }
+    }
+    
+    // Name in preset files
+    pub fn key(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease_in",
+            Easing::EaseOut => "ease_out",
+            Easing::EaseInOut => "ease_in_out",
+            Easing::Step => "step",
+        }
+    }
+    
+    // Progress in [0, 1] at a fraction of the way between two keyframes
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
+        }
+    }
+}
+
+// One parameter reaching a value at a time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64, // Simulated seconds
+    pub target: ModTarget,
+    pub value: f32,
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    // Value of a `keyframe` preset line
+    pub fn to_preset(self) -> String {
+        format!("\"{}, {}, {}, {}\"", self.time, self.target.key(), self.value, self.easing.key())
+    }
+    
+    // Parse a value written by to_preset (without the quotes, which the preset reader
+    // strips). The value is clamped to the parameter's slider range.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+        let [time, target, keyframe_value, easing] = fields[..] else {
+            return Err(format!("invalid keyframe: {} (expected time, parameter, value, easing)", value));
+        };
+        
+        let time = time.parse::<f64>().ok()
+            .filter(|time| time.is_finite() && *time >= 0.0)
+            .ok_or_else(|| format!("invalid keyframe time: {}", time))?;
+        let target = ModTarget::ALL.into_iter()
+            .find(|option| option.key() == target)
+            .ok_or_else(|| format!("invalid keyframe parameter: {}", target))?;
+        let range = target.range();
+        let keyframe_value = keyframe_value.parse::<f32>()
+            .map_err(|_| format!("invalid keyframe value: {}", keyframe_value))?
+            .clamp(*range.start(), *range.end());
+        let easing = Easing::ALL.into_iter()
+            .find(|option| option.key() == easing)
+            .ok_or_else(|| format!("invalid keyframe easing: {}", easing))?;
+        
+        Ok(Self { time, target, value: keyframe_value, easing })
+    }
+}
+
+// Keep keyframes in time order (the UI lists them that way); equal times keep their order
+pub fn sort_keyframes(keyframes: &mut [Keyframe]) {
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+}
+
+// Time of the last keyframe
+pub fn duration(keyframes: &[Keyframe]) -> f64 {
+    keyframes.iter().map(|keyframe| keyframe.time).fold(0.0, f64::max)
+}
+
+// A parameter's animated value at a time, or None if it has no keyframes
+pub fn value_at(keyframes: &[Keyframe], target: ModTarget, time: f64) -> Option<f32> {
+    let mut previous: Option<&Keyframe> = None;
+    let mut next: Option<&Keyframe> = None;
+    for keyframe in keyframes.iter().filter(|keyframe| keyframe.target == target) {
+        if keyframe.time <= time {
+            if previous.filter(|previous| previous.time > keyframe.time).is_none() {
+                previous = Some(keyframe);
+            }
+        } else if next.filter(|next| next.time <= keyframe.time).is_none() {
+            next = Some(keyframe);
+        }
+    }
+    
+    match (previous, next) {
+        (Some(previous), Some(next)) => {
+            let fraction = ((time - previous.time) / (next.time - previous.time)) as f32;
+            Some(previous.value + (next.value - previous.value) * next.easing.apply(fraction))
+        }
+        (Some(keyframe), None) | (None, Some(keyframe)) => Some(keyframe.value),
+        (None, None) => None,
+    }
+}
+
+// The playhead
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    pub time: f64, // Simulated seconds
+    pub playing: bool,
+    last_simulation_time: Option<f64>, // Simulated time at the last update
+    seeked: bool, // The playhead was moved since the last update
+}
+
+impl Timeline {
+    // Start playing; at the end of the keyframes playing starts over
+    pub fn play(&mut self, keyframes: &[Keyframe]) {
+        if self.time >= duration(keyframes) {
+            self.seek(0.0);
+        }
+        self.playing = true;
+    }
+    
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+    
+    // Move the playhead; the values there are applied on the next update even when stopped
+    pub fn seek(&mut self, time: f64) {
+        self.time = time.max(0.0);
+        self.seeked = true;
+    }
+    
+    // Advance the playhead by the simulated time since the last update (a simulation reset
+    // moves its clock back, which doesn't move the playhead), then write the animated values
+    // into the sliders. Playing stops at the last keyframe.
+    pub fn update(&mut self, simulation_time: f64, params: &mut SimulationParams) {
+        let elapsed = self.last_simulation_time
+            .map_or(0.0, |last| (simulation_time - last).max(0.0));
+        self.last_simulation_time = Some(simulation_time);
+        
+        if !self.playing && !self.seeked {
+            return;
+        }
+        self.seeked = false;
+        
+        if self.playing {
+            self.time += elapsed;
+            let end = duration(&params.keyframes);
+            if self.time >= end {
+                self.time = end;
+                self.playing = false;
+            }
+        }
+        
+        for target in ModTarget::ALL {
+            if let Some(value) = value_at(&params.keyframes, target, self.time) {
+                *target.slider_value(params) = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f64, target: ModTarget, value: f32, easing: Easing) -> Keyframe {
+        Keyframe { time, target, value, easing }
+    }
+
+    fn assert_approx_eq(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "expected {} to be approximately {}", a, b);
+    }
+
+    #[test]
+    fn values_are_interpolated_between_the_keyframes_of_their_parameter() {
+        let keyframes = [
+            keyframe(60.0, ModTarget::CohesionWeight, 3.0, Easing::Linear),
+            keyframe(0.0, ModTarget::CohesionWeight, 0.0, Easing::Linear),
+            keyframe(30.0, ModTarget::SeparationWeight, 1.0, Easing::Linear),
+            keyframe(70.0, ModTarget::CohesionWeight, 1.0, Easing::EaseInOut),
+            keyframe(80.0, ModTarget::CohesionWeight, 2.0, Easing::Step),
+        ];
+
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 15.0).unwrap(), 0.75);
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 60.0).unwrap(), 3.0);
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 65.0).unwrap(), 2.0);
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 79.0).unwrap(), 1.0);
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 80.0).unwrap(), 2.0);
+
+        // Values hold before the first and after the last keyframe
+        assert_approx_eq(value_at(&keyframes, ModTarget::SeparationWeight, 0.0).unwrap(), 1.0);
+        assert_approx_eq(value_at(&keyframes, ModTarget::CohesionWeight, 500.0).unwrap(), 2.0);
+        assert_eq!(value_at(&keyframes, ModTarget::MaxSpeed, 10.0), None);
+        assert_eq!(duration(&keyframes), 80.0);
+    }
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in Easing::ALL {
+            assert_eq!(easing.apply(0.0), 0.0, "{}", easing.label());
+            assert_eq!(easing.apply(1.0), 1.0, "{}", easing.label());
+        }
+        assert_approx_eq(Easing::EaseIn.apply(0.5), 0.25);
+        assert_approx_eq(Easing::EaseOut.apply(0.5), 0.75);
+        assert_approx_eq(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn keyframes_round_trip_through_presets() {
+        let mut params = SimulationParams::default();
+        params.keyframes = vec![
+            keyframe(0.0, ModTarget::CohesionWeight, 0.0, Easing::Linear),
+            keyframe(60.5, ModTarget::CohesionWeight, 3.0, Easing::EaseInOut),
+            keyframe(75.0, ModTarget::SeparationRadius, 80.0, Easing::Step),
+        ];
+
+        let mut loaded = SimulationParams::default();
+        loaded.apply_preset(&params.to_preset()).unwrap();
+        assert_eq!(loaded.keyframes, params.keyframes);
+
+        // A preset with keyframes replaces the animation; one without keeps it
+        loaded.apply_preset("keyframe = \"5, max_speed, 2, ease_out\"").unwrap();
+        assert_eq!(loaded.keyframes, vec![keyframe(5.0, ModTarget::MaxSpeed, 2.0, Easing::EaseOut)]);
+        loaded.apply_preset("max_speed = 3").unwrap();
+        assert_eq!(loaded.keyframes.len(), 1);
+
+        assert!(Keyframe::parse("5, max_speed, 2").is_err());
+        assert!(Keyframe::parse("-1, max_speed, 2, linear").is_err());
+        assert!(Keyframe::parse("5, num_boids, 2, linear").is_err());
+        assert!(Keyframe::parse("5, max_speed, 2, bounce").is_err());
+    }
+
+    #[test]
+    fn the_playhead_follows_simulated_time_while_playing() {
+        let mut params = SimulationParams::default();
+        params.keyframes = vec![
+            keyframe(0.0, ModTarget::CohesionWeight, 0.0, Easing::Linear),
+            keyframe(10.0, ModTarget::CohesionWeight, 2.0, Easing::Linear),
+        ];
+        let mut timeline = Timeline::default();
+        params.cohesion_weight = 1.5;
+
+        // Stopped, the sliders are left alone
+        timeline.update(0.0, &mut params);
+        timeline.update(5.0, &mut params);
+        assert_eq!(timeline.time, 0.0);
+        assert_eq!(params.cohesion_weight, 1.5);
+
+        timeline.play(&params.keyframes);
+        timeline.update(7.5, &mut params);
+        assert_approx_eq(params.cohesion_weight, 0.5);
+
+        // A paused simulation doesn't move the playhead, and a reset doesn't move it back
+        timeline.update(7.5, &mut params);
+        timeline.update(0.0, &mut params);
+        assert_eq!(timeline.time, 2.5);
+        timeline.update(5.0, &mut params);
+        assert_approx_eq(params.cohesion_weight, 1.5);
+
+        // Seeking applies the values even when stopped
+        timeline.stop();
+        timeline.seek(1.0);
+        timeline.update(6.0, &mut params);
+        assert_approx_eq(params.cohesion_weight, 0.2);
+        assert_eq!(timeline.time, 1.0);
+
+        // Playing stops at the last keyframe, and playing again starts over
+        timeline.play(&params.keyframes);
+        timeline.update(100.0, &mut params);
+        assert!(!timeline.playing);
+        assert_eq!(timeline.time, 10.0);
+        assert_approx_eq(params.cohesion_weight, 2.0);
+        timeline.play(&params.keyframes);
+        assert_eq!(timeline.time, 0.0);
+    }
+}
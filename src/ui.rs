@@ -4,179 +4,1945 @@
  * This module handles the user interface for the boid simulation.
  * It provides controls for adjusting simulation parameters and displays
  * debug information when enabled.
+ * 
+ * The controls are grouped into collapsible sections below a menu bar. Which
+ * sections are open, compact mode and whether the window is closed make up
+ * the UiLayout, which is saved in the config file.
  */
 
 use nannou::prelude::*;
 use nannou_egui::egui;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use nannou_egui::egui::emath::Numeric;
+use nannou_egui::egui::plot::{Line, Plot, Value, Values};
+use crate::boid::{boid_render_velocity, BoidShape, MAX_ENERGY};
 use crate::app::Model;
-use crate::params::SimulationParams;
-use crate::debug::DebugInfo;
+use crate::camera::{Camera, FollowTarget};
+use crate::config;
+use crate::params::{InitialLayout, SeparationFalloff, SimulationParams};
+use crate::physics_thread::SimulationView;
+use crate::region::{ParamOverrides, ParamRegion};
+use crate::spatial_grid::SpatialGrid;
+use crate::debug::{DebugInfo, NeighborHistogram, SimulationClock, SpeedStats, NEIGHBOR_BUCKETS, PHASE_AVERAGE_FRAMES, SPEED_HISTORY_SECONDS};
+use crate::export::ExportFormat;
+use crate::modulation::{self, Mapping, ModSource, ModTarget, Waveform};
+use crate::palette::{self, ColorMode, Palette, PaletteColors};
+use crate::renderer;
+use crate::tags::TAG_NAMES;
+use crate::timeline::{self, Easing, Keyframe};
 
 // UI response structure
 pub struct UiResponse {
-    // reset_boids field removed
+    pub reset_boids: bool,
+    pub reset_params: bool,
+    pub fit_view: bool,
+    pub species_changed: bool,
+    pub reload_breeds: bool, // Re-read the breeds file
+    pub deselect_boid: bool,
+    pub delete_boid: Option<usize>,
+    pub toggle_frozen: Option<usize>,
+    pub cure_infection: bool,
+    pub clear_walls: bool,
+    pub add_region: bool,
+    pub delete_region: Option<usize>,
+    pub regions_changed: bool, // The selected region's overrides were edited
+    pub tag_boids: Option<u8>, // Tag the boids in the selected region (or on screen)
+    pub clear_tags: bool,
+    pub step: bool,
+    pub export_frame: bool,
+    pub toggle_continuous_export: bool,
+    pub save_config: bool,
+    pub save_preset: bool,
+    pub load_preset: Option<PathBuf>,
+    pub screenshot: bool,
+}
+
+// Collapsible sections of the controls window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UiSection {
+    BoidParameters,
+    Forces,
+    Perception,
+    Modulation,
+    Timeline,
+    World,
+    Camera,
+    Performance,
+    Timing,
+    Export,
+    Streaming,
+    Osc,
+    Help,
+    Debug,
+}
+
+impl UiSection {
+    // In display order; a section's index in this list is its discriminant
+    pub const ALL: [UiSection; 14] = [
+        UiSection::BoidParameters,
+        UiSection::Forces,
+        UiSection::Perception,
+        UiSection::Modulation,
+        UiSection::Timeline,
+        UiSection::World,
+        UiSection::Camera,
+        UiSection::Performance,
+        UiSection::Timing,
+        UiSection::Export,
+        UiSection::Streaming,
+        UiSection::Osc,
+        UiSection::Help,
+        UiSection::Debug,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            UiSection::BoidParameters => "Boid Parameters",
+            UiSection::Forces => "Forces",
+            UiSection::Perception => "Perception",
+            UiSection::Modulation => "Modulation",
+            UiSection::Timeline => "Timeline",
+            UiSection::World => "World",
+            UiSection::Camera => "Camera",
+            UiSection::Performance => "Performance",
+            UiSection::Timing => "Timing",
+            UiSection::Export => "Export",
+            UiSection::Streaming => "Network Streaming",
+            UiSection::Osc => "OSC Output",
+            UiSection::Help => "Help",
+            UiSection::Debug => "Debug",
+        }
+    }
+    
+    // Key of the section's open state in the config file's [ui] section
+    pub fn key(self) -> &'static str {
+        match self {
+            UiSection::BoidParameters => "boid_parameters",
+            UiSection::Forces => "forces",
+            UiSection::Perception => "perception",
+            UiSection::Modulation => "modulation",
+            UiSection::Timeline => "timeline",
+            UiSection::World => "world",
+            UiSection::Camera => "camera",
+            UiSection::Performance => "performance",
+            UiSection::Timing => "timing",
+            UiSection::Export => "export",
+            UiSection::Streaming => "streaming",
+            UiSection::Osc => "osc",
+            UiSection::Help => "help",
+            UiSection::Debug => "debug",
+        }
+    }
+    
+    // Sections that are open until the user closes them
+    fn open_by_default(self) -> bool {
+        !matches!(self, UiSection::Modulation | UiSection::Timeline | UiSection::Performance | UiSection::Timing | UiSection::Export | UiSection::Streaming | UiSection::Osc | UiSection::Help)
+    }
+}
+
+// Layout of the controls window, kept across frames and saved in the config file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UiLayout {
+    pub window_open: bool, // Shown, rather than closed with its close button
+    pub compact: bool, // Only show the most-used sliders
+    open_sections: [bool; UiSection::ALL.len()],
+}
+
+impl Default for UiLayout {
+    fn default() -> Self {
+        Self {
+            window_open: true,
+            compact: false,
+            open_sections: UiSection::ALL.map(UiSection::open_by_default),
+        }
+    }
+}
+
+impl UiLayout {
+    pub fn is_open(&self, section: UiSection) -> bool {
+        self.open_sections[section as usize]
+    }
+    
+    pub fn set_open(&mut self, section: UiSection, open: bool) {
+        self.open_sections[section as usize] = open;
+    }
+}
+
+// What egui only reads when the controls window first appears: its position. A new
+// generation re-creates the window there.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowState {
+    generation: u32,
+    position: Option<egui::Pos2>,
+}
+
+impl WindowState {
+    // Move the window back on screen after the main window shrank, keeping at least
+    // its top left corner visible
+    pub fn fit_to(&mut self, screen_size: Vec2) {
+        let Some(position) = self.position else {
+            return;
+        };
+        let max = egui::pos2((screen_size.x - 100.0).max(0.0), (screen_size.y - 40.0).max(0.0));
+        let fitted = position.min(max).max(egui::pos2(0.0, 0.0));
+        if fitted != position {
+            self.position = Some(fitted);
+            self.generation += 1;
+        }
+    }
+}
+
+// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+// A short message shown over the scene for a few seconds, even while the controls window
+// is hidden (e.g. the result of reloading the watched parameter file)
+pub struct Toast {
+    text: String,
+    is_error: bool,
+    shown_at: Instant,
+}
+
+impl Toast {
+    pub fn new(text: String, is_error: bool) -> Self {
+        Self { text, is_error, shown_at: Instant::now() }
+    }
 }
 
 // Update the UI
 pub fn update_ui(app: &App, model: &mut Model, update: &Update) -> UiResponse {
-    // reset_boids variable removed
+    let mut reset_boids = false;
+    let mut reset_params = false;
+    let mut fit_view = false;
+    let mut species_changed = false;
+    let mut reload_breeds = false;
+    let mut deselect = false;
+    let mut delete_selected = None;
+    let mut toggle_frozen = None;
+    let mut cure_infection = false;
+    let mut clear_walls = false;
+    let mut add_region = false;
+    let mut delete_region = None;
+    let mut regions_changed = false;
+    let mut tag_boids = None;
+    let mut clear_tags = false;
+    let mut step = false;
+    let mut export_frame = false;
+    let mut toggle_continuous_export = false;
+    let mut save_config = false;
+    let mut save_preset = false;
+    let mut load_preset = None;
+    let mut screenshot = false;
     
     // Begin UI frame
     let ctx = model.egui.begin_frame();
     
-    // Create a window for the UI
-    egui::Window::new("Simulation Controls")
-        .default_pos([20.0, 20.0])
+    // Built-in defaults, which each parameter control can be reset to
+    let defaults = SimulationParams::default();
+    
+    // Identify the boid under the cursor
+    if let Some((index, boid)) = model.hovered_boid_index.and_then(|index| model.view.boids.get(index).map(|boid| (index, boid))) {
+        let speed = boid_render_velocity(boid, &model.params, model.view.interpolation_alpha).length();
+        egui::show_tooltip_at_pointer(&ctx, egui::Id::new("hovered_boid"), |ui| {
+            ui.label(format!("Boid {}", index));
+            ui.label(format!("Speed: {:.2}", speed));
+            ui.label(format!("Species: {}", model.params.species_name(boid.species as usize)));
+        });
+    }
+    
+    // Cursor coordinates, shown even while the window is hidden
+    if model.params.show_cursor_info {
+        cursor_readout(&ctx, &model.params, &model.camera, &model.view.spatial_grid, model.mouse_position, app.window_rect());
+    }
+    
+    if model.params.show_debug {
+        perception_legend(&ctx, &model.params, &model.regions, &model.view, model.selected_boid_index);
+    }
+    
+    if matches!(&model.toast, Some(toast) if toast.shown_at.elapsed() >= TOAST_DURATION) {
+        model.toast = None;
+    }
+    if let Some(toast) = &model.toast {
+        show_toast(&ctx, toast);
+    }
+    
+    // Gear button in the top right corner: shows the window when it is hidden or closed,
+    // and hides it otherwise
+    egui::Area::new("controls_toggle")
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
         .show(&ctx, |ui| {
-            ui.heading("Boid Parameters");
-            
-            // Number of boids slider - direct control without mapping
-            ui.add(egui::Slider::new(&mut model.params.num_boids, *SimulationParams::get_num_boids_range().start()..=*SimulationParams::get_num_boids_range().end())
-                .text("Number of Boids")
-                .clamp_to_range(true));
-            
-            // Weights
-            ui.add(egui::Slider::new(&mut model.params.separation_weight, SimulationParams::get_weight_range())
-                .text("Separation Weight")
-                .clamp_to_range(true));
-            
-            ui.add(egui::Slider::new(&mut model.params.alignment_weight, SimulationParams::get_weight_range())
-                .text("Alignment Weight")
-                .clamp_to_range(true));
-            
-            ui.add(egui::Slider::new(&mut model.params.cohesion_weight, SimulationParams::get_weight_range())
-                .text("Cohesion Weight")
-                .clamp_to_range(true));
-            
-            // Perception radii
-            ui.add(egui::Slider::new(&mut model.params.separation_radius, SimulationParams::get_radius_range())
-                .text("Separation Radius")
-                .clamp_to_range(true));
+            let hover = if model.ui_visible { "Hide the controls (H)" } else { "Show the controls (H)" };
+            if ui.button("⚙").on_hover_text(hover).clicked() {
+                if !model.ui_visible || !model.ui_layout.window_open {
+                    model.ui_visible = true;
+                    model.ui_layout.window_open = true;
+                } else {
+                    model.ui_visible = false;
+                }
+            }
+        });
+    
+    // The window can be hidden with the H shortcut
+    if !model.ui_visible {
+        return UiResponse {
+            reset_boids,
+            reset_params,
+            fit_view,
+            species_changed,
+            reload_breeds,
+            deselect_boid: deselect,
+            delete_boid: delete_selected,
+            toggle_frozen,
+            cure_infection,
+            clear_walls,
+            add_region,
+            delete_region,
+            regions_changed,
+            tag_boids,
+            clear_tags,
+            step,
+            export_frame,
+            toggle_continuous_export,
+            save_config,
+            save_preset,
+            load_preset,
+            screenshot,
+        };
+    }
+    
+    // The sections also borrow the model, so their open states are tracked in a copy
+    let mut layout = model.ui_layout;
+    
+    // Create a window for the UI (resizable, scrolling once it is shorter than its contents)
+    let mut window_open = layout.window_open;
+    let window = egui::Window::new("Simulation Controls")
+        .id(egui::Id::new("simulation_controls").with(model.ui_window.generation))
+        .default_pos(model.ui_window.position.unwrap_or(egui::pos2(20.0, 20.0)))
+        .open(&mut window_open)
+        .resizable(true)
+        .vscroll(true)
+        .show(&ctx, |ui| {
+            // Menu bar
+            egui::menu::bar(ui, |ui| {
+                egui::menu::menu(ui, "Reset", |ui| {
+                    if ui.button("Reset Boids").clicked() {
+                        reset_boids = true;
+                    }
+                    if ui.button("Reset Parameters to Defaults").clicked() {
+                        reset_params = true;
+                    }
+                });
+                
+                egui::menu::menu(ui, "Presets", |ui| {
+                    if ui.button("Save as Default").on_hover_text("Write the current settings to the config file now").clicked() {
+                        save_config = true;
+                    }
+                    if ui.button("Save as Preset").on_hover_text(format!("Write the current settings to a new file in {}/", config::PRESET_DIR)).clicked() {
+                        save_preset = true;
+                    }
+                    
+                    ui.separator();
+                    
+                    // Load one of the presets in the preset directory
+                    let presets = config::preset_files(Path::new(config::PRESET_DIR));
+                    if presets.is_empty() {
+                        ui.label(format!("No presets in {}/", config::PRESET_DIR));
+                    }
+                    for path in presets {
+                        let name = path.file_stem().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                        if ui.button(name).clicked() {
+                            load_preset = Some(path);
+                        }
+                    }
+                });
+                
+                if ui.button("Screenshot").clicked() {
+                    screenshot = true;
+                }
+                
+                ui.checkbox(&mut layout.compact, "Compact");
+            });
             
-            ui.add(egui::Slider::new(&mut model.params.alignment_radius, SimulationParams::get_radius_range())
-                .text("Alignment Radius")
-                .clamp_to_range(true));
+            // Simulated against wall time since the last reset
+            status_strip(ui, &model.debug_info.lock().unwrap().simulation_clock, model.params.pause_simulation);
             
-            ui.add(egui::Slider::new(&mut model.params.cohesion_radius, SimulationParams::get_radius_range())
-                .text("Cohesion Radius")
-                .clamp_to_range(true));
+            // Result of the last save, preset or screenshot
+            if let Some(status) = &model.status_message {
+                ui.label(status);
+            }
             
-            // Max speed
-            ui.add(egui::Slider::new(&mut model.params.max_speed, SimulationParams::get_max_speed_range())
-                .text("Max Speed")
-                .clamp_to_range(true));
+            // Pause toggle, with single stepping while paused
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut model.params.pause_simulation, "Pause Simulation");
+                if model.params.pause_simulation && ui.button("Step").clicked() {
+                    step = true;
+                }
+            });
             
-            // World size slider
-            ui.add(egui::Slider::new(&mut model.params.world_size, SimulationParams::get_world_size_range())
-                .text("World Size")
-                .clamp_to_range(true));
+            // Compact mode only shows the most-used sliders
+            if layout.compact {
+                compact_controls(ui, &mut model.params, &defaults, &mut model.clamp_flash);
+                return;
+            }
             
-            // Reset button removed
+            section(ui, &mut layout, UiSection::BoidParameters, |ui| {
+                // Target population slider; the flock grows or shrinks toward it while running
+                ParamSlider::new(&mut model.params.num_boids, SimulationParams::get_num_boids_range(), defaults.num_boids)
+                    .text("Target Boids")
+                    .show(ui, &mut model.clamp_flash);
+                ui.label(format!("Alive: {}", model.view.boids.len()));
+                ui.checkbox(&mut model.params.instant_resize, "Instant Resize")
+                    .on_hover_text("Add or remove boids as soon as the target changes, instead of a few thousand per second");
+                
+                // Where the boids start after a reset (picking a layout resets them)
+                let layout = model.params.initial_layout;
+                egui::ComboBox::from_label("Initial Layout")
+                    .selected_text(layout.label())
+                    .show_ui(ui, |ui| {
+                        for option in InitialLayout::ALL {
+                            ui.selectable_value(&mut model.params.initial_layout, option, option.label());
+                        }
+                    });
+                if model.params.initial_layout != layout {
+                    reset_boids = true;
+                }
+                ParamSlider::new(&mut model.params.min_spawn_spacing, SimulationParams::get_min_spawn_spacing_range(), defaults.min_spawn_spacing)
+                    .text("Min Spawn Spacing")
+                    .show(ui, &mut model.clamp_flash)
+                    .on_hover_text("New boids are placed at least this far apart when there is room (0 = anywhere)");
+                
+                // Quick buttons to add or remove boids without reshuffling the flock
+                ui.horizontal(|ui| {
+                    let range = SimulationParams::get_num_boids_range();
+                    if ui.button("-100").clicked() {
+                        model.params.num_boids = model.params.num_boids.saturating_sub(100).max(*range.start());
+                    }
+                    if ui.button("+100").clicked() {
+                        model.params.num_boids = (model.params.num_boids + 100).min(*range.end());
+                    }
+                });
+                
+                // Right-button emission rate
+                ParamSlider::new(&mut model.params.emission_rate, SimulationParams::get_emission_rate_range(), defaults.emission_rate)
+                    .text("Emission Rate (boids/s, hold right mouse)")
+                    .logarithmic()
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Spawning bursts of boids at the cursor
+                ui.checkbox(&mut model.params.spawn_at_cursor, "Spawn at Cursor (click empty space)");
+                if model.params.spawn_at_cursor {
+                    ParamSlider::new(&mut model.params.spawn_burst_size, SimulationParams::get_spawn_burst_size_range(), defaults.spawn_burst_size)
+                        .text("Burst Size")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Dragging boids to set up scenarios
+                ui.checkbox(&mut model.params.edit_mode, "Edit Mode (drag boids, or hold Ctrl)");
+                
+                // Walls: click twice to draw one, click near one in edit mode to delete it
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut model.params.wall_mode, "Wall Mode (click twice, right-click cancels)");
+                    if !model.walls.is_empty() && ui.button(format!("Clear Walls ({})", model.walls.len())).clicked() {
+                        clear_walls = true;
+                    }
+                });
+                if model.params.wall_mode || !model.walls.is_empty() {
+                    ParamSlider::new(&mut model.params.wall_avoid_radius, SimulationParams::get_wall_avoid_radius_range(), defaults.wall_avoid_radius)
+                        .text("Wall Avoid Radius")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.wall_avoid_weight, SimulationParams::get_weight_range(), defaults.wall_avoid_weight)
+                        .text("Wall Avoid Weight")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Species editor: the sliders below edit the active species
+                let previous_species = model.params.active_species;
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Species")
+                        .selected_text(model.params.species_name(model.params.active_species).to_string())
+                        .show_ui(ui, |ui| {
+                            for i in 0..model.params.species.len() {
+                                let name = model.params.species_name(i).to_string();
+                                ui.selectable_value(&mut model.params.active_species, i, name);
+                            }
+                        });
+                    
+                    let active = model.params.active_species;
+                    ui.color_edit_button_srgb(&mut model.params.species[active].color);
+                });
+                
+                if model.params.active_species != previous_species {
+                    model.params.load_active_species();
+                }
+                
+                ui.horizontal(|ui| {
+                    if ui.button("Add Species").clicked() {
+                        model.params.add_species();
+                        species_changed = true;
+                    }
+                    
+                    if model.params.species.len() > 1 && ui.button("Remove Species").clicked() {
+                        model.params.remove_active_species();
+                        species_changed = true;
+                    }
+                    
+                    if ui.button("Apply to All Species").clicked() {
+                        // Make sure the latest slider values are what gets copied
+                        model.params.sync_active_species();
+                        model.params.apply_active_to_all_species();
+                    }
+                });
+                
+                // Drawn size and steering force of the active species
+                let active = model.params.active_species;
+                ParamSlider::new(&mut model.params.species[active].size, SimulationParams::get_species_size_range(), defaults.species[0].size)
+                    .text("Species Size")
+                    .show(ui, &mut model.clamp_flash);
+                ParamSlider::new(&mut model.params.species[active].max_force, SimulationParams::get_max_force_range(), defaults.species[0].max_force)
+                    .text("Max Force")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Mixture: each species' percentage of the flock (the others follow, so the
+                // shares keep adding up to 100)
+                if model.params.species.len() > 1 {
+                    ui.label("Mixture");
+                    let shares: Vec<f32> = model.params.species.iter().map(|species| species.share).collect();
+                    for (i, mut share) in shares.into_iter().enumerate() {
+                        let slider = egui::Slider::new(&mut share, 0.0..=100.0)
+                            .text(format!("{} (%)", model.params.species_name(i)))
+                            .clamp_to_range(true);
+                        if ui.add(slider).changed() {
+                            model.params.set_species_share(i, share);
+                            species_changed = true;
+                        }
+                    }
+                }
+                
+                // Breeds are species defined in a file (see the breeds module)
+                let breeds_hover = format!("Replace the species with the breeds in {}", model.breeds_path.display());
+                if ui.button("Reload Breeds").on_hover_text(breeds_hover).clicked() {
+                    reload_breeds = true;
+                }
+                
+                // Infection mode: clicking a boid converts it to the infected species, which
+                // then converts every boid it touches
+                let was_armed = model.params.infection_mode;
+                ui.checkbox(&mut model.params.infection_mode, "Infection Mode (click a boid to infect it)");
+                if model.params.infection_mode && !was_armed && model.params.species.len() < 2 {
+                    // The infection needs a species to spread; add one without moving any boids into it
+                    let active = model.params.active_species;
+                    model.params.add_species();
+                    model.params.active_species = active;
+                    model.params.load_active_species();
+                }
+                if model.params.infection_mode {
+                    // Keep the choice valid after species are removed
+                    let last_species = model.params.species.len() - 1;
+                    model.params.infected_species = model.params.infected_species.clamp(1, last_species.max(1));
+                    
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("Infected Species")
+                            .selected_text(model.params.species_name(model.params.infected_species).to_string())
+                            .show_ui(ui, |ui| {
+                                // The first species is the base species that cured boids return to
+                                for i in 1..model.params.species.len() {
+                                    let name = model.params.species_name(i).to_string();
+                                    ui.selectable_value(&mut model.params.infected_species, i, name);
+                                }
+                            });
+                        
+                        if ui.button("Cure All").clicked() {
+                            cure_infection = true;
+                        }
+                    });
+                    
+                    ParamSlider::new(&mut model.params.contact_radius, SimulationParams::get_contact_radius_range(), defaults.contact_radius)
+                        .text("Contact Radius")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Energy mode: boids starve unless they flock and are replaced after a delay
+                ui.checkbox(&mut model.params.enable_energy, "Energy Mode (boids starve unless flocking)");
+                if model.params.enable_energy {
+                    ParamSlider::new(&mut model.params.energy_drain_rate, SimulationParams::get_energy_rate_range(), defaults.energy_drain_rate)
+                        .text("Energy Drain (/s)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.energy_gain_rate, SimulationParams::get_energy_rate_range(), defaults.energy_gain_rate)
+                        .text("Energy Gain (/s)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.energy_neighbor_threshold, SimulationParams::get_energy_neighbor_threshold_range(), defaults.energy_neighbor_threshold)
+                        .text("Flocking Neighbors (more than)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.respawn_delay, SimulationParams::get_respawn_delay_range(), defaults.respawn_delay)
+                        .text("Respawn Delay (s)")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Pheromones: boids leave fading trails and follow the trails ahead of them
+                ui.checkbox(&mut model.params.enable_pheromones, "Pheromone Trails");
+                if model.params.enable_pheromones {
+                    ParamSlider::new(&mut model.params.pheromone_deposit, SimulationParams::get_pheromone_deposit_range(), defaults.pheromone_deposit)
+                        .text("Deposit (per step)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.pheromone_decay, SimulationParams::get_pheromone_decay_range(), defaults.pheromone_decay)
+                        .text("Decay (per step)")
+                        .logarithmic()
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.pheromone_weight, SimulationParams::get_weight_range(), defaults.pheromone_weight)
+                        .text("Trail Following Weight")
+                        .show(ui, &mut model.clamp_flash);
+                    ui.checkbox(&mut model.params.show_pheromones, "Show Pheromone Heatmap");
+                }
+                
+                // Perching: boids land in a band at the bottom of the world and rest for a while
+                ui.checkbox(&mut model.params.enable_perching, "Perching (boids land at the bottom and rest)");
+                if model.params.enable_perching {
+                    ParamSlider::new(&mut model.params.perch_band_height, SimulationParams::get_perch_band_height_range(), defaults.perch_band_height)
+                        .text("Ground Band Height")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.perch_chance, SimulationParams::get_perch_chance_range(), defaults.perch_chance)
+                        .text("Landing Chance")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.perch_min_rest, SimulationParams::get_perch_rest_range(), defaults.perch_min_rest)
+                        .text("Min Rest (s)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.perch_max_rest, SimulationParams::get_perch_rest_range(), defaults.perch_max_rest)
+                        .text("Max Rest (s)")
+                        .show(ui, &mut model.clamp_flash);
+                    ParamSlider::new(&mut model.params.perch_takeoff_speed, SimulationParams::get_perch_takeoff_speed_range(), defaults.perch_takeoff_speed)
+                        .text("Takeoff Speed")
+                        .show(ui, &mut model.clamp_flash);
+                    ui.checkbox(&mut model.params.perched_influence, "Perched Boids Influence Neighbors");
+                }
+            });
             
-            ui.separator();
+            section(ui, &mut layout, UiSection::Forces, |ui| {
+                // Weights, each with a toggle that switches its rule off entirely
+                rule_slider(ui, &mut model.clamp_flash, &mut model.params.enable_separation, &mut model.params.separation_weight, defaults.separation_weight, "Separation Weight");
+                rule_slider(ui, &mut model.clamp_flash, &mut model.params.enable_alignment, &mut model.params.alignment_weight, defaults.alignment_weight, "Alignment Weight");
+                rule_slider(ui, &mut model.clamp_flash, &mut model.params.enable_cohesion, &mut model.params.cohesion_weight, defaults.cohesion_weight, "Cohesion Weight");
+                
+                // How the separation push falls off with distance (hover a curve for what it does)
+                let falloff = model.params.separation_falloff;
+                egui::ComboBox::from_label("Separation Falloff")
+                    .selected_text(falloff.label())
+                    .show_ui(ui, |ui| {
+                        for option in SeparationFalloff::ALL {
+                            ui.selectable_value(&mut model.params.separation_falloff, option, option.label())
+                                .on_hover_text(option.description());
+                        }
+                    })
+                    .response
+                    .on_hover_text(falloff.description());
+                if model.params.separation_falloff == SeparationFalloff::Power {
+                    ParamSlider::new(&mut model.params.separation_falloff_power, SimulationParams::get_separation_falloff_power_range(), defaults.separation_falloff_power)
+                        .text("Falloff Power")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Arrival: brake near the cohesion target instead of overshooting it
+                ui.checkbox(&mut model.params.enable_arrival, "Arrival (slow down near flock center)");
+                if model.params.enable_arrival {
+                    ParamSlider::new(&mut model.params.slowing_radius, SimulationParams::get_radius_range(), defaults.slowing_radius)
+                        .text("Slowing Radius")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Far-field cohesion: a weak pull toward distant groups, built from the grid cells
+                ParamSlider::new(&mut model.params.far_cohesion_weight, SimulationParams::get_weight_range(), defaults.far_cohesion_weight)
+                    .text("Far Cohesion")
+                    .show(ui, &mut model.clamp_flash);
+                if model.params.far_cohesion_weight > 0.0 {
+                    ParamSlider::new(&mut model.params.far_cohesion_radius, SimulationParams::get_far_cohesion_radius_range(), defaults.far_cohesion_radius)
+                        .text("Far Cohesion Radius")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Max speed (its range follows the world size while speeds are scaled with it)
+                let speed_range = scaled_range(SimulationParams::get_max_speed_range(), model.params.world_speed_scale());
+                ParamSlider::new(&mut model.params.max_speed, speed_range, defaults.max_speed)
+                    .text("Max Speed")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Noise ("temperature"): raise it to watch the flock go from ordered to disordered
+                ParamSlider::new(&mut model.params.angular_noise, SimulationParams::get_angular_noise_range(), defaults.angular_noise)
+                    .text("Angular Noise (°)")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Explore parameter space (chosen values are printed to stdout)
+                ui.horizontal(|ui| {
+                    if ui.button("Randomize").clicked() {
+                        model.params.randomize_physics();
+                    }
+                    
+                    if ui.button("Nudge").clicked() {
+                        model.params.nudge_physics();
+                    }
+                    
+                    ui.checkbox(&mut model.params.randomize_sane_ranges, "Sane Ranges");
+                });
+            });
             
-            ui.heading("Performance Settings");
+            section(ui, &mut layout, UiSection::Perception, |ui| {
+                // Perception radii (the effective values, which follow the world size if scaled with it)
+                let radius_scale = model.params.world_radius_scale();
+                let radius_range = scaled_range(SimulationParams::get_radius_range(), radius_scale);
+                ParamSlider::new(&mut model.params.separation_radius, radius_range.clone(), defaults.separation_radius)
+                    .text("Separation Radius")
+                    .show(ui, &mut model.clamp_flash);
+                
+                ParamSlider::new(&mut model.params.alignment_radius, radius_range.clone(), defaults.alignment_radius)
+                    .text("Alignment Radius")
+                    .show(ui, &mut model.clamp_flash);
+                
+                ParamSlider::new(&mut model.params.cohesion_radius, radius_range, defaults.cohesion_radius)
+                    .text("Cohesion Radius")
+                    .show(ui, &mut model.clamp_flash);
+                
+                if radius_scale != 1.0 {
+                    ui.label(format!("Scaled {:.2}x with the world size", radius_scale));
+                }
+            });
             
-            // Spatial grid toggle
-            ui.checkbox(&mut model.params.enable_spatial_grid, "Enable Spatial Grid");
+            section(ui, &mut layout, UiSection::Modulation, |ui| {
+                // LFOs swinging parameters around their slider values (e.g. for live visuals)
+                let time = model.modulation.time;
+                let mut remove = None;
+                for (index, mapping) in model.modulation.mappings.iter_mut().enumerate() {
+                    // Combo box ids default to their label, so each row's are keyed by the mapping index
+                    ui.scope(|ui| {
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source(egui::Id::new(("modulation target", index)))
+                                .selected_text(mapping.target.label())
+                                .show_ui(ui, |ui| {
+                                    for target in ModTarget::ALL {
+                                        ui.selectable_value(&mut mapping.target, target, target.label());
+                                    }
+                                });
+                            if ui.button("Remove").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                        
+                        let ModSource::Lfo { waveform, rate } = &mut mapping.source;
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source(egui::Id::new(("modulation waveform", index)))
+                                .selected_text(waveform.label())
+                                .show_ui(ui, |ui| {
+                                    for option in Waveform::ALL {
+                                        ui.selectable_value(waveform, option, option.label());
+                                    }
+                                });
+                            ui.label("Waveform");
+                        });
+                        ui.add(egui::Slider::new(rate, modulation::get_lfo_rate_range())
+                            .text("Rate (Hz)")
+                            .logarithmic(true)
+                            .clamp_to_range(true));
+                        
+                        let range = mapping.target.range();
+                        let span = modulation::span(&range);
+                        ui.add(egui::Slider::new(&mut mapping.amplitude, 0.0..=span)
+                            .text("Amplitude")
+                            .clamp_to_range(true));
+                        ui.add(egui::Slider::new(&mut mapping.offset, -span..=span)
+                            .text("Offset")
+                            .clamp_to_range(true));
+                        
+                        // The base value on the slider, with the modulated value marked over it
+                        let base = mapping.target.slider_value(&mut model.params);
+                        let modulated = mapping.modulated(*base, time);
+                        let response = ui.add(egui::Slider::new(base, range.clone())
+                            .text(format!("Base (now {:.2})", modulated))
+                            .clamp_to_range(true));
+                        slider_marker(ui, &response, range, modulated, MODULATION_MARKER_COLOR);
+                    });
+                    ui.separator();
+                }
+                if let Some(index) = remove {
+                    model.modulation.mappings.remove(index);
+                }
+                
+                if ui.button("Add Mapping").clicked() {
+                    model.modulation.mappings.push(Mapping::new(ModTarget::SeparationWeight));
+                }
+            });
             
-            if model.params.enable_spatial_grid {
-                // Cell size factor
-                ui.add(egui::Slider::new(&mut model.params.cell_size_factor, SimulationParams::get_cell_size_factor_range())
-                    .text("Cell Size Factor")
+            section(ui, &mut layout, UiSection::Timeline, |ui| {
+                // Keyframes moving the sliders over simulated time (e.g. for recording videos)
+                let duration = timeline::duration(&model.params.keyframes);
+                ui.horizontal(|ui| {
+                    if model.timeline.playing {
+                        if ui.button("Stop").clicked() {
+                            model.timeline.stop();
+                        }
+                    } else if ui.button("Play").clicked() {
+                        model.timeline.play(&model.params.keyframes);
+                    }
+                    ui.label(format!("{} / {}  (simulated {})",
+                        format_clock(model.timeline.time), format_clock(duration), format_clock(model.view.stats.simulation_time)));
+                });
+                
+                // Scrubber, with a marker at each keyframe
+                let range = 0.0..=duration.max(1.0) as f32;
+                let mut time = model.timeline.time as f32;
+                let response = ui.add(egui::Slider::new(&mut time, range.clone())
+                    .text("Time (s)")
                     .clamp_to_range(true));
+                if response.changed() {
+                    model.timeline.seek(time as f64);
+                }
+                for keyframe in &model.params.keyframes {
+                    slider_marker(ui, &response, range.clone(), keyframe.time as f32, KEYFRAME_MARKER_COLOR);
+                }
                 
-                // Adaptive cell sizing
-                ui.checkbox(&mut model.params.adaptive_cell_sizing, "Adaptive Cell Sizing");
+                // One row per keyframe, in time order (re-sorted once a time edit is finished)
+                let mut remove = None;
+                let mut reorder = false;
+                egui::Grid::new("keyframes").striped(true).show(ui, |ui| {
+                    for heading in ["Time (s)", "Parameter", "Value", "Easing", ""] {
+                        ui.label(heading);
+                    }
+                    ui.end_row();
+                    
+                    for (index, keyframe) in model.params.keyframes.iter_mut().enumerate() {
+                        let time_field = ui.add(egui::DragValue::new(&mut keyframe.time)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f64::MAX));
+                        reorder |= time_field.drag_released() || (time_field.changed() && !time_field.dragged());
+                        
+                        egui::ComboBox::from_id_source(("keyframe_target", index))
+                            .selected_text(keyframe.target.label())
+                            .show_ui(ui, |ui| {
+                                for target in ModTarget::ALL {
+                                    ui.selectable_value(&mut keyframe.target, target, target.label());
+                                }
+                            });
+                        
+                        let range = keyframe.target.range();
+                        keyframe.value = keyframe.value.clamp(*range.start(), *range.end());
+                        ui.add(egui::DragValue::new(&mut keyframe.value)
+                            .speed(modulation::span(&range) * 0.005)
+                            .clamp_range(range));
+                        
+                        egui::ComboBox::from_id_source(("keyframe_easing", index))
+                            .selected_text(keyframe.easing.label())
+                            .show_ui(ui, |ui| {
+                                for easing in Easing::ALL {
+                                    ui.selectable_value(&mut keyframe.easing, easing, easing.label());
+                                }
+                            });
+                        
+                        if ui.button("Remove").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(index) = remove {
+                    model.params.keyframes.remove(index);
+                }
+                if reorder {
+                    timeline::sort_keyframes(&mut model.params.keyframes);
+                }
                 
-                if model.params.adaptive_cell_sizing {
-                    ui.label(format!("Current Cell Size: {:.1}", model.spatial_grid.cell_size));
+                // New keyframes hold the slider's current value at the playhead
+                if ui.button("Add Keyframe").clicked() {
+                    let target = model.params.keyframes.last().map_or(ModTarget::CohesionWeight, |keyframe| keyframe.target);
+                    let value = *target.slider_value(&mut model.params);
+                    model.params.keyframes.push(Keyframe { time: model.timeline.time, target, value, easing: Easing::Linear });
+                    timeline::sort_keyframes(&mut model.params.keyframes);
                 }
-            }
-            
-            // Parallel processing toggle
-            ui.checkbox(&mut model.params.enable_parallel, "Enable Parallel Processing");
-            
-            // Squared distance toggle
-            ui.checkbox(&mut model.params.enable_squared_distance, "Use Squared Distances");
-            
-            // Frustum culling toggle
-            ui.checkbox(&mut model.params.enable_frustum_culling, "Enable Frustum Culling");
-            
-            ui.separator();
+            });
             
-            ui.heading("Timing Settings");
-            
-            // Physics FPS
-            ui.add(egui::Slider::new(&mut model.params.fixed_physics_fps, SimulationParams::get_physics_fps_range())
-                .text("Physics FPS")
-                .clamp_to_range(true));
-            
-            // Target render FPS
-            ui.add(egui::Slider::new(&mut model.params.target_render_fps, SimulationParams::get_render_fps_range())
-                .text("Target Render FPS (0 = unlimited)")
-                .clamp_to_range(true));
+            section(ui, &mut layout, UiSection::World, |ui| {
+                // World size slider
+                ParamSlider::new(&mut model.params.world_size, SimulationParams::get_world_size_range(), defaults.world_size)
+                    .text("World Size")
+                    .show(ui, &mut model.clamp_flash);
+                ui.checkbox(&mut model.params.scale_radii_with_world, "Scale Radii with World")
+                    .on_hover_text("Resizing the world scales the perception radii by the same ratio");
+                if model.params.scale_radii_with_world {
+                    ui.checkbox(&mut model.params.scale_speed_with_world, "Scale Max Speed Too");
+                }
+                
+                // Open left and right edges with a stream of boids across the world
+                ui.checkbox(&mut model.params.wind_tunnel_mode, "Wind Tunnel")
+                    .on_hover_text("Boids enter at the left edge and are removed once they leave the world");
+                if model.params.wind_tunnel_mode {
+                    ParamSlider::new(&mut model.params.wind_tunnel_rate, SimulationParams::get_wind_tunnel_rate_range(), defaults.wind_tunnel_rate)
+                        .text("Inflow (boids/s)")
+                        .logarithmic()
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Resulting spatial grid dimensions
+                ui.label(format!("Grid: {}x{} cells ({:.1} units each)",
+                    model.view.spatial_grid.grid_size, model.view.spatial_grid.grid_size, model.view.spatial_grid.cell_size));
+                
+                // Regions: rectangles with their own flocking parameters
+                egui::CollapsingHeader::new(format!("Regions ({})", model.regions.len()))
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("Add Region").clicked() {
+                                add_region = true;
+                            }
+                            if let Some(index) = model.selected_region {
+                                if ui.button("Delete Region").clicked() {
+                                    delete_region = Some(index);
+                                }
+                            }
+                        });
+                        ui.label("Drag a region's corners in edit mode to resize it");
+                        
+                        // Pick the region to edit
+                        for index in 0..model.regions.len() {
+                            ui.selectable_value(&mut model.selected_region, Some(index), format!("Region {}", index + 1));
+                        }
+                        
+                        // Overrides of the selected region, starting from the active species' values
+                        if let Some(index) = model.selected_region.filter(|&index| index < model.regions.len()) {
+                            regions_changed |= override_editor(ui, &mut model.regions[index].overrides, &model.params, &mut model.clamp_flash);
+                        }
+                    });
+                
+                // Tags: groups of boids followed as they disperse (measured in the debug section)
+                egui::CollapsingHeader::new("Tags")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        match model.selected_region.filter(|&index| index < model.regions.len()) {
+                            Some(index) => ui.label(format!("Tags the boids in Region {}", index + 1)),
+                            None => ui.label("Tags the boids on screen (select a region to tag its boids instead)"),
+                        };
+                        ui.horizontal(|ui| {
+                            for (tag, name) in TAG_NAMES.iter().enumerate() {
+                                if ui.button(format!("Tag as {}", name)).clicked() {
+                                    tag_boids = Some(tag as u8);
+                                }
+                            }
+                            if ui.button("Clear Tags").clicked() {
+                                clear_tags = true;
+                            }
+                        });
+                        ui.checkbox(&mut model.params.tint_tagged_boids, "Tint Tagged Boids")
+                            .on_hover_text("Draw tagged boids in their tag's color (in the species color mode)");
+                    });
+            });
             
-            // Interpolation toggle
-            ui.checkbox(&mut model.params.enable_interpolation, "Enable Interpolation");
+            section(ui, &mut layout, UiSection::Camera, |ui| {
+                // Zoom settings
+                ParamSlider::new(&mut model.params.zoom_sensitivity, SimulationParams::get_zoom_sensitivity_range(), defaults.zoom_sensitivity)
+                    .text("Zoom Sensitivity")
+                    .show(ui, &mut model.clamp_flash);
+                ui.checkbox(&mut model.params.invert_zoom, "Invert Zoom");
+                ui.checkbox(&mut model.params.preserve_view_on_resize, "Keep View on Resize")
+                    .on_hover_text("Zoom with the window when it is resized, so the same amount of the world stays visible");
+                
+                // How boids are drawn
+                ParamSlider::new(&mut model.params.boid_size, SimulationParams::get_boid_size_range(), defaults.boid_size)
+                    .text("Boid Size")
+                    .show(ui, &mut model.clamp_flash);
+                egui::ComboBox::from_label("Boid Shape")
+                    .selected_text(model.params.boid_shape.label())
+                    .show_ui(ui, |ui| {
+                        for shape in BoidShape::ALL {
+                            ui.selectable_value(&mut model.params.boid_shape, shape, shape.label());
+                        }
+                    });
+                ui.checkbox(&mut model.params.spawn_effects, "Spawn Effects")
+                    .on_hover_text("Boids fade in when they appear and fade out when they are removed (drawing only)");
+                
+                // Warning band inside the world edge
+                ui.checkbox(&mut model.params.show_edge_band, "Show Edge Band");
+                ui.checkbox(&mut model.params.tint_edge_boids, "Tint Boids Near the Edge");
+                if model.params.show_edge_band || model.params.tint_edge_boids {
+                    ParamSlider::new(&mut model.params.edge_band_width, SimulationParams::get_edge_band_width_range(), defaults.edge_band_width)
+                        .text("Edge Band Width")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // Debug coloring by spatial grid cell, to see the partitioning
+                egui::ComboBox::from_label("Color Boids By")
+                    .selected_text(model.params.color_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in ColorMode::ALL {
+                            ui.selectable_value(&mut model.params.color_mode, mode, mode.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Grid Cell gives each spatial grid cell its own color (only with the spatial grid)");
+                
+                // Palette, with a picker per color (editing one makes the palette custom)
+                egui::ComboBox::from_label("Palette")
+                    .selected_text(model.params.palette.label())
+                    .show_ui(ui, |ui| {
+                        for palette in Palette::ALL {
+                            if ui.selectable_label(model.params.palette == palette, palette.label()).clicked() {
+                                model.params.set_palette(palette);
+                            }
+                        }
+                    });
+                let mut colors = model.params.colors;
+                let mut colors_changed = false;
+                egui::Grid::new("palette_colors").show(ui, |ui| {
+                    for (label, color) in [
+                        ("Background", &mut colors.background),
+                        ("Boids", &mut colors.boid),
+                        ("Selection", &mut colors.selection),
+                        ("Debug", &mut colors.debug),
+                        ("Boundary", &mut colors.boundary),
+                    ] {
+                        ui.label(label);
+                        colors_changed |= ui.color_edit_button_srgb(color).changed();
+                        ui.end_row();
+                    }
+                });
+                if colors_changed {
+                    model.params.palette = Palette::Custom;
+                    model.params.set_boid_color(colors.boid);
+                    model.params.colors = colors;
+                }
+                
+                // Frame all boids in the window
+                if ui.button("Fit View (F)").clicked() {
+                    fit_view = true;
+                }
+                
+                // Camera follow target
+                let follow_label = |target: FollowTarget| match target {
+                    FollowTarget::None => "None".to_string(),
+                    FollowTarget::Boid(id) => format!("Boid #{}", id),
+                    FollowTarget::CenterOfMass => "Center of Mass".to_string(),
+                };
+                egui::ComboBox::from_label("Camera Follow")
+                    .selected_text(follow_label(model.camera.follow_target))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut model.camera.follow_target, FollowTarget::None, follow_label(FollowTarget::None));
+                        // Only offer boid following when a boid is selected
+                        if let Some(id) = model.selected_boid_id {
+                            ui.selectable_value(&mut model.camera.follow_target, FollowTarget::Boid(id), follow_label(FollowTarget::Boid(id)));
+                        }
+                        ui.selectable_value(&mut model.camera.follow_target, FollowTarget::CenterOfMass, follow_label(FollowTarget::CenterOfMass));
+                    });
+                ParamSlider::new(&mut model.params.follow_deadzone, SimulationParams::get_follow_deadzone_range(), defaults.follow_deadzone)
+                    .text("Follow Deadzone (fraction of window)")
+                    .show(ui, &mut model.clamp_flash);
+                ui.checkbox(&mut model.params.follow_look_ahead, "Look Ahead (lead a followed boid)");
+            });
             
-            ui.separator();
+            // Inspector for the selected boid
+            if model.selected_boid_id.is_some() || model.selected_boid_debug.is_some() {
+                ui.separator();
+                
+                egui::CollapsingHeader::new("Selected Boid")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        // The selection becomes invalid if the boid count drops below it
+                        let selected = model.selected_boid_index.filter(|&index| index < model.view.boids.len());
+                        let forces = model.selected_boid_debug.filter(|debug| Some(debug.index) == selected);
+                        
+                        ui.scope(|ui| {
+                            ui.set_enabled(selected.is_some());
+                            
+                            if let Some(index) = selected {
+                                let boid = &model.view.boids[index];
+                                let speed = boid.velocity.length();
+                                let heading = boid.velocity.y.atan2(boid.velocity.x).to_degrees();
+                                
+                                ui.label(format!("Id: {} (index {})", boid.id, index));
+                                ui.label(format!("Position: ({:.1}, {:.1})", boid.position.x, boid.position.y));
+                                ui.label(format!("Velocity: ({:.2}, {:.2})", boid.velocity.x, boid.velocity.y));
+                                ui.label(format!("Speed: {:.2}", speed));
+                                ui.label(format!("Heading: {:.1}°", heading));
+                            } else {
+                                ui.label("Selection is no longer valid");
+                            }
+                            
+                            // Forces from the last physics step (stale values are shown grayed out)
+                            let shown_forces = if selected.is_some() { forces } else { model.selected_boid_debug };
+                            if let Some(debug) = shown_forces {
+                                ui.label(format!("Neighbors: sep {} / align {} / coh {}",
+                                    debug.separation_count, debug.alignment_count, debug.cohesion_count));
+                                ui.label(format!("Separation Force: {:.4}", debug.separation_force.length()));
+                                ui.label(format!("Alignment Force: {:.4}", debug.alignment_force.length()));
+                                ui.label(format!("Cohesion Force: {:.4}", debug.cohesion_force.length()));
+                                ui.label(format!("Combined Force: {:.4}", debug.combined_force.length()));
+                            } else {
+                                ui.label("Forces: waiting for a physics step");
+                            }
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            if ui.button("Deselect").clicked() {
+                                deselect = true;
+                                if let FollowTarget::Boid(_) = model.camera.follow_target {
+                                    model.camera.follow_target = FollowTarget::None;
+                                }
+                            }
+                            
+                            if let Some(index) = selected {
+                                let frozen = model.view.boids[index].frozen;
+                                if ui.button(if frozen { "Unfreeze (P)" } else { "Freeze (P)" }).clicked() {
+                                    toggle_frozen = Some(index);
+                                }
+                                
+                                if ui.button("Delete").clicked() {
+                                    delete_selected = Some(index);
+                                }
+                            }
+                        });
+                    });
+            }
             
-            // Debug info toggle
-            ui.checkbox(&mut model.params.show_debug, "Show Debug Info");
+            section(ui, &mut layout, UiSection::Performance, |ui| {
+                // Spatial grid toggle
+                ui.checkbox(&mut model.params.enable_spatial_grid, "Enable Spatial Grid");
+                
+                if model.params.enable_spatial_grid {
+                    // Cell size factor
+                    ParamSlider::new(&mut model.params.cell_size_factor, SimulationParams::get_cell_size_factor_range(), defaults.cell_size_factor)
+                        .text("Cell Size Factor")
+                        .show(ui, &mut model.clamp_flash)
+                        .on_hover_text("Grid cell width relative to the largest perception radius. Smaller cells are searched in more rings, so this only changes speed, not which neighbors are found.");
+                    
+                    // Adaptive cell sizing
+                    ui.checkbox(&mut model.params.adaptive_cell_sizing, "Adaptive Cell Sizing");
+                    
+                    if model.params.adaptive_cell_sizing {
+                        ui.label(format!("Current Cell Size: {:.1}", model.view.spatial_grid.cell_size));
+                    }
+                    
+                    // Cache-friendly boid order (the speedup is shown in the frame breakdown)
+                    ui.checkbox(&mut model.params.enable_spatial_sorting, "Sort Boids by Grid Cell");
+                    
+                    if model.params.enable_spatial_sorting {
+                        ParamSlider::new(&mut model.params.spatial_sort_interval, SimulationParams::get_spatial_sort_interval_range(), defaults.spatial_sort_interval)
+                            .text("Sort Every N Steps")
+                            .show(ui, &mut model.clamp_flash);
+                    }
+                }
+                
+                // Parallel processing toggle
+                ui.checkbox(&mut model.params.enable_parallel, "Enable Parallel Processing");
+                
+                // Worker threads for the parallel physics (the pool is rebuilt when this changes)
+                if model.params.enable_parallel {
+                    ParamSlider::new(&mut model.params.num_threads, SimulationParams::get_num_threads_range(), defaults.num_threads)
+                        .text("Threads")
+                        .show(ui, &mut model.clamp_flash)
+                        .on_hover_text("Threads for the physics; 0 uses rayon's default of one per core");
+                }
+                
+                // Neighbor list reuse (only the grid pass keeps the lists)
+                if model.params.enable_spatial_grid {
+                    ParamSlider::new(&mut model.params.neighbor_list_interval, SimulationParams::get_neighbor_list_interval_range(), defaults.neighbor_list_interval)
+                        .text("Neighbor List Interval")
+                        .show(ui, &mut model.clamp_flash)
+                        .on_hover_text("Gather each boid's neighbors every this many physics steps and only measure the \
+                            distances again in between, skipping the grid rebuild too. Cheaper physics for slightly \
+                            less accurate flocking: boids that come within range are noticed up to this many steps late.");
+                }
+                
+                // Approximate square roots in the flocking rules
+                ui.checkbox(&mut model.params.fast_math, "Fast Math");
+                
+                // Frustum culling toggle
+                ui.checkbox(&mut model.params.enable_frustum_culling, "Enable Frustum Culling");
+                
+                // Margin around the window within which off-screen boids are still drawn
+                if model.params.enable_frustum_culling {
+                    ParamSlider::new(&mut model.params.culling_margin_multiplier, SimulationParams::get_culling_margin_multiplier_range(), defaults.culling_margin_multiplier)
+                        .text("Culling Margin")
+                        .show(ui, &mut model.clamp_flash);
+                }
+                
+                // One GPU draw call for all boids (the Line shape is always drawn one by one)
+                ui.checkbox(&mut model.params.instanced_rendering, "Instanced Boid Rendering");
+                
+                // Subsample the boids while drawing is slow, so the window stays responsive
+                ui.checkbox(&mut model.params.enable_render_budget, "Render Budget")
+                    .on_hover_text("Draw only every k-th visible boid while a frame takes longer than the budget to draw");
+                if model.params.enable_render_budget {
+                    ParamSlider::new(&mut model.params.render_budget_ms, SimulationParams::get_render_budget_ms_range(), defaults.render_budget_ms)
+                        .text("Draw Budget (ms)")
+                        .show(ui, &mut model.clamp_flash);
+                }
+            });
             
-            // Pause toggle
-            ui.checkbox(&mut model.params.pause_simulation, "Pause Simulation");
+            section(ui, &mut layout, UiSection::Timing, |ui| {
+                // Physics FPS
+                ParamSlider::new(&mut model.params.fixed_physics_fps, SimulationParams::get_physics_fps_range(), defaults.fixed_physics_fps)
+                    .text("Physics FPS")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Target render FPS
+                ParamSlider::new(&mut model.params.target_render_fps, SimulationParams::get_render_fps_range(), defaults.target_render_fps)
+                    .text("Target Render FPS (0 = unlimited)")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Interpolation toggle
+                ui.checkbox(&mut model.params.enable_interpolation, "Enable Interpolation");
+                
+                // Simulation speed
+                ParamSlider::new(&mut model.params.time_scale, SimulationParams::get_time_scale_range(), defaults.time_scale)
+                    .text("Time Scale")
+                    .logarithmic()
+                    .show(ui, &mut model.clamp_flash);
+                
+                ui.horizontal(|ui| {
+                    for scale in [0.25, 0.5, 1.0, 2.0, 4.0] {
+                        if ui.selectable_label(model.params.time_scale == scale, format!("{}x", scale)).clicked() {
+                            model.params.time_scale = scale;
+                        }
+                    }
+                });
+                
+                // Cap on physics steps per frame
+                ParamSlider::new(&mut model.params.max_steps_per_frame, SimulationParams::get_max_steps_per_frame_range(), defaults.max_steps_per_frame)
+                    .text("Max Physics Steps/Frame")
+                    .show(ui, &mut model.clamp_flash);
+                
+                // Subdivide each physics step for fast boids
+                ParamSlider::new(&mut model.params.physics_substeps, SimulationParams::get_physics_substeps_range(), defaults.physics_substeps)
+                    .text("Physics Substeps")
+                    .show(ui, &mut model.clamp_flash)
+                    .on_hover_text("Split every physics step into this many force and move passes. Fast boids then \
+                        react to walls and neighbors before flying past them, at up to this many times the force \
+                        cost per step (the grid is still built once per step).");
+            });
             
-            // Display debug info if enabled
-            if model.params.show_debug {
-                ui.separator();
-                ui.heading("Debug Info");
+            section(ui, &mut layout, UiSection::Export, |ui| {
+                // Output format for new exports
+                egui::ComboBox::from_label("Format")
+                    .selected_text(model.params.export_format.label())
+                    .show_ui(ui, |ui| {
+                        for format in [ExportFormat::Csv, ExportFormat::JsonLines] {
+                            ui.selectable_value(&mut model.params.export_format, format, format.label());
+                        }
+                    });
                 
-                ui.label(format!("FPS: {:.1}", app.fps()));
-                ui.label(format!("Frame Time: {:.2} ms", update.since_last.as_secs_f32() * 1000.0));
+                // Rows are appended every N physics steps in continuous mode
+                ParamSlider::new(&mut model.params.export_stride, SimulationParams::get_export_stride_range(), defaults.export_stride)
+                    .text("Continuous Stride (steps)")
+                    .logarithmic()
+                    .show(ui, &mut model.clamp_flash);
                 
-                let debug_info = unsafe { &*model.debug_info.get() };
+                let continuous_running = model.export_progress.as_ref()
+                    .is_some_and(|progress| progress.is_continuous() && progress.is_active());
                 
-                if let Some(chunk_size) = debug_info.chunk_size {
-                    ui.label(format!("Chunk Size: {}", chunk_size));
-                }
+                ui.horizontal(|ui| {
+                    if ui.button("Export Frame").clicked() {
+                        export_frame = true;
+                    }
+                    let label = if continuous_running { "Stop Continuous Export" } else { "Start Continuous Export" };
+                    if ui.button(label).clicked() {
+                        toggle_continuous_export = true;
+                    }
+                });
                 
-                if let Some(selected_boid) = debug_info.selected_boid_index {
-                    ui.label(format!("Selected Boid: {}", selected_boid));
-                    
-                    if debug_info.follow_mode_active {
-                        ui.label("Follow Mode: Active");
+                // Status of the current or last export
+                if let Some(progress) = &model.export_progress {
+                    let state = if progress.is_active() {
+                        "writing"
+                    } else if progress.is_done() {
+                        "done"
+                    } else {
+                        "finishing"
+                    };
+                    ui.label(format!("{} ({})", progress.path().display(), state));
+                    ui.label(format!("Rows written: {}", progress.rows_written()));
+                    if let Some(error) = progress.error() {
+                        ui.colored_label(egui::Color32::RED, error);
                     }
                 }
-                
-                if let Some(visible_count) = debug_info.visible_boids_count {
-                    ui.label(format!("Visible Boids: {}/{}", visible_count, model.boids.len()));
+                if let Some(error) = &model.export_error {
+                    ui.colored_label(egui::Color32::RED, error);
                 }
+            });
+            
+            section(ui, &mut layout, UiSection::Streaming, |ui| {
+                // Boid positions are sent to every client that subscribes to the UDP port
+                ui.checkbox(&mut model.params.enable_streaming, "Stream Boid Positions (UDP)");
+                ui.horizontal(|ui| {
+                    ui.label("Port");
+                    ui.add(egui::DragValue::new(&mut model.params.stream_port).clamp_range(1024..=65535));
+                });
+                ParamSlider::new(&mut model.params.stream_stride, SimulationParams::get_stream_stride_range(), defaults.stream_stride)
+                    .text("Stream Stride (steps)")
+                    .show(ui, &mut model.clamp_flash);
+                ParamSlider::new(&mut model.params.stream_max_boids, SimulationParams::get_stream_max_boids_range(), defaults.stream_max_boids)
+                    .text("Max Boids per Frame")
+                    .show(ui, &mut model.clamp_flash);
                 
-                if let Some(physics_updates) = debug_info.physics_updates_per_frame {
-                    ui.label(format!("Physics Updates: {}/frame", physics_updates));
+                // Status of the running stream
+                if let Some(stream) = &model.view.stats.stream {
+                    ui.label(format!("Streaming on UDP port {} to {} client(s)", stream.port(), stream.clients()));
+                    ui.label(format!("Frames sent: {}, dropped: {}", stream.frames_sent(), stream.frames_dropped()));
+                    if let Some(error) = stream.error() {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
                 }
+                if let Some(error) = &model.view.stats.stream_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+            
+            section(ui, &mut layout, UiSection::Osc, |ui| {
+                // Flock metrics sent as OSC messages, e.g. to drive lights or sound
+                ui.checkbox(&mut model.params.enable_osc, "Send OSC Messages");
+                ui.horizontal(|ui| {
+                    ui.label("Host");
+                    ui.text_edit_singleline(&mut model.params.osc_host);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Port");
+                    ui.add(egui::DragValue::new(&mut model.params.osc_port).clamp_range(1..=65535));
+                });
+                ParamSlider::new(&mut model.params.osc_rate, SimulationParams::get_osc_rate_range(), defaults.osc_rate)
+                    .text("Rate (per second)")
+                    .show(ui, &mut model.clamp_flash);
+                ui.checkbox(&mut model.params.osc_send_polarization, "/boids/polarization");
+                ui.checkbox(&mut model.params.osc_send_centroid, "/boids/centroid");
+                ui.checkbox(&mut model.params.osc_send_count, "/boids/count");
+                ui.checkbox(&mut model.params.osc_send_selected, "/boids/selected (selected boid)");
                 
-                if let Some(alpha) = debug_info.interpolation_alpha {
-                    ui.label(format!("Interpolation: {:.3}", alpha));
+                // Status of the running sender
+                if let Some(osc) = &model.view.stats.osc {
+                    ui.label(format!("Sending to {}", osc.target()));
+                    ui.label(format!("Messages sent: {}, batches dropped: {}", osc.messages_sent(), osc.batches_dropped()));
+                    if let Some(error) = osc.error() {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
                 }
+                if let Some(error) = &model.view.stats.osc_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+            
+            // Keyboard shortcut reference
+            section(ui, &mut layout, UiSection::Help, |ui| {
+                    ui.label("WASD / Arrows: Pan camera (Shift: faster)");
+                    ui.label("+/- or PageUp/PageDown: Zoom");
+                    ui.label("F: Fit view to all boids");
+                    ui.label("Space: Pause / resume");
+                    ui.label("F3: Toggle debug info");
+                    ui.label("G: Toggle spatial grid");
+                    ui.label("C: Toggle frustum culling");
+                    ui.label("H / Tab: Hide / show this window (or the ⚙ button)");
+                    ui.label("R: Reset boids");
+                    ui.label("Delete: Remove the selected boid");
+                    ui.label("P: Freeze / unfreeze the selected boid");
+                    ui.label("E: Export frame (Shift+E: start/stop continuous export)");
+                    ui.label("Escape: Deselect the boid and stop following, or else close this window");
+            });
+            
+            section(ui, &mut layout, UiSection::Debug, |ui| {
+                // Debug info toggle
+                ui.checkbox(&mut model.params.show_debug, "Show Debug Info");
                 
-                if let Some(culling_efficiency) = debug_info.culling_efficiency {
-                    ui.label(format!("Culling Efficiency: {:.1}%", culling_efficiency));
+                // Velocity vectors for all visible boids (only drawn in debug mode)
+                if model.params.show_debug {
+                    ui.checkbox(&mut model.params.show_velocity_vectors, "Show Velocity Vectors");
+                    
+                    // Circles around the selected boid (or the first), so one radius can be tuned alone
+                    ui.horizontal(|ui| {
+                        ui.label("Perception Circles:");
+                        ui.checkbox(&mut model.params.show_separation_radius, "Separation");
+                        ui.checkbox(&mut model.params.show_alignment_radius, "Alignment");
+                        ui.checkbox(&mut model.params.show_cohesion_radius, "Cohesion");
+                    });
                 }
                 
-                if let Some(frustum_ratio) = debug_info.frustum_area_ratio {
-                    ui.label(format!("Frustum/World Ratio: {:.2}%", frustum_ratio * 100.0));
+                // Cursor readout (the crosshair is only drawn in debug mode)
+                ui.checkbox(&mut model.params.show_cursor_info, "Show Cursor Coordinates");
+                
+                // Keep the metrics graph recording while the debug info is hidden
+                ui.checkbox(&mut model.params.compute_metrics, "Always Compute Flock Metrics");
+                
+                // Display debug info if enabled
+                if model.params.show_debug {
+                    ui.separator();
+                    
+                    ui.label(format!("FPS: {:.1}", app.fps()));
+                    ui.label(format!("Frame Time: {:.2} ms", update.since_last.as_secs_f32() * 1000.0));
+                    
+                    let debug_info = model.debug_info.lock().unwrap();
+                    
+                    if let Some(chunk_size) = debug_info.chunk_size {
+                        ui.label(format!("Chunk Size: {}", chunk_size));
+                    }
+                    
+                    if let Some(threads) = debug_info.physics_threads {
+                        ui.label(format!("Physics Threads: {}", threads));
+                    }
+                    
+                    if let Some(selected_boid) = debug_info.selected_boid_id {
+                        ui.label(format!("Selected Boid: {}", selected_boid));
+                    }
+                    
+                    match debug_info.follow_target {
+                        FollowTarget::None => {}
+                        FollowTarget::Boid(id) => { ui.label(format!("Follow Mode: Boid #{}", id)); }
+                        FollowTarget::CenterOfMass => { ui.label("Follow Mode: Center of Mass"); }
+                    }
+                    
+                    if let Some(visible_count) = debug_info.visible_boids_count {
+                        ui.label(format!("Visible Boids: {}/{}", visible_count, model.view.boids.len()));
+                    }
+                    
+                    if let Some(physics_updates) = debug_info.physics_updates_per_frame {
+                        ui.label(format!("Physics Updates: {}/frame (max {})", physics_updates,
+                            debug_info.physics_updates_max.unwrap_or(physics_updates)));
+                    }
+                    
+                    if let (Some(duration), Some(budget)) = (debug_info.physics_step_duration, debug_info.physics_step_budget) {
+                        // Steps per second the physics could run at, for comparing thread counts
+                        let seconds = duration.as_secs_f32();
+                        let throughput = if seconds > 0.0 { 1.0 / seconds } else { 0.0 };
+                        let text = format!("Physics Step: {:.2} ms (budget {:.2} ms, {:.0} steps/s)",
+                            seconds * 1000.0, budget.as_secs_f32() * 1000.0, throughput);
+                        if duration > budget {
+                            ui.colored_label(egui::Color32::RED, text);
+                        } else {
+                            ui.label(text);
+                        }
+                    }
+                    
+                    if debug_info.render_stride > 1 {
+                        ui.colored_label(egui::Color32::YELLOW, format!("Render Subsampling: 1 in {} boids", debug_info.render_stride));
+                    }
+                    
+                    if let Some(allocations) = debug_info.allocations_per_frame {
+                        ui.label(format!("Allocations: {}/frame", allocations));
+                    }
+                    
+                    if let Some(max_steps) = debug_info.max_steps_per_frame {
+                        ui.label(format!("Max Physics Steps: {}/frame{}", max_steps,
+                            if debug_info.physics_steps_capped { " (capped)" } else { "" }));
+                    }
+                    
+                    // The simulation runs slower than real time while steps are dropped
+                    if !debug_info.dropped_time.is_zero() {
+                        ui.colored_label(egui::Color32::YELLOW,
+                            format!("Dropped Time: {:.2} s", debug_info.dropped_time.as_secs_f64()));
+                    }
+                    
+                    if let Some(alpha) = debug_info.interpolation_alpha {
+                        ui.label(format!("Interpolation: {:.3}", alpha));
+                    }
+                    
+                    if let Some(culling_efficiency) = debug_info.culling_efficiency {
+                        ui.label(format!("Culling Efficiency: {:.1}%", culling_efficiency));
+                    }
+                    
+                    if let Some(frustum_ratio) = debug_info.frustum_area_ratio {
+                        ui.label(format!("Frustum/World Ratio: {:.2}%", frustum_ratio * 100.0));
+                    }
+                    
+                    // Frame time history graph
+                    let history = &debug_info.frame_history;
+                    if let Some(stats) = history.stats() {
+                        ui.label(format!("Frame Time (last {}): min {:.2} / avg {:.2} / max {:.2} ms",
+                            history.len(), stats.min_ms, stats.avg_ms, stats.max_ms));
+                        ui.label(format!("1% Low: {:.1} FPS", stats.one_percent_low_fps));
+                        
+                        let frame_time_line = Line::new(Values::from_values_iter(
+                            history.iter().enumerate().map(|(i, (frame_time, _))| Value::new(i as f64, frame_time as f64))
+                        )).name("Frame time (ms)");
+                        
+                        let physics_steps_line = Line::new(Values::from_values_iter(
+                            history.iter().enumerate().map(|(i, (_, steps))| Value::new(i as f64, steps as f64))
+                        )).name("Physics steps");
+                        
+                        ui.add(Plot::new("frame_history")
+                            .line(frame_time_line)
+                            .line(physics_steps_line)
+                            .include_y(0.0)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .height(120.0));
+                    }
+                    
+                    // Flock metrics: polarization and milling over time
+                    let metrics_history = &debug_info.metrics_history;
+                    if let Some(metrics) = metrics_history.latest() {
+                        ui.label(format!("Polarization: {:.3}", metrics.polarization));
+                        ui.label(format!("Angular Momentum: {:.3}", metrics.angular_momentum));
+                        match metrics.mean_nearest_neighbor_distance {
+                            Some(distance) => ui.label(format!("Mean Nearest Neighbor: {:.1}", distance)),
+                            None => ui.label("Mean Nearest Neighbor: -"),
+                        };
+                        
+                        let polarization_line = Line::new(Values::from_values_iter(
+                            metrics_history.iter().enumerate().map(|(i, metrics)| Value::new(i as f64, metrics.polarization as f64))
+                        )).name("Polarization");
+                        
+                        let angular_momentum_line = Line::new(Values::from_values_iter(
+                            metrics_history.iter().enumerate().map(|(i, metrics)| Value::new(i as f64, metrics.angular_momentum as f64))
+                        )).name("Angular momentum");
+                        
+                        ui.add(Plot::new("metrics_history")
+                            .line(polarization_line)
+                            .line(angular_momentum_line)
+                            .include_y(-1.0)
+                            .include_y(1.0)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .height(120.0));
+                    }
+                    
+                    // Tagged groups: where they are, how far they spread and how mixed they are
+                    let tag_metrics = TAG_NAMES.iter().zip(model.view.stats.tag_metrics)
+                        .filter_map(|(name, metrics)| metrics.map(|metrics| (name, metrics)));
+                    for (name, metrics) in tag_metrics {
+                        let centroid = metrics.centroid
+                            .map(|centroid| format!("({:.0}, {:.0})", centroid.x, centroid.y))
+                            .unwrap_or_else(|| "-".to_string());
+                        let spread = metrics.spread.map(|spread| format!("{:.1}", spread)).unwrap_or_else(|| "-".to_string());
+                        let mixing = metrics.mixing.map(|mixing| format!("{:.2}", mixing)).unwrap_or_else(|| "-".to_string());
+                        ui.label(format!("Tag {}: {} boids, centroid {}, spread {}, mixing {}",
+                            name, metrics.count, centroid, spread, mixing));
+                    }
+                    
+                    // Flock speed over the last seconds of simulated time: the mean inside a band
+                    // from the slowest to the fastest boid, and the selected boid's speed
+                    let speed_history = &debug_info.speed_history;
+                    if let Some((_, speeds)) = speed_history.latest() {
+                        ui.label(format!("Speed: mean {:.2} / min {:.2} / max {:.2} (max speed {:.2})",
+                            speeds.mean, speeds.min, speeds.max, model.params.max_speed));
+                        if let Some(selected) = speeds.selected {
+                            ui.label(format!("Selected Boid Speed: {:.2}", selected));
+                        }
+                        
+                        let band_color = egui::Color32::from_rgba_unmultiplied(100, 150, 255, 90);
+                        let speed_line = |value: fn(&SpeedStats) -> Option<f32>| Line::new(Values::from_values_iter(
+                            speed_history.recent().filter_map(move |(age, speeds)| value(&speeds).map(|speed| Value::new(age, speed as f64)))
+                        ));
+                        
+                        ui.add(Plot::new("speed_history")
+                            .line(speed_line(|speeds| Some(speeds.max)).color(band_color).name("Max"))
+                            .line(speed_line(|speeds| Some(speeds.mean)).color(egui::Color32::from_rgb(100, 150, 255)).name("Mean"))
+                            .line(speed_line(|speeds| Some(speeds.min)).color(band_color).name("Min"))
+                            .line(speed_line(|speeds| speeds.selected).color(egui::Color32::YELLOW).name("Selected"))
+                            .include_x(-SPEED_HISTORY_SECONDS)
+                            .include_x(0.0)
+                            .include_y(0.0)
+                            .include_y(model.params.max_speed as f64)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .height(120.0));
+                    }
+                    
+                    // Energy mode population
+                    if let (Some(alive), Some(energy)) = (debug_info.alive_boids, debug_info.average_energy) {
+                        ui.label(format!("Alive Boids: {} ({} respawning)", alive, debug_info.respawning_boids));
+                        ui.label(format!("Average Energy: {:.0}%", energy / MAX_ENERGY * 100.0));
+                    }
+                    
+                    // Distribution of the spatial grid neighbor counts over the whole flock
+                    if let Some(histogram) = &debug_info.neighbor_histogram {
+                        ui.label(format!("Neighbors per Boid: mean {:.1} / p95 {}", histogram.mean, histogram.p95));
+                        
+                        // One bar per bucket, painted directly (this egui has no bar chart);
+                        // hovering a bar shows its bucket and how many boids are in it
+                        let (rect, response) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 120.0), egui::Sense::hover());
+                        let painter = ui.painter();
+                        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                        
+                        let tallest = histogram.buckets.iter().copied().max().unwrap_or(0).max(1) as f32;
+                        let bar_width = rect.width() / NEIGHBOR_BUCKETS as f32;
+                        for (i, &boids) in histogram.buckets.iter().enumerate() {
+                            let left = rect.left() + i as f32 * bar_width;
+                            let top = rect.bottom() - rect.height() * boids as f32 / tallest;
+                            let bar = egui::Rect::from_min_max(egui::pos2(left + 1.0, top), egui::pos2(left + bar_width - 1.0, rect.bottom()));
+                            painter.rect_filled(bar, 0.0, ui.visuals().selection.bg_fill);
+                        }
+                        
+                        if let Some(pos) = response.hover_pos() {
+                            let i = (((pos.x - rect.left()) / bar_width) as usize).min(NEIGHBOR_BUCKETS - 1);
+                            response.on_hover_text(format!("{} neighbors: {} boids", NeighborHistogram::bucket_label(i), histogram.buckets[i]));
+                        }
+                        ui.small(format!("Neighbors: {} to {}", NeighborHistogram::bucket_label(0), NeighborHistogram::bucket_label(NEIGHBOR_BUCKETS - 1)));
+                    }
+                    
+                    // Boids per species over time (e.g. an infection sweeping through the flock)
+                    let species_history = &debug_info.species_history;
+                    if let Some(counts) = species_history.latest().filter(|counts| counts.len() > 1) {
+                        let species_color = |i: usize| model.params.species.get(i)
+                            .map_or(egui::Color32::GRAY, |species| egui::Color32::from_rgb(species.color[0], species.color[1], species.color[2]));
+                        
+                        for (i, count) in counts.iter().enumerate() {
+                            ui.colored_label(species_color(i), format!("{}: {} boids", model.params.species_name(i), count));
+                        }
+                        
+                        let mut plot = Plot::new("species_history")
+                            .include_y(0.0)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .height(120.0);
+                        for i in 0..counts.len() {
+                            let line = Line::new(Values::from_values_iter(
+                                species_history.series(i).enumerate().map(|(x, count)| Value::new(x as f64, count as f64))
+                            )).color(species_color(i)).name(model.params.species_name(i));
+                            plot = plot.line(line);
+                        }
+                        ui.add(plot);
+                    }
+                    
+                    // Per-phase breakdown as a share of the average frame time
+                    let breakdown = &debug_info.phase_breakdown;
+                    let frame_ms = breakdown.frame.average_ms();
+                    if frame_ms > 0.0 {
+                        egui::CollapsingHeader::new(format!("Frame Breakdown (avg of {} frames)", PHASE_AVERAGE_FRAMES))
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                let phases = [
+                                    ("Store previous state", &breakdown.store_previous),
+                                    ("Grid clear + insert", &breakdown.grid_rebuild),
+                                    ("Neighbor gathering", &breakdown.neighbor_gather),
+                                    ("Forces + wrap", &breakdown.forces),
+                                    ("Spatial sort", &breakdown.spatial_sort),
+                                    ("Culling", &breakdown.culling),
+                                    ("Draw", &breakdown.draw),
+                                ];
+                                for (name, average) in phases {
+                                    let ms = average.average_ms();
+                                    ui.label(format!("{}: {:.2} ms ({:.1}%)", name, ms, ms / frame_ms * 100.0));
+                                }
+                                ui.label(format!("Frame: {:.2} ms", frame_ms));
+                                if breakdown.substeps > 1 {
+                                    ui.label(format!("Forces + wrap run {} substeps per physics step", breakdown.substeps));
+                                }
+                                
+                                // Compare the neighbor and force phases with and without sorting
+                                if let Some(speedup) = breakdown.spatial_sort_speedup() {
+                                    ui.label(format!("Neighbors + forces: {:.2} ms unsorted, {:.2} ms sorted ({:.2}x)",
+                                        breakdown.neighbors_and_forces_unsorted.average_ms(),
+                                        breakdown.neighbors_and_forces_sorted.average_ms(),
+                                        speedup));
+                                }
+                            });
+                    }
                 }
-            }
+            });
         });
     
+    // Remember whether the window was closed, and where it is
+    layout.window_open = window_open;
+    if let Some(response) = window {
+        model.ui_window.position = Some(response.response.rect.min);
+    }
+    model.ui_layout = layout;
+    
     UiResponse {
-        // reset_boids field removed
+        reset_boids,
+        reset_params,
+        fit_view,
+        species_changed,
+        reload_breeds,
+        deselect_boid: deselect,
+        delete_boid: delete_selected,
+        toggle_frozen,
+        cure_infection,
+        clear_walls,
+        add_region,
+        delete_region,
+        regions_changed,
+        tag_boids,
+        clear_tags,
+        step,
+        export_frame,
+        toggle_continuous_export,
+        save_config,
+        save_preset,
+        load_preset,
+        screenshot,
+    }
+}
+
+// A collapsible section of the controls window. Its open state starts from the layout
+// (so it survives restarts) and is written back every frame.
+fn section(ui: &mut egui::Ui, layout: &mut UiLayout, section: UiSection, add_contents: impl FnOnce(&mut egui::Ui)) {
+    let response = egui::CollapsingHeader::new(section.label())
+        .default_open(layout.is_open(section))
+        .show(ui, add_contents);
+    layout.set_open(section, response.body_response.is_some());
+}
+
+// Cursor position in world coordinates, and the spatial grid cell under it with its boid
+// count, in the bottom left corner of the window
+fn cursor_readout(ctx: &egui::CtxRef, params: &SimulationParams, camera: &Camera, grid: &SpatialGrid, mouse_position: Vec2, window_rect: Rect) {
+    let world_size = params.world_size;
+    let cursor = camera.screen_to_world(mouse_position, window_rect);
+    
+    let mut text = format!("Cursor: ({:.1}, {:.1})", cursor.x, cursor.y);
+    if params.enable_spatial_grid {
+        match grid.cell_at(cursor, world_size) {
+            Some((column, row)) => {
+                let boids = grid.grid[row * grid.grid_size + column].len();
+                text += &format!("  Cell ({}, {}): {} boids", column, row, boids);
+            }
+            None => text += "  Outside the world",
+        }
+    }
+    
+    egui::Area::new("cursor_readout")
+        .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            ui.label(text);
+        });
+}
+
+// A toast at the top of the window, red for errors
+fn show_toast(ctx: &egui::CtxRef, toast: &Toast) {
+    egui::Area::new("toast")
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                if toast.is_error {
+                    ui.colored_label(egui::Color32::RED, &toast.text);
+                } else {
+                    ui.label(&toast.text);
+                }
+            });
+        });
+}
+
+// The color, rule and radius of each perception circle drawn around the debug boid, in
+// the bottom right corner of the window. Rules that don't affect the boid are dimmed.
+fn perception_legend(ctx: &egui::CtxRef, params: &SimulationParams, regions: &[ParamRegion], view: &SimulationView, selected_index: Option<usize>) {
+    let (id, circles) = match renderer::perception_boid(&view.boids, selected_index) {
+        Some(boid) => (boid.id, renderer::perception_circles(params, regions, view.interpolation_alpha, boid)),
+        None => return,
+    };
+    if circles.is_empty() {
+        return;
+    }
+    
+    egui::Area::new("perception_legend")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("Perception of boid #{}", id));
+                for circle in circles {
+                    let color = egui::Color32::from_rgb(circle.color.red, circle.color.green, circle.color.blue);
+                    let text = format!("{}: {:.0}", circle.label, circle.radius);
+                    if circle.active {
+                        ui.colored_label(color, text);
+                    } else {
+                        ui.colored_label(color.linear_multiply(0.4), format!("{} (off)", text));
+                    }
+                }
+            });
+        });
+}
+
+// One line with the step count, the simulated and (running) wall time since the last reset
+// and the real-time factor, in yellow while the simulation falls behind the clock
+fn status_strip(ui: &mut egui::Ui, clock: &SimulationClock, paused: bool) {
+    let factor = match clock.realtime_factor() {
+        _ if paused => "paused".to_string(),
+        Some(factor) => format!("{:.2}x real time", factor),
+        None => "measuring".to_string(),
+    };
+    let text = format!("Step {}  |  Sim {}  |  Wall {}  |  {}",
+        clock.step, format_clock(clock.simulation_time), format_clock(clock.wall_time), factor);
+    
+    match clock.realtime_factor() {
+        Some(factor) if !paused && factor < 0.95 => ui.colored_label(egui::Color32::YELLOW, text),
+        _ => ui.label(text),
+    };
+}
+
+// Seconds as m:ss.s, or h:mm:ss.s from an hour on
+fn format_clock(seconds: f64) -> String {
+    let tenths = (seconds.max(0.0) * 10.0).round() as u64;
+    let (hours, minutes, seconds) = (tenths / 36_000, tenths / 600 % 60, tenths % 600);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}.{}", hours, minutes, seconds / 10, seconds % 10)
+    } else {
+        format!("{}:{:02}.{}", minutes, seconds / 10, seconds % 10)
     }
 }
 
+// Seconds a numeric field stays outlined after a typed value was clamped to its range
+const CLAMP_FLASH_SECONDS: f64 = 0.6;
+
+// A parameter's slider followed by a field showing its exact value, which can be dragged
+// or typed into. Typed values are clamped to the slider's range (the field is outlined
+// in red for a moment when that happens). Right-clicking either widget resets the
+// parameter to its default immediately: there is no menu and no confirmation.
+struct ParamSlider<'a, T: Numeric> {
+    value: &'a mut T,
+    range: RangeInclusive<T>,
+    default: T,
+    text: &'a str,
+    logarithmic: bool,
+}
+
+impl<'a, T: Numeric> ParamSlider<'a, T> {
+    fn new(value: &'a mut T, range: RangeInclusive<T>, default: T) -> Self {
+        Self { value, range, default, text: "", logarithmic: false }
+    }
+    
+    fn text(mut self, text: &'a str) -> Self {
+        self.text = text;
+        self
+    }
+    
+    fn logarithmic(mut self) -> Self {
+        self.logarithmic = true;
+        self
+    }
+    
+    // Drag step of the numeric field: a power of ten giving a few hundred steps across the
+    // range (1 for integers)
+    fn step(&self) -> f64 {
+        let span = self.range.end().to_f64() - self.range.start().to_f64();
+        let step = 10f64.powf((span / 300.0).log10().floor());
+        if T::INTEGRAL { step.max(1.0) } else { step }
+    }
+    
+    // Add both widgets on one line; `flash` remembers the field that clamped a value last
+    fn show(self, ui: &mut egui::Ui, flash: &mut Option<(egui::Id, f64)>) -> egui::Response {
+        let step = self.step();
+        let Self { value, range, default, text, logarithmic } = self;
+        
+        ui.horizontal(|ui| {
+            let before = *value;
+            let slider = ui.add(egui::Slider::new(&mut *value, range.clone())
+                .logarithmic(logarithmic)
+                .show_value(false)
+                .clamp_to_range(true));
+            let field = ui.add(egui::DragValue::new(&mut *value).speed(step));
+            ui.label(text);
+            
+            // The field itself doesn't clamp, so the clamping can be noticed
+            let (min, max) = (range.start().to_f64(), range.end().to_f64());
+            let now = ui.input().time;
+            if field.changed() && !(min..=max).contains(&value.to_f64()) {
+                *value = T::from_f64(value.to_f64().clamp(min, max));
+                *flash = Some((field.id, now));
+            }
+            if let Some((id, time)) = *flash {
+                if id == field.id && now - time < CLAMP_FLASH_SECONDS {
+                    ui.painter().rect_stroke(field.rect, 2.0, (1.5, egui::Color32::RED));
+                    ui.ctx().request_repaint();
+                }
+            }
+            
+            let mut response = slider.union(field).on_hover_text("Right-click to reset to the default");
+            if response.secondary_clicked() {
+                *value = default;
+            }
+            
+            // Report a reset like any other edit
+            if *value != before {
+                response.mark_changed();
+            }
+            response
+        }).inner
+    }
+}
+
+// A flocking rule's weight slider, with a checkbox that enables the rule (the slider is
+// grayed out while it is disabled)
+fn rule_slider(ui: &mut egui::Ui, flash: &mut Option<(egui::Id, f64)>, enabled: &mut bool, weight: &mut f32, default: f32, text: &str) {
+    ui.horizontal(|ui| {
+        ui.checkbox(enabled, "");
+        ui.set_enabled(*enabled);
+        ParamSlider::new(weight, SimulationParams::get_weight_range(), default)
+            .text(text)
+            .show(ui, flash);
+    });
+}
+
+// A slider range multiplied by a scale (for values scaled with the world size)
+fn scaled_range(range: RangeInclusive<f32>, scale: f32) -> RangeInclusive<f32> {
+    range.start() * scale..=range.end() * scale
+}
+
+// Colors of the slider markers for modulated values and keyframes
+const MODULATION_MARKER_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 170, 0);
+const KEYFRAME_MARKER_COLOR: egui::Color32 = egui::Color32::from_rgb(90, 170, 255);
+
+// A vertical line over a slider's track at the position of a value
+fn slider_marker(ui: &egui::Ui, slider: &egui::Response, range: std::ops::RangeInclusive<f32>, value: f32, color: egui::Color32) {
+    // The track spans slider_width from the left of the response, inset by the handle radius
+    let rect = slider.rect;
+    let handle_radius = rect.height() / 2.5;
+    let left = rect.left() + handle_radius;
+    let right = rect.left() + ui.spacing().slider_width - handle_radius;
+    let fraction = (value - range.start()) / modulation::span(&range);
+    let x = left + (right - left) * fraction.clamp(0.0, 1.0);
+    
+    ui.painter().line_segment(
+        [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+        (2.0, color),
+    );
+}
+
+// The most-used sliders, shown instead of the sections in compact mode
+fn compact_controls(ui: &mut egui::Ui, params: &mut SimulationParams, defaults: &SimulationParams, flash: &mut Option<(egui::Id, f64)>) {
+    ParamSlider::new(&mut params.num_boids, SimulationParams::get_num_boids_range(), defaults.num_boids)
+        .text("Target Boids")
+        .logarithmic()
+        .show(ui, flash);
+    ParamSlider::new(&mut params.separation_weight, SimulationParams::get_weight_range(), defaults.separation_weight)
+        .text("Separation Weight")
+        .show(ui, flash);
+    ParamSlider::new(&mut params.alignment_weight, SimulationParams::get_weight_range(), defaults.alignment_weight)
+        .text("Alignment Weight")
+        .show(ui, flash);
+    ParamSlider::new(&mut params.cohesion_weight, SimulationParams::get_weight_range(), defaults.cohesion_weight)
+        .text("Cohesion Weight")
+        .show(ui, flash);
+    let speed_range = scaled_range(SimulationParams::get_max_speed_range(), params.world_speed_scale());
+    ParamSlider::new(&mut params.max_speed, speed_range, defaults.max_speed)
+        .text("Max Speed")
+        .show(ui, flash);
+    ParamSlider::new(&mut params.time_scale, SimulationParams::get_time_scale_range(), defaults.time_scale)
+        .text("Time Scale")
+        .logarithmic()
+        .show(ui, flash);
+}
+
+// Checkboxes choosing which parameters a region overrides, each with a slider for
+// the value used inside the region (resetting it goes back to the species' value).
+// Returns whether anything changed.
+fn override_editor(ui: &mut egui::Ui, overrides: &mut ParamOverrides, params: &SimulationParams, flash: &mut Option<(egui::Id, f64)>) -> bool {
+    let fields = [
+        ("Separation Weight", &mut overrides.separation_weight, params.separation_weight, SimulationParams::get_weight_range()),
+        ("Alignment Weight", &mut overrides.alignment_weight, params.alignment_weight, SimulationParams::get_weight_range()),
+        ("Cohesion Weight", &mut overrides.cohesion_weight, params.cohesion_weight, SimulationParams::get_weight_range()),
+        ("Separation Radius", &mut overrides.separation_radius, params.separation_radius, SimulationParams::get_radius_range()),
+        ("Alignment Radius", &mut overrides.alignment_radius, params.alignment_radius, SimulationParams::get_radius_range()),
+        ("Cohesion Radius", &mut overrides.cohesion_radius, params.cohesion_radius, SimulationParams::get_radius_range()),
+    ];
+    
+    let mut changed = false;
+    for (label, value, species_value, range) in fields {
+        ui.horizontal(|ui| {
+            let mut enabled = value.is_some();
+            if ui.checkbox(&mut enabled, label).changed() {
+                *value = enabled.then_some(species_value);
+                changed = true;
+            }
+            if let Some(value) = value {
+                changed |= ParamSlider::new(value, range, species_value).show(ui, flash).changed();
+            }
+        });
+    }
+    
+    changed
+}
+
 // Draw debug information on the screen
 pub fn draw_debug_info(
     draw: &nannou::Draw, 
@@ -184,13 +1950,14 @@ pub fn draw_debug_info(
     window_rect: nannou::geom::Rect, 
     boids_len: usize,
     camera_zoom: f32,
-    world_size: f32
+    world_size: f32,
+    colors: &PaletteColors
 ) {
     // Create a background panel in the top-left corner
     let margin = 20.0;
     let line_height = 20.0;
     let panel_width = 300.0; // Increased width for longer text
-    let panel_height = line_height * 17.0 + margin; // Increased for additional grid stats lines
+    let panel_height = line_height * 18.0 + margin; // Increased for additional grid stats lines
     let panel_x = window_rect.left() + panel_width / 2.0;
     let panel_y = window_rect.top() - panel_height / 2.0;
     
@@ -198,7 +1965,7 @@ pub fn draw_debug_info(
     draw.rect()
         .x_y(panel_x, panel_y)
         .w_h(panel_width, panel_height)
-        .color(nannou::color::rgba(0.0, 0.0, 0.0, 0.7));
+        .color(palette::color_alpha(colors.background, 0.7));
     
     // For left-aligned text in nannou, we need to position each text element
     // at the left edge of our panel plus half the text's width
@@ -213,7 +1980,10 @@ pub fn draw_debug_info(
     let mut debug_texts = vec![
         format!("FPS: {:.1}", debug_info.fps),
         format!("Frame time: {:.2} ms", debug_info.frame_time.as_secs_f64() * 1000.0),
-        format!("Physics updates: {}", debug_info.physics_updates_per_frame.unwrap_or(0)),
+        format!("Physics updates: {} (max {})", debug_info.physics_updates_per_frame.unwrap_or(0),
+                debug_info.physics_updates_max.unwrap_or(0)),
+        format!("Physics step: {:.2} ms", debug_info.physics_step_duration.unwrap_or_default().as_secs_f64() * 1000.0),
+        format!("Dropped time: {:.2} s", debug_info.dropped_time.as_secs_f64()),
         format!("Interpolation: {:.3}", debug_info.interpolation_alpha.unwrap_or(0.0)),
         format!("Total Boids: {}", boids_len),
         format!("Visible Boids: {}", debug_info.visible_boids_count.unwrap_or(0)),
@@ -237,12 +2007,12 @@ pub fn draw_debug_info(
     }
     
     // Add selected boid information
-    if let Some(boid_idx) = debug_info.selected_boid_index {
-        debug_texts.push(format!("Selected Boid: #{}", boid_idx));
-        debug_texts.push(if debug_info.follow_mode_active {
-            "Camera: Following boid".to_string()
-        } else {
-            "Camera: Free movement".to_string()
+    if let Some(boid_id) = debug_info.selected_boid_id {
+        debug_texts.push(format!("Selected Boid: #{}", boid_id));
+        debug_texts.push(match debug_info.follow_target {
+            FollowTarget::Boid(_) => "Camera: Following boid".to_string(),
+            FollowTarget::CenterOfMass => "Camera: Following center of mass".to_string(),
+            FollowTarget::None => "Camera: Free movement".to_string(),
         });
     } else {
         debug_texts.push("No boid selected".to_string());
@@ -254,6 +2024,6 @@ pub fn draw_debug_info(
         draw.text(text)
             .left_justify()
             .x_y(text_x, text_y - i as f32 * line_height)
-            .color(nannou::color::WHITE);
+            .color(palette::color(colors.debug));
     }
 } 
\ No newline at end of file
@@ -0,0 +1,201 @@
+/*
+ * Wall Module
+ * 
+ * This module defines impassable walls drawn as line segments, and the WallGrid
+ * used to find the walls near a boid without testing every wall.
+ * 
+ * Boids are kept off walls in two ways:
+ * - A steering force away from the closest point of every wall within the avoidance radius
+ * - A hard correction that stops a boid at a wall if its step would cross it
+ * 
+ * Walls don't wrap around the world edges: a wall near one edge has no effect on
+ * boids just across the opposite edge.
+ */
+
+use nannou::prelude::*;
+
+// Distance kept between a boid stopped by a wall and the wall itself
+pub const WALL_MARGIN: f32 = 0.5;
+
+// A wall segment from a to b
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wall {
+    pub a: Point2,
+    pub b: Point2,
+}
+
+impl Wall {
+    pub fn new(a: Point2, b: Point2) -> Self {
+        Self { a, b }
+    }
+    
+    // The point on the wall closest to a position
+    pub fn closest_point(&self, position: Point2) -> Point2 {
+        let segment = self.b - self.a;
+        let length_squared = segment.length_squared();
+        if length_squared <= 0.0 {
+            return self.a;
+        }
+        
+        let t = ((position - self.a).dot(segment) / length_squared).clamp(0.0, 1.0);
+        self.a + segment * t
+    }
+    
+    pub fn distance_squared(&self, position: Point2) -> f32 {
+        self.closest_point(position).distance_squared(position)
+    }
+    
+    // Where the movement from `from` to `to` crosses the wall, as the fraction of the
+    // movement travelled before the crossing (segment-segment intersection)
+    pub fn crossing(&self, from: Point2, to: Point2) -> Option<f32> {
+        let movement = to - from;
+        let segment = self.b - self.a;
+        let denominator = movement.perp_dot(segment);
+        if denominator.abs() <= f32::EPSILON {
+            return None; // Parallel (or no movement)
+        }
+        
+        let offset = self.a - from;
+        let t = offset.perp_dot(segment) / denominator; // Along the movement
+        let u = offset.perp_dot(movement) / denominator; // Along the wall
+        
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            Some(t)
+        } else {
+            None
+        }
+    }
+    
+    // Unit normal of the wall on the side of `position`
+    pub fn normal_towards(&self, position: Point2) -> Vec2 {
+        let normal = (self.b - self.a).perp().normalize_or_zero();
+        if (position - self.a).dot(normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    }
+}
+
+// Uniform grid of wall indices. Each wall is inserted into every cell it passes through,
+// and cells are at least as large as the avoidance radius, so the walls within that
+// radius of a position are always in its cell or one of the eight cells around it.
+// Walls rarely change, so unlike the boid grid this one is only rebuilt when they do.
+pub struct WallGrid {
+    cell_size: f32,
+    grid_size: usize,
+    world_size: f32,
+    cells: Vec<Vec<usize>>,
+}
+
+impl WallGrid {
+    pub fn new(walls: &[Wall], cell_size: f32, world_size: f32) -> Self {
+        // Keep the cell count reasonable for tiny radii
+        let cell_size = cell_size.max(world_size / 256.0);
+        let grid_size = ((world_size / cell_size).ceil() as usize).max(1);
+        let mut grid = Self {
+            cell_size,
+            grid_size,
+            world_size,
+            cells: vec![Vec::new(); grid_size * grid_size],
+        };
+        
+        for (index, wall) in walls.iter().enumerate() {
+            grid.insert(index, wall);
+        }
+        
+        grid
+    }
+    
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+    
+    // Cell coordinates of a position, clamped to the grid
+    fn cell_coords(&self, position: Point2) -> (usize, usize) {
+        let half_world = self.world_size / 2.0;
+        let to_cell = |value: f32| (((value + half_world) / self.cell_size).floor().max(0.0) as usize).min(self.grid_size - 1);
+        (to_cell(position.x), to_cell(position.y))
+    }
+    
+    // Add a wall to every cell whose square it passes through (or comes within half a
+    // cell diagonal of, which is cheap to test and never misses a cell)
+    fn insert(&mut self, index: usize, wall: &Wall) {
+        let (min_x, min_y) = self.cell_coords(wall.a.min(wall.b));
+        let (max_x, max_y) = self.cell_coords(wall.a.max(wall.b));
+        let half_world = self.world_size / 2.0;
+        let half_diagonal_squared = self.cell_size * self.cell_size / 2.0;
+        
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let center = pt2(
+                    (x as f32 + 0.5) * self.cell_size - half_world,
+                    (y as f32 + 0.5) * self.cell_size - half_world,
+                );
+                if wall.distance_squared(center) <= half_diagonal_squared {
+                    self.cells[y * self.grid_size + x].push(index);
+                }
+            }
+        }
+    }
+    
+    // Indices of the walls in the 3x3 cells around a position, without duplicates
+    pub fn walls_near(&self, position: Point2, out: &mut Vec<usize>) {
+        out.clear();
+        let (cell_x, cell_y) = self.cell_coords(position);
+        
+        for y in cell_y.saturating_sub(1)..=(cell_y + 1).min(self.grid_size - 1) {
+            for x in cell_x.saturating_sub(1)..=(cell_x + 1).min(self.grid_size - 1) {
+                out.extend_from_slice(&self.cells[y * self.grid_size + x]);
+            }
+        }
+        
+        // A wall spanning several cells is listed once per cell
+        out.sort_unstable();
+        out.dedup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_point_is_clamped_to_the_segment() {
+        let wall = Wall::new(pt2(0.0, 0.0), pt2(10.0, 0.0));
+
+        assert_eq!(wall.closest_point(pt2(5.0, 3.0)), pt2(5.0, 0.0));
+        assert_eq!(wall.closest_point(pt2(-4.0, 3.0)), pt2(0.0, 0.0));
+        assert_eq!(wall.distance_squared(pt2(13.0, 4.0)), 25.0);
+    }
+
+    #[test]
+    fn crossing_reports_where_the_movement_hits_the_wall() {
+        let wall = Wall::new(pt2(0.0, -10.0), pt2(0.0, 10.0));
+
+        assert_eq!(wall.crossing(pt2(-2.0, 0.0), pt2(2.0, 0.0)), Some(0.5));
+        assert_eq!(wall.crossing(pt2(-2.0, 0.0), pt2(-1.0, 0.0)), None);
+        assert_eq!(wall.crossing(pt2(-2.0, 20.0), pt2(2.0, 20.0)), None);
+        assert_eq!(wall.normal_towards(pt2(-2.0, 0.0)), vec2(-1.0, 0.0));
+    }
+
+    #[test]
+    fn grid_finds_long_walls_from_any_cell_they_pass() {
+        let walls = [
+            Wall::new(pt2(-400.0, -300.0), pt2(450.0, 350.0)),
+            Wall::new(pt2(-450.0, 450.0), pt2(-400.0, 450.0)),
+        ];
+        let grid = WallGrid::new(&walls, 50.0, 1000.0);
+        let mut near = Vec::new();
+
+        // Anywhere within a cell of the diagonal wall finds it exactly once
+        for i in 0..=20 {
+            let point = walls[0].a.lerp(walls[0].b, i as f32 / 20.0) + vec2(30.0, -30.0);
+            grid.walls_near(point, &mut near);
+            assert_eq!(near, vec![0]);
+        }
+
+        grid.walls_near(pt2(-420.0, 440.0), &mut near);
+        assert_eq!(near, vec![1]);
+    }
+}
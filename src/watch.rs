@@ -0,0 +1,109 @@
+/*
+ * Watch Module
+ * 
+ * Watches the parameter file given with --watch-params, so its values can be edited in a
+ * text editor while the simulation runs (e.g. during a live performance). A background
+ * thread checks the file's modification time and size every second and reports each
+ * change; the app then applies the file like a preset loaded from the UI, through the
+ * usual change detection.
+ * 
+ * The file is reported once at startup as well, and again when it is deleted, so a
+ * missing or unreadable file shows up as an error instead of being ignored.
+ */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How often the app's watcher checks the file
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Modification time and size of the file (None if it can't be read). The size catches
+// a rewrite within the file system's timestamp resolution.
+type FileStamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> FileStamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Holds a flag rather than a channel receiver, so the app's model stays shareable
+// with the rayon workers
+pub struct ParamWatcher {
+    path: PathBuf,
+    changed: Arc<AtomicBool>,
+}
+
+impl ParamWatcher {
+    // Start checking a file every interval. The first check reports it as changed.
+    pub fn spawn(path: PathBuf, interval: Duration) -> io::Result<Self> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&changed);
+        let watched = path.clone();
+        
+        thread::Builder::new()
+            .name("param-watcher".to_string())
+            .spawn(move || {
+                let mut last: Option<FileStamp> = None;
+                // Stops once the ParamWatcher is dropped
+                while Arc::strong_count(&flag) > 1 {
+                    let current = stamp(&watched);
+                    if last != Some(current) {
+                        last = Some(current);
+                        flag.store(true, Ordering::Release);
+                    }
+                    thread::sleep(interval);
+                }
+            })?;
+        
+        Ok(Self { path, changed })
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    // Whether the file changed since the last call (several changes count as one)
+    pub fn changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wait up to two seconds for the watcher to report a change
+    fn wait_for_change(watcher: &ParamWatcher) -> bool {
+        for _ in 0..200 {
+            if watcher.changed() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn the_file_is_reported_at_startup_and_after_each_change() {
+        let path = std::env::temp_dir().join(format!("boids_watch_test_{}.toml", std::process::id()));
+        fs::write(&path, "num_boids = 10\n").unwrap();
+        let watcher = ParamWatcher::spawn(path.clone(), Duration::from_millis(10)).unwrap();
+
+        assert!(wait_for_change(&watcher));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!watcher.changed(), "an unchanged file was reported");
+
+        // A different size is noticed even within the timestamp resolution
+        fs::write(&path, "num_boids = 20000\n").unwrap();
+        assert!(wait_for_change(&watcher));
+
+        // So is deleting it
+        fs::remove_file(&path).unwrap();
+        assert!(wait_for_change(&watcher));
+    }
+}
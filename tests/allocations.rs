@@ -0,0 +1,39 @@
+/*
+ * Allocation Test
+ * 
+ * Counts the heap allocations of a physics step with the allocator the app uses for its
+ * debug panel. It needs its own test binary: the allocator is global, and no other test
+ * may allocate while a step is being measured.
+ */
+
+use boids::alloc_counter::{self, CountingAllocator};
+use boids::{Simulation, SimulationParams};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Average allocations per step, once the scratch buffers have grown to the flock
+fn allocations_per_step(num_boids: usize, enable_parallel: bool) -> usize {
+    let mut params = SimulationParams::default();
+    params.num_boids = num_boids;
+    params.enable_parallel = enable_parallel;
+    let mut sim = Simulation::with_seed(params, 1);
+    sim.step_n(5);
+
+    let before = alloc_counter::allocation_count();
+    sim.step_n(10);
+    (alloc_counter::allocation_count() - before) / 10
+}
+
+#[test]
+fn steps_allocate_independently_of_the_flock_size() {
+    for enable_parallel in [true, false] {
+        let small = allocations_per_step(1000, enable_parallel);
+        let large = allocations_per_step(4000, enable_parallel);
+        println!("allocations per step (parallel: {}): {} with 1000 boids, {} with 4000", enable_parallel, small, large);
+
+        // Gathering a separate neighbor list per boid used to cost one allocation per boid
+        assert!(large < 400, "{} allocations per step", large);
+        assert!(large <= small + 100, "{} allocations per step with 4000 boids, {} with 1000", large, small);
+    }
+}
@@ -0,0 +1,41 @@
+/*
+ * Grid Neighbor Test
+ * 
+ * Runs the same seeded flock through the spatial grid and the brute-force physics and
+ * checks that both find the same neighbors. The grid is only an acceleration structure,
+ * so its cell size must never change which boids a rule sees.
+ */
+
+use boids::{Simulation, SimulationParams};
+
+// Cohesion neighbor count of each boid after one step, with or without the grid
+fn neighbor_counts(mut params: SimulationParams, enable_spatial_grid: bool) -> Vec<usize> {
+    params.enable_spatial_grid = enable_spatial_grid;
+    // The brute-force rules only count neighbors when something uses the counts
+    params.enable_energy = true;
+    let mut sim = Simulation::with_seed(params, 7);
+    sim.step_n(1);
+    sim.neighbor_counts.clone()
+}
+
+#[test]
+fn grid_and_brute_force_find_the_same_neighbors_at_the_defaults() {
+    let params = SimulationParams::default();
+    let with_grid = neighbor_counts(params.clone(), true);
+    let brute_force = neighbor_counts(params, false);
+
+    assert!(with_grid.iter().sum::<usize>() > 0, "no boid has a neighbor");
+    assert_eq!(with_grid, brute_force);
+}
+
+#[test]
+fn small_cells_find_the_same_neighbors_as_the_brute_force_scan() {
+    // Cells a tenth of the perception radius wide (the old default) used to miss neighbors
+    // outside the 3x3 neighborhood
+    let mut params = SimulationParams::default();
+    params.cell_size_factor = 0.1;
+    let with_grid = neighbor_counts(params.clone(), true);
+    let brute_force = neighbor_counts(params, false);
+
+    assert_eq!(with_grid, brute_force);
+}
@@ -0,0 +1,48 @@
+/*
+ * Neighbor Reuse Test
+ * 
+ * Runs the same seeded flock with neighbor lists gathered every step and every third step
+ * and compares how aligned the flocks become. The runs diverge boid by boid, but reusing
+ * the lists for a few steps must not change the flock's statistics by more than a little.
+ */
+
+use boids::{Simulation, SimulationParams};
+
+const STEPS: usize = 1000;
+// Steps at the end of each run the polarization is averaged over
+const MEASURED_STEPS: usize = 200;
+// Single runs are chaotic, so the statistics are averaged over a few seeds
+const SEEDS: [u64; 3] = [11, 12, 13];
+
+// Mean polarization over the last MEASURED_STEPS of a run
+fn mean_polarization(neighbor_list_interval: usize, seed: u64) -> f32 {
+    let mut params = SimulationParams::default();
+    params.num_boids = 300;
+    params.world_size = 2000.0;
+    params.enable_spatial_grid = true;
+    params.enable_parallel = true;
+    params.neighbor_list_interval = neighbor_list_interval;
+    let mut sim = Simulation::with_seed(params, seed);
+
+    sim.step_n(STEPS - MEASURED_STEPS);
+    let mut total = 0.0;
+    for _ in 0..MEASURED_STEPS {
+        sim.step_n(1);
+        total += sim.order_parameter().expect("the flock is empty");
+    }
+    total / MEASURED_STEPS as f32
+}
+
+#[test]
+fn reusing_neighbor_lists_keeps_the_polarization_close() {
+    let average = |interval| SEEDS.iter().map(|&seed| mean_polarization(interval, seed)).sum::<f32>() / SEEDS.len() as f32;
+    let every_step = average(1);
+    let every_third_step = average(3);
+
+    assert!(
+        (every_step - every_third_step).abs() < 0.1,
+        "polarization {} with lists gathered every step, {} every third step",
+        every_step,
+        every_third_step
+    );
+}